@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result, bail};
 
@@ -29,7 +29,7 @@ pub fn get_app_dirs() -> Result<Vec<PathBuf>> {
         }
     }
 
-    dirs.sort_by(|a, b| parse_version(a).cmp(&parse_version(b)));
+    dirs.sort_by_key(|dir| parse_version(dir));
     Ok(dirs)
 }
 
@@ -86,7 +86,7 @@ pub fn detect_proxy_client() -> Option<(&'static str, &'static str, u16)> {
     None
 }
 
-fn parse_version(path: &PathBuf) -> Vec<u32> {
+fn parse_version(path: &Path) -> Vec<u32> {
     path.file_name()
         .and_then(|n| n.to_str())
         .and_then(|n| n.strip_prefix("app-"))
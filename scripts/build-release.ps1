@@ -5,7 +5,7 @@
 #   .\scripts\build-release.ps1
 #
 # 输出：
-#   ./dist/我的服务器.exe
+#   ./dist/<服务器名称>.exe（名称取自 upmc/branding.toml 的 app.server_name）
 #
 # 这就是需要分发给玩家的唯一文件。
 # 玩家双击后，exe 会自动下载所有依赖组件。
@@ -54,7 +54,18 @@ if (-not (Test-Path $ExePath)) {
 Write-Host "[2/3] 复制到 dist/..." -ForegroundColor Yellow
 
 New-Item -ItemType Directory -Path $DistDir -Force | Out-Null
-$OutputExe = Join-Path $DistDir "我的服务器.exe"
+# 与 build.rs 一致：设置了 UPMC_BRANDING 时读取它（相对路径相对于 upmc/，绝对路径原样使用）
+if ($env:UPMC_BRANDING) {
+    $BrandingToml = [System.IO.Path]::Combine($UpdaterDir, $env:UPMC_BRANDING)
+} else {
+    $BrandingToml = Join-Path $UpdaterDir "branding.toml"
+}
+if ((Get-Content $BrandingToml -Raw -Encoding UTF8) -match 'server_name\s*=\s*"([^"]+)"') {
+    $ServerName = $Matches[1]
+} else {
+    $ServerName = "upmc"
+}
+$OutputExe = Join-Path $DistDir "$ServerName.exe"
 Copy-Item $ExePath $OutputExe -Force
 
 $size = [math]::Round((Get-Item $OutputExe).Length / 1MB, 2)
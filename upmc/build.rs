@@ -2,22 +2,96 @@
 // build.rs — 构建脚本
 // ============================================================
 // 在编译时运行，用于：
-//   1. 嵌入应用图标 (.ico) 到 exe 文件
-//   2. 设置 exe 的版本信息（右键属性可见）
-//   3. 传递 UPMC_BUILD_ID 环境变量供 option_env!() 使用
+//   1. 读取并校验品牌配置 branding.toml，生成 branding.rs 使用的常量
+//   2. 嵌入应用图标 (.ico) 到 exe 文件
+//   3. 设置 exe 的版本信息（右键属性可见）
+//   4. 传递 UPMC_BUILD_ID 环境变量供 option_env!() 使用
 //
-// 依赖 winresource crate。
+// 依赖 winresource、toml crate。
 // ============================================================
 
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// 品牌配置文件（见 branding.toml 中的说明）
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Branding {
+    app: App,
+    launcher: Launcher,
+    #[serde(default)]
+    colors: BTreeMap<String, String>,
+    endpoints: Endpoints,
+    downloads: Downloads,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct App {
+    server_name: String,
+    product_name: String,
+    description: String,
+    install_dir_name: String,
+    icon: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Launcher {
+    setup_ini: PathBuf,
+    subtitle: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Endpoints {
+    server_json: String,
+    updater_version: String,
+    updater_dev_version: String,
+    github_proxy: String,
+    server_json_github_repo: Option<String>,
+    updater_github_repo: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Downloads {
+    trusted_hosts: Vec<String>,
+}
+
 fn main() {
+    let branding_path = branding_path();
+    let branding_dir = branding_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .to_path_buf();
+    let branding = load_branding(&branding_path);
+    let icon = branding
+        .app
+        .icon
+        .as_ref()
+        .map(|icon| branding_dir.join(icon));
+    let setup_ini = read_setup_ini(&branding_dir.join(&branding.launcher.setup_ini));
+
+    let errors = validate(&branding, icon.as_deref(), &setup_ini);
+    if !errors.is_empty() {
+        panic!(
+            "品牌配置 {} 无效:\n  - {}",
+            branding_path.display(),
+            errors.join("\n  - ")
+        );
+    }
+    write_branding_rs(&branding, &setup_ini);
+
     // 只在 Windows 上执行
     if std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_default() == "windows" {
         let mut res = winresource::WindowsResource::new();
 
-        // 设置 exe 图标（如果 assets/icon.ico 存在）
-        // 如果没有图标文件，注释掉这行即可正常编译
-        if std::path::Path::new("assets/icon.ico").exists() {
-            res.set_icon("assets/icon.ico");
+        // 设置 exe 图标（branding.toml 中的 app.icon，未配置时不嵌入）
+        if let Some(icon) = &icon {
+            res.set_icon(&icon.to_string_lossy());
         }
 
         // 嵌入应用程序清单（启用 Common Controls v6，
@@ -27,12 +101,11 @@ fn main() {
         }
 
         // 设置 exe 版本信息（右键 → 属性 → 详细信息）
-        res.set("ProductName", "UPMC 服务器更新器");
-        res.set("FileDescription", "Minecraft 服务器整合包自动更新工具");
+        res.set("ProductName", &branding.app.product_name);
+        res.set("FileDescription", &branding.app.description);
         res.set("LegalCopyright", "MIT License");
 
         // 资源文件变更时触发重新编译
-        println!("cargo:rerun-if-changed=assets/icon.ico");
         println!("cargo:rerun-if-changed=assets/app.manifest");
 
         // 编译资源
@@ -64,3 +137,222 @@ fn main() {
     // 未设置时 option_env!() 返回 None，回退为空字符串
     println!("cargo:rerun-if-env-changed=UPMC_SUB_URL");
 }
+
+// ── 品牌配置 ──
+
+/// 品牌配置文件路径：UPMC_BRANDING 环境变量指定，默认为 upmc/branding.toml
+fn branding_path() -> PathBuf {
+    println!("cargo:rerun-if-env-changed=UPMC_BRANDING");
+    std::env::var_os("UPMC_BRANDING")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("branding.toml"))
+}
+
+fn load_branding(path: &Path) -> Branding {
+    println!("cargo:rerun-if-changed={}", path.display());
+    let text = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("读取品牌配置 {} 失败: {e}", path.display()));
+    toml::from_str(&text).unwrap_or_else(|e| panic!("解析品牌配置 {} 失败:\n{e}", path.display()))
+}
+
+/// 读取 Setup.ini 模板，统一为 CRLF 换行（PCL2 写出的格式）
+fn read_setup_ini(path: &Path) -> String {
+    println!("cargo:rerun-if-changed={}", path.display());
+    let text = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("读取 Setup.ini 模板 {} 失败: {e}", path.display()));
+    let mut text = text.replace("\r\n", "\n").replace('\n', "\r\n");
+    if !text.ends_with("\r\n") {
+        text.push_str("\r\n");
+    }
+    text
+}
+
+/// 校验品牌配置，返回所有问题（一次列出，免得反复构建）
+fn validate(branding: &Branding, icon: Option<&Path>, setup_ini: &str) -> Vec<String> {
+    let mut errors = Vec::new();
+    let app = &branding.app;
+
+    for (key, value) in [
+        ("app.server_name", &app.server_name),
+        ("app.product_name", &app.product_name),
+        ("app.description", &app.description),
+        ("launcher.subtitle", &branding.launcher.subtitle),
+    ] {
+        if value.trim().is_empty() {
+            errors.push(format!("{key} 不能为空"));
+        } else if value.chars().any(char::is_control) {
+            errors.push(format!("{key} 不能包含换行等控制字符"));
+        }
+    }
+
+    // 安装目录名会直接拼进文档文件夹路径，必须是合法的 Windows 目录名
+    let dir_name = &app.install_dir_name;
+    if dir_name.trim().is_empty()
+        || dir_name
+            .chars()
+            .any(|c| c.is_control() || r#"<>:"/\|?*"#.contains(c))
+        || dir_name.ends_with(['.', ' '])
+        || dir_name == ".."
+    {
+        errors.push(format!(
+            "app.install_dir_name 不是合法的目录名: {dir_name:?}"
+        ));
+    }
+
+    if let Some(icon) = icon {
+        if !icon.is_file() {
+            errors.push(format!("app.icon 文件不存在: {}", icon.display()));
+        } else if icon.extension().is_none_or(|ext| ext != "ico") {
+            errors.push(format!("app.icon 必须是 .ico 文件: {}", icon.display()));
+        }
+        println!("cargo:rerun-if-changed={}", icon.display());
+    }
+
+    for (name, color) in &branding.colors {
+        if !is_key(name) {
+            errors.push(format!("colors.{name}: 名称只能包含小写字母、数字和下划线"));
+        }
+        let hex = color.strip_prefix('#').unwrap_or("");
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            errors.push(format!("colors.{name} 必须是 #RRGGBB 格式: {color:?}"));
+        }
+    }
+
+    // Setup.ini：占位符必须都认识；必须关闭版本隔离（packwiz 安装到 .minecraft/）
+    let mut rest = setup_ini;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            errors.push("Setup.ini 模板中有未闭合的 {".to_string());
+            break;
+        };
+        let name = &rest[start + 1..start + len];
+        let known = matches!(name, "server_name" | "launcher_subtitle")
+            || name
+                .strip_prefix("color.")
+                .is_some_and(|color| branding.colors.contains_key(color));
+        if !known {
+            errors.push(format!("Setup.ini 模板中有未知的占位符: {{{name}}}"));
+        }
+        rest = &rest[start + len + 1..];
+    }
+    if !setup_ini
+        .lines()
+        .any(|line| line.trim() == "VersionArgumentIndie=1")
+    {
+        errors.push("Setup.ini 模板必须包含 VersionArgumentIndie=1（关闭版本隔离）".to_string());
+    }
+
+    let endpoints = &branding.endpoints;
+    for (key, url) in [
+        ("endpoints.server_json", &endpoints.server_json),
+        ("endpoints.updater_version", &endpoints.updater_version),
+        (
+            "endpoints.updater_dev_version",
+            &endpoints.updater_dev_version,
+        ),
+        ("endpoints.github_proxy", &endpoints.github_proxy),
+    ] {
+        if !url.starts_with("https://") || url.len() <= "https://".len() || url.contains(' ') {
+            errors.push(format!("{key} 必须是 HTTPS 地址: {url:?}"));
+        }
+    }
+    if !endpoints.github_proxy.ends_with('/') {
+        errors.push("endpoints.github_proxy 必须以 / 结尾（直接拼接 GitHub 地址）".to_string());
+    }
+    for (key, repo) in [
+        (
+            "endpoints.server_json_github_repo",
+            &endpoints.server_json_github_repo,
+        ),
+        ("endpoints.updater_github_repo", &endpoints.updater_github_repo),
+    ] {
+        let Some(repo) = repo else {
+            continue;
+        };
+        let valid = repo.split('/').count() == 2
+            && repo.split('/').all(|part| {
+                !part.is_empty()
+                    && part
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+            });
+        if !valid {
+            errors.push(format!("{key} 必须是 owner/repo 格式: {repo:?}"));
+        }
+    }
+
+    let hosts = &branding.downloads.trusted_hosts;
+    if hosts.is_empty() {
+        errors.push("downloads.trusted_hosts 不能为空".to_string());
+    }
+    for host in hosts {
+        let valid = host.contains('.')
+            && !host.starts_with('.')
+            && !host.ends_with('.')
+            && host
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' || c == '-');
+        if !valid {
+            errors.push(format!(
+                "downloads.trusted_hosts 只能填写小写域名（不含协议和路径）: {host:?}"
+            ));
+        }
+    }
+
+    errors
+}
+
+fn is_key(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// 生成 $OUT_DIR/branding.rs，由 src/branding.rs include!
+fn write_branding_rs(branding: &Branding, setup_ini: &str) {
+    let mut out = String::from("// 由 build.rs 根据品牌配置生成，请勿手动修改\n\n");
+    let consts = [
+        ("SERVER_NAME", &branding.app.server_name),
+        ("LAUNCHER_SUBTITLE", &branding.launcher.subtitle),
+        ("INSTALL_DIR_NAME", &branding.app.install_dir_name),
+        ("SERVER_JSON_URL", &branding.endpoints.server_json),
+        ("UPDATER_VERSION_URL", &branding.endpoints.updater_version),
+        (
+            "UPDATER_DEV_VERSION_URL",
+            &branding.endpoints.updater_dev_version,
+        ),
+        ("GITHUB_PROXY", &branding.endpoints.github_proxy),
+    ];
+    for (name, value) in consts {
+        writeln!(out, "pub const {name}: &str = {value:?};").unwrap();
+    }
+    writeln!(out, "pub const SETUP_INI_TEMPLATE: &str = {setup_ini:?};").unwrap();
+    writeln!(
+        out,
+        "pub const SERVER_JSON_GITHUB_REPO: Option<&str> = {:?};",
+        branding.endpoints.server_json_github_repo
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub const UPDATER_GITHUB_REPO: Option<&str> = {:?};",
+        branding.endpoints.updater_github_repo
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub const TRUSTED_HOSTS: &[&str] = &{:?};",
+        branding.downloads.trusted_hosts
+    )
+    .unwrap();
+    let colors: Vec<(&str, &str)> = branding
+        .colors
+        .iter()
+        .map(|(name, color)| (name.as_str(), color.as_str()))
+        .collect();
+    writeln!(out, "pub const COLORS: &[(&str, &str)] = &{colors:?};").unwrap();
+
+    let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").expect("缺少 OUT_DIR"));
+    std::fs::write(out_dir.join("branding.rs"), out).expect("写入 branding.rs 失败");
+}
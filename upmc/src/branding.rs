@@ -0,0 +1,147 @@
+// ============================================================
+// branding.rs — 服务器品牌（白标配置）
+// ============================================================
+// 服务器名称、安装目录、图标、颜色、Setup.ini 模板、服务端地址和
+// 可信下载域名都写在 upmc/branding.toml（或构建时 UPMC_BRANDING
+// 指定的文件）中，由 build.rs 校验后生成这里的常量。
+// 其他服务器使用本项目只需修改品牌配置，不用改代码。
+//
+// server.json 的 branding 字段可以覆盖显示用的文字（服务器名称、
+// 启动器副标题），保存在 updater/branding.json，下次启动时生效。
+// 地址和可信域名只能在构建时配置，不能被远程覆盖。
+// ============================================================
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::state;
+
+include!(concat!(env!("OUT_DIR"), "/branding.rs"));
+
+/// 远程覆盖的显示文字的长度上限（字符数）
+const MAX_OVERRIDE_CHARS: usize = 32;
+
+/// server.json 中 branding 字段：可覆盖的显示文字
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisplayOverrides {
+    /// 服务器名称（窗口标题、启动器 Logo）
+    #[serde(default)]
+    pub server_name: Option<String>,
+    /// 启动器 Logo 副标题
+    #[serde(default)]
+    pub launcher_subtitle: Option<String>,
+}
+
+/// 实际显示的品牌文字（编译期配置 + server.json 覆盖）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Display {
+    pub server_name: String,
+    pub launcher_subtitle: String,
+}
+
+impl Display {
+    /// 编译期配置的显示文字
+    pub fn builtin() -> Self {
+        Self {
+            server_name: SERVER_NAME.to_string(),
+            launcher_subtitle: LAUNCHER_SUBTITLE.to_string(),
+        }
+    }
+
+    /// 应用远程覆盖。不合法的值（空、含控制字符、过长）给出警告并忽略，
+    /// 避免远程配置借换行往 Setup.ini 中注入其他设置项。
+    fn apply(&mut self, overrides: &DisplayOverrides) {
+        for (target, value, key) in [
+            (&mut self.server_name, &overrides.server_name, "server_name"),
+            (
+                &mut self.launcher_subtitle,
+                &overrides.launcher_subtitle,
+                "launcher_subtitle",
+            ),
+        ] {
+            let Some(value) = value else { continue };
+            let value = value.trim();
+            if value.is_empty()
+                || value.chars().any(char::is_control)
+                || value.chars().count() > MAX_OVERRIDE_CHARS
+            {
+                eprintln!("警告: 忽略 server.json 中无效的 branding.{key}: {value:?}");
+                continue;
+            }
+            *target = value.to_string();
+        }
+    }
+
+    /// 首次安装时写入的 PCL2 Setup.ini
+    pub fn setup_ini(&self) -> String {
+        let mut text = SETUP_INI_TEMPLATE
+            .replace("{server_name}", &self.server_name)
+            .replace("{launcher_subtitle}", &self.launcher_subtitle);
+        for (name, color) in COLORS {
+            text = text.replace(&format!("{{color.{name}}}"), color);
+        }
+        text
+    }
+}
+
+/// 当前显示的品牌文字
+pub fn display(base_dir: &Path) -> Display {
+    let mut display = Display::builtin();
+    if let Some(overrides) = state::BRANDING.read_json(base_dir) {
+        display.apply(&overrides);
+    }
+    display
+}
+
+/// 保存 server.json 下发的显示文字覆盖，下次启动时生效
+pub fn save_server_overrides(base_dir: &Path, overrides: &DisplayOverrides) {
+    let existing: Option<DisplayOverrides> = state::BRANDING.read_json(base_dir);
+    if existing.as_ref() == Some(overrides)
+        || (existing.is_none() && *overrides == DisplayOverrides::default())
+    {
+        return;
+    }
+    if let Err(e) = state::BRANDING.write_json(base_dir, overrides) {
+        eprintln!("保存品牌覆盖失败: {e:#}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setup_ini_keeps_required_settings() {
+        let ini = Display::builtin().setup_ini();
+        assert!(ini.contains(&format!("Logo={SERVER_NAME}\r\n")));
+        assert!(ini.contains("VersionArgumentIndie=1\r\n"));
+        assert!(!ini.contains('{'));
+        assert!(!ini.replace("\r\n", "").contains('\n'));
+    }
+
+    #[test]
+    fn server_overrides_only_accept_plain_text() {
+        let mut display = Display::builtin();
+        display.apply(&DisplayOverrides {
+            server_name: Some("测试服\r\nVersionArgumentIndie=0".to_string()),
+            launcher_subtitle: Some(" 周末开服 ".to_string()),
+        });
+        assert_eq!(display.server_name, SERVER_NAME);
+        assert_eq!(display.launcher_subtitle, "周末开服");
+        assert!(display.setup_ini().contains("LogoSub=周末开服\r\n"));
+    }
+
+    #[test]
+    fn overrides_are_saved_and_applied() {
+        let dir = crate::http::fake::temp_dir("branding");
+        let overrides = DisplayOverrides {
+            server_name: Some("另一个服务器".to_string()),
+            launcher_subtitle: None,
+        };
+        save_server_overrides(&dir, &overrides);
+        let display = display(&dir);
+        assert_eq!(display.server_name, "另一个服务器");
+        assert_eq!(display.launcher_subtitle, LAUNCHER_SUBTITLE);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,709 @@
+// ============================================================
+// bundle.rs — 离线安装包导出 / 导入
+// ============================================================
+// 局域网活动或网络很差的玩家无法完成首次安装。已安装好的机器可以用
+//   upmc bundle export [输出路径]
+// 把首次运行所需的全部内容打成一个 ZIP：
+//   bundle/manifest.json   版本信息 + server.json / pack.toml 的来源 URL
+//   bundle/server.json     server.json 快照
+//   bundle/pack.toml       pack.toml 快照
+//   files/...              相对安装目录的文件（PCL2、工具 jar、版本目录、
+//                          libraries、mods、config、packwiz 状态等）
+//
+//   bundle/pack_index.json index.toml / .pw.toml 快照（按 URL），用于离线校验模组
+//
+// 导入时解压 files/ 并把快照写入 HTTP 缓存，之后即使离线也能启动；
+// 联网后按正常流程对比版本、增量同步，自动追上最新整合包。
+// 快照只写入当前来源的 server.json 地址（公共来源为编译期地址或其镜像）和
+// 它声明的 pack.toml 地址，且不覆盖已有的缓存；其他地址的安装包直接拒绝。
+//
+// 只导入明确指定的安装包：首次安装时的 --bundle <路径或 file:// URL>，
+// 或 upmc bundle import <路径>。放在 exe 旁边或安装目录中的 ZIP 不会被自动使用。
+//
+// 解压任何文件之前先校验整个安装包，任何一项不符都拒绝整个安装包：
+//   - PCL2、packwiz-installer、Fabric 安装器：server.json 的 downloads.*_sha256
+//   - 整合包的模组（.pw.toml 指向的文件）：整合包索引中的哈希；
+//     mods/ 中不属于整合包的文件直接拒绝
+// 索引中的其他文件（配置等，运行后可能被修改）哈希不符时只跳过该文件，
+// 之后由 packwiz 同步。校验依据按可信程度依次为：
+//   1. 联网获取的 server.json 和索引
+//   2. 离线时，以前联网缓存的 server.json 和 pack.toml（索引和 .pw.toml 缺少
+//      缓存时读取安装包中的快照，pack.toml → index.toml → .pw.toml 逐级校验哈希）
+//   3. 都没有时只能用安装包自带的快照：这只能发现损坏，不能发现篡改
+// 版本目录、libraries 等其余文件不在索引中，任何情况下都原样导入、不经校验，
+// 只应导入可信来源的安装包。
+// ============================================================
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use crate::bootstrap;
+use crate::config;
+use crate::error::{self, ErrorCode};
+use crate::http_cache;
+use crate::mirrors::{self, Endpoint};
+use crate::pack_index::{self, ResolvedFile};
+use crate::source;
+use crate::update::Progress;
+use crate::version::{self, Downloads, RemoteVersion, ServerConfig};
+
+/// 当前安装包格式版本。导入时拒绝更新的格式。
+/// 2：增加整合包索引快照（bundle/pack_index.json）
+const BUNDLE_FORMAT: u32 = 2;
+
+/// 能离线校验模组的最低格式版本
+const MIN_BUNDLE_FORMAT: u32 = 2;
+
+const MANIFEST_ENTRY: &str = "bundle/manifest.json";
+const SERVER_JSON_ENTRY: &str = "bundle/server.json";
+const PACK_TOML_ENTRY: &str = "bundle/pack.toml";
+const PACK_INDEX_ENTRY: &str = "bundle/pack_index.json";
+const FILES_PREFIX: &str = "files/";
+
+/// 整合包模组目录（相对安装目录）
+const MODS_PREFIX: &str = ".minecraft/mods/";
+
+/// 命令行指定安装包的参数
+const BUNDLE_ARG: &str = "--bundle";
+
+/// 安装包元信息（bundle/manifest.json）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format: u32,
+    pub mc_version: String,
+    pub fabric_version: String,
+    pub version_tag: String,
+    /// 快照所对应的 server.json URL，导入时以此为键写入 HTTP 缓存
+    pub server_url: String,
+    /// 快照所对应的 pack.toml URL
+    pub pack_url: String,
+}
+
+// ── 导出 ───────────────────────────────────────────────────
+
+/// 把当前安装导出为离线安装包。
+///
+/// 要求本机已联网完成过一次更新，且本地版本与缓存的清单一致。
+pub fn export(base_dir: &Path, dest: &Path) -> Result<BundleManifest> {
+    if !bootstrap::is_bootstrapped(base_dir) {
+        bail!("当前没有可导出的安装，请先正常运行一次更新器");
+    }
+
+    let (server_url, server_json) = version::cached_server_json(base_dir)
+        .context("缺少 server.json 快照，请先联网运行一次更新器")?;
+    let remote = version::cached_remote_version(base_dir)
+        .context("缺少 pack.toml 快照，请先联网运行一次更新器")?;
+    let local = version::read_local_version(base_dir);
+    if version::needs_version_upgrade(&remote, &local) {
+        bail!(
+            "本地安装（{}）与上次获取的整合包版本（{}）不一致，请先联网完成更新后再导出",
+            local.version_tag,
+            remote.version_tag
+        );
+    }
+
+    let manifest = BundleManifest {
+        format: BUNDLE_FORMAT,
+        mc_version: remote.mc_version.clone(),
+        fabric_version: remote.fabric_version.clone(),
+        version_tag: remote.version_tag.clone(),
+        server_url,
+        pack_url: remote.pack_url.clone(),
+    };
+
+    // 记录解析索引时读到的 index.toml / .pw.toml，供离线导入时校验模组
+    let pack_texts = RefCell::new(BTreeMap::new());
+    let pack_files =
+        pack_index::resolve_files_with(&remote.pack_url, &remote.pack_toml_raw, &|url| {
+            let text = source::read_text(base_dir, url)
+                .or_else(|e| source::cached_text(base_dir, url).ok_or(e))?;
+            pack_texts
+                .borrow_mut()
+                .insert(url.to_string(), text.clone());
+            Ok(text)
+        })
+        .context("读取整合包索引失败，请先联网完成更新后再导出")?;
+    let pack_index_json =
+        serde_json::to_string(&pack_texts.into_inner()).context("序列化整合包索引快照失败")?;
+
+    let files = collect_files(
+        base_dir,
+        &remote.mc_version,
+        &remote.version_tag,
+        &pack_files,
+    )?;
+
+    // 先写临时文件，完成后再改名，避免留下半截安装包
+    let tmp_path = dest.with_extension("zip.tmp");
+    let file = fs::File::create(&tmp_path)
+        .with_context(|| format!("创建安装包失败: {}", tmp_path.display()))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .large_file(true);
+
+    let manifest_json = serde_json::to_string_pretty(&manifest).context("序列化安装包信息失败")?;
+    for (name, content) in [
+        (MANIFEST_ENTRY, manifest_json.as_str()),
+        (SERVER_JSON_ENTRY, server_json.as_str()),
+        (PACK_TOML_ENTRY, remote.pack_toml_raw.as_str()),
+        (PACK_INDEX_ENTRY, pack_index_json.as_str()),
+    ] {
+        zip.start_file(name, options)?;
+        zip.write_all(content.as_bytes())?;
+    }
+
+    for relative in &files {
+        let file_path = base_dir.join(relative);
+        zip.start_file(format!("{FILES_PREFIX}{relative}"), options)
+            .with_context(|| format!("写入安装包条目失败: {relative}"))?;
+        let mut input = fs::File::open(&file_path)
+            .with_context(|| format!("读取文件失败: {}", file_path.display()))?;
+        std::io::copy(&mut input, &mut zip)
+            .with_context(|| format!("写入安装包条目失败: {relative}"))?;
+    }
+
+    zip.finish().context("完成安装包写入失败")?;
+    fs::rename(&tmp_path, dest).with_context(|| format!("保存安装包失败: {}", dest.display()))?;
+
+    Ok(manifest)
+}
+
+/// 列出需要打包的文件（相对安装目录、以 `/` 分隔）。
+///
+/// mods/ 中只打包属于整合包的文件，玩家自己添加的模组不会进入安装包。
+fn collect_files(
+    base_dir: &Path,
+    mc_version: &str,
+    version_tag: &str,
+    pack_files: &[ResolvedFile],
+) -> Result<Vec<String>> {
+    let required_files = [
+        config::PCL2_EXE,
+        config::PACKWIZ_BOOTSTRAP_JAR,
+        config::FABRIC_INSTALLER_JAR,
+        config::LOCAL_VERSION_FILE,
+    ];
+    let optional_files = [
+        config::PCL2_SETUP_INI_PATH,
+        config::PACK_TOML_CACHE_FILE,
+        ".minecraft/packwiz.json",
+    ];
+    let vanilla_dir = format!("{}/versions/{mc_version}", config::MINECRAFT_DIR);
+    let fabric_dir = format!("{}/versions/{version_tag}", config::MINECRAFT_DIR);
+    let required_dirs = [vanilla_dir.as_str(), fabric_dir.as_str()];
+    let optional_dirs = [".minecraft/libraries", ".minecraft/config"];
+
+    let mut files = Vec::new();
+    for relative in required_files {
+        if !base_dir.join(relative).is_file() {
+            bail!("安装不完整，缺少 {relative}，请先联网运行一次更新器");
+        }
+        files.push(relative.to_string());
+    }
+    for relative in optional_files {
+        if base_dir.join(relative).is_file() {
+            files.push(relative.to_string());
+        }
+    }
+    for relative in required_dirs {
+        if !base_dir.join(relative).is_dir() {
+            bail!("安装不完整，缺少 {relative}，请先联网运行一次更新器");
+        }
+        walk_dir(base_dir, relative, &mut files)?;
+    }
+    for relative in optional_dirs {
+        if base_dir.join(relative).is_dir() {
+            walk_dir(base_dir, relative, &mut files)?;
+        }
+    }
+    for file in pack_files {
+        let relative = format!("{}/{}", config::MINECRAFT_DIR, file.target);
+        if relative.starts_with(MODS_PREFIX) && base_dir.join(&relative).is_file() {
+            files.push(relative);
+        }
+    }
+    Ok(files)
+}
+
+/// 递归收集目录下的所有文件。
+fn walk_dir(base_dir: &Path, relative: &str, files: &mut Vec<String>) -> Result<()> {
+    let dir = base_dir.join(relative);
+    let entries = fs::read_dir(&dir).with_context(|| format!("读取目录失败: {}", dir.display()))?;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let child = format!("{relative}/{name}");
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            walk_dir(base_dir, &child, files)?;
+        } else if file_type.is_file() {
+            files.push(child);
+        }
+    }
+    Ok(())
+}
+
+// ── 导入 ───────────────────────────────────────────────────
+
+/// 命令行 `--bundle <路径或 file:// URL>` 指定的离线安装包。
+///
+/// 只使用明确指定的安装包，不会在 exe 旁边或安装目录中自动查找。
+pub fn requested() -> Option<PathBuf> {
+    let args: Vec<String> = std::env::args().collect();
+    let i = args.iter().position(|a| a == BUNDLE_ARG)?;
+    args.get(i + 1).map(|value| source::path_from_arg(value))
+}
+
+/// 从离线安装包导入。
+///
+/// `reference` 为联网获取的远程版本时，按服务器上的 server.json 和整合包索引校验；
+/// 为 None（离线）时按以前缓存的清单校验，没有缓存时只能按安装包自带的快照检查。
+/// 检查全部通过后才解压文件。
+/// 已存在的文件不会被覆盖（与默认设置包一致），
+/// 导入后 server.json / pack.toml 快照会写入 HTTP 缓存供离线模式使用。
+pub fn import(
+    base_dir: &Path,
+    bundle_path: &Path,
+    reference: Option<&RemoteVersion>,
+    on_progress: &dyn Fn(Progress),
+    progress_start: u32,
+    progress_end: u32,
+) -> Result<BundleManifest> {
+    let file = fs::File::open(bundle_path)
+        .with_context(|| format!("打开离线安装包失败: {}", bundle_path.display()))?;
+    let mut archive = zip::ZipArchive::new(file).context("读取离线安装包失败")?;
+
+    let manifest: BundleManifest = serde_json::from_str(&read_entry(&mut archive, MANIFEST_ENTRY)?)
+        .context("解析安装包信息失败")?;
+    if manifest.format > BUNDLE_FORMAT {
+        bail!(
+            "离线安装包格式版本过新（{}），请先更新 upmc",
+            manifest.format
+        );
+    }
+    if manifest.format < MIN_BUNDLE_FORMAT {
+        bail!("离线安装包格式过旧，无法校验其中的模组，请用新版更新器重新导出");
+    }
+    let server_json = read_entry(&mut archive, SERVER_JSON_ENTRY)?;
+    let pack_toml = read_entry(&mut archive, PACK_TOML_ENTRY)?;
+    // 快照必须能被正常解析，避免导入一个以后无法使用的缓存
+    let server_config: ServerConfig =
+        serde_json::from_str(&server_json).context("安装包中的 server.json 无效")?;
+    version::parse_pack_toml_versions(&pack_toml).context("安装包中的 pack.toml 无效")?;
+    let (server_url, pack_url) = cache_urls(base_dir, &manifest.server_url, &server_config)?;
+
+    on_progress(Progress::new(progress_start, "正在校验离线安装包..."));
+    let texts: BTreeMap<String, String> =
+        serde_json::from_str(&read_entry(&mut archive, PACK_INDEX_ENTRY)?)
+            .context("安装包中的整合包索引快照无效")?;
+    let from_snapshot = |url: &str| {
+        texts
+            .get(url)
+            .cloned()
+            .with_context(|| format!("安装包中缺少 {url} 的快照"))
+    };
+    let expected = if let Some(remote) = reference {
+        expected_hashes(
+            &remote.downloads,
+            &pack_index::resolve_files(base_dir, &remote.pack_url, &remote.pack_toml_raw)
+                .context("获取整合包索引失败，无法校验离线安装包")?,
+        )
+    } else if let Some(remote) = version::cached_remote_version(base_dir) {
+        let pack_files =
+            pack_index::resolve_files_with(&remote.pack_url, &remote.pack_toml_raw, &|url| {
+                source::cached_text(base_dir, url).map_or_else(|| from_snapshot(url), Ok)
+            })
+            .context("安装包与以前获取的整合包不一致，请联网后重试")?;
+        expected_hashes(&remote.downloads, &pack_files)
+    } else {
+        eprintln!(
+            "警告: 没有可信的 server.json，只能按离线安装包自带的快照检查（能发现损坏，不能发现篡改）"
+        );
+        let pack_files =
+            pack_index::resolve_files_with(&manifest.pack_url, &pack_toml, &from_snapshot)
+                .context("安装包中的整合包索引快照校验失败")?;
+        expected_hashes(&server_config.downloads, &pack_files)
+    };
+    let accepted = verify_entries(&mut archive, &expected)
+        .with_context(|| format!("离线安装包校验未通过: {}", bundle_path.display()))?;
+
+    let total = accepted.len().max(1);
+    for (i, (index, relative)) in accepted.iter().enumerate() {
+        let out_path = bootstrap::safe_zip_output_path(base_dir, relative)?;
+        if !out_path.exists() {
+            let mut entry = archive
+                .by_index(*index)
+                .with_context(|| format!("读取安装包条目失败: {relative}"))?;
+            if let Some(parent) = out_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut outfile = fs::File::create(&out_path)
+                .with_context(|| format!("创建文件失败: {}", out_path.display()))?;
+            std::io::copy(&mut entry, &mut outfile)
+                .with_context(|| format!("解压失败: {relative}"))?;
+        }
+
+        let pct =
+            progress_start + ((i + 1) * (progress_end - progress_start) as usize / total) as u32;
+        on_progress(Progress::new(
+            pct.min(progress_end),
+            "正在从离线安装包导入...",
+        ));
+    }
+
+    // 不覆盖联网获取的内容
+    for (url, body) in [(&server_url, &server_json), (&pack_url, &pack_toml)] {
+        if http_cache::cached_text(base_dir, url).is_none() {
+            http_cache::store_text(base_dir, url, body)?;
+        }
+    }
+
+    Ok(manifest)
+}
+
+/// 快照写入 HTTP 缓存时使用的 (server.json URL, pack.toml URL)。
+///
+/// 安装包中的 URL 不可信：server.json 只能是当前来源的地址（公共来源为编译期地址或
+/// 已校验的镜像），pack.toml 必须是 HTTPS 或当前来源下的 .toml 文件，否则拒绝整个安装包。
+fn cache_urls(
+    base_dir: &Path,
+    server_url: &str,
+    server_config: &ServerConfig,
+) -> Result<(String, String)> {
+    let server_source = source::resolve();
+    let trusted = match server_source.server_json_url() {
+        Some(url) => vec![url],
+        None => mirrors::candidates(base_dir, Endpoint::ServerJson),
+    };
+    if !trusted.iter().any(|url| url == server_url) {
+        return Err(error::new(
+            ErrorCode::UntrustedHost,
+            format!("离线安装包的 server.json 地址不是当前整合包的来源: {server_url}"),
+        ));
+    }
+
+    let pack_url = source::resolve_relative(server_url, &server_config.pack_url);
+    let path = pack_url.split(['?', '#']).next().unwrap_or_default();
+    if !(pack_url.starts_with("https://") || server_source.allows(&pack_url))
+        || !path.ends_with(".toml")
+    {
+        return Err(error::new(
+            ErrorCode::UntrustedHost,
+            format!("离线安装包的 pack_url 不可信: {pack_url}"),
+        ));
+    }
+    Ok((server_url.to_string(), pack_url))
+}
+
+/// 安装包中一个文件的校验依据
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Expected {
+    /// (哈希格式, 哈希)；Err 为无法校验的原因
+    hash: Result<(String, String), String>,
+    /// 不符时拒绝整个安装包（工具、模组）；否则只跳过该文件
+    required: bool,
+}
+
+/// 汇总校验依据：相对安装目录的路径 → 期望哈希
+fn expected_hashes(
+    downloads: &Downloads,
+    pack_files: &[ResolvedFile],
+) -> BTreeMap<String, Expected> {
+    let tools = [
+        (config::PCL2_EXE, &downloads.pcl2_sha256, "pcl2_sha256"),
+        (
+            config::PACKWIZ_BOOTSTRAP_JAR,
+            &downloads.packwiz_bootstrap_sha256,
+            "packwiz_bootstrap_sha256",
+        ),
+        (
+            config::FABRIC_INSTALLER_JAR,
+            &downloads.fabric_installer_sha256,
+            "fabric_installer_sha256",
+        ),
+    ];
+
+    let mut expected = BTreeMap::new();
+    for (path, sha256, field) in tools {
+        let hash = bootstrap::require_download_sha(sha256.as_deref(), field)
+            .map(|hash| ("sha256".to_string(), hash.to_ascii_lowercase()))
+            .map_err(|e| format!("{e:#}"));
+        expected.insert(
+            path.to_string(),
+            Expected {
+                hash,
+                required: true,
+            },
+        );
+    }
+    for file in pack_files {
+        let relative = format!("{}/{}", config::MINECRAFT_DIR, file.target);
+        let (hash_format, hash) = match &file.meta {
+            Some(meta) => (&meta.hash_format, &meta.hash),
+            None => (&file.entry.hash_format, &file.entry.hash),
+        };
+        let hash = if hash.is_empty() {
+            Err("整合包索引中没有该文件的哈希".to_string())
+        } else {
+            Ok((hash_format.clone(), hash.clone()))
+        };
+        // .pw.toml 指向的文件（模组、光影等）和 mods/ 下的文件必须一致
+        let required = file.meta.is_some() || relative.starts_with(MODS_PREFIX);
+        expected.insert(relative, Expected { hash, required });
+    }
+    expected
+}
+
+/// 校验安装包中的全部文件，返回需要解压的条目（序号, 相对安装目录的路径）。
+///
+/// 工具或模组无法校验、哈希不符，或 mods/ 中有整合包之外的文件时返回错误。
+fn verify_entries<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    expected: &BTreeMap<String, Expected>,
+) -> Result<Vec<(usize, String)>> {
+    let mut accepted = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .with_context(|| format!("读取安装包条目 #{index} 失败"))?;
+        let Some(relative) = entry.name().strip_prefix(FILES_PREFIX).map(str::to_string) else {
+            continue;
+        };
+        if relative.is_empty() || entry.is_dir() {
+            continue;
+        }
+        // 路径先整体检查一遍，避免解压到一半才发现非法条目
+        bootstrap::safe_zip_output_path(Path::new("."), &relative)?;
+
+        let Some(check) = expected.get(&relative) else {
+            if relative.starts_with(MODS_PREFIX) {
+                return Err(error::new(
+                    ErrorCode::HashMismatch,
+                    format!("安装包中有不属于整合包的模组: {relative}"),
+                ));
+            }
+            accepted.push((index, relative));
+            continue;
+        };
+
+        let result = match &check.hash {
+            Ok((hash_format, hash)) => {
+                let mut data = Vec::new();
+                entry
+                    .read_to_end(&mut data)
+                    .with_context(|| format!("读取安装包条目失败: {relative}"))?;
+                pack_index::verify_hash(hash_format, &data, hash)
+            }
+            Err(reason) => Err(anyhow::anyhow!("{reason}")),
+        };
+        match result {
+            Ok(()) => accepted.push((index, relative)),
+            Err(e) if check.required => {
+                return Err(error::new(
+                    ErrorCode::HashMismatch,
+                    format!("{relative} 校验失败: {e:#}"),
+                ));
+            }
+            Err(e) => eprintln!("跳过与整合包不一致的文件 {relative}: {e:#}"),
+        }
+    }
+    Ok(accepted)
+}
+
+fn read_entry<R: Read + std::io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    name: &str,
+) -> Result<String> {
+    let mut entry = archive
+        .by_name(name)
+        .with_context(|| format!("离线安装包缺少 {name}"))?;
+    let mut text = String::new();
+    entry
+        .read_to_string(&mut text)
+        .with_context(|| format!("读取 {name} 失败"))?;
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walk_dir_lists_nested_files() {
+        let dir = crate::http::fake::temp_dir("bundle_walk");
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("a/one.txt"), "1").unwrap();
+        fs::write(dir.join("a/b/two.txt"), "2").unwrap();
+
+        let mut files = Vec::new();
+        walk_dir(&dir, "a", &mut files).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            vec!["a/b/two.txt".to_string(), "a/one.txt".to_string()]
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn snapshots_are_cached_only_under_the_current_source() {
+        let dir = crate::http::fake::temp_dir("bundle_cache_urls");
+        let server_config = |pack_url: &str| -> ServerConfig {
+            serde_json::from_value(serde_json::json!({ "pack_url": pack_url })).unwrap()
+        };
+        let pack = server_config("https://update.mc.chenjicheng.cn/pack.toml");
+
+        let (server_url, pack_url) =
+            cache_urls(&dir, config::REMOTE_SERVER_JSON_URL, &pack).unwrap();
+        assert_eq!(server_url, config::REMOTE_SERVER_JSON_URL);
+        assert_eq!(pack_url, "https://update.mc.chenjicheng.cn/pack.toml");
+
+        // 伪造的安装包不能改写任意 URL 的缓存
+        let untrusted = [
+            cache_urls(&dir, "https://launchermeta.mojang.com/server.json", &pack),
+            cache_urls(
+                &dir,
+                config::REMOTE_SERVER_JSON_URL,
+                &server_config("https://piston-meta.mojang.com/mc/game/version_manifest_v2.json"),
+            ),
+            cache_urls(
+                &dir,
+                config::REMOTE_SERVER_JSON_URL,
+                &server_config("http://update.mc.chenjicheng.cn/pack.toml"),
+            ),
+        ];
+        for result in untrusted {
+            let err = result.unwrap_err();
+            assert_eq!(
+                error::classify(&err),
+                Some(ErrorCode::UntrustedHost),
+                "{err:#}"
+            );
+        }
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    fn sha256(data: &[u8]) -> String {
+        pack_index::hash_bytes("sha256", data).unwrap()
+    }
+
+    /// 构造只含 files/ 条目的安装包
+    fn archive(files: &[(&str, &[u8])]) -> zip::ZipArchive<std::io::Cursor<Vec<u8>>> {
+        use std::io::Write;
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, data) in files {
+            zip.start_file(
+                format!("{FILES_PREFIX}{name}"),
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip::ZipArchive::new(zip.finish().unwrap()).unwrap()
+    }
+
+    /// 三个工具 + 一个模组（元数据文件）+ 一个普通配置文件
+    fn expected() -> BTreeMap<String, Expected> {
+        let downloads = Downloads {
+            pcl2_sha256: Some(sha256(b"pcl2")),
+            packwiz_bootstrap_sha256: Some(sha256(b"packwiz")),
+            fabric_installer_sha256: Some(sha256(b"fabric")),
+            ..Default::default()
+        };
+        let entry = |file: &str, hash: String, metafile: bool| pack_index::IndexEntry {
+            file: file.to_string(),
+            hash,
+            hash_format: "sha256".to_string(),
+            metafile,
+            preserve: false,
+        };
+        let pack_files = vec![
+            ResolvedFile {
+                entry: entry("mods/sodium.pw.toml", sha256(b"meta"), true),
+                target: "mods/sodium.jar".to_string(),
+                meta: Some(pack_index::ModMeta {
+                    hash_format: "sha256".to_string(),
+                    hash: sha256(b"sodium"),
+                    ..Default::default()
+                }),
+            },
+            ResolvedFile {
+                entry: entry("config/a.txt", sha256(b"config"), false),
+                target: "config/a.txt".to_string(),
+                meta: None,
+            },
+        ];
+        expected_hashes(&downloads, &pack_files)
+    }
+
+    const VALID: [(&str, &[u8]); 4] = [
+        (config::PCL2_EXE, b"pcl2"),
+        (config::PACKWIZ_BOOTSTRAP_JAR, b"packwiz"),
+        (config::FABRIC_INSTALLER_JAR, b"fabric"),
+        (".minecraft/mods/sodium.jar", b"sodium"),
+    ];
+
+    fn is_hash_mismatch(e: &anyhow::Error) -> bool {
+        error::classify(e) == Some(ErrorCode::HashMismatch)
+    }
+
+    #[test]
+    fn verify_entries_accepts_matching_bundle() {
+        let mut files = VALID.to_vec();
+        files.push((".minecraft/config/a.txt", b"config"));
+        files.push((".minecraft/options.txt", b"extra"));
+        let accepted = verify_entries(&mut archive(&files), &expected()).unwrap();
+        assert_eq!(accepted.len(), 6);
+    }
+
+    #[test]
+    fn verify_entries_rejects_tampered_tool_or_mod() {
+        for name in [config::PACKWIZ_BOOTSTRAP_JAR, ".minecraft/mods/sodium.jar"] {
+            let files: Vec<(&str, &[u8])> = VALID
+                .iter()
+                .map(|&(n, data)| {
+                    if n == name {
+                        (n, &b"evil"[..])
+                    } else {
+                        (n, data)
+                    }
+                })
+                .collect();
+            let err = verify_entries(&mut archive(&files), &expected()).unwrap_err();
+            assert!(is_hash_mismatch(&err), "{name}: {err:#}");
+        }
+    }
+
+    #[test]
+    fn verify_entries_rejects_unknown_mod_and_unverifiable_tool() {
+        let mut files = VALID.to_vec();
+        files.push((".minecraft/mods/extra.jar", b"extra"));
+        let err = verify_entries(&mut archive(&files), &expected()).unwrap_err();
+        assert!(is_hash_mismatch(&err), "{err:#}");
+
+        // server.json 没有工具的 sha256 时无法校验，同样拒绝
+        let empty = expected_hashes(&Downloads::default(), &[]);
+        let err = verify_entries(&mut archive(&VALID[..1]), &empty).unwrap_err();
+        assert!(is_hash_mismatch(&err), "{err:#}");
+    }
+
+    #[test]
+    fn verify_entries_skips_mismatched_config() {
+        let mut files = VALID.to_vec();
+        files.push((".minecraft/config/a.txt", b"changed"));
+        let accepted = verify_entries(&mut archive(&files), &expected()).unwrap();
+        assert_eq!(accepted.len(), 4);
+        assert!(
+            accepted
+                .iter()
+                .all(|(_, path)| path != ".minecraft/config/a.txt")
+        );
+    }
+}
@@ -0,0 +1,311 @@
+// ============================================================
+// changelog.rs — 整合包更新日志
+// ============================================================
+// 玩家总是问「这次更新改了什么？」。每次同步后把整合包文件列表
+// 保存为快照（updater/pack_snapshot.json），下次整合包变化时与新列表对比：
+//   - 模组（.pw.toml）：新增 / 移除 / 更新（旧版本 → 新版本；
+//     .pw.toml 中没有版本信息时显示文件名）
+//   - 其他文件（配置等）：新增 / 删除 / 修改
+// 结果在同步后显示给玩家，并追加到 updater/changelog_history.json。
+// ============================================================
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::pack_index::ResolvedFile;
+use crate::platform;
+use crate::state;
+
+/// 历史记录最多保留的条数
+const MAX_HISTORY: usize = 30;
+
+/// 快照中的一个文件（键为索引中的路径）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    /// 模组显示名称；普通文件为空
+    pub name: String,
+    /// 实际文件路径（相对 .minecraft）
+    pub target: String,
+    /// 索引中的哈希（模组为 .pw.toml 的哈希）
+    pub hash: String,
+    pub metafile: bool,
+    /// 模组版本（见 `ModMeta::version`）；没有版本信息或旧快照为空
+    #[serde(default)]
+    pub version: String,
+}
+
+/// 整合包文件列表快照
+pub type Snapshot = BTreeMap<String, SnapshotEntry>;
+
+/// 一次更新的变更
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Changelog {
+    /// 本地时间，如 "2026-10-18 20:31"
+    pub date: String,
+    pub version_tag: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub updated: Vec<String>,
+    pub configs: Vec<String>,
+}
+
+impl Changelog {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.updated.is_empty()
+            && self.configs.is_empty()
+    }
+
+    /// 给玩家看的文本
+    pub fn render(&self) -> String {
+        let mut lines = vec![format!("{}  {}", self.date, self.version_tag)];
+        for (title, items) in [
+            ("新增模组", &self.added),
+            ("移除模组", &self.removed),
+            ("更新模组", &self.updated),
+            ("配置变更", &self.configs),
+        ] {
+            if items.is_empty() {
+                continue;
+            }
+            lines.push(format!("{title}（{}）:", items.len()));
+            lines.extend(items.iter().map(|item| format!("  • {item}")));
+        }
+        if self.is_empty() {
+            lines.push("  没有文件变化".to_string());
+        }
+        lines.join("\r\n")
+    }
+}
+
+/// 从整合包文件列表生成快照
+pub fn snapshot_of(files: &[ResolvedFile]) -> Snapshot {
+    files
+        .iter()
+        .map(|file| {
+            let entry = SnapshotEntry {
+                name: file.meta.as_ref().map(|m| m.name.clone()).unwrap_or_default(),
+                target: file.target.clone(),
+                hash: file.entry.hash.clone(),
+                metafile: file.entry.metafile,
+                version: file
+                    .meta
+                    .as_ref()
+                    .and_then(|m| m.version.clone())
+                    .unwrap_or_default(),
+            };
+            (file.entry.file.clone(), entry)
+        })
+        .collect()
+}
+
+/// 对比两次快照
+pub fn diff(old: &Snapshot, new: &Snapshot, version_tag: &str) -> Changelog {
+    let mut log = Changelog {
+        date: platform::local_timestamp(),
+        version_tag: version_tag.to_string(),
+        ..Default::default()
+    };
+
+    for (file, entry) in new {
+        match old.get(file) {
+            None if entry.metafile => {
+                log.added
+                    .push(format!("{} ({})", entry.name, version_label(entry)))
+            }
+            None => log.configs.push(format!("{}（新增）", entry.target)),
+            Some(previous) if previous.hash == entry.hash => {}
+            Some(previous) if entry.metafile => log.updated.push(format!(
+                "{}: {} → {}",
+                entry.name,
+                version_label(previous),
+                version_label(entry)
+            )),
+            Some(_) => log.configs.push(format!("{}（修改）", entry.target)),
+        }
+    }
+    for (file, entry) in old {
+        if new.contains_key(file) {
+            continue;
+        }
+        if entry.metafile {
+            log.removed
+                .push(format!("{} ({})", entry.name, version_label(entry)));
+        } else {
+            log.configs.push(format!("{}（删除）", entry.target));
+        }
+    }
+    log
+}
+
+/// 保存新快照，并与上一次的快照对比生成更新日志（追加到历史）。
+///
+/// 没有上一次的快照（首次安装、旧版本更新器）时只保存快照，返回 None。
+pub fn record(base_dir: &Path, files: &[ResolvedFile], version_tag: &str) -> Result<Option<Changelog>> {
+    let new = snapshot_of(files);
+    let old = load_snapshot(base_dir);
+    state::PACK_SNAPSHOT
+        .write_json(base_dir, &new)
+        .context("写入整合包快照失败")?;
+
+    let Some(old) = old else {
+        return Ok(None);
+    };
+    let log = diff(&old, &new, version_tag);
+    if log.is_empty() {
+        return Ok(None);
+    }
+
+    let mut history = load_history(base_dir);
+    history.insert(0, log.clone());
+    history.truncate(MAX_HISTORY);
+    state::CHANGELOG_HISTORY
+        .write_list(base_dir, &history)
+        .context("写入更新日志失败")?;
+    Ok(Some(log))
+}
+
+/// 读取历史记录（最新的在前）
+pub fn load_history(base_dir: &Path) -> Vec<Changelog> {
+    state::CHANGELOG_HISTORY.read_list(base_dir)
+}
+
+/// 历史记录的文本（设置窗口「更新记录」使用）
+#[cfg(windows)]
+pub fn render_history(base_dir: &Path) -> String {
+    let history = load_history(base_dir);
+    if history.is_empty() {
+        return "还没有更新记录".to_string();
+    }
+    history
+        .iter()
+        .map(Changelog::render)
+        .collect::<Vec<_>>()
+        .join("\r\n\r\n")
+}
+
+fn load_snapshot(base_dir: &Path) -> Option<Snapshot> {
+    state::PACK_SNAPSHOT.read_json(base_dir)
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// 模组的版本，没有时用文件名代替
+fn version_label(entry: &SnapshotEntry) -> &str {
+    if entry.version.is_empty() {
+        file_name(&entry.target)
+    } else {
+        &entry.version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, target: &str, hash: &str, metafile: bool) -> SnapshotEntry {
+        SnapshotEntry {
+            name: name.to_string(),
+            target: target.to_string(),
+            hash: hash.to_string(),
+            metafile,
+            version: String::new(),
+        }
+    }
+
+    fn versioned(name: &str, target: &str, hash: &str, version: &str) -> SnapshotEntry {
+        SnapshotEntry {
+            version: version.to_string(),
+            ..entry(name, target, hash, true)
+        }
+    }
+
+    #[test]
+    fn diff_classifies_changes() {
+        let mut old = Snapshot::new();
+        old.insert("mods/sodium.pw.toml".into(), entry("Sodium", "mods/sodium-0.5.jar", "a", true));
+        old.insert("mods/old.pw.toml".into(), entry("Old", "mods/old.jar", "b", true));
+        old.insert("config/a.json".into(), entry("", "config/a.json", "c", false));
+        old.insert("config/same.json".into(), entry("", "config/same.json", "s", false));
+
+        let mut new = Snapshot::new();
+        new.insert("mods/sodium.pw.toml".into(), entry("Sodium", "mods/sodium-0.6.jar", "a2", true));
+        new.insert("mods/iris.pw.toml".into(), entry("Iris", "mods/iris.jar", "d", true));
+        new.insert("config/a.json".into(), entry("", "config/a.json", "c2", false));
+        new.insert("config/same.json".into(), entry("", "config/same.json", "s", false));
+        new.insert("config/b.json".into(), entry("", "config/b.json", "e", false));
+
+        let log = diff(&old, &new, "tag");
+        assert_eq!(log.added, vec!["Iris (iris.jar)".to_string()]);
+        assert_eq!(log.removed, vec!["Old (old.jar)".to_string()]);
+        assert_eq!(log.updated, vec!["Sodium: sodium-0.5.jar → sodium-0.6.jar".to_string()]);
+        assert_eq!(
+            log.configs,
+            vec!["config/a.json（修改）".to_string(), "config/b.json（新增）".to_string()]
+        );
+        assert!(log.render().contains("更新模组（1）:"));
+    }
+
+    #[test]
+    fn diff_shows_versions_and_falls_back_to_file_names() {
+        let old = Snapshot::from([
+            (
+                "mods/sodium.pw.toml".into(),
+                versioned("Sodium", "mods/sodium.jar", "a", "0.5.8"),
+            ),
+            (
+                "mods/lithium.pw.toml".into(),
+                entry("Lithium", "mods/lithium-0.11.jar", "b", true),
+            ),
+            (
+                "mods/old.pw.toml".into(),
+                versioned("Old", "mods/old.jar", "c", "2.0"),
+            ),
+        ]);
+        let new = Snapshot::from([
+            (
+                "mods/sodium.pw.toml".into(),
+                versioned("Sodium", "mods/sodium.jar", "a2", "0.6.0"),
+            ),
+            (
+                "mods/lithium.pw.toml".into(),
+                entry("Lithium", "mods/lithium-0.12.jar", "b2", true),
+            ),
+            (
+                "mods/iris.pw.toml".into(),
+                versioned("Iris", "mods/iris.jar", "d", "1.8.0"),
+            ),
+        ]);
+
+        let log = diff(&old, &new, "tag");
+        assert_eq!(log.added, vec!["Iris (1.8.0)".to_string()]);
+        assert_eq!(log.removed, vec!["Old (2.0)".to_string()]);
+        assert_eq!(
+            log.updated,
+            vec![
+                "Lithium: lithium-0.11.jar → lithium-0.12.jar".to_string(),
+                "Sodium: 0.5.8 → 0.6.0".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn old_snapshot_without_versions_still_loads() {
+        let json =
+            r#"{"mods/a.pw.toml":{"name":"A","target":"mods/a.jar","hash":"x","metafile":true}}"#;
+        let snapshot: Snapshot = serde_json::from_str(json).unwrap();
+        assert_eq!(version_label(&snapshot["mods/a.pw.toml"]), "a.jar");
+    }
+
+    #[test]
+    fn identical_snapshots_produce_empty_log() {
+        let mut snapshot = Snapshot::new();
+        snapshot.insert("mods/a.pw.toml".into(), entry("A", "mods/a.jar", "x", true));
+        assert!(diff(&snapshot, &snapshot, "tag").is_empty());
+    }
+}
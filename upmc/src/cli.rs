@@ -0,0 +1,351 @@
+// ============================================================
+// cli.rs — 命令行子命令
+// ============================================================
+// 不带子命令时正常启动 GUI。目前支持：
+//   upmc bundle export [输出路径]   导出离线安装包（默认 upmc-bundle.zip）
+//   upmc bundle import <路径>       从离线安装包导入
+//   upmc repair                     校验并修复安装
+//   upmc pack list                  列出保存的整合包版本
+//   upmc pack rollback              回退到上一个整合包版本并固定
+//   upmc pack unpin                 取消固定，恢复跟随服务器
+//   upmc server install <目录> [--accept-eula]
+//                                   安装 / 更新专用服务器（与玩家同一份整合包）
+//   upmc settings                   列出生效的设置及其来源（见 settings.rs）
+//   upmc mod-report verify <文件>   校验玩家提交的模组报告签名（见 mod_policy.rs）
+//
+// 有多个整合包（见 profiles.rs）时，子命令必须用 --profile <id> 指定整合包。
+// 命令行只在这里解析一次：--profile、--wait 和设置覆盖（--channel、--source、
+// --set，交给 settings.rs 合并）可以出现在任意位置，其余参数为子命令。
+//
+// 同一安装目录同时只能运行一个更新器（见 instance_lock.rs）。另一个更新器
+// 正在运行时子命令报错退出；加 --wait 则等待其结束后再执行。
+// 只读的子命令（settings、pack list、mod-report）不受限制。
+//
+// release 版本是 windows 子系统程序，没有自己的控制台，
+// 执行子命令时附加到父进程（cmd / PowerShell）的控制台输出结果。
+// ============================================================
+
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::bundle;
+use crate::config;
+use crate::error;
+use crate::mod_policy;
+use crate::packs;
+use crate::repair;
+use crate::server;
+use crate::settings;
+
+/// 等待其他更新器结束，而不是报错退出
+pub const WAIT_FLAG: &str = "--wait";
+
+/// 指定要操作的整合包 id
+pub const PROFILE_FLAG: &str = "--profile";
+
+/// server install：同意 Minecraft EULA，不再询问
+const ACCEPT_EULA_FLAG: &str = "--accept-eula";
+
+/// 启动方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Invocation {
+    /// 不带子命令：启动 GUI
+    Gui,
+    /// 会修改安装目录的子命令
+    Command,
+    /// 只读的子命令，GUI 运行时也可以使用
+    ReadOnlyCommand,
+}
+
+/// 解析后的命令行
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CommandLine {
+    /// 子命令及其参数（不含程序名和下面的全局选项）
+    pub args: Vec<String>,
+    /// --profile 指定的整合包 id
+    pub profile: Option<String>,
+    /// 是否带有 --wait
+    pub wait: bool,
+    /// 覆盖设置的选项（见 settings.rs）
+    pub settings: settings::CliOverrides,
+}
+
+impl CommandLine {
+    /// 解析命令行参数（不含程序名）
+    fn parse(args: impl IntoIterator<Item = String>) -> Self {
+        let mut line = Self::default();
+        let mut iter = args.into_iter();
+        while let Some(arg) = iter.next() {
+            if arg == WAIT_FLAG {
+                line.wait = true;
+            } else if arg == PROFILE_FLAG {
+                line.profile = iter.next();
+            } else if settings::CLI_FLAGS.contains(&arg.as_str()) {
+                if let Some(value) = iter.next() {
+                    line.settings.push(arg, value);
+                }
+            } else {
+                line.args.push(arg);
+            }
+        }
+        line
+    }
+}
+
+/// 本次运行的命令行（首次调用时解析）
+pub fn command_line() -> &'static CommandLine {
+    static LINE: OnceLock<CommandLine> = OnceLock::new();
+    LINE.get_or_init(|| CommandLine::parse(std::env::args().skip(1)))
+}
+
+/// 根据命令行判断启动方式
+pub fn invocation() -> Invocation {
+    let args = &command_line().args;
+    match (
+        args.first().map(String::as_str),
+        args.get(1).map(String::as_str),
+    ) {
+        (Some("settings" | "mod-report"), _) | (Some("pack"), Some("list")) => {
+            Invocation::ReadOnlyCommand
+        }
+        (Some("bundle" | "repair" | "pack" | "server"), _) => Invocation::Command,
+        _ => Invocation::Gui,
+    }
+}
+
+/// 按命令行格式输出错误及解决办法
+pub fn print_error(e: &anyhow::Error) {
+    eprintln!("错误: {e:#}");
+    if let Some(code) = error::classify(e) {
+        eprintln!("建议（{code}）: {}", code.remediation());
+    }
+}
+
+/// 如果命令行包含子命令则执行并返回退出码，否则返回 None（继续启动 GUI）。
+pub fn try_run_from_args(base_dir: &Path) -> Option<i32> {
+    if invocation() == Invocation::Gui {
+        return None;
+    }
+    let args = &command_line().args;
+    let command = args.first()?.as_str();
+
+    crate::platform::attach_parent_console();
+    let result = match (command, args.get(1).map(String::as_str), args.get(2)) {
+        ("repair", _, _) => run_repair(base_dir),
+        ("settings", _, _) => {
+            println!("{}", settings::current().explain());
+            Ok(())
+        }
+        ("pack", Some("list"), _) => {
+            println!("{}", packs::render_history(base_dir));
+            Ok(())
+        }
+        ("pack", Some("rollback"), _) => run_pack_rollback(base_dir),
+        ("pack", Some("unpin"), _) => run_pack_unpin(base_dir),
+        ("pack", _, _) => Err(anyhow::anyhow!(
+            "用法:\n  upmc pack list\n  upmc pack rollback\n  upmc pack unpin"
+        )),
+        ("server", Some("install"), _) => parse_server_install(&args[2..])
+            .and_then(|(dir, accept_eula)| run_server_install(base_dir, dir, accept_eula)),
+        ("server", _, _) => Err(anyhow::anyhow!(SERVER_USAGE)),
+        ("mod-report", Some("verify"), Some(file)) => run_mod_report_verify(Path::new(file)),
+        ("mod-report", _, _) => Err(anyhow::anyhow!("用法:\n  upmc mod-report verify <文件>")),
+        (_, Some("export"), dest) => run_bundle_export(base_dir, dest.map(PathBuf::from)),
+        (_, Some("import"), Some(source)) => run_bundle_import(base_dir, Path::new(source)),
+        _ => Err(anyhow::anyhow!(
+            "用法:\n  upmc bundle export [输出路径]\n  upmc bundle import <路径>\n  upmc repair\n  upmc pack list|rollback|unpin\n  upmc server install <目录> [--accept-eula]\n  upmc settings\n  upmc mod-report verify <文件>\n\n有多个整合包时加 --profile <id> 指定整合包\n另一个更新器正在运行时加 --wait 等待其结束"
+        )),
+    };
+
+    match result {
+        Ok(()) => Some(0),
+        Err(e) => {
+            print_error(&e);
+            Some(1)
+        }
+    }
+}
+
+fn run_bundle_export(base_dir: &Path, dest: Option<PathBuf>) -> Result<()> {
+    let dest = dest.unwrap_or_else(|| PathBuf::from(config::BUNDLE_FILE_NAME));
+    println!("正在导出离线安装包: {}", dest.display());
+    let manifest = bundle::export(base_dir, &dest)?;
+    println!(
+        "导出完成: MC {} / Fabric {}\n离线安装: upmc --bundle {}",
+        manifest.mc_version,
+        manifest.fabric_version,
+        dest.display()
+    );
+    Ok(())
+}
+
+fn run_bundle_import(base_dir: &Path, source: &Path) -> Result<()> {
+    if !source.is_file() {
+        bail!("离线安装包不存在: {}", source.display());
+    }
+    std::fs::create_dir_all(base_dir).context("创建安装目录失败")?;
+    println!("正在从 {} 导入...", source.display());
+    // 按以前缓存的清单检查（没有时只能按安装包自带的快照），全部通过后才解压
+    let manifest = bundle::import(base_dir, source, None, &|_| {}, 0, 100)?;
+    println!(
+        "导入完成: MC {} / Fabric {}",
+        manifest.mc_version, manifest.fabric_version
+    );
+    Ok(())
+}
+
+fn run_repair(base_dir: &Path) -> Result<()> {
+    println!("正在校验安装...");
+    let report = repair::run(base_dir, &|progress| {
+        println!("[{:>3}%] {}", progress.percent, progress.message);
+    })?;
+    println!("\n{}", report.render_table());
+    if report.failed_count() > 0 {
+        bail!("部分文件修复失败");
+    }
+    Ok(())
+}
+
+fn run_pack_rollback(base_dir: &Path) -> Result<()> {
+    let record = packs::rollback(base_dir)?;
+    println!(
+        "已固定到整合包版本 {}（{} 同步，{}），下次启动时同步。\n\
+         服务器发布新版本或执行 upmc pack unpin 后恢复跟随服务器。",
+        record.id, record.date, record.version_tag
+    );
+    Ok(())
+}
+
+fn run_pack_unpin(base_dir: &Path) -> Result<()> {
+    if packs::unpin(base_dir)? {
+        println!("已取消固定，下次启动时同步服务器上的最新整合包");
+    } else {
+        println!("当前没有固定的整合包版本");
+    }
+    Ok(())
+}
+
+const SERVER_USAGE: &str = "用法:\n  upmc server install <目录> [--accept-eula]";
+
+/// 解析 `server install` 之后的参数：一个目录，可选 --accept-eula（位置不限）
+fn parse_server_install(args: &[String]) -> Result<(&Path, bool)> {
+    let accept_eula = args.iter().any(|arg| arg == ACCEPT_EULA_FLAG);
+    let mut rest = args.iter().filter(|arg| *arg != ACCEPT_EULA_FLAG);
+    match (rest.next(), rest.next()) {
+        (Some(dir), None) if !dir.starts_with("--") => Ok((Path::new(dir), accept_eula)),
+        (Some(arg), None) => bail!("未知选项: {arg}\n{SERVER_USAGE}"),
+        _ => bail!("{SERVER_USAGE}"),
+    }
+}
+
+fn run_server_install(base_dir: &Path, server_dir: &Path, accept_eula: bool) -> Result<()> {
+    println!("正在安装专用服务器: {}", server_dir.display());
+    let accept_eula = accept_eula
+        || server::is_eula_accepted(server_dir)
+        || confirm(&format!(
+            "运行服务器需要同意 Minecraft EULA（{}）。是否同意？[y/N] ",
+            server::EULA_URL
+        ));
+    let state = server::install(base_dir, server_dir, accept_eula, &|progress| {
+        println!("[{:>3}%] {}", progress.percent, progress.message);
+    })?;
+
+    println!(
+        "\n安装完成: MC {} / Fabric {}\n启动命令: java -jar {} nogui",
+        state.mc_version,
+        state.fabric_version,
+        server::FABRIC_SERVER_LAUNCH_JAR
+    );
+    if !server::is_eula_accepted(server_dir) {
+        println!("未同意 EULA，未写入 eula.txt。同意后请加上 --accept-eula 重新运行。");
+    }
+    Ok(())
+}
+
+fn run_mod_report_verify(file: &Path) -> Result<()> {
+    let report = mod_policy::verify_report_file(file)?;
+    println!(
+        "签名有效，报告生成后未被改动。\n\
+         公钥: {}\n\
+         （同一玩家的报告应始终使用同一公钥；签名不能证明报告内容属实）\n\
+         整合包版本: {}\n生成时间: {}（Unix 秒）\n",
+        report.public_key, report.version_tag, report.generated_at
+    );
+    for module in &report.mods {
+        println!("{}", module.describe());
+    }
+    Ok(())
+}
+
+/// 在控制台询问是/否，无法读取输入时视为否。
+fn confirm(prompt: &str) -> bool {
+    use std::io::Write;
+
+    print!("{prompt}");
+    let _ = std::io::stdout().flush();
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> CommandLine {
+        CommandLine::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn global_options_anywhere() {
+        let line = parse(&[
+            "--channel",
+            "beta",
+            "pack",
+            "--profile",
+            "creative",
+            "rollback",
+            "--wait",
+            "--set",
+            "http_timeout_secs=60",
+        ]);
+        assert_eq!(line.args, ["pack", "rollback"]);
+        assert_eq!(line.profile.as_deref(), Some("creative"));
+        assert!(line.wait);
+
+        let mut expected = settings::CliOverrides::default();
+        expected.push("--channel".to_string(), "beta".to_string());
+        expected.push("--set".to_string(), "http_timeout_secs=60".to_string());
+        assert_eq!(line.settings, expected);
+    }
+
+    #[test]
+    fn server_install_flag_before_directory() {
+        let args = parse(&["server", "install", "--accept-eula", "/srv/mc"]).args;
+        let (dir, accept_eula) = parse_server_install(&args[2..]).unwrap();
+        assert_eq!(dir, Path::new("/srv/mc"));
+        assert!(accept_eula);
+
+        let args = parse(&["server", "install", "/srv/mc"]).args;
+        assert_eq!(
+            parse_server_install(&args[2..]).unwrap(),
+            (Path::new("/srv/mc"), false)
+        );
+
+        for args in [
+            &["--accept-eula"][..],
+            &["--eula", "/srv/mc"],
+            &["--eula"],
+            &["a", "b"],
+        ] {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            assert!(parse_server_install(&args).is_err(), "{args:?}");
+        }
+    }
+
+    #[test]
+    fn empty_command_line() {
+        assert_eq!(parse(&[]), CommandLine::default());
+    }
+}
@@ -2,45 +2,42 @@
 // config.rs — 配置常量 + Java 查找 + 更新通道
 // ============================================================
 // 集中管理所有可配置的路径和 URL。
-// 修改这里的常量即可适配不同服务器。
+// 服务器名称、服务端地址、可信域名等品牌相关的值来自 branding.toml（见 branding.rs），
+// 适配其他服务器时修改品牌配置即可。
+// 超时、重试、代理端口等常量是编译期默认值，运行时可被
+// server.json / 用户设置 / 环境变量 / 命令行覆盖，见 settings.rs。
 // ============================================================
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::os::windows::process::CommandExt;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+use crate::branding;
+use crate::error::{self, ErrorCode};
+use crate::platform::{self, HideWindow};
+use crate::source::ServerSource;
+use crate::state;
+
 // ── 远程配置 ──
 
-/// 远程 server.json 的 URL（GitHub Pages 托管，packwiz 仓库）
-pub const REMOTE_SERVER_JSON_URL: &str =
-    "https://update.mc.chenjicheng.cn/server.json";
+/// 远程 server.json 的 URL（branding.toml 的 endpoints.server_json）
+pub const REMOTE_SERVER_JSON_URL: &str = branding::SERVER_JSON_URL;
 
-/// 允许远程配置引用的下载域名后缀。
+/// 允许远程配置引用的下载域名后缀（branding.toml 的 downloads.trusted_hosts）。
 ///
 /// 远程 server.json 能控制首次安装下载项，因此必须限制下载来源，
 /// 避免被篡改后变成任意文件下载器。
-pub const TRUSTED_DOWNLOAD_HOST_SUFFIXES: &[&str] = &[
-    "chenjicheng.cn",
-    "github.com",
-    "githubusercontent.com",
-    "objects.githubusercontent.com",
-    "gh.cjcx.org",
-    "bmclapi2.bangbang93.com",
-    "maven.fabricmc.net",
-];
-
-/// 更新器版本信息 URL — 稳定通道（GitHub Pages 托管，upmc 仓库）
+pub const TRUSTED_DOWNLOAD_HOST_SUFFIXES: &[&str] = branding::TRUSTED_HOSTS;
+
+/// 更新器版本信息 URL — 稳定通道（branding.toml 的 endpoints.updater_version）
 /// 返回 JSON: { "version": "x.y.z", "download_url": "..." }
-pub const UPDATER_VERSION_URL: &str =
-    "https://upmc.chenjicheng.cn/version.json";
+pub const UPDATER_VERSION_URL: &str = branding::UPDATER_VERSION_URL;
 
 /// 更新器版本信息 URL — 开发通道
 /// 返回 JSON: { "version": "x.y.z", "download_url": "...", "build_id": "a1b2c3d" }
-pub const UPDATER_DEV_VERSION_URL: &str =
-    "https://upmc.chenjicheng.cn/dev/version.json";
+pub const UPDATER_DEV_VERSION_URL: &str = branding::UPDATER_DEV_VERSION_URL;
 
 // ── 更新通道 ──
 
@@ -83,8 +80,7 @@ impl std::fmt::Display for UpdateChannel {
 
 /// 通道配置文件内容
 ///
-/// 注意：旧版 channel.json 可能含有 `dev_build_id` 字段，
-/// 由于未启用 `deny_unknown_fields`，serde 会自动忽略。
+/// 旧版 channel.json 中的 `dev_build_id` 字段由 state.rs 的迁移删除。
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ChannelConfig {
     /// 当前选择的通道
@@ -92,72 +88,138 @@ pub struct ChannelConfig {
     pub channel: UpdateChannel,
 }
 
-/// 保存通道配置到 channel.json（仅在内容变化时写入）。
-pub fn save_channel_config(base_dir: &Path, config: &ChannelConfig) -> Result<()> {
-    let path = base_dir.join(CHANNEL_CONFIG_FILE);
-    let json = serde_json::to_string_pretty(config).context("序列化通道配置失败")?;
+/// 读取通道配置，不存在或解析失败时返回默认值。
+#[cfg(windows)]
+pub fn load_channel_config(base_dir: &Path) -> ChannelConfig {
+    state::CHANNEL_CONFIG
+        .read_json(base_dir)
+        .unwrap_or_default()
+}
 
-    // 仅在内容变化时写入，减少不必要的 I/O
-    if let Ok(existing) = fs::read_to_string(&path) {
-        if existing == json {
-            return Ok(());
-        }
-    }
+// ── 整合包通道 ──
 
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).context("创建 updater 目录失败")?;
-    }
-    fs::write(&path, json).context("写入 channel.json 失败")?;
-    Ok(())
+/// 整合包通道配置文件（与更新器通道 channel.json 相互独立）
+pub const PACK_CHANNEL_FILE: &str = "updater/pack_channel.json";
+
+/// 默认整合包通道，对应 server.json 顶层的 pack_url
+pub const DEFAULT_PACK_CHANNEL: &str = "stable";
+
+/// 整合包通道配置文件内容
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct PackChannelConfig {
+    /// 玩家订阅的整合包通道（server.json 中 pack_channels 的键），None 为默认通道
+    #[serde(default)]
+    pub channel: Option<String>,
+
+    /// 当前安装的整合包来自哪个通道（同步后记录，用于检测通道切换）
+    #[serde(default)]
+    pub synced: Option<String>,
 }
 
-/// 根据通道返回对应的更新器版本信息 URL。
-pub fn updater_version_url(channel: UpdateChannel) -> &'static str {
-    match channel {
-        UpdateChannel::Stable => UPDATER_VERSION_URL,
-        UpdateChannel::Dev => UPDATER_DEV_VERSION_URL,
+impl PackChannelConfig {
+    /// 订阅的通道名称
+    pub fn name(&self) -> &str {
+        self.channel.as_deref().unwrap_or(DEFAULT_PACK_CHANNEL)
     }
 }
 
+/// 读取整合包通道配置，不存在或解析失败时返回默认值。
+pub fn load_pack_channel(base_dir: &Path) -> PackChannelConfig {
+    state::PACK_CHANNEL.read_json(base_dir).unwrap_or_default()
+}
+
+/// 保存整合包通道配置。
+pub fn save_pack_channel(base_dir: &Path, config: &PackChannelConfig) -> Result<()> {
+    state::PACK_CHANNEL
+        .write_json(base_dir, config)
+        .context("写入 pack_channel.json 失败")
+}
+
 // ── 本地路径（相对于安装基准目录） ──
 
 pub const LOCAL_VERSION_FILE: &str = "updater/local.json";
+/// 灰度发布分桶文件：首次生成后固定不变，保证同一台机器每次判定一致
+pub const ROLLOUT_BUCKET_FILE: &str = "updater/rollout_bucket.txt";
 pub const PACK_TOML_CACHE_FILE: &str = "updater/pack_toml_cache.txt";
+/// 首次安装时已应用默认设置包的标记
+pub const SETTINGS_INSTALLED_FILE: &str = "updater/.settings_installed";
 pub const PACKWIZ_BOOTSTRAP_JAR: &str = "updater/packwiz-installer-bootstrap.jar";
 pub const FABRIC_INSTALLER_JAR: &str = "updater/fabric-installer.jar";
 pub const MINECRAFT_DIR: &str = ".minecraft";
 /// Fabric Installer 镜像（BMCLAPI）
 pub const FABRIC_META_URL: &str = "https://bmclapi2.bangbang93.com/fabric-meta/";
 pub const FABRIC_MAVEN_URL: &str = "https://bmclapi2.bangbang93.com/maven/";
+/// Fabric 官方源（BMCLAPI 不可用时的备用镜像）
+pub const FABRIC_OFFICIAL_META_URL: &str = "https://meta.fabricmc.net/";
+pub const FABRIC_OFFICIAL_MAVEN_URL: &str = "https://maven.fabricmc.net/";
+/// Mojang 版本清单 API
+pub const VERSION_MANIFEST_URL: &str =
+    "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json";
+/// Mojang 版本清单镜像（BMCLAPI）
+pub const VERSION_MANIFEST_MIRROR_URL: &str =
+    "https://bmclapi2.bangbang93.com/mc/game/version_manifest_v2.json";
+/// server.json 下发的设置覆盖（超时、重试等，见 settings.rs）
+pub const SERVER_SETTINGS_FILE: &str = "updater/server_settings.json";
+/// 镜像状态文件：server.json 下发的镜像列表 + 每类端点上次可用的镜像
+pub const MIRROR_STATE_FILE: &str = "updater/mirrors.json";
+/// 离线安装包默认文件名（放在 upmc.exe 同级或安装目录下会被自动识别）
+pub const BUNDLE_FILE_NAME: &str = "upmc-bundle.zip";
+/// 可选模组列表缓存（从整合包索引解析）
+pub const OPTIONAL_MODS_FILE: &str = "updater/optional_mods.json";
+/// 整合包管理的文件清单（路径 + 哈希），用于区分玩家自己添加的模组
+pub const MANAGED_FILES_FILE: &str = "updater/managed_files.json";
+/// 上次同步的整合包文件列表快照（用于生成更新日志）
+pub const PACK_SNAPSHOT_FILE: &str = "updater/pack_snapshot.json";
+/// server.json 下发的显示文字覆盖（服务器名称等，见 branding.rs）
+pub const BRANDING_FILE: &str = "updater/branding.json";
+/// 整合包更新日志历史
+pub const CHANGELOG_HISTORY_FILE: &str = "updater/changelog_history.json";
+/// 最近几次同步的整合包快照（pack.toml + 索引 + 元数据），用于回退
+pub const PACK_HISTORY_DIR: &str = "updater/packs";
+/// 快照顺序记录（位于 PACK_HISTORY_DIR 下）
+pub const PACK_HISTORY_FILE: &str = "updater/packs/history.json";
+/// 固定的整合包版本（回退后写入，取消固定或服务器发布新版本时删除）
+pub const PACK_PIN_FILE: &str = "updater/pack_pin.json";
+/// 玩家添加的整合包和上次选择的整合包（只保存在安装基准目录）
+pub const PROFILES_FILE: &str = "updater/profiles.json";
+/// server.json 声明的整合包列表缓存
+pub const SERVER_PROFILES_FILE: &str = "updater/server_profiles.json";
+/// 模组策略检查生成的签名报告（玩家可提交给管理员）
+pub const MOD_REPORT_FILE: &str = "updater/mod_report.json";
+/// 模组报告的签名私钥（每个安装一个，只保存在本机）
+pub const REPORT_KEY_FILE: &str = "updater/report_key.txt";
+/// 单实例锁：正在使用本安装目录的更新器进程（见 instance_lock.rs）
+pub const INSTANCE_LOCK_FILE: &str = "updater/upmc.lock";
+/// 单实例锁持有者的 PID 和启动时间（仅用于提示）
+pub const INSTANCE_HOLDER_FILE: &str = "updater/upmc.lock.json";
+/// 迁移旧版安装目录时的暂存目录：旧目录整体移入此处，再逐项移到安装目录
+pub const LEGACY_MIGRATION_DIR: &str = "legacy_migration";
+/// server.json 下发的画质预设缓存
+pub const PRESETS_FILE: &str = "updater/presets.json";
+/// 上次应用的画质预设指纹（预设变化时才重新写入 options.txt / 内存）
+pub const PRESET_APPLIED_FILE: &str = "updater/preset_applied.txt";
+/// 专用服务器的安装状态（位于服务器目录，见 server.rs）
+pub const SERVER_INSTALL_FILE: &str = "upmc-server.json";
+/// HTTP 条件缓存目录（清单类响应体 + ETag/Last-Modified）
+pub const HTTP_CACHE_DIR: &str = "updater/cache";
 pub const PCL2_EXE: &str = "Plain Craft Launcher 2.exe";
 pub const PCL2_SETUP_INI_PATH: &str = "Setup.ini";
 
 /// Java 下载页面 URL（当系统未安装 Java 时自动打开）
+#[cfg(windows)]
 pub const JAVA_DOWNLOAD_URL: &str =
     "https://mirrors.tuna.tsinghua.edu.cn/Adoptium/21/jre/x64/windows";
-
-/// Java 未找到时返回的错误类型，GUI 据此 downcast 识别并显示友好安装提示。
-#[derive(Debug)]
-pub struct JavaNotFound;
-
-impl std::fmt::Display for JavaNotFound {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "系统中未检测到 Java 环境。\n\
-             正在尝试打开 Java 下载页面，如未自动打开请手动访问：\n\
-             {JAVA_DOWNLOAD_URL}"
-        )
-    }
-}
-
-impl std::error::Error for JavaNotFound {}
+#[cfg(target_os = "macos")]
+pub const JAVA_DOWNLOAD_URL: &str = "https://mirrors.tuna.tsinghua.edu.cn/Adoptium/21/jre/x64/mac";
+#[cfg(not(any(windows, target_os = "macos")))]
+pub const JAVA_DOWNLOAD_URL: &str =
+    "https://mirrors.tuna.tsinghua.edu.cn/Adoptium/21/jre/x64/linux";
 
 // ── 安装目录 ──
 
-/// 安装子目录名称
+/// 安装子目录名称（branding.toml 的 app.install_dir_name）
 /// 所有游戏组件（PCL2、.minecraft 等）存放在 文档/CJC整合包/ 下
-pub const INSTALL_DIR_NAME: &str = "CJC整合包";
+pub const INSTALL_DIR_NAME: &str = branding::INSTALL_DIR_NAME;
 
 /// 获取安装基准目录：用户文档文件夹下的 INSTALL_DIR_NAME 子目录。
 ///
@@ -188,33 +250,35 @@ pub fn get_legacy_install_dir() -> PathBuf {
 
 // ── GUI ──
 
-/// 生成窗口标题。
+/// 生成窗口标题（`server_name` 见 branding.rs）。
 ///
 /// - Stable: `我的服务器 - 更新器 v0.3.6`
 /// - Dev:    `我的服务器 - 更新器 dev-a1b2c3d`（7 位 commit SHA）
 /// - Dev（无 build_id）: `我的服务器 - 更新器 dev`
-pub fn window_title(channel: UpdateChannel) -> String {
-    match channel {
+/// - 本地 / 局域网来源时追加 ` [局域网源: http://192.168.1.10:8000]` 等后缀
+#[cfg(windows)]
+pub fn window_title(channel: UpdateChannel, source: &ServerSource, server_name: &str) -> String {
+    let title = match channel {
         UpdateChannel::Stable => {
-            format!("我的服务器 - 更新器 v{}", env!("CARGO_PKG_VERSION"))
+            format!("{server_name} - 更新器 v{}", env!("CARGO_PKG_VERSION"))
         }
         UpdateChannel::Dev => {
             if let Some(id) = option_env!("UPMC_BUILD_ID") {
                 let short = if id.len() >= 7 { &id[..7] } else { id };
-                format!("我的服务器 - 更新器 dev-{short}")
+                format!("{server_name} - 更新器 dev-{short}")
             } else {
-                "我的服务器 - 更新器 dev".to_string()
+                format!("{server_name} - 更新器 dev")
             }
         }
+    };
+    // 非公共来源时标出来源，让玩家知道自己不在公共服务器上
+    match source.title_suffix() {
+        Some(suffix) => format!("{title} [{suffix}]"),
+        None => title,
     }
 }
 
-// ── Windows 进程创建标志 ──
-
-/// 创建子进程时不弹出控制台窗口
-pub const CREATE_NO_WINDOW: u32 = 0x0800_0000;
-
-// ── 超时 ──
+// ── 超时（编译期默认值，见 settings.rs） ──
 
 /// 小文件请求超时（server.json 等）
 pub const HTTP_TIMEOUT_SECS: u64 = 30;
@@ -222,60 +286,51 @@ pub const HTTP_TIMEOUT_SECS: u64 = 30;
 pub const DOWNLOAD_TIMEOUT_SECS: u64 = 600;
 
 /// 创建用于小文件 API 请求的 HTTP Agent
-pub fn http_agent() -> ureq::Agent {
+pub fn http_agent(secs: u64) -> ureq::Agent {
     ureq::Agent::config_builder()
-        .timeout_global(Some(std::time::Duration::from_secs(HTTP_TIMEOUT_SECS)))
+        .timeout_global(Some(std::time::Duration::from_secs(secs)))
         .build()
         .into()
 }
 
 /// 创建用于大文件下载的 HTTP Agent（超时更长）
-pub fn download_agent() -> ureq::Agent {
+pub fn download_agent(secs: u64) -> ureq::Agent {
     ureq::Agent::config_builder()
-        .timeout_global(Some(std::time::Duration::from_secs(DOWNLOAD_TIMEOUT_SECS)))
+        .timeout_global(Some(std::time::Duration::from_secs(secs)))
         .build()
         .into()
 }
 
-// ── 重试 ──
+// ── 重试（编译期默认值，见 settings.rs） ──
 
 /// 网络操作最大重试次数（含首次尝试）
 pub const RETRY_MAX_ATTEMPTS: u32 = 3;
-/// 首次重试前等待秒数（后续指数退避：3s → 6s）
+/// 首次重试前等待秒数（后续指数退避：3s → 6s，实际等待带随机抖动）
 pub const RETRY_BASE_DELAY_SECS: u64 = 3;
-
-// ── PCL2 配置模板 ──
-
-/// 首次安装时自动生成的 Setup.ini
-///
-/// 关键设置：
-///   - VersionArgumentIndie=1: 不隔离，使用 .minecraft/ 作为游戏目录
-///     （packwiz 把 mods/config 等安装到 .minecraft/，必须关闭隔离）
-///
-/// PCL2 会自动检测同目录下的 .minecraft 文件夹，
-/// 无需手动指定游戏目录。
-pub const PCL2_SETUP_INI: &str = "\
-; ===== 服务器专属启动器 =====\r\n\
-Logo=我的服务器\r\n\
-LogoSub=专属启动器\r\n\
-; 不隔离版本，使用 .minecraft 作为游戏目录\r\n\
-VersionArgumentIndie=1\r\n\
-; 默认游戏窗口大小 720p\r\n\
-LaunchArgumentWindowWidth=1280\r\n\
-LaunchArgumentWindowHeight=720\r\n\
-";
+/// 单次退避等待上限（秒）
+pub const RETRY_MAX_DELAY_SECS: u64 = 30;
+/// 一个操作所有重试的总时间预算（秒），超出后不再重试
+pub const RETRY_BUDGET_SECS: u64 = 180;
+/// 同一主机连续失败多少次后熔断（暂停请求，改用镜像）
+pub const CIRCUIT_FAILURE_THRESHOLD: u32 = 3;
+/// 熔断持续秒数，之后允许再次尝试该主机
+pub const CIRCUIT_OPEN_SECS: u64 = 60;
+
+// ── packwiz-installer ──
+
+/// packwiz-installer 单次运行的总时长上限（秒），超时后结束进程树
+pub const PACKWIZ_TIMEOUT_SECS: u64 = 1800;
+/// packwiz-installer 连续无输出的时长上限（秒），视为卡死
+pub const PACKWIZ_IDLE_TIMEOUT_SECS: u64 = 300;
 
 // ── Discord 代理 / Xray ──
 
-/// GitHub 下载代理前缀（与自更新模块使用同一镜像）
-pub const GITHUB_PROXY: &str = "https://gh.cjcx.org/";
+/// GitHub 下载代理前缀（与自更新模块使用同一镜像，branding.toml 的 endpoints.github_proxy）
+pub const GITHUB_PROXY: &str = branding::GITHUB_PROXY;
 
 /// Xray GitHub 仓库
 pub const XRAY_GITHUB_REPO: &str = "XTLS/Xray-core";
 
-/// Xray Release 中 Windows 64-bit 的资产名
-pub const XRAY_ASSET_NAME: &str = "Xray-windows-64.zip";
-
 /// 代理订阅地址（VLESS REALITY），通过编译时环境变量 UPMC_SUB_URL 注入
 pub const SUBSCRIPTION_URL: &str = match option_env!("UPMC_SUB_URL") {
     Some(url) => url,
@@ -287,42 +342,66 @@ pub const XRAY_SOCKS_PORT: u16 = 10808;
 
 /// Xray 文件存放目录（相对于 base_dir）
 pub const XRAY_DIR: &str = "updater/xray";
+/// 已下载的 Xray 版本号（相对于 base_dir）
+pub const XRAY_VERSION_FILE: &str = "updater/xray/version.txt";
+/// 本程序启动的 Xray 进程 PID（相对于 base_dir）
+pub const XRAY_PID_FILE: &str = "updater/xray/xray.pid";
 
 /// 用户设置文件（相对于 base_dir）
 pub const USER_SETTINGS_FILE: &str = "updater/settings.json";
 
-/// 用户可修改的设置
+/// 用户可修改的设置（设置窗口读写；其中的分层设置由 settings.rs 合并）
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserSettings {
     /// 是否劫持 UDP 流量（Discord 语音走代理）。默认 false。
     #[serde(default)]
     pub proxy_udp: bool,
+
+    /// 忽略灰度发布比例，总是更新到最新构建（供测试者使用）。默认 false。
+    #[serde(default)]
+    pub ignore_rollout: bool,
+
+    /// 用户自定义镜像列表，键为端点名（见 `mirrors::Endpoint::key`），
+    /// 优先级高于 server.json 和编译期默认值。
+    #[serde(default)]
+    pub mirrors: BTreeMap<String, Vec<String>>,
+
+    /// server.json 来源（公共服务器 / 本地目录 / 局域网）。命令行 `--source` 优先。
+    #[serde(default)]
+    pub server_source: ServerSource,
+
+    /// 可选模组的启用选择，键为元数据文件路径（如 "mods/iris.pw.toml"）。
+    /// 未出现的模组使用整合包的默认值。
+    #[serde(default)]
+    pub optional_mods: BTreeMap<String, bool>,
+
+    /// 选择的画质预设 id。None 表示按硬件自动选择。
+    #[serde(default)]
+    pub preset: Option<String>,
+
+    /// 手动添加的其他设置（如 "http_timeout_secs"），原样保留，见 settings.rs
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
 }
 
 impl Default for UserSettings {
     fn default() -> Self {
-        Self { proxy_udp: false }
+        Self {
+            proxy_udp: false,
+            ignore_rollout: false,
+            mirrors: BTreeMap::new(),
+            server_source: ServerSource::Public,
+            optional_mods: BTreeMap::new(),
+            preset: None,
+            extra: BTreeMap::new(),
+        }
     }
 }
 
-/// 读取用户设置（文件不存在时返回默认值）。
+/// 读取用户设置（文件不存在或解析失败时返回默认值）。
+/// 保存经 `settings::save_user`，同时刷新当前设置。
 pub fn load_user_settings(base_dir: &Path) -> UserSettings {
-    let path = base_dir.join(USER_SETTINGS_FILE);
-    std::fs::read_to_string(&path)
-        .ok()
-        .and_then(|s| serde_json::from_str(&s).ok())
-        .unwrap_or_default()
-}
-
-/// 保存用户设置。
-pub fn save_user_settings(base_dir: &Path, settings: &UserSettings) -> Result<()> {
-    let path = base_dir.join(USER_SETTINGS_FILE);
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
-    }
-    let json = serde_json::to_string_pretty(settings)?;
-    fs::write(&path, json)?;
-    Ok(())
+    state::USER_SETTINGS.read_json(base_dir).unwrap_or_default()
 }
 
 // ── Java 查找 ──
@@ -337,30 +416,65 @@ pub fn save_user_settings(base_dir: &Path, settings: &UserSettings) -> Result<()
 pub fn find_java() -> Result<PathBuf> {
     // 1. JAVA_HOME
     if let Ok(java_home) = std::env::var("JAVA_HOME") {
-        let p = PathBuf::from(&java_home).join("bin/java.exe");
+        let p = PathBuf::from(&java_home).join("bin").join(platform::exe_name("java"));
         if p.exists() {
             return Ok(p);
         }
     }
 
-    // 2. PATH（使用 where 命令查找）
-    if let Ok(output) = Command::new("where").arg("java").creation_flags(CREATE_NO_WINDOW).output()
-        && output.status.success()
-    {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        if let Some(first_line) = stdout.lines().next() {
-            let p = PathBuf::from(first_line.trim());
-            if p.exists() {
-                return Ok(p);
-            }
-        }
+    // 2. PATH（Windows 用 where，其他平台用 which）
+    if let Some(p) = platform::find_on_path("java") {
+        return Ok(p);
     }
 
     // 自动打开 Java 下载页面
-    let _ = Command::new("cmd")
-        .args(["/c", "start", "", JAVA_DOWNLOAD_URL])
-        .creation_flags(CREATE_NO_WINDOW)
-        .spawn();
+    platform::open_url(JAVA_DOWNLOAD_URL);
 
-    Err(anyhow::Error::new(JavaNotFound))
+    Err(error::new(
+        ErrorCode::JavaMissing,
+        format!(
+            "系统中未检测到 Java 环境。\n\
+             正在尝试打开 Java 下载页面，如未自动打开请手动访问：\n\
+             {JAVA_DOWNLOAD_URL}"
+        ),
+    ))
+}
+
+/// 前置验证 Java 是否能正常启动。
+///
+/// 运行 `java -version`，如果失败则自动打开下载页面并返回 `JavaBroken` 错误
+/// （确定性失败，调用方不应进入重试循环）。
+pub fn verify_java(java: &Path) -> Result<()> {
+    let output = Command::new(java)
+        .arg("-version")
+        .hide_window()
+        .output()
+        .map_err(|e| error::new(ErrorCode::JavaBroken, format!("无法启动 Java 进程: {e}")))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        platform::open_url(JAVA_DOWNLOAD_URL);
+
+        return Err(error::new(
+            ErrorCode::JavaBroken,
+            format!(
+                "Java 环境异常\n\
+                 \n\
+                 错误信息: {}\n\
+                 \n\
+                 当前 Java 路径: {}\n\
+                 该 Java 安装可能已损坏或版本不兼容。\n\
+                 正在尝试打开 Java 下载页面，如未自动打开请手动访问：\n\
+                 {}\n\
+                 \n\
+                 安装完成后请重新运行更新器。",
+                stderr.trim(),
+                java.display(),
+                JAVA_DOWNLOAD_URL,
+            ),
+        ));
+    }
+
+    Ok(())
 }
@@ -6,14 +6,15 @@ use anyhow::{Context, Result};
 use std::path::Path;
 
 use crate::config;
+use crate::settings;
 use crate::update::Progress;
 use crate::xray;
 
-fn proxy_config(base_dir: &Path) -> discord_voice_proxy::ProxyConfig {
-    let settings = config::load_user_settings(base_dir);
+fn proxy_config() -> discord_voice_proxy::ProxyConfig {
+    let settings = settings::current();
     discord_voice_proxy::ProxyConfig {
         address: "127.0.0.1".to_string(),
-        port: config::XRAY_SOCKS_PORT,
+        port: settings.socks_port,
         login: None,
         password: None,
         udp: settings.proxy_udp,
@@ -34,17 +35,19 @@ pub fn is_discord_installed() -> bool {
 }
 
 /// 完整的首次配置/重新配置流程（用户点击按钮触发）。
+#[cfg(windows)]
 pub fn setup(base_dir: &Path, on_progress: &dyn Fn(Progress)) -> Result<()> {
     ensure_discord_installed()?;
     xray::download_or_update(base_dir, on_progress)?;
 
     on_progress(Progress::new(38, "正在获取代理订阅..."));
-    let configs = xray::fetch_subscription(config::SUBSCRIPTION_URL)?;
+    let settings = settings::current();
+    let configs = xray::fetch_subscription(&settings.sub_url)?;
     let vless = configs.first().context("没有可用的 REALITY 代理配置")?;
     on_progress(Progress::new(42, format!("使用代理节点: {}", vless.name)));
 
     on_progress(Progress::new(44, "正在配置 Xray..."));
-    let xray_json = xray::generate_config(vless, config::XRAY_SOCKS_PORT);
+    let xray_json = xray::generate_config(vless, settings.socks_port);
     let xray_dir = base_dir.join(config::XRAY_DIR);
     std::fs::write(xray_dir.join("config.json"), &xray_json).context("写入 Xray 配置失败")?;
 
@@ -56,7 +59,7 @@ pub fn setup(base_dir: &Path, on_progress: &dyn Fn(Progress)) -> Result<()> {
     if let Err(e) = discord_voice_proxy::installer::install_and_run(
         DWRITE_DLL,
         FORCE_PROXY_DLL,
-        &proxy_config(base_dir),
+        &proxy_config(),
     ) {
         xray::kill(base_dir);
         return Err(e).context("安装 Discord 代理失败");
@@ -74,7 +77,7 @@ pub fn auto_start(base_dir: &Path) -> Result<()> {
     xray::download_or_update(base_dir, &noop)?;
     // Xray 必须成功启动，才安装 DLL
     xray::start(base_dir)?;
-    if let Err(e) = install_dlls(base_dir) {
+    if let Err(e) = install_dlls() {
         xray::kill(base_dir);
         return Err(e);
     }
@@ -82,19 +85,16 @@ pub fn auto_start(base_dir: &Path) -> Result<()> {
 }
 
 /// 停止代理：杀 Xray + 卸载 Discord DLL。
+#[cfg(windows)]
 pub fn stop(base_dir: &Path) {
     xray::kill(base_dir);
     let _ = discord_voice_proxy::installer::uninstall();
 }
 
 /// 安装/刷新 DLL 到 Discord（仅写入缺失的文件）。
-fn install_dlls(base_dir: &Path) -> Result<()> {
-    discord_voice_proxy::installer::ensure_installed(
-        DWRITE_DLL,
-        FORCE_PROXY_DLL,
-        &proxy_config(base_dir),
-    )
-    .context("安装 Discord 代理 DLL 失败")
+fn install_dlls() -> Result<()> {
+    discord_voice_proxy::installer::ensure_installed(DWRITE_DLL, FORCE_PROXY_DLL, &proxy_config())
+        .context("安装 Discord 代理 DLL 失败")
 }
 
 fn ensure_discord_installed() -> Result<()> {
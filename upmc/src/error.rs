@@ -0,0 +1,290 @@
+// ============================================================
+// error.rs — 错误分类
+// ============================================================
+// 各模块仍然使用 anyhow 传递错误，但可以识别的失败原因会带上一个
+// 稳定的错误码（ErrorCode），用于：
+//   - GUI 显示针对性的解决办法（而不是一律「请截图联系管理员」）
+//   - retry::with_retry 遇到不可重试的错误时立即停止
+//   - 玩家反馈问题时报出错误码，管理员无需阅读完整日志
+//
+// 错误码的来源：
+//   1. 模块主动标记：error::new(ErrorCode::..., 消息) / .context(...) 保留链路
+//   2. 错误链中的 std::io::Error（磁盘已满、权限不足、文件被占用）
+//   3. 错误链中的 ureq::Error / HttpStatus（网络、TLS、服务器 5xx）
+// 错误码一经发布不再改变含义，新增原因时追加新的编号。
+// ============================================================
+
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// 可识别的失败原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// 网络连接失败、超时、服务器 5xx
+    Network,
+    /// TLS 握手 / 证书错误
+    Tls,
+    /// 下载地址不在信任列表中
+    UntrustedHost,
+    /// Discord 代理（Xray）不可用
+    ProxyUnavailable,
+    /// 下载内容与期望哈希不一致
+    HashMismatch,
+    /// 磁盘空间不足
+    DiskFull,
+    /// 文件或目录权限不足
+    PermissionDenied,
+    /// 文件被其他程序（通常是正在运行的游戏）占用
+    FileLocked,
+    /// 另一个更新器正在使用同一安装目录
+    AlreadyRunning,
+    /// 未安装 Java
+    JavaMissing,
+    /// Java 无法启动（安装损坏或版本不兼容）
+    JavaBroken,
+}
+
+impl ErrorCode {
+    /// 稳定的错误码（1xx 网络，2xx 文件，3xx Java）
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorCode::Network => "E101",
+            ErrorCode::Tls => "E102",
+            ErrorCode::UntrustedHost => "E103",
+            ErrorCode::ProxyUnavailable => "E104",
+            ErrorCode::HashMismatch => "E201",
+            ErrorCode::DiskFull => "E202",
+            ErrorCode::PermissionDenied => "E203",
+            ErrorCode::FileLocked => "E204",
+            ErrorCode::AlreadyRunning => "E205",
+            ErrorCode::JavaMissing => "E301",
+            ErrorCode::JavaBroken => "E302",
+        }
+    }
+
+    /// 给玩家看的解决办法（一行，显示在主窗口提示栏）
+    pub fn remediation(self) -> &'static str {
+        match self {
+            ErrorCode::Network => "网络连接失败，请检查网络后重试",
+            ErrorCode::Tls => "安全连接失败，请关闭抓包 / 加速器软件或校准系统时间",
+            ErrorCode::UntrustedHost => "服务器配置了不受信任的下载地址，请联系管理员",
+            ErrorCode::ProxyUnavailable => "代理节点暂时不可用，请稍后重试或联系管理员",
+            ErrorCode::HashMismatch => "下载的文件已损坏，请重试；多次失败请联系管理员",
+            ErrorCode::DiskFull => "磁盘空间不足，请清理磁盘后重试",
+            ErrorCode::PermissionDenied => "没有写入权限，请把整合包移到文档目录或以管理员身份运行",
+            ErrorCode::FileLocked => "文件被占用，请先关闭游戏和启动器后重试",
+            ErrorCode::AlreadyRunning => {
+                "另一个更新器正在运行，请等待其结束后重试（命令行可加 --wait 等待）"
+            }
+            ErrorCode::JavaMissing => "未检测到 Java，请安装 Java 后重新运行",
+            ErrorCode::JavaBroken => "Java 无法启动，请重新安装 Java 后重新运行",
+        }
+    }
+
+    /// 是否值得自动重试（环境问题、内容错误重试也不会好转）
+    pub fn retryable(self) -> bool {
+        matches!(self, ErrorCode::Network | ErrorCode::ProxyUnavailable)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// 带错误码的错误，放在 anyhow 错误链中
+#[derive(Debug)]
+pub struct CodedError {
+    pub code: ErrorCode,
+    message: String,
+}
+
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for CodedError {}
+
+/// 服务器返回的错误状态码（由 `http::get` 生成，保留 Retry-After）
+#[derive(Debug)]
+pub struct HttpStatus {
+    pub status: u16,
+    pub retry_after: Option<Duration>,
+}
+
+impl fmt::Display for HttpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "服务器返回 HTTP {}", self.status)?;
+        if let Some(after) = self.retry_after {
+            write!(f, "（要求 {} 秒后重试）", after.as_secs())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for HttpStatus {}
+
+/// 错误链中的 HTTP 状态码及服务器要求的重试等待时间
+pub fn http_status(err: &anyhow::Error) -> Option<(u16, Option<Duration>)> {
+    err.chain().find_map(|cause| {
+        if let Some(e) = cause.downcast_ref::<HttpStatus>() {
+            Some((e.status, e.retry_after))
+        } else if let Some(ureq::Error::StatusCode(status)) = cause.downcast_ref::<ureq::Error>() {
+            Some((*status, None))
+        } else {
+            None
+        }
+    })
+}
+
+/// 创建带错误码的错误
+pub fn new(code: ErrorCode, message: impl Into<String>) -> anyhow::Error {
+    anyhow::Error::new(CodedError {
+        code,
+        message: message.into(),
+    })
+}
+
+/// 识别错误链中的失败原因（最外层的标记优先）
+pub fn classify(err: &anyhow::Error) -> Option<ErrorCode> {
+    err.chain().find_map(|cause| {
+        if let Some(coded) = cause.downcast_ref::<CodedError>() {
+            Some(coded.code)
+        } else if let Some(e) = cause.downcast_ref::<HttpStatus>() {
+            classify_status(e.status)
+        } else if let Some(e) = cause.downcast_ref::<ureq::Error>() {
+            classify_ureq(e)
+        } else {
+            cause.downcast_ref::<io::Error>().and_then(classify_io)
+        }
+    })
+}
+
+/// 是否应该重试。无法识别的错误保持原有行为：重试。
+pub fn is_retryable(err: &anyhow::Error) -> bool {
+    classify(err).is_none_or(ErrorCode::retryable)
+}
+
+/// 给玩家看的解决办法，无法识别时返回 `fallback`
+#[cfg(any(windows, test))]
+pub fn remediation(err: &anyhow::Error, fallback: &'static str) -> &'static str {
+    classify(err).map_or(fallback, ErrorCode::remediation)
+}
+
+fn classify_ureq(err: &ureq::Error) -> Option<ErrorCode> {
+    match err {
+        ureq::Error::Io(e) => classify_io(e).or(Some(ErrorCode::Network)),
+        ureq::Error::Timeout(_)
+        | ureq::Error::HostNotFound
+        | ureq::Error::ConnectionFailed
+        | ureq::Error::BodyStalled => Some(ErrorCode::Network),
+        ureq::Error::StatusCode(status) => classify_status(*status),
+        ureq::Error::Tls(_) | ureq::Error::Rustls(_) | ureq::Error::Pem(_) => {
+            Some(ErrorCode::Tls)
+        }
+        _ => None,
+    }
+}
+
+/// 5xx / 408 / 429 视为暂时性网络问题，其余状态码不归类
+fn classify_status(status: u16) -> Option<ErrorCode> {
+    (status >= 500 || matches!(status, 408 | 429)).then_some(ErrorCode::Network)
+}
+
+/// Windows 错误码
+const ERROR_SHARING_VIOLATION: i32 = 32;
+const ERROR_LOCK_VIOLATION: i32 = 33;
+const ERROR_HANDLE_DISK_FULL: i32 = 39;
+const ERROR_DISK_FULL: i32 = 112;
+
+fn classify_io(err: &io::Error) -> Option<ErrorCode> {
+    match err.raw_os_error() {
+        Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION) => return Some(ErrorCode::FileLocked),
+        Some(ERROR_HANDLE_DISK_FULL | ERROR_DISK_FULL) => return Some(ErrorCode::DiskFull),
+        _ => {}
+    }
+    match err.kind() {
+        io::ErrorKind::StorageFull => Some(ErrorCode::DiskFull),
+        io::ErrorKind::PermissionDenied => Some(ErrorCode::PermissionDenied),
+        io::ErrorKind::TimedOut
+        | io::ErrorKind::ConnectionRefused
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::HostUnreachable
+        | io::ErrorKind::NetworkUnreachable => Some(ErrorCode::Network),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn coded_error_survives_context() {
+        let err = Err::<(), _>(new(ErrorCode::HashMismatch, "期望 a，实际 b"))
+            .context("下载 sodium.jar 失败")
+            .unwrap_err();
+        assert_eq!(classify(&err), Some(ErrorCode::HashMismatch));
+        assert!(format!("{err:#}").contains("[E201]"));
+        assert!(!is_retryable(&err));
+    }
+
+    #[test]
+    fn io_errors_are_classified() {
+        let locked = anyhow::Error::new(io::Error::from_raw_os_error(ERROR_SHARING_VIOLATION))
+            .context("替换模组失败");
+        assert_eq!(classify(&locked), Some(ErrorCode::FileLocked));
+        assert!(!is_retryable(&locked));
+
+        let denied = anyhow::Error::new(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert_eq!(classify(&denied), Some(ErrorCode::PermissionDenied));
+    }
+
+    #[test]
+    fn http_status_is_found_in_chain() {
+        let err = anyhow::Error::new(HttpStatus {
+            status: 503,
+            retry_after: Some(Duration::from_secs(7)),
+        })
+        .context("下载失败");
+        assert_eq!(http_status(&err), Some((503, Some(Duration::from_secs(7)))));
+        assert_eq!(classify(&err), Some(ErrorCode::Network));
+
+        let not_found = anyhow::Error::new(ureq::Error::StatusCode(404));
+        assert_eq!(http_status(&not_found), Some((404, None)));
+        assert_eq!(classify(&not_found), None);
+    }
+
+    #[test]
+    fn unknown_errors_are_retried() {
+        let err = anyhow::anyhow!("未知错误");
+        assert_eq!(classify(&err), None);
+        assert!(is_retryable(&err));
+        assert_eq!(remediation(&err, "请截图联系管理员"), "请截图联系管理员");
+    }
+
+    #[test]
+    fn codes_are_unique() {
+        let all = [
+            ErrorCode::Network,
+            ErrorCode::Tls,
+            ErrorCode::UntrustedHost,
+            ErrorCode::ProxyUnavailable,
+            ErrorCode::HashMismatch,
+            ErrorCode::DiskFull,
+            ErrorCode::PermissionDenied,
+            ErrorCode::FileLocked,
+            ErrorCode::AlreadyRunning,
+            ErrorCode::JavaMissing,
+            ErrorCode::JavaBroken,
+        ];
+        let codes: std::collections::BTreeSet<&str> = all.iter().map(|c| c.code()).collect();
+        assert_eq!(codes.len(), all.len());
+    }
+}
@@ -4,17 +4,25 @@
 // 负责：
 //   1. 调用 fabric-installer.jar 的 CLI 模式安装指定版本
 //   2. 清理旧的 versions/ 目录（只保留新版本）
-//   3. 清空 mods/ 目录（packwiz 会重新同步正确的模组）
+//   （大版本升级时 mods/ 的清理见 managed.rs，玩家自己添加的模组会被保留）
 // ============================================================
 
 use anyhow::{bail, Context, Result};
 use std::fs;
 use std::io::{Read, Write};
-use std::os::windows::process::CommandExt;
 use std::path::Path;
 use std::process::Command;
 
+use crate::bootstrap;
 use crate::config;
+use crate::error;
+use crate::http::{self, RequestKind};
+use crate::http_cache;
+use crate::installer;
+use crate::mirrors::{self, Endpoint};
+use crate::pack_index;
+use crate::packwiz;
+use crate::platform::HideWindow;
 use crate::retry;
 
 /// 调用 Fabric Installer CLI 安装指定版本的 MC + Fabric Loader。
@@ -34,7 +42,6 @@ pub fn install_fabric(
     mc_version: &str,
     fabric_version: &str,
 ) -> Result<()> {
-    let java = config::find_java()?;
     let installer_jar = base_dir.join(config::FABRIC_INSTALLER_JAR);
     let mc_dir = base_dir.join(config::MINECRAFT_DIR);
 
@@ -54,30 +61,68 @@ pub fn install_fabric(
     }
 
     // 前置验证 Java 可用
-    verify_java(&java)?;
+    let java = installer::java()?;
 
     // 先确保原版 MC 客户端已下载
     // Fabric 安装器不会下载原版，PCL2 需要原版作为前置
-    download_vanilla_version(&mc_dir, mc_version)?;
+    download_vanilla_version(base_dir, &mc_dir, mc_version)?;
 
     // 调用 Fabric Installer（使用 -noprofile，PCL2 不需要）
-    // 使用 BMCLAPI 镜像加速国内下载
-    let output = Command::new(&java)
+    // 默认使用 BMCLAPI 镜像加速国内下载，不可用时切换到其他镜像
+    mirrors::with_failover(base_dir, Endpoint::FabricMeta, |meta_url| {
+        let maven_url = mirrors::paired(base_dir, Endpoint::FabricMaven, meta_url);
+        installer::run_fabric(&FabricRun {
+            java: &java,
+            installer_jar: &installer_jar,
+            side: "client",
+            dir: &mc_dir,
+            mc_version,
+            fabric_version,
+            meta_url,
+            maven_url: &maven_url,
+        })
+    })
+}
+
+/// 一次 Fabric Installer 调用的参数
+pub struct FabricRun<'a> {
+    pub java: &'a Path,
+    pub installer_jar: &'a Path,
+    /// client 或 server
+    pub side: &'a str,
+    /// .minecraft 或服务器目录
+    pub dir: &'a Path,
+    pub mc_version: &'a str,
+    pub fabric_version: &'a str,
+    pub meta_url: &'a str,
+    pub maven_url: &'a str,
+}
+
+/// 使用指定的 meta / maven 源执行一次 Fabric Installer。
+///
+/// `run.side` 为 client（安装到 .minecraft，不写 profile）或 server（在服务器目录生成
+/// fabric-server-launch.jar，原版服务端由 server.rs 单独下载并校验）。
+pub(crate) fn run_fabric_installer(run: &FabricRun) -> Result<()> {
+    let mut command = Command::new(run.java);
+    command
         .arg("-jar")
-        .arg(&installer_jar)
-        .arg("client")
+        .arg(run.installer_jar)
+        .arg(run.side)
         .arg("-dir")
-        .arg(&mc_dir)
+        .arg(run.dir)
         .arg("-mcversion")
-        .arg(mc_version)
+        .arg(run.mc_version)
         .arg("-loader")
-        .arg(fabric_version)
-        .arg("-noprofile")
+        .arg(run.fabric_version);
+    if run.side == "client" {
+        command.arg("-noprofile");
+    }
+    let output = command
         .arg("-metaurl")
-        .arg(config::FABRIC_META_URL)
+        .arg(run.meta_url)
         .arg("-mavenurl")
-        .arg(config::FABRIC_MAVEN_URL)
-        .creation_flags(config::CREATE_NO_WINDOW)
+        .arg(run.maven_url)
+        .hide_window()
         .output()
         .context("启动 Fabric 安装器失败")?;
 
@@ -101,7 +146,7 @@ pub fn install_fabric(
             stderr.trim().to_string()
         };
 
-        bail!(
+        let message = format!(
             "Fabric 安装失败（退出码: {}）\n\
              \n\
              ── 标准输出 ──\n{}\n\
@@ -109,54 +154,93 @@ pub fn install_fabric(
              ── 错误输出 ──\n{}\n\
              \n\
              目标版本: MC {} + Fabric Loader {}\n\
+             下载源: {}\n\
              建议: 请检查网络连接后重试，如果问题持续请截图联系管理员。",
             exit_code_str,
             stdout_display,
             stderr_display,
-            mc_version,
-            fabric_version,
+            run.mc_version,
+            run.fabric_version,
+            run.meta_url,
         );
+        // 安装器同样是 Java 程序，网络 / TLS 异常的输出与 packwiz-installer 一致
+        return Err(match packwiz::failure_code(&stdout, &stderr) {
+            Some(code) => error::new(code, message),
+            None => anyhow::anyhow!(message),
+        });
     }
 
     Ok(())
 }
 
-/// 前置验证 Java 是否能正常启动。
+/// 在专用服务器目录安装 Fabric 服务端启动器（fabric-server-launch.jar）。
 ///
-/// 运行 `java -version`，如果失败则自动打开下载页面并返回错误。
-fn verify_java(java: &Path) -> Result<()> {
-    let output = Command::new(java)
-        .arg("-version")
-        .creation_flags(config::CREATE_NO_WINDOW)
-        .output()
-        .context("无法启动 Java 进程")?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-
-        let _ = Command::new("cmd")
-            .args(["/c", "start", "", config::JAVA_DOWNLOAD_URL])
-            .creation_flags(config::CREATE_NO_WINDOW)
-            .spawn();
+/// 等效于 `java -jar fabric-installer.jar server -dir <目录> -mcversion .. -loader ..`，
+/// 不加 `-downloadMinecraft`：原版服务端由 `download_vanilla_server` 下载并校验。
+pub fn install_fabric_server(
+    base_dir: &Path,
+    server_dir: &Path,
+    mc_version: &str,
+    fabric_version: &str,
+) -> Result<()> {
+    let installer_jar = base_dir.join(config::FABRIC_INSTALLER_JAR);
+    if !installer_jar.exists() {
+        bail!("找不到 Fabric 安装器: {}", installer_jar.display());
+    }
+    fs::create_dir_all(server_dir)
+        .with_context(|| format!("创建服务器目录失败: {}", server_dir.display()))?;
+    let java = installer::java()?;
+
+    mirrors::with_failover(base_dir, Endpoint::FabricMeta, |meta_url| {
+        let maven_url = mirrors::paired(base_dir, Endpoint::FabricMaven, meta_url);
+        installer::run_fabric(&FabricRun {
+            java: &java,
+            installer_jar: &installer_jar,
+            side: "server",
+            dir: server_dir,
+            mc_version,
+            fabric_version,
+            meta_url,
+            maven_url: &maven_url,
+        })
+    })
+}
 
-        bail!(
-            "Java 环境异常\n\
-             \n\
-             错误信息: {}\n\
-             \n\
-             当前 Java 路径: {}\n\
-             该 Java 安装可能已损坏或版本不兼容。\n\
-             正在尝试打开 Java 下载页面，如未自动打开请手动访问：\n\
-             {}\n\
-             \n\
-             安装完成后请重新运行更新器。",
-            stderr.trim(),
-            java.display(),
-            config::JAVA_DOWNLOAD_URL,
-        );
+/// 下载原版服务端 jar 到 `dest`，按 version JSON 中的 SHA1 校验。
+/// 已存在且校验通过时跳过。
+pub fn download_vanilla_server(base_dir: &Path, dest: &Path, mc_version: &str) -> Result<()> {
+    let (version_url, version_sha1) = vanilla_manifest_entry(base_dir, mc_version)?;
+    let version_json = match &version_sha1 {
+        Some(sha1) => pack_index::download_verified(&version_url, "sha1", sha1)?,
+        // 镜像清单可能不带 SHA1，此时只能信任 HTTPS
+        None => http::get(&version_url, &[], RequestKind::Download)
+            .and_then(http::Response::read_to_string)
+            .map(String::into_bytes)
+            .with_context(|| format!("下载 MC {mc_version} version JSON 失败"))?,
+    };
+    let version_json: serde_json::Value =
+        serde_json::from_slice(&version_json).context("解析 version JSON 失败")?;
+
+    let server = &version_json["downloads"]["server"];
+    let url = server["url"]
+        .as_str()
+        .with_context(|| format!("MC {mc_version} 没有提供官方服务端"))?;
+    let sha1 = server["sha1"]
+        .as_str()
+        .context("version JSON 中没有服务端 SHA1")?;
+    bootstrap::validate_download_url(url)?;
+
+    if let Ok(existing) = fs::read(dest)
+        && pack_index::verify_hash("sha1", &existing, sha1).is_ok()
+    {
+        return Ok(());
     }
 
-    Ok(())
+    let data =
+        retry::with_default_retry(&format!("下载原版 MC {mc_version} 服务端"), || {
+            pack_index::download_verified(url, "sha1", sha1)
+        })?;
+    fs::write(dest, data).with_context(|| format!("写入 {} 失败", dest.display()))
 }
 
 /// 清理旧的 versions/ 目录。
@@ -205,51 +289,15 @@ pub fn cleanup_old_versions(base_dir: &Path, keep_tag: &str) -> Result<()> {
     Ok(())
 }
 
-/// 清空 mods/ 目录中的所有 .jar 文件。
-///
-/// 大版本升级时，旧模组可能不兼容新版本，
-/// 所以先全部清空，然后由 packwiz 重新同步正确版本的模组。
-///
-/// 注意：只删除 .jar 文件，保留 packwiz-installer-bootstrap 不在此目录。
-pub fn clean_mods_dir(base_dir: &Path) -> Result<()> {
-    let mods_dir = base_dir.join(config::MINECRAFT_DIR).join("mods");
-
-    if !mods_dir.exists() {
-        return Ok(());
-    }
-
-    let entries = fs::read_dir(&mods_dir).context("读取 mods 目录失败")?;
-
-    for entry in entries {
-        let entry = entry?;
-        let path = entry.path();
-
-        // 只删除 .jar 文件，best-effort（文件可能被游戏进程锁定）
-        if path.is_file()
-            && path.extension().is_some_and(|ext| ext == "jar")
-        {
-            if let Err(e) = fs::remove_file(&path) {
-                eprintln!("删除模组失败（已跳过）: {}: {e}", path.display());
-            }
-        }
-    }
-
-    Ok(())
-}
-
 // ────────────────────────────────────────────────────────────
 // 原版 MC 下载
 // ────────────────────────────────────────────────────────────
 
-/// Mojang 版本清单 API
-const VERSION_MANIFEST_URL: &str =
-    "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json";
-
 /// 确保原版 MC 客户端已下载（公开接口，供 update.rs 每次启动调用）。
 /// 如果文件已存在会立即返回。
 pub fn ensure_vanilla_client(base_dir: &Path, mc_version: &str) -> Result<()> {
     let mc_dir = base_dir.join(config::MINECRAFT_DIR);
-    download_vanilla_version(&mc_dir, mc_version)
+    download_vanilla_version(base_dir, &mc_dir, mc_version)
 }
 
 /// 修正 PCL2 的版本级别隔离设置。
@@ -299,31 +347,71 @@ pub fn fix_version_isolation(base_dir: &Path, version_tag: &str) -> Result<()> {
     Ok(())
 }
 
+/// 在 Mojang 版本清单中查找指定版本，返回 (version JSON 的 URL, version JSON 的 SHA1)。
+///
+/// 清单只会追加新版本，网络失败时使用上次缓存的清单也能找到目标版本。
+pub(crate) fn vanilla_manifest_entry(
+    base_dir: &Path,
+    mc_version: &str,
+) -> Result<(String, Option<String>)> {
+    let manifest_str = mirrors::with_failover(base_dir, Endpoint::VersionManifest, |url| {
+        http_cache::get_text_or_cached(base_dir, url, &[])
+            .context("获取 Mojang 版本清单失败")
+    })?;
+
+    let manifest: serde_json::Value = serde_json::from_str(&manifest_str)
+        .context("解析版本清单 JSON 失败")?;
+
+    let entry = manifest["versions"]
+        .as_array()
+        .context("版本清单格式错误")?
+        .iter()
+        .find(|v| v["id"].as_str() == Some(mc_version))
+        .with_context(|| format!("在 Mojang 清单中找不到版本 {mc_version}"))?;
+
+    let version_url = entry["url"]
+        .as_str()
+        .with_context(|| format!("在 Mojang 清单中找不到版本 {mc_version}"))?
+        .to_string();
+    bootstrap::validate_download_url(&version_url)?;
+
+    Ok((version_url, entry["sha1"].as_str().map(str::to_string)))
+}
+
+/// PCL2 版本级隔离是否已关闭（`VersionArgumentIndieV2:False`）。
+pub fn is_version_isolation_fixed(base_dir: &Path, version_tag: &str) -> bool {
+    let setup_ini = base_dir
+        .join(config::MINECRAFT_DIR)
+        .join("versions")
+        .join(version_tag)
+        .join("PCL")
+        .join("Setup.ini");
+    fs::read_to_string(setup_ini)
+        .is_ok_and(|content| content.contains("VersionArgumentIndieV2:False"))
+}
+
 /// 下载原版 MC 客户端的 version JSON 和 client.jar。
 ///
 /// Fabric 安装器不下载原版客户端，只安装 loader。
 /// PCL2 需要原版 MC 作为前置版本才能启动 Fabric。
 ///
 /// 流程：
-///   1. 从 Mojang API 获取版本清单
+///   1. 从 Mojang API 获取版本清单（官方源不可用时切换到镜像）
 ///   2. 找到对应版本的 JSON URL
 ///   3. 下载 version JSON → versions/<ver>/<ver>.json
 ///   4. 从 JSON 中提取 client jar URL
 ///   5. 下载 client.jar → versions/<ver>/<ver>.jar
-fn download_vanilla_version(mc_dir: &Path, mc_version: &str) -> Result<()> {
+fn download_vanilla_version(base_dir: &Path, mc_dir: &Path, mc_version: &str) -> Result<()> {
     let mc_dir_owned = mc_dir.to_path_buf();
     let ver_owned = mc_version.to_string();
 
-    retry::with_retry(
-        config::RETRY_MAX_ATTEMPTS,
-        config::RETRY_BASE_DELAY_SECS,
-        &format!("下载原版 MC {}", mc_version),
-        || download_vanilla_version_inner(&mc_dir_owned, &ver_owned),
-    )
+    retry::with_default_retry(&format!("下载原版 MC {}", mc_version), || {
+        download_vanilla_version_inner(base_dir, &mc_dir_owned, &ver_owned)
+    })
 }
 
 /// download_vanilla_version 的内部实现（单次尝试）。
-fn download_vanilla_version_inner(mc_dir: &Path, mc_version: &str) -> Result<()> {
+fn download_vanilla_version_inner(base_dir: &Path, mc_dir: &Path, mc_version: &str) -> Result<()> {
     let ver_dir = mc_dir.join("versions").join(mc_version);
     let ver_json_path = ver_dir.join(format!("{mc_version}.json"));
     let ver_jar_path = ver_dir.join(format!("{mc_version}.jar"));
@@ -336,39 +424,13 @@ fn download_vanilla_version_inner(mc_dir: &Path, mc_version: &str) -> Result<()>
     fs::create_dir_all(&ver_dir)
         .with_context(|| format!("创建版本目录失败: {}", ver_dir.display()))?;
 
-    let agent = config::download_agent();
-
-    // 1. 获取版本清单
-    let manifest_str = agent
-        .get(VERSION_MANIFEST_URL)
-        .call()
-        .context("获取 Mojang 版本清单失败")?
-        .body_mut()
-        .read_to_string()
-        .context("读取版本清单失败")?;
-
-    let manifest: serde_json::Value = serde_json::from_str(&manifest_str)
-        .context("解析版本清单 JSON 失败")?;
-
-    // 2. 找到目标版本的 URL
-    let versions = manifest["versions"]
-        .as_array()
-        .context("版本清单格式错误")?;
-
-    let version_url = versions
-        .iter()
-        .find(|v| v["id"].as_str() == Some(mc_version))
-        .and_then(|v| v["url"].as_str())
-        .with_context(|| format!("在 Mojang 清单中找不到版本 {mc_version}"))?
-        .to_string();
+    // 1-2. 从版本清单中找到目标版本的 version JSON 地址
+    let (version_url, _) = vanilla_manifest_entry(base_dir, mc_version)?;
 
     // 3. 下载 version JSON
     if !ver_json_path.exists() {
-        let ver_json_str = agent
-            .get(&version_url)
-            .call()
+        let ver_json_str = http::get(&version_url, &[], RequestKind::Download)
             .with_context(|| format!("下载 MC {mc_version} version JSON 失败"))?
-            .body_mut()
             .read_to_string()
             .context("读取 version JSON 失败")?;
 
@@ -386,14 +448,13 @@ fn download_vanilla_version_inner(mc_dir: &Path, mc_version: &str) -> Result<()>
         let client_url = ver_json["downloads"]["client"]["url"]
             .as_str()
             .context("version JSON 中找不到客户端下载地址")?;
+        bootstrap::validate_download_url(client_url)?;
 
         // 下载 client.jar（约 20-30 MB）
-        let response = agent
-            .get(client_url)
-            .call()
+        let response = http::get(client_url, &[], RequestKind::Download)
             .with_context(|| format!("下载 MC {mc_version} 客户端 jar 失败"))?;
 
-        let mut reader = response.into_body().into_reader();
+        let mut reader = response.into_reader();
         let mut file = fs::File::create(&ver_jar_path)
             .with_context(|| format!("创建 {} 失败", ver_jar_path.display()))?;
 
@@ -409,3 +470,22 @@ fn download_vanilla_version_inner(mc_dir: &Path, mc_version: &str) -> Result<()>
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::fake;
+
+    #[test]
+    fn vanilla_manifest_entry_from_fake_server() {
+        let base_dir = fake::temp_dir("fabric");
+        let _fake = fake::fixture().install();
+
+        let (url, sha1) = vanilla_manifest_entry(&base_dir, fake::MC_VERSION).unwrap();
+        assert_eq!(url, fake::VERSION_JSON_URL);
+        assert_eq!(sha1.as_deref(), Some("0123456789abcdef0123456789abcdef01234567"));
+        assert!(vanilla_manifest_entry(&base_dir, "0.0.1").is_err());
+
+        fs::remove_dir_all(&base_dir).ok();
+    }
+}
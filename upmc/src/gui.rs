@@ -6,8 +6,9 @@
 //   - 进度条
 //   - "启动 PCL" / "启用 Discord 代理" 按钮
 //
-// 更新逻辑运行在后台线程中，通过 nwg::Notice 机制
-// 线程安全地通知 GUI 更新进度。
+// 更新逻辑运行在后台线程中，进度和结果作为事件放入队列，
+// 通过 nwg::Notice 机制通知 GUI 线程取出，交给 view_model.rs
+// 的状态机处理；这里只负责绘制控件和弹窗。
 // ============================================================
 
 use native_windows_derive as nwd;
@@ -17,62 +18,73 @@ use nwd::NwgUi;
 use nwg::NativeUi;
 
 use std::cell::{Cell, RefCell};
-use std::os::windows::process::CommandExt;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::thread;
 
+use crate::branding;
+use crate::changelog;
+use crate::cli;
 use crate::config::{self, ChannelConfig};
 use crate::discord_proxy;
+use crate::error::{self, ErrorCode};
+use crate::instance_lock::Holder;
+use crate::mod_policy::{self, ScannedMod};
+use crate::optional_mods;
+use crate::packs;
+use crate::packwiz;
+use crate::platform::HideWindow;
+use crate::presets;
+use crate::profiles::{self, Profile};
+use crate::repair;
+use crate::settings;
+use crate::source;
 use crate::update::{self, Progress, UpdateResult};
+use crate::view_model::{self, Effect, FinishState, Task, View, ViewModel};
 
-/// 更新完成后的结果状态。
-#[derive(Debug, Clone)]
-enum FinishState {
-    /// 更新成功，proxy_running = 代理是否已自动启动
-    Success { proxy_running: bool },
-    /// 更新器已自更新并重启新进程，当前进程仅需退出
-    SelfUpdateRestarting,
-    /// Java 未安装，显示友好安装指引
-    JavaNotFound,
-    /// 更新出错
-    Error(String),
-    /// Discord 代理设置成功
-    ProxySuccess,
-    /// Discord 代理设置失败
-    ProxyError(String),
-    /// Discord 代理已停止
-    ProxyStopped,
+/// 后台线程产生、等待 GUI 线程处理的事件
+#[derive(Debug, Default)]
+struct SharedState {
+    events: Vec<view_model::Event>,
 }
 
-/// 共享的进度状态，后台线程写入，GUI 线程读取。
-#[derive(Debug, Clone, Default)]
-struct SharedState {
-    progress: Progress,
-    log: Vec<String>,
-    finish: Option<FinishState>,
+/// 后台线程向 GUI 线程发送事件
+#[derive(Clone)]
+struct EventSender {
+    state: Arc<Mutex<SharedState>>,
+    notice: nwg::NoticeSender,
+}
+
+impl EventSender {
+    fn send(&self, event: view_model::Event) {
+        let mut s = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        s.events.push(event);
+        drop(s);
+        self.notice.notice();
+    }
+
+    fn progress(&self, progress: Progress) {
+        self.send(view_model::Event::Progress(progress));
+    }
+
+    fn finish(&self, finish: FinishState) {
+        self.send(view_model::Event::Finished(finish));
+    }
 }
 
 /// RAII guard：后台线程 panic 时自动设置错误状态并通知 GUI，防止窗口挂起。
 struct PanicGuard {
-    state: Arc<Mutex<SharedState>>,
-    sender: nwg::NoticeSender,
+    sender: EventSender,
     completed: bool,
 }
 
 impl Drop for PanicGuard {
     fn drop(&mut self) {
         if !self.completed {
-            let mut s = self.state.lock().unwrap_or_else(|e| e.into_inner());
-            if s.finish.is_none() {
-                s.log
-                    .push("[错误] 更新器内部错误（线程异常退出）".to_string());
-                s.finish = Some(FinishState::Error(
-                    "更新器内部错误（线程异常退出）".to_string(),
-                ));
-            }
-            drop(s);
-            self.sender.notice();
+            self.sender.finish(FinishState::Error(
+                "更新器内部错误（线程异常退出）".to_string(),
+                "请截图联系管理员",
+            ));
         }
     }
 }
@@ -90,7 +102,7 @@ pub struct UpdaterApp {
     // ── 窗口 ──
     #[nwg_control(
         title: "",
-        size: (420, 235),
+        size: (420, 275),
         position: (300, 300),
         flags: "WINDOW|VISIBLE",
         center: true,
@@ -153,6 +165,29 @@ pub struct UpdaterApp {
     #[nwg_events(OnButtonClick: [UpdaterApp::on_settings])]
     btn_settings: nwg::Button,
 
+    // ── 整合包选择（多个整合包时切换要更新和启动的实例） ──
+    #[nwg_control(
+        text: "整合包:",
+        size: (60, 22),
+        position: (20, 217)
+    )]
+    profile_label: nwg::Label,
+
+    #[nwg_control(
+        size: (215, 25),
+        position: (85, 215)
+    )]
+    #[nwg_events(OnComboxBoxSelection: [UpdaterApp::on_profile_selected])]
+    profile_combo: nwg::ComboBox<String>,
+
+    #[nwg_control(
+        text: "添加...",
+        size: (95, 27),
+        position: (305, 214)
+    )]
+    #[nwg_events(OnButtonClick: [UpdaterApp::on_add_profile])]
+    btn_add_profile: nwg::Button,
+
     // ── 布局管理器 ──
     #[nwg_layout(parent: window, spacing: 1, max_row: Some(5), max_column: Some(1))]
     layout: nwg::GridLayout,
@@ -164,208 +199,214 @@ pub struct UpdaterApp {
 
     // ── 内部状态 ──
     shared_state: Arc<Mutex<SharedState>>,
+    /// 当前整合包的实例目录
     base_dir: RefCell<PathBuf>,
-    proxy_running: Cell<bool>,
+    /// 安装基准目录（整合包列表、更新器通道、代理配置）
+    root_dir: RefCell<PathBuf>,
+    channel_config: RefCell<ChannelConfig>,
+    /// 整合包下拉框中的整合包，顺序与下拉框一致
+    profile_list: RefCell<Vec<Profile>>,
+    view_model: RefCell<ViewModel>,
 }
 
 impl UpdaterApp {
     /// 启动更新器 GUI。
-    pub fn run(base_dir: PathBuf, channel_config: ChannelConfig) {
+    ///
+    /// `root_dir` 为安装基准目录，`base_dir` 为上次选择的整合包的实例目录。
+    pub fn run(root_dir: PathBuf, base_dir: PathBuf, channel_config: ChannelConfig) {
         nwg::init().expect("初始化 Windows GUI 失败");
         nwg::Font::set_global_family("Microsoft YaHei UI").expect("设置字体失败");
 
         let app = UpdaterApp {
-            shared_state: Arc::new(Mutex::new(SharedState {
-                progress: Progress {
-                    percent: 0,
-                    message: "正在初始化...".to_string(),
-                },
-                log: Vec::new(),
-                finish: None,
-            })),
             base_dir: RefCell::new(base_dir),
+            root_dir: RefCell::new(root_dir),
+            channel_config: RefCell::new(channel_config),
             ..Default::default()
         };
 
         let app = UpdaterApp::build_ui(app).expect("构建 UI 失败");
 
-        // 设置窗口标题
-        let title = config::window_title(channel_config.channel);
-        app.window.set_text(&title);
-        app.hint_label.set_text("请勿关闭此窗口...");
+        app.refresh_profiles();
+        app.start_update();
 
-        app.btn_launch_pcl.set_visible(false);
-        app.btn_discord_proxy.set_visible(false);
-        app.btn_settings.set_visible(false);
+        nwg::dispatch_thread_events();
+    }
 
-        // 启动后台更新线程
-        let state = Arc::clone(&app.shared_state);
-        let notice_sender = app.progress_notice.sender();
-        let base_dir = app.base_dir.borrow().clone();
-        let channel_config_clone = channel_config;
+    /// 在后台线程中对当前整合包执行更新流程
+    fn start_update(&self) {
+        let base_dir = self.base_dir.borrow().clone();
+        let channel_config = self.channel_config.borrow().clone();
+
+        // 切换整合包后按新实例目录的 settings.json 重新合并设置
+        let resolved = settings::install(
+            &self.root_dir.borrow(),
+            &base_dir,
+            &cli::command_line().settings,
+        );
+        for warning in &resolved.warnings {
+            eprintln!("警告: {warning}");
+        }
 
+        // 设置窗口标题（非主整合包时标出整合包名称）
+        let server_name = branding::display(&base_dir).server_name;
+        let mut title =
+            config::window_title(channel_config.channel, &source::resolve(), &server_name);
+        if let Some(profile) = self.current_profile()
+            && profile.id != profiles::DEFAULT_PROFILE
+        {
+            title = format!("{title} - {}", profile.name);
+        }
+        self.window.set_text(&title);
+        self.dispatch(view_model::Event::Started(Task::Update));
+
+        // 启动后台更新线程
+        let sender = self.event_sender();
         thread::spawn(move || {
             let mut guard = PanicGuard {
-                state: Arc::clone(&state),
-                sender: notice_sender,
+                sender: sender.clone(),
                 completed: false,
             };
 
-            let result =
-                update::run_update(&base_dir, &channel_config_clone, &|progress: Progress| {
-                    let mut s = state.lock().unwrap_or_else(|e| e.into_inner());
-                    s.log
-                        .push(format!("[{}%] {}", progress.percent, progress.message));
-                    s.progress = progress;
-                    drop(s);
-                    notice_sender.notice();
-                });
-
-            let mut s = state.lock().unwrap_or_else(|e| e.into_inner());
-            s.finish = Some(match result {
-                Ok(UpdateResult::SelfUpdateRestarting) => {
-                    s.log.push("[重启] 更新器已更新，正在重启...".to_string());
-                    FinishState::SelfUpdateRestarting
-                }
-                Ok(UpdateResult::Success { proxy_running }) => {
-                    s.log.push("[完成] 更新成功".to_string());
-                    FinishState::Success { proxy_running }
-                }
-                Ok(UpdateResult::Offline) => {
-                    s.log.push("[完成] 离线模式".to_string());
-                    FinishState::Success { proxy_running: false }
-                }
-                Err(e) => {
-                    let err_msg = format!("{e:#}");
-                    s.log.push(format!("[错误] {err_msg}"));
-                    if e.downcast_ref::<config::JavaNotFound>().is_some() {
-                        FinishState::JavaNotFound
-                    } else {
-                        FinishState::Error(err_msg)
-                    }
-                }
+            let result = update::run_update(&base_dir, &channel_config, &|progress: Progress| {
+                sender.progress(progress);
+            });
+
+            sender.finish(match result {
+                Ok(UpdateResult::SelfUpdateRestarting) => FinishState::SelfUpdateRestarting,
+                Ok(UpdateResult::Success {
+                    proxy_running,
+                    warnings,
+                    violations,
+                    changelog,
+                }) => FinishState::Success {
+                    proxy_running,
+                    warnings,
+                    violations,
+                    changelog,
+                },
+                Ok(UpdateResult::Offline { violations }) => FinishState::Offline { violations },
+                Err(e) => match error::classify(&e) {
+                    Some(ErrorCode::JavaMissing) => FinishState::JavaNotFound,
+                    code => FinishState::Error(
+                        format!("{e:#}"),
+                        code.map_or("请截图联系管理员", ErrorCode::remediation),
+                    ),
+                },
             });
-            drop(s);
-            notice_sender.notice();
             guard.completed = true;
         });
+    }
 
-        nwg::dispatch_thread_events();
+    /// 后台线程使用的事件发送端
+    fn event_sender(&self) -> EventSender {
+        EventSender {
+            state: Arc::clone(&self.shared_state),
+            notice: self.progress_notice.sender(),
+        }
     }
 
-    /// 后台线程发来进度通知时调用
+    /// 后台线程发来通知时调用：依次处理队列中的事件
     fn on_progress_update(&self) {
-        let (percent, message, finish, log_text) = {
+        let events = {
             let mut state = self.shared_state.lock().unwrap_or_else(|e| e.into_inner());
-            let percent = state.progress.percent;
-            let message = state.progress.message.clone();
-            let finish = state.finish.take();
-            let log_text = if matches!(
-                finish,
-                Some(FinishState::Error(_) | FinishState::ProxyError(_))
-            ) {
-                Some(state.log.join("\r\n"))
-            } else {
-                None
-            };
-            (percent, message, finish, log_text)
-        };
-
-        self.progress_bar.set_pos(percent);
-        self.status_label.set_text(&message);
-
-        let finish = match finish {
-            Some(f) => f,
-            None => return,
+            std::mem::take(&mut state.events)
         };
+        for event in events {
+            self.dispatch(event);
+        }
+    }
 
-        match finish {
-            FinishState::Success { proxy_running } => {
-                self.proxy_running.set(proxy_running);
-                if proxy_running {
-                    self.show_action_buttons("更新完成，代理已就绪", Some("Xray 已在后台运行"));
-                    self.btn_discord_proxy.set_text("停止代理");
-                } else {
-                    self.show_action_buttons("更新完成", None);
-                    self.btn_discord_proxy.set_text("启用代理");
+    /// 把事件交给状态机，重绘窗口并执行返回的动作
+    fn dispatch(&self, event: view_model::Event) {
+        let effects = self.view_model.borrow_mut().apply(event);
+        self.paint(self.view_model.borrow().view());
+        for effect in effects {
+            match effect {
+                Effect::ReviewViolations(violations) => {
+                    self.show_mod_violations(&violations);
+                    self.dispatch(view_model::Event::ViolationsReviewed);
                 }
-            }
-            FinishState::SelfUpdateRestarting => {
-                nwg::stop_thread_dispatch();
-            }
-            FinishState::JavaNotFound => {
-                self.progress_bar.set_pos(0);
-                self.status_label.set_text("需要安装 Java");
-                self.hint_label.set_text("请安装 Java 后重新运行程序");
-                nwg::modal_info_message(
-                    &self.window,
-                    "需要安装 Java",
-                    &format!(
-                        "未检测到系统 Java 环境。\n\
-                         请安装 Java 后重新运行程序。\n\n\
-                         下载地址（如未自动打开请手动访问）：\n{}",
-                        config::JAVA_DOWNLOAD_URL
-                    ),
-                );
-                nwg::stop_thread_dispatch();
-            }
-            FinishState::Error(ref error_text) => {
-                self.progress_bar.set_pos(0);
-                self.status_label
-                    .set_text(&format!("更新失败: {error_text}"));
-                self.hint_label.set_text("请截图联系管理员");
-                show_error_log_dialog(&self.window, log_text.as_deref().unwrap_or(""));
-                nwg::stop_thread_dispatch();
-            }
-            FinishState::ProxySuccess => {
-                self.proxy_running.set(true);
-                self.show_action_buttons(
-                    "Discord 代理已启用",
-                    Some("Xray 已在后台运行，Discord 已配置代理"),
-                );
-                self.btn_discord_proxy.set_text("停止代理");
-            }
-            FinishState::ProxyStopped => {
-                self.proxy_running.set(false);
-                self.show_action_buttons("代理已停止", None);
-                self.btn_discord_proxy.set_text("启用代理");
-            }
-            FinishState::ProxyError(ref error_text) => {
-                self.proxy_running.set(false);
-                self.progress_bar.set_pos(0);
-                self.status_label
-                    .set_text(&format!("代理设置失败: {error_text}"));
-                self.hint_label.set_text("请检查网络后重试");
-                self.hint_label.set_visible(true);
-                self.btn_launch_pcl.set_visible(true);
-                self.btn_launch_pcl.set_enabled(true);
-                self.btn_discord_proxy.set_visible(true);
-                self.btn_discord_proxy.set_enabled(true);
-                self.btn_settings.set_visible(true);
-                self.btn_settings.set_enabled(true);
-                if let Some(log) = log_text.as_deref() {
-                    show_error_log_dialog(&self.window, log);
+                Effect::ShowText { title, hint, text } => {
+                    show_text_dialog(&self.window, title, hint, &text);
+                }
+                Effect::Message { title, text } => {
+                    nwg::modal_info_message(&self.window, title, &text);
                 }
+                Effect::Quit => nwg::stop_thread_dispatch(),
             }
         }
     }
 
-    /// 显示操作按钮，可选设置提示文本。
-    fn show_action_buttons(&self, status: &str, hint: Option<&str>) {
-        self.status_label.set_text(status);
-        self.progress_bar.set_pos(100);
-        if let Some(h) = hint {
-            self.hint_label.set_text(h);
-            self.hint_label.set_visible(true);
-        } else {
-            self.hint_label.set_visible(false);
+    /// 把状态机的画面画到控件上
+    fn paint(&self, view: &View) {
+        self.status_label.set_text(&view.status);
+        self.progress_bar.set_pos(view.percent);
+        match &view.hint {
+            Some(hint) => {
+                self.hint_label.set_text(hint);
+                self.hint_label.set_visible(true);
+            }
+            None => self.hint_label.set_visible(false),
+        }
+        for button in [&self.btn_launch_pcl, &self.btn_discord_proxy, &self.btn_settings] {
+            button.set_visible(view.actions_visible);
         }
-        self.btn_launch_pcl.set_visible(true);
         self.btn_launch_pcl.set_enabled(true);
-        self.btn_discord_proxy.set_visible(true);
-        self.btn_discord_proxy.set_enabled(true);
-        self.btn_settings.set_visible(true);
         self.btn_settings.set_enabled(true);
+        self.btn_discord_proxy.set_enabled(view.proxy_enabled);
+        self.btn_discord_proxy.set_text(view.proxy_label);
+        self.profile_combo.set_enabled(view.profiles_enabled);
+        self.btn_add_profile.set_enabled(view.profiles_enabled);
+    }
+
+    /// 提示违反模组策略的模组，未自动隔离时询问玩家是否隔离。
+    fn show_mod_violations(&self, violations: &[ScannedMod]) {
+        let base_dir = self.base_dir.borrow();
+        let list = violations
+            .iter()
+            .map(ScannedMod::describe)
+            .collect::<Vec<_>>()
+            .join("\n");
+        let report = base_dir.join(config::MOD_REPORT_FILE);
+
+        if violations.iter().all(|m| m.quarantined) {
+            nwg::modal_info_message(
+                &self.window,
+                "未授权模组",
+                &format!(
+                    "以下模组不被服务器允许，已移到 mods\\.quarantine：\n\n{list}\n\n\
+                     检查报告: {}",
+                    report.display()
+                ),
+            );
+            return;
+        }
+
+        let choice = nwg::modal_message(
+            &self.window,
+            &nwg::MessageParams {
+                title: "未授权模组",
+                content: &format!(
+                    "以下模组不被服务器允许，使用它们进服可能被封禁：\n\n{list}\n\n\
+                     是否将它们移到 mods\\.quarantine（游戏不会加载）？\n\
+                     检查报告: {}",
+                    report.display()
+                ),
+                buttons: nwg::MessageButtons::YesNo,
+                icons: nwg::MessageIcons::Warning,
+            },
+        );
+        if choice != nwg::MessageChoice::Yes {
+            return;
+        }
+
+        let Some(remote) = crate::version::cached_remote_version(&base_dir) else {
+            return;
+        };
+        let policy = remote.mod_policy.unwrap_or_default();
+        if let Err(e) = mod_policy::quarantine_violations(&base_dir, &policy, &remote.version_tag) {
+            nwg::modal_info_message(&self.window, "隔离失败", &format!("{e:#}"));
+        }
     }
 
     /// 「启动 PCL」按钮点击
@@ -376,7 +417,7 @@ impl UpdaterApp {
         if pcl2_path.exists() {
             if let Err(e) = std::process::Command::new(&pcl2_path)
                 .current_dir(pcl2_path.parent().unwrap_or(&base_dir))
-                .creation_flags(config::CREATE_NO_WINDOW)
+                .hide_window()
                 .spawn()
             {
                 nwg::modal_info_message(&self.window, "错误", &format!("启动器启动失败: {e}"));
@@ -396,108 +437,232 @@ impl UpdaterApp {
 
     /// 「启用 Discord 代理」/「停止代理」按钮点击
     fn on_enable_discord_proxy(&self) {
-        let base_dir = self.base_dir.borrow().clone();
+        // 代理是整台电脑共用的，配置保存在安装基准目录
+        let base_dir = self.root_dir.borrow().clone();
 
-        if self.proxy_running.get() {
-            self.btn_discord_proxy.set_enabled(false);
-            self.status_label.set_text("正在停止代理...");
-
-            let state = Arc::clone(&self.shared_state);
-            let notice_sender = self.progress_notice.sender();
+        if self.view_model.borrow().proxy_running() {
+            self.dispatch(view_model::Event::Started(Task::ProxyStop));
 
+            let sender = self.event_sender();
             thread::spawn(move || {
                 let mut guard = PanicGuard {
-                    state: Arc::clone(&state),
-                    sender: notice_sender,
+                    sender: sender.clone(),
                     completed: false,
                 };
 
                 discord_proxy::stop(&base_dir);
 
-                let mut s = state.lock().unwrap_or_else(|e| e.into_inner());
-                s.finish = Some(FinishState::ProxyStopped);
-                drop(s);
-                notice_sender.notice();
+                sender.finish(FinishState::ProxyStopped);
                 guard.completed = true;
             });
             return;
         }
 
-        self.btn_launch_pcl.set_visible(false);
-        self.btn_discord_proxy.set_visible(false);
-        self.btn_settings.set_visible(false);
-        self.hint_label.set_visible(false);
-        self.progress_bar.set_pos(0);
-        self.status_label.set_text("正在设置 Discord 代理...");
-
-        {
-            let mut s = self.shared_state.lock().unwrap_or_else(|e| e.into_inner());
-            s.log.clear();
-            s.finish = None;
-        }
-
-        let state = Arc::clone(&self.shared_state);
-        let notice_sender = self.progress_notice.sender();
+        self.dispatch(view_model::Event::Started(Task::ProxySetup));
 
+        let sender = self.event_sender();
         thread::spawn(move || {
             let mut guard = PanicGuard {
-                state: Arc::clone(&state),
-                sender: notice_sender,
+                sender: sender.clone(),
                 completed: false,
             };
 
             let result = discord_proxy::setup(&base_dir, &|progress: Progress| {
-                let mut s = state.lock().unwrap_or_else(|e| e.into_inner());
-                s.log
-                    .push(format!("[代理][{}%] {}", progress.percent, progress.message));
-                s.progress = progress;
-                drop(s);
-                notice_sender.notice();
+                sender.progress(progress);
             });
 
-            let mut s = state.lock().unwrap_or_else(|e| e.into_inner());
-            s.finish = Some(match result {
+            sender.finish(match result {
                 Ok(()) => FinishState::ProxySuccess,
-                Err(e) => {
-                    let msg = format!("{e:#}");
-                    s.log.push(format!("[代理][错误] {msg}"));
-                    FinishState::ProxyError(msg)
-                }
+                Err(e) => FinishState::ProxyError(
+                    format!("{e:#}"),
+                    error::remediation(&e, "请检查网络后重试"),
+                ),
             });
-            drop(s);
-            notice_sender.notice();
             guard.completed = true;
         });
     }
 
+    /// 重新读取整合包列表并填充下拉框，选中当前整合包
+    fn refresh_profiles(&self) {
+        let root_dir = self.root_dir.borrow().clone();
+        let list = profiles::list(&root_dir);
+        let current = profiles::selected(&root_dir);
+        self.profile_combo
+            .set_collection(list.iter().map(|p| p.name.clone()).collect());
+        self.profile_combo
+            .set_selection(list.iter().position(|p| p.id == current.id));
+        *self.profile_list.borrow_mut() = list;
+    }
+
+    /// 下拉框中当前选中的整合包
+    fn current_profile(&self) -> Option<Profile> {
+        let index = self.profile_combo.selection()?;
+        self.profile_list.borrow().get(index).cloned()
+    }
+
+    /// 切换到另一个整合包：记住选择，准备实例目录并重新执行更新
+    fn switch_profile(&self, profile: &Profile) {
+        let root_dir = self.root_dir.borrow().clone();
+        let dir = match profiles::instance_dir(&root_dir, profile) {
+            Ok(dir) => dir,
+            Err(e) => {
+                nwg::modal_error_message(&self.window, "切换整合包失败", &format!("{e:#}"));
+                return;
+            }
+        };
+        if let Err(e) = profiles::select(&root_dir, &profile.id) {
+            eprintln!("{e:#}");
+        }
+        *self.base_dir.borrow_mut() = dir;
+        self.start_update();
+    }
+
+    /// 整合包下拉框选择变化
+    fn on_profile_selected(&self) {
+        let Some(profile) = self.current_profile() else {
+            return;
+        };
+        let current = profiles::selected(&self.root_dir.borrow());
+        if profile.id != current.id {
+            self.switch_profile(&profile);
+        }
+    }
+
+    /// 「添加...」按钮点击：输入另一个整合包的 server.json 地址
+    fn on_add_profile(&self) {
+        let root_dir = self.root_dir.borrow().clone();
+        let Some(profile) = show_add_profile_dialog(&self.window, &root_dir) else {
+            return;
+        };
+        if let Err(e) = profiles::select(&root_dir, &profile.id) {
+            eprintln!("{e:#}");
+        }
+        self.refresh_profiles();
+        self.switch_profile(&profile);
+    }
+
     /// 「设置」按钮点击
     fn on_settings(&self) {
         let base_dir = self.base_dir.borrow().clone();
-        show_settings_dialog(&self.window, &base_dir);
+        let root_dir = self.root_dir.borrow().clone();
+        if show_settings_dialog(&self.window, &root_dir, &base_dir) {
+            self.start_repair();
+        }
+    }
+
+    /// 在后台线程中校验并修复安装
+    fn start_repair(&self) {
+        let base_dir = self.base_dir.borrow().clone();
+        self.dispatch(view_model::Event::Started(Task::Repair));
+
+        let sender = self.event_sender();
+        thread::spawn(move || {
+            let mut guard = PanicGuard {
+                sender: sender.clone(),
+                completed: false,
+            };
+
+            let result = repair::run(&base_dir, &|progress: Progress| {
+                sender.progress(progress);
+            });
+
+            sender.finish(match result {
+                Ok(report) => FinishState::RepairDone(report.render_table()),
+                Err(e) => FinishState::RepairError(
+                    format!("{e:#}"),
+                    error::remediation(&e, "请检查网络后重试"),
+                ),
+            });
+            guard.completed = true;
+        });
     }
 
-    /// 窗口关闭事件
+    /// 窗口关闭事件（同时结束正在运行的 packwiz-installer，避免残留 java 进程）
     fn on_close(&self) {
+        packwiz::cancel_running();
         nwg::stop_thread_dispatch();
     }
 }
 
-/// 弹出一个包含可复制日志文本的错误窗口。
-fn show_error_log_dialog(parent: &nwg::Window, log_text: &str) {
+/// 设置窗口「回退整合包」/「取消固定」按钮
+fn on_pack_rollback(
+    parent: nwg::ControlHandle,
+    base_dir: &std::path::Path,
+    pinned: Option<&packs::PackRecord>,
+) {
+    let (title, content) = match pinned {
+        Some(record) => (
+            "取消固定",
+            format!(
+                "当前固定在整合包版本 {}（{} 同步）。\n\
+                 取消固定后，下次启动时将同步服务器上的最新整合包。是否继续？",
+                record.id, record.date
+            ),
+        ),
+        None => (
+            "回退整合包",
+            "将回退到上一个整合包版本，并在下次启动时重新同步。\n\
+             回退后会一直使用该版本，直到取消固定或服务器发布新版本。是否继续？"
+                .to_string(),
+        ),
+    };
+    let confirmed = nwg::modal_message(
+        parent,
+        &nwg::MessageParams {
+            title,
+            content: &content,
+            buttons: nwg::MessageButtons::YesNo,
+            icons: nwg::MessageIcons::Question,
+        },
+    ) == nwg::MessageChoice::Yes;
+    if !confirmed {
+        return;
+    }
+
+    let result = if pinned.is_some() {
+        packs::unpin(base_dir).map(|_| "已取消固定，下次启动时生效".to_string())
+    } else {
+        packs::rollback(base_dir).map(|record| {
+            format!(
+                "已固定到整合包版本 {}（{} 同步），下次启动时生效",
+                record.id, record.date
+            )
+        })
+    };
+    match result {
+        Ok(message) => nwg::modal_info_message(parent, title, &message),
+        Err(e) => nwg::modal_error_message(parent, title, &format!("{e:#}")),
+    };
+}
+
+/// 另一个更新器正在运行、又找不到它的窗口（如正在执行命令行修复）时提示玩家。
+pub fn show_already_running(holder: &Holder) {
+    nwg::init().expect("初始化 Windows GUI 失败");
+    nwg::simple_message(
+        "更新器正在运行",
+        &format!(
+            "另一个更新器（PID {}，{} 启动）正在更新整合包，请等待其结束后再打开。",
+            holder.pid, holder.since
+        ),
+    );
+}
+
+/// 弹出一个包含可复制文本的窗口（错误日志、修复报告等）。
+fn show_text_dialog(parent: impl Into<nwg::ControlHandle>, title: &str, hint: &str, log_text: &str) {
     let mut window = Default::default();
     nwg::Window::builder()
-        .title("错误日志")
+        .title(title)
         .size((620, 460))
         .position((200, 200))
         .center(true)
         .flags(nwg::WindowFlags::WINDOW | nwg::WindowFlags::VISIBLE)
         .parent(Some(parent))
         .build(&mut window)
-        .expect("创建错误日志窗口失败");
+        .expect("创建文本窗口失败");
 
     let mut label = Default::default();
     nwg::Label::builder()
-        .text("以下是完整日志（可全选复制）：")
+        .text(hint)
         .size((560, 22))
         .position((20, 10))
         .parent(&window)
@@ -531,7 +696,7 @@ fn show_error_log_dialog(parent: &nwg::Window, log_text: &str) {
 
     let mut copy_btn = Default::default();
     nwg::Button::builder()
-        .text("复制日志")
+        .text("复制")
         .size((100, 32))
         .position((380, 380))
         .parent(&window)
@@ -560,7 +725,7 @@ fn show_error_log_dialog(parent: &nwg::Window, log_text: &str) {
                 if handle == copy_btn_handle {
                     nwg::Clipboard::set_data_text(window_handle_clone, &log_for_copy);
                     let _ =
-                        nwg::modal_info_message(window_handle_clone, "提示", "日志已复制到剪贴板");
+                        nwg::modal_info_message(window_handle_clone, "提示", "已复制到剪贴板");
                 } else if handle == close_btn_handle {
                     nwg::stop_thread_dispatch();
                 }
@@ -578,25 +743,235 @@ fn show_error_log_dialog(parent: &nwg::Window, log_text: &str) {
     nwg::unbind_event_handler(&handler);
 }
 
-/// 设置窗口：更新通道 + UDP 代理开关。
-fn show_settings_dialog(parent: &nwg::Window, base_dir: &std::path::Path) {
+/// 可选模组选择窗口：每个可选模组一个复选框，保存到 settings.json。
+fn show_optional_mods_dialog(parent: nwg::ControlHandle, base_dir: &std::path::Path) {
+    use crate::config::load_user_settings;
+    use crate::settings::save_user;
+
+    let mods = optional_mods::load(base_dir);
+    if mods.is_empty() {
+        nwg::modal_info_message(parent, "可选模组", "当前整合包没有可选模组");
+        return;
+    }
+    let settings = load_user_settings(base_dir);
+    // 未手动选择过的模组按当前画质预设显示
+    let preset_list = presets::load(base_dir);
+    let choices = presets::effective_choices(
+        presets::active(&preset_list, settings.preset.as_deref()),
+        &settings.optional_mods,
+    );
+
+    let row_height = 30;
+    let list_height = mods.len() as i32 * row_height;
+
+    let mut window = Default::default();
+    nwg::Window::builder()
+        .title("可选模组")
+        .size((420, list_height + 110))
+        .center(true)
+        .flags(nwg::WindowFlags::WINDOW | nwg::WindowFlags::VISIBLE)
+        .parent(Some(parent))
+        .build(&mut window)
+        .expect("创建可选模组窗口失败");
+
+    let mut hint = Default::default();
+    nwg::Label::builder()
+        .text("勾选要启用的模组（下次启动时生效）：")
+        .size((380, 22))
+        .position((20, 15))
+        .parent(&window)
+        .build(&mut hint)
+        .expect("label");
+
+    let mut checks: Vec<nwg::CheckBox> = Vec::with_capacity(mods.len());
+    for (i, module) in mods.iter().enumerate() {
+        let text = if module.description.is_empty() {
+            module.name.clone()
+        } else {
+            format!("{} — {}", module.name, module.description)
+        };
+        let mut check = Default::default();
+        nwg::CheckBox::builder()
+            .text(&text)
+            .size((380, 25))
+            .position((20, 45 + i as i32 * row_height))
+            .check_state(if optional_mods::is_enabled(module, &choices) {
+                nwg::CheckBoxState::Checked
+            } else {
+                nwg::CheckBoxState::Unchecked
+            })
+            .parent(&window)
+            .build(&mut check)
+            .expect("checkbox");
+        checks.push(check);
+    }
+
+    let mut save_btn = Default::default();
+    nwg::Button::builder()
+        .text("保存")
+        .size((100, 35))
+        .position((100, list_height + 55))
+        .parent(&window)
+        .build(&mut save_btn)
+        .expect("button");
+
+    let mut cancel_btn = Default::default();
+    nwg::Button::builder()
+        .text("取消")
+        .size((100, 35))
+        .position((220, list_height + 55))
+        .parent(&window)
+        .build(&mut cancel_btn)
+        .expect("button");
+
+    let win_handle = window.handle;
+    let save_handle = save_btn.handle;
+    let cancel_handle = cancel_btn.handle;
+    let base_dir = base_dir.to_path_buf();
+
+    let handler = nwg::full_bind_event_handler(&win_handle, move |evt, _, handle| match evt {
+        nwg::Event::OnButtonClick => {
+            if handle == save_handle {
+                let mut settings = load_user_settings(&base_dir);
+                for (module, check) in mods.iter().zip(&checks) {
+                    let enabled = check.check_state() == nwg::CheckBoxState::Checked;
+                    settings
+                        .optional_mods
+                        .insert(module.metafile.clone(), enabled);
+                }
+                let _ = save_user(&base_dir, &settings);
+
+                nwg::modal_info_message(win_handle, "提示", "可选模组已保存，下次启动时生效");
+                nwg::stop_thread_dispatch();
+            } else if handle == cancel_handle {
+                nwg::stop_thread_dispatch();
+            }
+        }
+        nwg::Event::OnWindowClose if handle == win_handle => {
+            nwg::stop_thread_dispatch();
+        }
+        _ => {}
+    });
+
+    nwg::dispatch_thread_events();
+    nwg::unbind_event_handler(&handler);
+}
+
+/// 设置窗口：更新通道 + UDP 代理开关 + 灰度发布开关 + 服务器来源。
+/// 返回玩家是否点击了「修复安装...」（窗口关闭后由主窗口执行修复）。
+/// 添加整合包窗口。返回添加的整合包，取消时返回 None。
+fn show_add_profile_dialog(parent: &nwg::Window, root_dir: &std::path::Path) -> Option<Profile> {
+    let mut window = Default::default();
+    nwg::Window::builder()
+        .title("添加整合包")
+        .size((340, 170))
+        .center(true)
+        .flags(nwg::WindowFlags::WINDOW | nwg::WindowFlags::VISIBLE)
+        .parent(Some(parent))
+        .build(&mut window)
+        .expect("创建添加整合包窗口失败");
+
+    let mut name_label = Default::default();
+    nwg::Label::builder()
+        .text("名称:")
+        .size((80, 22))
+        .position((20, 22))
+        .parent(&window)
+        .build(&mut name_label)
+        .expect("label");
+
+    let mut name_input = Default::default();
+    nwg::TextInput::builder()
+        .placeholder_text(Some("如：创造服"))
+        .size((215, 25))
+        .position((105, 20))
+        .parent(&window)
+        .build(&mut name_input)
+        .expect("input");
+
+    let mut url_label = Default::default();
+    nwg::Label::builder()
+        .text("server.json:")
+        .size((80, 22))
+        .position((20, 62))
+        .parent(&window)
+        .build(&mut url_label)
+        .expect("label");
+
+    let mut url_input = Default::default();
+    nwg::TextInput::builder()
+        .placeholder_text(Some("https://... 或本地目录"))
+        .size((215, 25))
+        .position((105, 60))
+        .parent(&window)
+        .build(&mut url_input)
+        .expect("input");
+
+    let mut add_btn = Default::default();
+    nwg::Button::builder()
+        .text("添加")
+        .size((95, 35))
+        .position((120, 105))
+        .parent(&window)
+        .build(&mut add_btn)
+        .expect("button");
+
+    let mut cancel_btn = Default::default();
+    nwg::Button::builder()
+        .text("取消")
+        .size((95, 35))
+        .position((225, 105))
+        .parent(&window)
+        .build(&mut cancel_btn)
+        .expect("button");
+
+    let win_handle = window.handle;
+    let add_handle = add_btn.handle;
+    let cancel_handle = cancel_btn.handle;
+    let added: std::rc::Rc<RefCell<Option<Profile>>> = Default::default();
+    let added_slot = std::rc::Rc::clone(&added);
+    let root_dir = root_dir.to_path_buf();
+
+    let handler = nwg::full_bind_event_handler(&win_handle, move |evt, _, handle| match evt {
+        nwg::Event::OnButtonClick if handle == add_handle => {
+            match profiles::add(&root_dir, &name_input.text(), &url_input.text()) {
+                Ok(profile) => {
+                    *added_slot.borrow_mut() = Some(profile);
+                    nwg::stop_thread_dispatch();
+                }
+                Err(e) => {
+                    nwg::modal_error_message(win_handle, "添加整合包失败", &format!("{e:#}"));
+                }
+            }
+        }
+        nwg::Event::OnButtonClick if handle == cancel_handle => nwg::stop_thread_dispatch(),
+        nwg::Event::OnWindowClose if handle == win_handle => nwg::stop_thread_dispatch(),
+        _ => {}
+    });
+
+    nwg::dispatch_thread_events();
+    nwg::unbind_event_handler(&handler);
+    added.take()
+}
+
+/// 设置窗口。更新器通道保存在安装基准目录 `root_dir`，其余设置保存在当前整合包的实例目录。
+fn show_settings_dialog(
+    parent: &nwg::Window,
+    root_dir: &std::path::Path,
+    base_dir: &std::path::Path,
+) -> bool {
     use crate::config::{
-        ChannelConfig, UpdateChannel, UserSettings,
-        load_user_settings, save_channel_config, save_user_settings,
+        ChannelConfig, UpdateChannel, UserSettings, load_channel_config, load_user_settings,
     };
+    use crate::settings::{save_channel, save_user};
 
-    let channel_cfg_path = base_dir.join(config::CHANNEL_CONFIG_FILE);
-    let current_channel = std::fs::read_to_string(&channel_cfg_path)
-        .ok()
-        .and_then(|s| serde_json::from_str::<ChannelConfig>(&s).ok())
-        .unwrap_or_default()
-        .channel;
+    let current_channel = load_channel_config(root_dir).channel;
     let current_settings = load_user_settings(base_dir);
 
     let mut window = Default::default();
     nwg::Window::builder()
         .title("设置")
-        .size((340, 190))
+        .size((340, 385))
         .center(true)
         .flags(nwg::WindowFlags::WINDOW | nwg::WindowFlags::VISIBLE)
         .parent(Some(parent))
@@ -638,12 +1013,166 @@ fn show_settings_dialog(parent: &nwg::Window, base_dir: &std::path::Path) {
         .build(&mut udp_check)
         .expect("checkbox");
 
+    // 忽略灰度发布（测试者总是获取最新构建）
+    let mut rollout_check = Default::default();
+    nwg::CheckBox::builder()
+        .text("总是更新到最新构建（忽略灰度发布）")
+        .size((300, 25))
+        .position((20, 95))
+        .check_state(if current_settings.ignore_rollout {
+            nwg::CheckBoxState::Checked
+        } else {
+            nwg::CheckBoxState::Unchecked
+        })
+        .parent(&window)
+        .build(&mut rollout_check)
+        .expect("checkbox");
+
+    // 服务器来源（留空 = 公共服务器；本地目录或局域网 http:// 地址）
+    let mut source_label = Default::default();
+    nwg::Label::builder()
+        .text("服务器来源:")
+        .size((80, 22))
+        .position((20, 137))
+        .parent(&window)
+        .build(&mut source_label)
+        .expect("label");
+
+    let mut source_input = Default::default();
+    nwg::TextInput::builder()
+        .text(current_settings.server_source.as_arg())
+        .placeholder_text(Some("留空使用公共服务器"))
+        .size((215, 25))
+        .position((105, 135))
+        .parent(&window)
+        .build(&mut source_input)
+        .expect("input");
+
+    // 画质预设（第一项为按硬件自动选择）
+    let preset_list = presets::load(base_dir);
+    let mut preset_names = vec!["自动（按硬件）".to_string()];
+    preset_names.extend(preset_list.iter().map(|p| p.label.clone()));
+    let current_preset_index = current_settings
+        .preset
+        .as_deref()
+        .and_then(|id| preset_list.iter().position(|p| p.id == id))
+        .map_or(0, |i| i + 1);
+
+    let mut preset_label = Default::default();
+    nwg::Label::builder()
+        .text("画质预设:")
+        .size((80, 22))
+        .position((20, 177))
+        .parent(&window)
+        .build(&mut preset_label)
+        .expect("label");
+
+    let mut preset_combo = Default::default();
+    nwg::ComboBox::builder()
+        .size((215, 25))
+        .position((105, 175))
+        .collection(preset_names)
+        .selected_index(Some(current_preset_index))
+        .parent(&window)
+        .build(&mut preset_combo)
+        .expect("combo");
+
+    // 整合包通道（与更新器通道相互独立，第一项为默认的 stable）
+    let pack_channel_cfg = config::load_pack_channel(base_dir);
+    let declared_channels = crate::version::cached_remote_version(base_dir)
+        .map(|remote| remote.pack_channels)
+        .unwrap_or_default();
+    let mut pack_channel_ids = vec![config::DEFAULT_PACK_CHANNEL.to_string()];
+    pack_channel_ids.extend(
+        declared_channels
+            .keys()
+            .filter(|id| id.as_str() != config::DEFAULT_PACK_CHANNEL)
+            .cloned(),
+    );
+    if !pack_channel_ids.iter().any(|id| id == pack_channel_cfg.name()) {
+        // 订阅的通道已不在服务器列表中，仍显示出来以便切回 stable
+        pack_channel_ids.push(pack_channel_cfg.name().to_string());
+    }
+    let pack_channel_names: Vec<String> = pack_channel_ids
+        .iter()
+        .map(|id| {
+            match declared_channels.get(id).and_then(|c| c.label.as_deref()) {
+                Some(label) => format!("{label} ({id})"),
+                None => id.clone(),
+            }
+        })
+        .collect();
+    let current_pack_channel_index = pack_channel_ids
+        .iter()
+        .position(|id| id == pack_channel_cfg.name())
+        .unwrap_or(0);
+
+    let mut pack_channel_label = Default::default();
+    nwg::Label::builder()
+        .text("整合包通道:")
+        .size((80, 22))
+        .position((20, 217))
+        .parent(&window)
+        .build(&mut pack_channel_label)
+        .expect("label");
+
+    let mut pack_channel_combo = Default::default();
+    nwg::ComboBox::builder()
+        .size((215, 25))
+        .position((105, 215))
+        .collection(pack_channel_names)
+        .selected_index(Some(current_pack_channel_index))
+        .parent(&window)
+        .build(&mut pack_channel_combo)
+        .expect("combo");
+
+    // 可选模组按钮
+    let mut optional_btn = Default::default();
+    nwg::Button::builder()
+        .text("可选模组...")
+        .size((95, 35))
+        .position((20, 260))
+        .parent(&window)
+        .build(&mut optional_btn)
+        .expect("button");
+
+    // 修复安装按钮
+    let mut repair_btn = Default::default();
+    nwg::Button::builder()
+        .text("修复安装...")
+        .size((95, 35))
+        .position((120, 260))
+        .parent(&window)
+        .build(&mut repair_btn)
+        .expect("button");
+
+    // 更新记录按钮
+    let mut history_btn = Default::default();
+    nwg::Button::builder()
+        .text("更新记录...")
+        .size((95, 35))
+        .position((225, 260))
+        .parent(&window)
+        .build(&mut history_btn)
+        .expect("button");
+
+    // 回退整合包按钮（已固定时改为取消固定）
+    let pinned = packs::pinned_record(base_dir);
+    let mut rollback_btn = Default::default();
+    nwg::Button::builder()
+        .text(if pinned.is_some() { "取消固定" } else { "回退整合包..." })
+        .size((95, 35))
+        .position((20, 305))
+        .parent(&window)
+        .build(&mut rollback_btn)
+        .expect("button");
+
     // 保存按钮
     let mut save_btn = Default::default();
     nwg::Button::builder()
         .text("保存")
-        .size((100, 35))
-        .position((80, 110))
+        .size((95, 35))
+        .position((120, 305))
         .parent(&window)
         .build(&mut save_btn)
         .expect("button");
@@ -652,8 +1181,8 @@ fn show_settings_dialog(parent: &nwg::Window, base_dir: &std::path::Path) {
     let mut cancel_btn = Default::default();
     nwg::Button::builder()
         .text("取消")
-        .size((100, 35))
-        .position((200, 110))
+        .size((95, 35))
+        .position((225, 305))
         .parent(&window)
         .build(&mut cancel_btn)
         .expect("button");
@@ -661,11 +1190,22 @@ fn show_settings_dialog(parent: &nwg::Window, base_dir: &std::path::Path) {
     let win_handle = window.handle;
     let save_handle = save_btn.handle;
     let cancel_handle = cancel_btn.handle;
+    let optional_handle = optional_btn.handle;
+    let repair_handle = repair_btn.handle;
+    let history_handle = history_btn.handle;
+    let rollback_handle = rollback_btn.handle;
+    let repair_requested = std::rc::Rc::new(Cell::new(false));
+    let repair_flag = std::rc::Rc::clone(&repair_requested);
     let base_dir = base_dir.to_path_buf();
+    let root_dir = root_dir.to_path_buf();
 
     // 用 RefCell 包装控件以便在闭包中读取值
     let channel_combo = std::cell::RefCell::new(channel_combo);
     let udp_check = std::cell::RefCell::new(udp_check);
+    let rollout_check = std::cell::RefCell::new(rollout_check);
+    let source_input = std::cell::RefCell::new(source_input);
+    let preset_combo = std::cell::RefCell::new(preset_combo);
+    let pack_channel_combo = std::cell::RefCell::new(pack_channel_combo);
 
     let handler = nwg::full_bind_event_handler(&win_handle, move |evt, _, handle| match evt {
         nwg::Event::OnButtonClick => {
@@ -675,17 +1215,99 @@ fn show_settings_dialog(parent: &nwg::Window, base_dir: &std::path::Path) {
                 } else {
                     UpdateChannel::Stable
                 };
-                let _ = save_channel_config(&base_dir, &ChannelConfig { channel });
+                let _ = save_channel(&root_dir, &ChannelConfig { channel });
 
                 let udp = channel_combo.borrow(); // just to keep the borrow checker happy
                 drop(udp);
                 let udp = udp_check.borrow().check_state() == nwg::CheckBoxState::Checked;
-                let _ = save_user_settings(&base_dir, &UserSettings { proxy_udp: udp });
+                let ignore_rollout =
+                    rollout_check.borrow().check_state() == nwg::CheckBoxState::Checked;
+                let server_source = source::ServerSource::from_arg(&source_input.borrow().text());
+                let preset_index = preset_combo.borrow().selection().unwrap_or(0);
+                let preset = preset_index
+                    .checked_sub(1)
+                    .and_then(|i| preset_list.get(i))
+                    .map(|p| p.id.clone());
+                // 重新读取，保留可选模组窗口中刚保存的选择
+                let mut settings = load_user_settings(&base_dir);
+                if preset_index != current_preset_index {
+                    // 换了预设：该预设覆盖的模组改回跟随预设
+                    let active = presets::active(&preset_list, preset.as_deref());
+                    if let Some(active) = active {
+                        settings
+                            .optional_mods
+                            .retain(|key, _| !active.optional_mods.contains_key(key));
+                    }
+                }
+                let _ = save_user(
+                    &base_dir,
+                    &UserSettings {
+                        proxy_udp: udp,
+                        ignore_rollout,
+                        server_source,
+                        preset,
+                        ..settings
+                    },
+                );
 
-                nwg::modal_info_message(win_handle, "提示", "设置已保存，下次启动时生效");
+                let pack_channel_index = pack_channel_combo
+                    .borrow()
+                    .selection()
+                    .unwrap_or(current_pack_channel_index);
+                let pack_channel_changed = pack_channel_index != current_pack_channel_index;
+                if pack_channel_changed {
+                    let channel = pack_channel_ids
+                        .get(pack_channel_index)
+                        .filter(|id| id.as_str() != config::DEFAULT_PACK_CHANNEL)
+                        .cloned();
+                    let _ = config::save_pack_channel(
+                        &base_dir,
+                        &config::PackChannelConfig {
+                            channel,
+                            ..config::load_pack_channel(&base_dir)
+                        },
+                    );
+                }
+
+                nwg::modal_info_message(
+                    win_handle,
+                    "提示",
+                    if pack_channel_changed {
+                        "设置已保存，下次启动时生效。\n\
+                         切换整合包通道后会删除当前通道的模组并重新同步，自行添加的模组不受影响。"
+                    } else {
+                        "设置已保存，下次启动时生效"
+                    },
+                );
                 nwg::stop_thread_dispatch();
             } else if handle == cancel_handle {
                 nwg::stop_thread_dispatch();
+            } else if handle == optional_handle {
+                show_optional_mods_dialog(win_handle, &base_dir);
+            } else if handle == history_handle {
+                show_text_dialog(
+                    win_handle,
+                    "更新记录",
+                    "整合包历次更新内容（最新的在前）：",
+                    &changelog::render_history(&base_dir),
+                );
+            } else if handle == rollback_handle {
+                on_pack_rollback(win_handle, &base_dir, pinned.as_ref());
+            } else if handle == repair_handle {
+                let confirmed = nwg::modal_message(
+                    win_handle,
+                    &nwg::MessageParams {
+                        title: "修复安装",
+                        content: "将校验所有组件和整合包文件，并重新下载损坏或缺失的文件。\n\
+                                  需要联网，可能需要几分钟。是否继续？",
+                        buttons: nwg::MessageButtons::YesNo,
+                        icons: nwg::MessageIcons::Question,
+                    },
+                ) == nwg::MessageChoice::Yes;
+                if confirmed {
+                    repair_flag.set(true);
+                    nwg::stop_thread_dispatch();
+                }
             }
         }
         nwg::Event::OnWindowClose => {
@@ -698,4 +1320,5 @@ fn show_settings_dialog(parent: &nwg::Window, base_dir: &std::path::Path) {
 
     nwg::dispatch_thread_events();
     nwg::unbind_event_handler(&handler);
+    repair_requested.get()
 }
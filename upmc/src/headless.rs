@@ -0,0 +1,83 @@
+// ============================================================
+// headless.rs — 终端前端（非 Windows 平台）
+// ============================================================
+// GUI 基于 Win32，只有 Windows 版。Linux / macOS 上直接在终端中
+// 运行同一套更新流程，进度逐行输出到标准输出，结束后打印更新日志、
+// 模组冲突和违规模组。
+//
+// 不启动 PCL2（仅 Windows 可用），玩家用自己的启动器打开实例目录即可。
+//
+// 收到 Ctrl+C 或终止信号（SIGTERM / SIGHUP）时先结束正在运行的
+// packwiz-installer 进程树再退出，不留下继续写 .minecraft 的 Java 进程。
+// ============================================================
+
+use std::path::Path;
+
+use crate::cli;
+use crate::config::ChannelConfig;
+use crate::mod_policy::ScannedMod;
+use crate::packwiz;
+use crate::update::{self, Progress, UpdateResult};
+
+/// 运行一次完整更新，返回进程退出码。
+pub fn run(instance_dir: &Path, channel_config: &ChannelConfig) -> i32 {
+    println!("更新通道: {}", channel_config.channel);
+    println!("实例目录: {}", instance_dir.display());
+
+    if let Err(e) = ctrlc::set_handler(|| {
+        eprintln!("\n已中断，正在停止...");
+        packwiz::cancel_running();
+        std::process::exit(130);
+    }) {
+        eprintln!("警告: 无法处理中断信号: {e}");
+    }
+
+    let result = update::run_update(instance_dir, channel_config, &|progress: Progress| {
+        println!("[{:>3}%] {}", progress.percent, progress.message);
+    });
+
+    match result {
+        Ok(UpdateResult::Success {
+            proxy_running,
+            warnings,
+            violations,
+            changelog,
+        }) => {
+            println!("[完成] 更新成功");
+            if proxy_running {
+                println!("Xray 代理已在后台运行");
+            }
+            if let Some(log) = changelog {
+                println!("\n本次整合包更新：\n{}", log.render());
+            }
+            if !warnings.is_empty() {
+                println!("\n以下自行添加的模组与整合包重复，可能导致游戏崩溃：");
+                for warning in &warnings {
+                    println!("  {warning}");
+                }
+            }
+            print_violations(&violations);
+            0
+        }
+        Ok(UpdateResult::Offline { violations }) => {
+            println!("[完成] 离线模式");
+            print_violations(&violations);
+            0
+        }
+        Ok(UpdateResult::SelfUpdateRestarting) => 0,
+        Err(e) => {
+            cli::print_error(&e);
+            1
+        }
+    }
+}
+
+fn print_violations(violations: &[ScannedMod]) {
+    if violations.is_empty() {
+        return;
+    }
+    println!("\n以下模组不被服务器允许：");
+    for scanned in violations {
+        println!("  {}", scanned.describe());
+    }
+}
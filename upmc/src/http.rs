@@ -0,0 +1,547 @@
+// ============================================================
+// http.rs — HTTP 客户端抽象
+// ============================================================
+// 所有联网请求都经过 http::get，由它统一处理：
+//   - 错误状态码 → error::HttpStatus（保留 Retry-After，供 retry.rs 使用）
+//   - 主机熔断：熔断期间直接失败，并记录每次请求的成败
+//
+// 实际发送请求的是 HttpClient trait 的实现：
+//   - 正式运行：UreqClient（ureq，小请求 / 大文件下载使用不同超时）
+//   - 测试：fake::FakeServer（进程内按 URL 返回预置内容，无需联网），
+//     通过 fake::FakeServer::install 替换当前线程的客户端
+// ============================================================
+
+use anyhow::{Context, Result};
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config;
+use crate::error::{self, ErrorCode};
+use crate::retry;
+use crate::settings;
+
+/// 请求类别，决定超时时间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKind {
+    /// 清单类小请求（server.json、version.json 等）
+    Api,
+    /// 大文件下载
+    Download,
+}
+
+/// 发送 GET 请求的客户端
+pub trait HttpClient {
+    /// 发送请求并返回响应（任何状态码都作为响应返回，只有传输失败才返回错误）
+    fn get(&self, url: &str, headers: &[(&str, &str)], kind: RequestKind) -> Result<Response>;
+}
+
+/// HTTP 响应
+pub struct Response {
+    pub status: u16,
+    headers: Vec<(String, String)>,
+    content_length: Option<u64>,
+    body: Box<dyn Read + Send>,
+}
+
+impl Response {
+    pub fn new(
+        status: u16,
+        headers: Vec<(String, String)>,
+        content_length: Option<u64>,
+        body: Box<dyn Read + Send>,
+    ) -> Self {
+        Response {
+            status,
+            headers,
+            content_length,
+            body,
+        }
+    }
+
+    /// 响应头（名称不区分大小写）
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// 响应体长度（未知时为 None）
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    /// 以流的方式读取响应体（大文件下载）
+    pub fn into_reader(self) -> Box<dyn Read + Send> {
+        self.body
+    }
+
+    /// 读取整个响应体，超过 `limit` 字节时报错
+    pub fn read_to_vec(self, limit: u64) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.body
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut data)
+            .context("读取响应失败")?;
+        anyhow::ensure!(data.len() as u64 <= limit, "响应超过 {limit} 字节");
+        Ok(data)
+    }
+
+    /// 读取整个响应体为文本（清单类响应，上限 10 MB）
+    pub fn read_to_string(self) -> Result<String> {
+        let data = self.read_to_vec(MAX_TEXT_SIZE)?;
+        String::from_utf8(data).context("响应不是有效的 UTF-8 文本")
+    }
+}
+
+/// 文本响应的大小上限
+const MAX_TEXT_SIZE: u64 = 10 * 1024 * 1024;
+
+/// 发送 GET 请求。状态码 ≥ 400 时返回 `error::HttpStatus` 错误。
+pub fn get(url: &str, headers: &[(&str, &str)], kind: RequestKind) -> Result<Response> {
+    if let Some(remaining) = retry::circuit_remaining(url) {
+        return Err(error::new(
+            ErrorCode::Network,
+            format!(
+                "{url} 所在主机连续请求失败，暂停请求 {} 秒",
+                remaining.as_secs().max(1)
+            ),
+        ));
+    }
+
+    let result = with_client(|client| client.get(url, headers, kind)).and_then(|response| {
+        if response.status < 400 {
+            return Ok(response);
+        }
+        Err(anyhow::Error::new(error::HttpStatus {
+            status: response.status,
+            retry_after: response.header("Retry-After").and_then(parse_retry_after),
+        }))
+    });
+
+    // 只有主机本身的问题（网络 / 5xx）才计入熔断，404 之类不算
+    let host_failed = result.as_ref().err().and_then(error::classify) == Some(ErrorCode::Network);
+    retry::record_host(url, !host_failed);
+    result
+}
+
+/// 解析 Retry-After（只支持秒数形式，HTTP 日期形式忽略，回到普通退避）
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+/// 基于 ureq 的正式客户端
+struct UreqClient {
+    /// 创建时使用的 (API 超时, 下载超时) 秒数
+    timeouts: (u64, u64),
+    api: ureq::Agent,
+    download: ureq::Agent,
+}
+
+impl UreqClient {
+    fn new(timeouts: (u64, u64)) -> Self {
+        Self {
+            timeouts,
+            api: config::http_agent(timeouts.0),
+            download: config::download_agent(timeouts.1),
+        }
+    }
+}
+
+impl HttpClient for UreqClient {
+    fn get(&self, url: &str, headers: &[(&str, &str)], kind: RequestKind) -> Result<Response> {
+        let agent = match kind {
+            RequestKind::Api => &self.api,
+            RequestKind::Download => &self.download,
+        };
+        let mut request = agent.get(url);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let response = request
+            .config()
+            .http_status_as_error(false)
+            .build()
+            .call()
+            .map_err(anyhow::Error::new)?;
+
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((name.as_str().to_string(), value.to_str().ok()?.to_string()))
+            })
+            .collect();
+        let status = response.status().as_u16();
+        let content_length = response.body().content_length();
+        let body = Box::new(response.into_body().into_reader());
+        Ok(Response::new(status, headers, content_length, body))
+    }
+}
+
+/// 复用的正式客户端。超时取自当前设置，设置变化（切换整合包、保存设置）后
+/// 下一次请求重新创建客户端。
+struct ClientCache(Mutex<Option<Arc<UreqClient>>>);
+
+impl ClientCache {
+    const fn new() -> Self {
+        Self(Mutex::new(None))
+    }
+
+    fn get(&self, timeouts: (u64, u64)) -> Arc<UreqClient> {
+        let mut cached = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match cached.as_ref() {
+            Some(client) if client.timeouts == timeouts => client.clone(),
+            _ => cached.insert(Arc::new(UreqClient::new(timeouts))).clone(),
+        }
+    }
+}
+
+static CLIENT: ClientCache = ClientCache::new();
+
+fn default_client() -> Arc<UreqClient> {
+    let settings = settings::current();
+    CLIENT.get((settings.http_timeout_secs, settings.download_timeout_secs))
+}
+
+#[cfg(not(test))]
+fn with_client<T>(f: impl FnOnce(&dyn HttpClient) -> T) -> T {
+    f(&*default_client())
+}
+
+#[cfg(test)]
+fn with_client<T>(f: impl FnOnce(&dyn HttpClient) -> T) -> T {
+    match fake::current() {
+        Some(server) => f(&*server),
+        None => f(&*default_client()),
+    }
+}
+
+/// 测试用的进程内假服务器
+#[cfg(test)]
+pub(crate) mod fake {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+
+    thread_local! {
+        static CURRENT: RefCell<Option<Rc<FakeServer>>> = const { RefCell::new(None) };
+    }
+
+    pub(super) fn current() -> Option<Rc<FakeServer>> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// 一条预置响应
+    #[derive(Clone)]
+    struct Route {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    /// 按 URL 返回预置内容；未配置的 URL 返回 404。
+    #[derive(Default)]
+    pub struct FakeServer {
+        routes: RefCell<BTreeMap<String, Route>>,
+        requests: RefCell<Vec<Recorded>>,
+    }
+
+    /// 收到的一次请求
+    struct Recorded {
+        url: String,
+        headers: Vec<(String, String)>,
+    }
+
+    /// 安装期间当前线程的请求都发往假服务器，离开作用域后恢复
+    pub struct Installed {
+        pub server: Rc<FakeServer>,
+        previous: Option<Rc<FakeServer>>,
+    }
+
+    impl Drop for Installed {
+        fn drop(&mut self) {
+            let previous = self.previous.take();
+            CURRENT.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+
+    impl FakeServer {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// 200 响应
+        pub fn route(self, url: &str, body: impl Into<Vec<u8>>) -> Self {
+            self.route_with(url, 200, &[], body)
+        }
+
+        /// 指定状态码和响应头
+        pub fn route_with(
+            self,
+            url: &str,
+            status: u16,
+            headers: &[(&str, &str)],
+            body: impl Into<Vec<u8>>,
+        ) -> Self {
+            self.set(url, status, headers, body);
+            self
+        }
+
+        /// 修改已安装服务器上的响应
+        pub fn set(
+            &self,
+            url: &str,
+            status: u16,
+            headers: &[(&str, &str)],
+            body: impl Into<Vec<u8>>,
+        ) {
+            let route = Route {
+                status,
+                headers: headers
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                body: body.into(),
+            };
+            self.routes.borrow_mut().insert(url.to_string(), route);
+        }
+
+        /// 替换当前线程的 HTTP 客户端
+        pub fn install(self) -> Installed {
+            let server = Rc::new(self);
+            let previous = CURRENT.with(|current| current.borrow_mut().replace(server.clone()));
+            Installed { server, previous }
+        }
+
+        /// 收到的请求 URL（按顺序）
+        pub fn requested_urls(&self) -> Vec<String> {
+            self.requests
+                .borrow()
+                .iter()
+                .map(|r| r.url.clone())
+                .collect()
+        }
+
+        /// 发布一个整合包版本：pack.toml、index.toml，每个模组一个 .pw.toml 和 jar。
+        /// `mods` 为 (模组 id, jar 内容)。
+        pub fn publish_pack(&self, version: &str, mods: &[(&str, &[u8])]) {
+            let sha256 = |data: &[u8]| crate::pack_index::hash_bytes("sha256", data).unwrap();
+            let base = PACK_URL.trim_end_matches("pack.toml");
+            let mut index = String::from("hash-format = \"sha256\"\n");
+            for (id, jar) in mods {
+                let jar_url = format!("{base}files/{id}.jar");
+                let meta = format!(
+                    "name = \"{id}\"\nfilename = \"{id}.jar\"\nside = \"both\"\n\n\
+                     [download]\nurl = \"{jar_url}\"\nhash-format = \"sha256\"\nhash = \"{}\"\n",
+                    sha256(jar)
+                );
+                index.push_str(&format!(
+                    "\n[[files]]\nfile = \"mods/{id}.pw.toml\"\nhash = \"{}\"\nmetafile = true\n",
+                    sha256(meta.as_bytes())
+                ));
+                self.set(&format!("{base}mods/{id}.pw.toml"), 200, &[], meta);
+                self.set(&jar_url, 200, &[], *jar);
+            }
+            let pack_toml = format!(
+                "name = \"CJC\"\nversion = \"{version}\"\npack-format = \"packwiz:1.1.0\"\n\n\
+                 [index]\nfile = \"index.toml\"\nhash-format = \"sha256\"\nhash = \"{}\"\n\n\
+                 [versions]\nminecraft = \"{MC_VERSION}\"\nfabric = \"{FABRIC_VERSION}\"\n",
+                sha256(index.as_bytes())
+            );
+            self.set(&format!("{base}index.toml"), 200, &[], index);
+            self.set(PACK_URL, 200, &[], pack_toml);
+        }
+
+        /// 某个 URL 最近一次请求携带的请求头
+        pub fn last_request_header(&self, url: &str, name: &str) -> Option<String> {
+            self.requests
+                .borrow()
+                .iter()
+                .rev()
+                .find(|r| r.url == url)
+                .and_then(|r| {
+                    r.headers
+                        .iter()
+                        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                        .map(|(_, v)| v.clone())
+                })
+        }
+    }
+
+    impl HttpClient for FakeServer {
+        fn get(&self, url: &str, headers: &[(&str, &str)], _kind: RequestKind) -> Result<Response> {
+            self.requests.borrow_mut().push(Recorded {
+                url: url.to_string(),
+                headers: headers
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            });
+            let route = self.routes.borrow().get(url).cloned().unwrap_or(Route {
+                status: 404,
+                headers: Vec::new(),
+                body: Vec::new(),
+            });
+            let length = route.body.len() as u64;
+            Ok(Response::new(
+                route.status,
+                route.headers,
+                Some(length),
+                Box::new(std::io::Cursor::new(route.body)),
+            ))
+        }
+    }
+
+    /// 测试整合包的 pack.toml 地址
+    pub const PACK_URL: &str = "https://update.mc.chenjicheng.cn/pack.toml";
+    /// 测试用的 Minecraft / Fabric 版本
+    pub const MC_VERSION: &str = "1.21.1";
+    pub const FABRIC_VERSION: &str = "0.16.5";
+    /// 版本清单中 MC_VERSION 的 version JSON 地址
+    pub const VERSION_JSON_URL: &str = "https://piston-meta.mojang.com/v1/packages/abc/1.21.1.json";
+
+    /// 测试用的 Minecraft 客户端 jar 地址
+    pub const CLIENT_JAR_URL: &str = "https://piston-data.mojang.com/v1/objects/abc/client.jar";
+
+    /// 完整的一套远程数据：server.json（含 PCL2 和工具 jar 的下载项）、
+    /// 整合包（一个模组）、Mojang 版本清单和 version JSON、
+    /// Xray 的 GitHub Release JSON、更新器 version.json。
+    pub fn fixture() -> FakeServer {
+        let sha256 = |data: &[u8]| crate::pack_index::hash_bytes("sha256", data).unwrap();
+        let tools = "https://update.mc.chenjicheng.cn/tools";
+        let pcl2: &[u8] = b"MZ fake pcl2";
+        let tool_jar: &[u8] = b"PK fake jar";
+        let server_json = serde_json::json!({
+            "pack_url": PACK_URL,
+            "mirrors": { "server_json": [config::REMOTE_SERVER_JSON_URL] },
+            "downloads": {
+                "pcl2_url": format!("{tools}/pcl2.exe"),
+                "pcl2_sha256": sha256(pcl2),
+                "packwiz_bootstrap_url": format!("{tools}/packwiz-installer-bootstrap.jar"),
+                "packwiz_bootstrap_sha256": sha256(tool_jar),
+                "fabric_installer_url": format!("{tools}/fabric-installer.jar"),
+                "fabric_installer_sha256": sha256(tool_jar),
+            },
+        });
+        let manifest = serde_json::json!({
+            "latest": { "release": MC_VERSION },
+            "versions": [
+                { "id": MC_VERSION, "url": VERSION_JSON_URL, "sha1": "0123456789abcdef0123456789abcdef01234567" },
+            ],
+        });
+        let version_json = serde_json::json!({
+            "id": MC_VERSION,
+            "downloads": { "client": { "url": CLIENT_JAR_URL } },
+        });
+        let release = serde_json::json!({
+            "tag_name": "v25.1.1",
+            "assets": [{
+                "name": "Xray-windows-64.zip",
+                "browser_download_url": "https://github.com/XTLS/Xray-core/releases/download/v25.1.1/Xray-windows-64.zip",
+            }],
+        });
+        let updater = serde_json::json!({
+            "download_url": "https://gh.cjcx.org/https://github.com/chenjicheng/upmc/releases/download/v9.9.9/upmc.exe",
+            "build_id": "fixture",
+        });
+
+        let server = FakeServer::new()
+            .route(config::REMOTE_SERVER_JSON_URL, server_json.to_string())
+            .route(&format!("{tools}/pcl2.exe"), pcl2)
+            .route(
+                &format!("{tools}/packwiz-installer-bootstrap.jar"),
+                tool_jar,
+            )
+            .route(&format!("{tools}/fabric-installer.jar"), tool_jar)
+            .route(config::VERSION_MANIFEST_URL, manifest.to_string())
+            .route(VERSION_JSON_URL, version_json.to_string())
+            .route(CLIENT_JAR_URL, "PK fake client")
+            .route(
+                &format!(
+                    "https://api.github.com/repos/{}/releases/latest",
+                    config::XRAY_GITHUB_REPO
+                ),
+                release.to_string(),
+            )
+            .route(config::UPDATER_VERSION_URL, updater.to_string());
+        server.publish_pack("2026.10", &[("sodium", b"PK sodium 0.6")]);
+        server
+    }
+
+    /// 每个测试独立的临时目录
+    pub fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("upmc_http_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_is_rebuilt_when_timeouts_change() {
+        let cache = ClientCache::new();
+        let first = cache.get((30, 600));
+        assert!(Arc::ptr_eq(&first, &cache.get((30, 600))));
+
+        let changed = cache.get((5, 600));
+        assert_eq!(changed.timeouts, (5, 600));
+        assert!(!Arc::ptr_eq(&first, &changed));
+    }
+
+    #[test]
+    fn error_status_keeps_retry_after() {
+        let _fake = fake::FakeServer::new()
+            .route_with(
+                "https://a.example.com/x",
+                503,
+                &[("Retry-After", " 12 ")],
+                "",
+            )
+            .install();
+        let err = get("https://a.example.com/x", &[], RequestKind::Api)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error::http_status(&err),
+            Some((503, Some(Duration::from_secs(12))))
+        );
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+    }
+
+    #[test]
+    fn unknown_url_is_not_found() {
+        let fake = fake::FakeServer::new()
+            .route("https://b.example.com/ok", "hello")
+            .install();
+        assert_eq!(
+            get("https://b.example.com/ok", &[], RequestKind::Api)
+                .unwrap()
+                .read_to_string()
+                .unwrap(),
+            "hello"
+        );
+        let err = get("https://b.example.com/missing", &[], RequestKind::Api)
+            .err()
+            .unwrap();
+        assert_eq!(error::http_status(&err), Some((404, None)));
+        assert_eq!(fake.server.requested_urls().len(), 2);
+    }
+
+    #[test]
+    fn read_to_vec_enforces_limit() {
+        let response = Response::new(
+            200,
+            Vec::new(),
+            None,
+            Box::new(std::io::Cursor::new(vec![0u8; 11])),
+        );
+        assert!(response.read_to_vec(10).is_err());
+    }
+}
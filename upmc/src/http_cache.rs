@@ -0,0 +1,188 @@
+// ============================================================
+// http_cache.rs — 清单类请求的 HTTP 条件缓存
+// ============================================================
+// server.json、pack.toml、更新器 version.json、Mojang 版本清单、
+// Xray Release JSON 每次启动都要请求一次。这里把响应体和校验器
+// （ETag / Last-Modified）保存在 updater/cache/ 下：
+//   - 再次请求时携带 If-None-Match / If-Modified-Since
+//   - 服务器返回 304 时直接使用缓存内容
+//   - 网络不可用时可读取上次成功的内容作为离线兜底
+//
+// 只用于小体积文本，大文件下载不经过这里。
+// ============================================================
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+use crate::http::{self, RequestKind};
+use crate::state;
+
+/// 单个 URL 的缓存条目（updater/cache/<sha256(url)>.json）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    url: String,
+    #[serde(default)]
+    etag: Option<String>,
+    #[serde(default)]
+    last_modified: Option<String>,
+    body: String,
+}
+
+fn entry_name(url: &str) -> String {
+    format!("{:x}.json", Sha256::digest(url.as_bytes()))
+}
+
+fn load_entry(base_dir: &Path, url: &str) -> Option<CacheEntry> {
+    let entry: CacheEntry = state::HTTP_CACHE.read_json_in(base_dir, &entry_name(url))?;
+    // 哈希碰撞或手动改动时不使用
+    (entry.url == url).then_some(entry)
+}
+
+fn save_entry(base_dir: &Path, entry: &CacheEntry) -> Result<()> {
+    state::HTTP_CACHE
+        .write_json_in(base_dir, &entry_name(&entry.url), entry)
+        .context("写入缓存失败")
+}
+
+/// 读取某个 URL 上次成功获取的内容（不发请求）。
+pub fn cached_text(base_dir: &Path, url: &str) -> Option<String> {
+    load_entry(base_dir, url).map(|entry| entry.body)
+}
+
+/// 直接写入一条不带校验器的缓存（如从离线安装包导入的清单快照）。
+///
+/// 下次联网请求时不会发送条件头，服务器会返回完整内容覆盖它。
+pub fn store_text(base_dir: &Path, url: &str, body: &str) -> Result<()> {
+    save_entry(
+        base_dir,
+        &CacheEntry {
+            url: url.to_string(),
+            etag: None,
+            last_modified: None,
+            body: body.to_string(),
+        },
+    )
+}
+
+/// 发送条件 GET 请求并返回响应文本。
+///
+/// 有缓存时携带校验器；服务器返回 304 时直接返回缓存内容。
+/// 成功获取的新内容会写入缓存（写入失败只记录日志）。
+pub fn get_text(base_dir: &Path, url: &str, headers: &[(&str, &str)]) -> Result<String> {
+    let cached = load_entry(base_dir, url);
+
+    let mut request_headers = headers.to_vec();
+    if let Some(entry) = &cached {
+        if let Some(etag) = &entry.etag {
+            request_headers.push(("If-None-Match", etag));
+        }
+        if let Some(last_modified) = &entry.last_modified {
+            request_headers.push(("If-Modified-Since", last_modified));
+        }
+    }
+
+    let response = http::get(url, &request_headers, RequestKind::Api)
+        .with_context(|| format!("请求失败: {url}"))?;
+
+    if response.status == 304 {
+        match cached {
+            Some(entry) => return Ok(entry.body),
+            None => bail!("服务器返回 304 但本地没有缓存: {url}"),
+        }
+    }
+
+    let etag = response.header("ETag").map(str::to_string);
+    let last_modified = response.header("Last-Modified").map(str::to_string);
+
+    let body = response
+        .read_to_string()
+        .with_context(|| format!("读取响应失败: {url}"))?;
+
+    let entry = CacheEntry {
+        url: url.to_string(),
+        etag,
+        last_modified,
+        body,
+    };
+    if let Err(e) = save_entry(base_dir, &entry) {
+        eprintln!("保存 HTTP 缓存失败: {e:#}");
+    }
+    Ok(entry.body)
+}
+
+/// 与 `get_text` 相同，但网络请求失败时回退到上次成功的缓存内容。
+///
+/// 适用于内容变化不频繁、过期一点也能继续工作的清单（如 Mojang 版本清单）。
+pub fn get_text_or_cached(base_dir: &Path, url: &str, headers: &[(&str, &str)]) -> Result<String> {
+    match get_text(base_dir, url, headers) {
+        Ok(body) => Ok(body),
+        Err(e) => match cached_text(base_dir, url) {
+            Some(body) => {
+                eprintln!("网络请求失败，使用缓存内容: {url}\n  原因: {e:#}");
+                Ok(body)
+            }
+            None => Err(e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use std::fs;
+
+    #[test]
+    fn entry_roundtrip() {
+        let dir = crate::http::fake::temp_dir("http_cache_roundtrip");
+        let entry = CacheEntry {
+            url: "https://example.com/server.json".to_string(),
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+            body: "{}".to_string(),
+        };
+        save_entry(&dir, &entry).unwrap();
+        assert_eq!(cached_text(&dir, &entry.url).as_deref(), Some("{}"));
+        assert!(cached_text(&dir, "https://example.com/other.json").is_none());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn mismatched_url_is_ignored() {
+        let dir = crate::http::fake::temp_dir("http_cache_mismatch");
+        let url = "https://example.com/pack.toml";
+        let entry = CacheEntry {
+            url: "https://example.com/other.toml".to_string(),
+            etag: None,
+            last_modified: None,
+            body: "x".to_string(),
+        };
+        let path = dir.join(config::HTTP_CACHE_DIR).join(entry_name(url));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, serde_json::to_string(&entry).unwrap()).unwrap();
+        assert!(cached_text(&dir, url).is_none());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn conditional_request_uses_cache_on_304() {
+        let base_dir = crate::http::fake::temp_dir("http_cache");
+        let url = "https://update.mc.chenjicheng.cn/server.json";
+        let fake = crate::http::fake::FakeServer::new()
+            .route_with(url, 200, &[("ETag", "\"v1\"")], "{\"pack_url\":\"a\"}")
+            .install();
+
+        assert_eq!(get_text(&base_dir, url, &[]).unwrap(), "{\"pack_url\":\"a\"}");
+
+        fake.server.set(url, 304, &[], "");
+        assert_eq!(get_text(&base_dir, url, &[]).unwrap(), "{\"pack_url\":\"a\"}");
+        assert_eq!(
+            fake.server.last_request_header(url, "If-None-Match").as_deref(),
+            Some("\"v1\"")
+        );
+
+        fs::remove_dir_all(&base_dir).ok();
+    }
+}
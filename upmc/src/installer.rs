@@ -0,0 +1,214 @@
+// ============================================================
+// installer.rs — Java 安装器调用抽象
+// ============================================================
+// Fabric 安装器和 packwiz-installer 都是 Java 程序。更新流程中的
+// 前置检查、镜像切换、重试和原版下载与平台无关，只有「找到 Java」和
+// 「启动安装器进程」两步需要真正的 Java 运行时，由 JavaInstaller trait 完成：
+//   - 正式运行：ProcessInstaller（启动子进程，见 fabric.rs / packwiz.rs）
+//   - 测试：fake::FakeInstaller（不启动进程，直接模拟安装结果），
+//     通过 fake::FakeInstaller::install 替换当前线程的实现
+// ============================================================
+
+use anyhow::Result;
+use std::path::PathBuf;
+
+use crate::config;
+use crate::fabric::{self, FabricRun};
+use crate::packwiz::{self, PackwizRun};
+use crate::update::Progress;
+
+/// 运行 Java 安装器
+pub trait JavaInstaller {
+    /// 找到可用的 Java（找不到或无法运行时报错）
+    fn java(&self) -> Result<PathBuf>;
+
+    /// 运行一次 packwiz-installer（单次尝试，重试由调用方负责），
+    /// 进度映射到 `percent_range`
+    fn run_packwiz(
+        &self,
+        run: &PackwizRun,
+        percent_range: (u32, u32),
+        on_progress: &dyn Fn(Progress),
+    ) -> Result<()>;
+
+    /// 使用指定的 meta / maven 源运行一次 Fabric 安装器
+    fn run_fabric(&self, run: &FabricRun) -> Result<()>;
+}
+
+/// 找到可用的 Java
+pub fn java() -> Result<PathBuf> {
+    with_installer(|installer| installer.java())
+}
+
+/// 运行一次 packwiz-installer
+pub fn run_packwiz(
+    run: &PackwizRun,
+    percent_range: (u32, u32),
+    on_progress: &dyn Fn(Progress),
+) -> Result<()> {
+    with_installer(|installer| installer.run_packwiz(run, percent_range, on_progress))
+}
+
+/// 运行一次 Fabric 安装器
+pub fn run_fabric(run: &FabricRun) -> Result<()> {
+    with_installer(|installer| installer.run_fabric(run))
+}
+
+/// 启动真正的 Java 进程
+struct ProcessInstaller;
+
+impl JavaInstaller for ProcessInstaller {
+    fn java(&self) -> Result<PathBuf> {
+        let java = config::find_java()?;
+        config::verify_java(&java)?;
+        Ok(java)
+    }
+
+    fn run_packwiz(
+        &self,
+        run: &PackwizRun,
+        percent_range: (u32, u32),
+        on_progress: &dyn Fn(Progress),
+    ) -> Result<()> {
+        packwiz::run_packwiz_installer(run, percent_range, on_progress)
+    }
+
+    fn run_fabric(&self, run: &FabricRun) -> Result<()> {
+        fabric::run_fabric_installer(run)
+    }
+}
+
+#[cfg(not(test))]
+fn with_installer<T>(f: impl FnOnce(&dyn JavaInstaller) -> T) -> T {
+    f(&ProcessInstaller)
+}
+
+#[cfg(test)]
+fn with_installer<T>(f: impl FnOnce(&dyn JavaInstaller) -> T) -> T {
+    match fake::current() {
+        Some(installer) => f(&*installer),
+        None => f(&ProcessInstaller),
+    }
+}
+
+/// 测试用的安装器：不启动 Java
+#[cfg(test)]
+pub(crate) mod fake {
+    use super::*;
+    use anyhow::Context;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::path::Path;
+    use std::rc::Rc;
+
+    use crate::pack_index;
+    use crate::source;
+
+    thread_local! {
+        static CURRENT: RefCell<Option<Rc<FakeInstaller>>> = const { RefCell::new(None) };
+    }
+
+    pub(super) fn current() -> Option<Rc<FakeInstaller>> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// 记录每次调用并模拟安装结果：
+    ///   - packwiz：按索引下载全部文件（不删除已移出整合包的文件）
+    ///   - Fabric：只生成 Fabric 版本的 version JSON（服务端为启动器 jar）
+    pub struct FakeInstaller {
+        /// 读取整合包来源所用的安装基准目录
+        base_dir: PathBuf,
+        runs: RefCell<Vec<String>>,
+    }
+
+    /// 安装期间当前线程的安装器调用都由假安装器处理，离开作用域后恢复
+    pub struct Installed {
+        pub installer: Rc<FakeInstaller>,
+        previous: Option<Rc<FakeInstaller>>,
+    }
+
+    impl Drop for Installed {
+        fn drop(&mut self) {
+            let previous = self.previous.take();
+            CURRENT.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+
+    impl FakeInstaller {
+        pub fn new(base_dir: &Path) -> Self {
+            Self {
+                base_dir: base_dir.to_path_buf(),
+                runs: RefCell::new(Vec::new()),
+            }
+        }
+
+        /// 替换当前线程的安装器
+        pub fn install(self) -> Installed {
+            let installer = Rc::new(self);
+            let previous = CURRENT.with(|current| current.borrow_mut().replace(installer.clone()));
+            Installed {
+                installer,
+                previous,
+            }
+        }
+
+        /// 安装器调用记录（按顺序，"fabric" / "packwiz"）
+        pub fn runs(&self) -> Vec<String> {
+            self.runs.borrow().clone()
+        }
+    }
+
+    impl JavaInstaller for FakeInstaller {
+        fn java(&self) -> Result<PathBuf> {
+            Ok(PathBuf::from("java"))
+        }
+
+        fn run_packwiz(
+            &self,
+            run: &PackwizRun,
+            _percent_range: (u32, u32),
+            _on_progress: &dyn Fn(Progress),
+        ) -> Result<()> {
+            self.runs.borrow_mut().push("packwiz".to_string());
+            let pack_toml = source::read_text(&self.base_dir, run.pack_url)?;
+            let (index_url, _) = pack_index::fetch_index(&self.base_dir, run.pack_url, &pack_toml)?;
+            for file in pack_index::resolve_files(&self.base_dir, run.pack_url, &pack_toml)? {
+                let data = match &file.meta {
+                    Some(meta) => pack_index::download_verified(
+                        meta.download_url.as_deref().context("缺少下载地址")?,
+                        &meta.hash_format,
+                        &meta.hash,
+                    )?,
+                    None => pack_index::download_verified(
+                        &source::resolve_relative(&index_url, &file.entry.file),
+                        &file.entry.hash_format,
+                        &file.entry.hash,
+                    )?,
+                };
+                let path = run.mc_dir.join(&file.target);
+                fs::create_dir_all(path.parent().context("无效的目标路径")?)?;
+                fs::write(path, data)?;
+            }
+            Ok(())
+        }
+
+        fn run_fabric(&self, run: &FabricRun) -> Result<()> {
+            self.runs.borrow_mut().push("fabric".to_string());
+            if run.side == "server" {
+                fs::write(
+                    run.dir.join(crate::server::FABRIC_SERVER_LAUNCH_JAR),
+                    "PK fake launcher",
+                )?;
+                return Ok(());
+            }
+            let tag = format!("fabric-loader-{}-{}", run.fabric_version, run.mc_version);
+            let ver_dir = run.dir.join("versions").join(&tag);
+            fs::create_dir_all(&ver_dir)?;
+            fs::write(
+                ver_dir.join(format!("{tag}.json")),
+                format!(r#"{{"id":"{tag}","inheritsFrom":"{}"}}"#, run.mc_version),
+            )?;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// ============================================================
+// instance_lock.rs — 安装目录单实例锁
+// ============================================================
+// 同一安装目录同时只允许一个更新器修改文件：两个进程同时执行
+// run_update 会让两个 packwiz 争抢 .minecraft，xray::start 也会
+// 通过 xray.pid 互相结束对方的 Xray。
+//
+// 锁是对 updater/upmc.lock 的操作系统文件锁（File::try_lock），句柄在
+// 进程整个生命周期内保持打开：
+//   - 进程退出（包括崩溃、被结束）时系统自动释放锁，不需要清理失效的锁，
+//     也不依赖 PID 判断持有者是否存活
+//   - 持有者的 PID 和启动时间另存在 updater/upmc.lock.json，只用于提示和
+//     切换到已有窗口（Windows 的文件锁是强制锁，其他进程读不了被锁的文件）
+//
+// 已有更新器在运行时如何处理由 main.rs 决定：GUI 把已有窗口切到前台，
+// 命令行默认报错，带 --wait 时等待其结束。
+// ============================================================
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, TryLockError};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config;
+use crate::platform;
+use crate::state;
+
+/// 等待其他实例时的检查间隔
+const WAIT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 持有锁的进程（upmc.lock.json 的内容）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Holder {
+    /// 对方刚获取锁、还没写入时为 0
+    pub pid: u32,
+    /// 获取锁的本地时间（仅用于提示）
+    #[serde(default)]
+    pub since: String,
+}
+
+/// 已获取的锁，drop（进程退出）时由系统释放
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+}
+
+/// 尝试获取锁的结果
+#[derive(Debug)]
+pub enum LockState {
+    Acquired(InstanceLock),
+    /// 另一个仍在运行的更新器持有锁
+    HeldBy(Holder),
+}
+
+/// 尝试获取安装目录的锁，不等待
+pub fn try_acquire(base_dir: &Path) -> Result<LockState> {
+    let path = base_dir.join(config::INSTANCE_LOCK_FILE);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("创建目录失败: {}", parent.display()))?;
+    }
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("打开锁文件失败: {}", path.display()))?;
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => return Ok(LockState::HeldBy(read_holder(base_dir))),
+        Err(TryLockError::Error(e)) => {
+            return Err(e).with_context(|| format!("锁定失败: {}", path.display()));
+        }
+    }
+
+    let me = Holder {
+        pid: std::process::id(),
+        since: platform::local_timestamp(),
+    };
+    let json = serde_json::to_string(&me).context("序列化锁信息失败")?;
+    state::write_atomic(
+        &base_dir.join(config::INSTANCE_HOLDER_FILE),
+        json.as_bytes(),
+    )?;
+    Ok(LockState::Acquired(InstanceLock { _file: file }))
+}
+
+/// 获取锁，已被占用时每秒重试直到对方退出。开始等待时调用一次 `on_wait`。
+pub fn acquire_waiting(base_dir: &Path, on_wait: impl FnOnce(&Holder)) -> Result<InstanceLock> {
+    let mut on_wait = Some(on_wait);
+    loop {
+        match try_acquire(base_dir)? {
+            LockState::Acquired(lock) => return Ok(lock),
+            LockState::HeldBy(holder) => {
+                if let Some(on_wait) = on_wait.take() {
+                    on_wait(&holder);
+                }
+                std::thread::sleep(WAIT_POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+/// 读取持有者信息。只在锁被占用时调用，读不到（对方还没写入）时为空。
+fn read_holder(base_dir: &Path) -> Holder {
+    fs::read_to_string(base_dir.join(config::INSTANCE_HOLDER_FILE))
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::fake;
+
+    #[test]
+    fn second_handle_is_refused_until_drop() {
+        let dir = fake::temp_dir("lock_exclusive");
+        let LockState::Acquired(lock) = try_acquire(&dir).unwrap() else {
+            panic!("空目录应能获取锁");
+        };
+        match try_acquire(&dir).unwrap() {
+            LockState::HeldBy(holder) => assert_eq!(holder.pid, std::process::id()),
+            LockState::Acquired(_) => panic!("锁被持有时不应获取成功"),
+        }
+        drop(lock);
+        assert!(matches!(try_acquire(&dir).unwrap(), LockState::Acquired(_)));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn leftover_files_do_not_block() {
+        let dir = fake::temp_dir("lock_leftover");
+        // 崩溃的进程留下的锁文件和持有者信息
+        fs::create_dir_all(dir.join("updater")).unwrap();
+        fs::write(dir.join(config::INSTANCE_LOCK_FILE), "").unwrap();
+        fs::write(
+            dir.join(config::INSTANCE_HOLDER_FILE),
+            r#"{"pid":1234,"since":""}"#,
+        )
+        .unwrap();
+        assert!(matches!(try_acquire(&dir).unwrap(), LockState::Acquired(_)));
+        assert_eq!(read_holder(&dir).pid, std::process::id());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
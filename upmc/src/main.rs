@@ -2,11 +2,13 @@
 // main.rs — 程序入口
 // ============================================================
 // 职责：
-//   1. 解析命令行参数（--channel dev/stable）
+//   1. 解析命令行参数（--channel dev/stable、--profile、bundle 子命令）
 //   2. 确定安装基准路径（用户文档文件夹），并处理旧位置迁移
-//   3. 读取/持久化更新通道选择
-//   4. 隐藏控制台窗口（release 模式下）
-//   5. 启动 GUI
+//   3. 获取安装目录的单实例锁（instance_lock.rs）
+//   4. 升级旧格式的状态文件（state.rs）
+//   5. 合并分层设置（settings.rs），持久化更新通道选择
+//   6. 隐藏控制台窗口（release 模式下）
+//   7. 启动 GUI（非 Windows 平台为终端前端 headless.rs）
 // ============================================================
 
 // 在 release 模式下隐藏控制台黑框
@@ -14,19 +16,52 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod bootstrap;
+mod branding;
+mod bundle;
+mod changelog;
+mod cli;
 mod config;
 mod discord_proxy;
+mod error;
 mod fabric;
+#[cfg(windows)]
 mod gui;
+#[cfg(not(windows))]
+mod headless;
+mod http;
+mod http_cache;
+mod installer;
+mod instance_lock;
+mod managed;
+mod mirrors;
+mod mod_info;
+mod mod_policy;
+mod optional_mods;
+mod pack_index;
+mod packs;
 mod packwiz;
+mod platform;
+mod presets;
+mod profiles;
+mod repair;
 mod retry;
 mod selfupdate;
+mod server;
+mod settings;
+mod source;
+mod state;
 mod update;
 mod version;
+#[cfg(any(windows, test))]
+mod view_model;
 mod xray;
 
-use config::{ChannelConfig, UpdateChannel};
-use std::path::PathBuf;
+use cli::Invocation;
+use config::ChannelConfig;
+use error::ErrorCode;
+use instance_lock::{InstanceLock, LockState};
+use settings::Layer;
+use std::path::{Path, PathBuf};
 
 fn main() {
     // 自更新 helper 模式必须最先处理，避免 helper 初始化 GUI 或执行正常更新流程。
@@ -42,84 +77,151 @@ fn main() {
     // 清理上次自更新残留的临时文件（.new / .old / helper）
     selfupdate::cleanup_old_exe();
 
+    // 同一安装目录只允许一个更新器修改文件。锁总是放在文档文件夹的安装目录，
+    // 持有锁之后才迁移旧位置的安装，两个更新器不会同时迁移
+    let install_dir = config::get_install_dir();
+    let lock = acquire_lock(&install_dir);
+
     // 获取安装基准路径（用户文档文件夹）
     // 如果旧位置有安装，先迁移到新位置
-    let base_dir = get_base_dir();
+    let base_dir = get_base_dir(&install_dir, lock.is_some());
+
+    // 本次运行的整合包：--profile 指定的，或 GUI 上次选择的（见 profiles.rs）
+    let gui = cli::invocation() == Invocation::Gui;
+    let requested = cli::command_line().profile.as_deref();
+    let profile = match profiles::resolve(&base_dir, requested, gui) {
+        Ok(profile) => profile,
+        Err(e) if gui => {
+            eprintln!("{e:#}，使用上次选择的整合包");
+            profiles::selected(&base_dir)
+        }
+        Err(e) => {
+            platform::attach_parent_console();
+            cli::print_error(&e);
+            drop(lock);
+            std::process::exit(1);
+        }
+    };
+    // 实例目录（默认整合包就是安装基准目录）
+    let instance_dir = profiles::instance_dir(&base_dir, &profile).unwrap_or_else(|e| {
+        eprintln!("准备整合包 {} 的目录失败，使用主整合包: {e:#}", profile.name);
+        base_dir.clone()
+    });
 
-    // 解析命令行参数，确定更新通道
-    let channel_config = resolve_channel(&base_dir);
+    // 旧版更新器写入的状态文件升级到当前格式（实例目录有自己的 local.json 等）
+    state::migrate_all(&base_dir);
+    if instance_dir != base_dir {
+        state::migrate_all(&instance_dir);
+    }
+
+    // 合并编译期默认值、server.json、用户设置、环境变量和命令行参数
+    let channel_config = load_settings(&base_dir, &instance_dir);
+
+    // 命令行子命令（如 bundle export）执行完直接退出，不启动 GUI
+    if let Some(code) = cli::try_run_from_args(&instance_dir) {
+        drop(lock);
+        std::process::exit(code);
+    }
 
     // 启动 GUI（内部会开后台线程执行更新）
-    gui::UpdaterApp::run(base_dir, channel_config);
+    #[cfg(windows)]
+    gui::UpdaterApp::run(base_dir, instance_dir, channel_config);
+
+    // 其他平台没有 GUI，在终端中执行更新
+    #[cfg(not(windows))]
+    {
+        let code = headless::run(&instance_dir, &channel_config);
+        drop(lock);
+        std::process::exit(code);
+    }
 }
 
-/// 解析更新通道。
+/// 获取安装目录的单实例锁（见 instance_lock.rs）。只读的子命令不需要锁，返回 None。
 ///
-/// 优先级：命令行参数 > channel.json（设置窗口保存） > 编译期默认值
-fn resolve_channel(base_dir: &std::path::Path) -> ChannelConfig {
-    let args: Vec<String> = std::env::args().collect();
-    let mut cli_channel: Option<UpdateChannel> = None;
-
-    let mut i = 1;
-    while i < args.len() {
-        if args[i] == "--channel" {
-            if let Some(val) = args.get(i + 1) {
-                match val.to_lowercase().as_str() {
-                    "dev" => cli_channel = Some(UpdateChannel::Dev),
-                    "stable" => cli_channel = Some(UpdateChannel::Stable),
-                    other => {
-                        eprintln!("未知通道: {other}，使用编译期默认值");
-                    }
-                }
-                i += 2;
-                continue;
-            }
-        }
-        i += 1;
+/// 已有更新器在运行时：GUI 把它的窗口切到前台后退出；命令行（以及其他平台的
+/// 终端前端）带 --wait 时等待其结束，否则报错退出。
+fn acquire_lock(base_dir: &Path) -> Option<InstanceLock> {
+    let invocation = cli::invocation();
+    if invocation == Invocation::ReadOnlyCommand {
+        return None;
+    }
+    let terminal = invocation == Invocation::Command || !cfg!(windows);
+    if terminal {
+        platform::attach_parent_console();
     }
 
-    // 命令行 > channel.json > 编译期默认值
-    let path = base_dir.join(config::CHANNEL_CONFIG_FILE);
-    let (channel, from_file) = if let Some(ch) = cli_channel {
-        (ch, false)
+    let state = if terminal && cli::command_line().wait {
+        instance_lock::acquire_waiting(base_dir, |holder| {
+            eprintln!("另一个更新器正在运行（PID {}），等待其结束...", holder.pid);
+        })
+        .map(LockState::Acquired)
     } else {
-        match std::fs::read_to_string(&path) {
-            Ok(s) => match serde_json::from_str::<ChannelConfig>(&s) {
-                Ok(cfg) => (cfg.channel, true),
-                Err(e) => {
-                    eprintln!("警告: channel.json 解析失败，使用默认通道: {e}");
-                    (UpdateChannel::COMPILED_DEFAULT, false)
-                }
-            },
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                (UpdateChannel::COMPILED_DEFAULT, false)
+        instance_lock::try_acquire(base_dir)
+    };
+
+    match state {
+        Ok(LockState::Acquired(lock)) => Some(lock),
+        Ok(LockState::HeldBy(holder)) => {
+            if terminal {
+                cli::print_error(&error::new(
+                    ErrorCode::AlreadyRunning,
+                    format!(
+                        "另一个更新器（PID {}，{} 启动）正在使用安装目录 {}",
+                        holder.pid,
+                        holder.since,
+                        base_dir.display()
+                    ),
+                ));
+                std::process::exit(1);
             }
-            Err(e) => {
-                eprintln!("警告: 读取 channel.json 失败，使用默认通道: {e}");
-                (UpdateChannel::COMPILED_DEFAULT, false)
+            // 找不到它的窗口（如正在执行命令行修复）时提示玩家
+            #[cfg(windows)]
+            if !platform::focus_process_window(holder.pid) {
+                gui::show_already_running(&holder);
             }
+            std::process::exit(0);
         }
-    };
-
-    let cfg = ChannelConfig { channel };
-    // 仅在 CLI 指定或文件不存在时写入，避免覆盖损坏的配置
-    if cli_channel.is_some() || !from_file {
-        if let Err(e) = config::save_channel_config(base_dir, &cfg) {
-            eprintln!("保存通道配置失败: {e:#}");
+        // 锁文件无法创建（如目录只读）时不阻止启动，后续写入文件时会报告具体原因
+        Err(e) => {
+            eprintln!("警告: 获取更新器锁失败: {e:#}");
+            None
         }
     }
-    cfg
+}
+
+/// 读取分层设置（见 settings.rs）并设为当前设置。
+///
+/// 更新通道整台电脑共用，保存在安装基准目录：命令行指定或 channel.json 不存在时写入，
+/// 之后不带参数启动也使用同一通道。
+fn load_settings(base_dir: &Path, instance_dir: &Path) -> ChannelConfig {
+    let resolved = settings::install(base_dir, instance_dir, &cli::command_line().settings);
+    for warning in &resolved.warnings {
+        eprintln!("警告: {warning}");
+    }
+
+    let channel_config = ChannelConfig {
+        channel: resolved.channel,
+    };
+    let persist = match resolved.source("channel") {
+        Layer::Cli => true,
+        Layer::Default => !base_dir.join(config::CHANNEL_CONFIG_FILE).exists(),
+        _ => false,
+    };
+    if persist && let Err(e) = settings::save_channel(base_dir, &channel_config) {
+        eprintln!("保存通道配置失败: {e:#}");
+    }
+    channel_config
 }
 
 /// 获取组件安装的基准目录。
 ///
-/// 返回用户文档文件夹下的 `CJC整合包/` 子目录。
+/// 返回用户文档文件夹下的 `CJC整合包/` 子目录（`install_dir`）。
 /// 例如：`C:\Users\<用户>\Documents\CJC整合包\`
 ///
-/// 如果检测到旧版安装目录（exe 同级的 CJC整合包/），
-/// 会自动将其迁移到文档文件夹。
-fn get_base_dir() -> PathBuf {
-    let new_dir = config::get_install_dir();
+/// 如果检测到旧版安装目录（exe 同级的 CJC整合包/），持有 `install_dir` 的锁时
+/// （`migrate`）会自动将其迁移到文档文件夹；否则（只读的子命令）继续使用旧目录。
+fn get_base_dir(install_dir: &Path, migrate: bool) -> PathBuf {
+    let new_dir = install_dir.to_path_buf();
     let legacy_dir = config::get_legacy_install_dir();
 
     // 新旧路径相同时无需迁移（exe 本身就在文档文件夹中）
@@ -127,8 +229,21 @@ fn get_base_dir() -> PathBuf {
         return new_dir;
     }
 
-    // 新旧目录都存在 → 使用新目录，提示用户可清理旧目录
-    if legacy_dir.exists() && new_dir.exists() {
+    // 上次迁移中途退出：继续把暂存的文件移到新目录
+    let staging = new_dir.join(config::LEGACY_MIGRATION_DIR);
+    if migrate
+        && staging.exists()
+        && let Err(e) = finish_migration(&staging, &new_dir)
+    {
+        eprintln!("继续迁移旧版安装失败: {e}");
+    }
+
+    if !legacy_dir.exists() {
+        return new_dir;
+    }
+
+    // 新旧目录都有安装 → 使用新目录，提示用户可清理旧目录
+    if has_install(&new_dir) {
         eprintln!(
             "新旧安装目录同时存在，使用新位置: {}\n\
              旧目录可手动删除: {}",
@@ -138,38 +253,119 @@ fn get_base_dir() -> PathBuf {
         return new_dir;
     }
 
-    // 旧目录存在且新目录不存在 → 迁移
-    if legacy_dir.exists() && !new_dir.exists() {
-        eprintln!(
-            "检测到旧版安装，正在迁移: {} → {}",
-            legacy_dir.display(),
-            new_dir.display()
-        );
+    if !migrate {
+        return legacy_dir;
+    }
 
-        // 确保新目录的父目录存在
-        if let Some(parent) = new_dir.parent() {
-            if let Err(e) = std::fs::create_dir_all(parent) {
-                eprintln!("创建目标目录失败: {e}");
-            }
+    // 旧目录存在且新目录没有安装 → 迁移
+    eprintln!(
+        "检测到旧版安装，正在迁移: {} → {}",
+        legacy_dir.display(),
+        new_dir.display()
+    );
+    match migrate_legacy(&legacy_dir, &new_dir) {
+        Ok(()) => {
+            eprintln!("迁移成功");
+            new_dir
+        }
+        Err(e) => {
+            // rename 失败（跨盘符等），回退到使用旧目录
+            eprintln!(
+                "迁移失败（将继续使用旧位置）: {e}\n\
+                 旧位置: {}\n新位置: {}",
+                legacy_dir.display(),
+                new_dir.display()
+            );
+            legacy_dir
         }
+    }
+}
 
-        // 尝试 rename（同盘符下是原子操作，速度极快）
-        match std::fs::rename(&legacy_dir, &new_dir) {
-            Ok(()) => {
-                eprintln!("迁移成功");
-            }
-            Err(e) => {
-                // rename 失败（跨盘符等），回退到使用旧目录
-                eprintln!(
-                    "迁移失败（将继续使用旧位置）: {e}\n\
-                     旧位置: {}\n新位置: {}",
-                    legacy_dir.display(),
-                    new_dir.display()
-                );
-                return legacy_dir;
+/// 新目录中是否已有安装（只有锁文件时不算：锁在迁移之前就已创建）
+fn has_install(dir: &Path) -> bool {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return false;
+    };
+    entries
+        .flatten()
+        .any(|entry| match entry.file_name().to_str() {
+            Some("updater") => std::fs::read_dir(entry.path()).is_ok_and(|children| {
+                children
+                    .flatten()
+                    .any(|child| !is_lock_file(dir, &child.path()))
+            }),
+            Some(config::LEGACY_MIGRATION_DIR) => false,
+            _ => true,
+        })
+}
+
+/// `path` 是否为安装目录 `dir` 的单实例锁文件
+fn is_lock_file(dir: &Path, path: &Path) -> bool {
+    [config::INSTANCE_LOCK_FILE, config::INSTANCE_HOLDER_FILE]
+        .iter()
+        .any(|lock| dir.join(lock) == path)
+}
+
+/// 把旧目录迁移到新目录（新目录中已有本进程的锁文件，不能直接 rename 整个目录）。
+///
+/// 先把旧目录整体 rename 到新目录下的暂存目录（同盘符下是原子操作，失败时旧目录
+/// 保持原样），再逐项移到新目录。
+fn migrate_legacy(legacy_dir: &Path, new_dir: &Path) -> std::io::Result<()> {
+    let staging = new_dir.join(config::LEGACY_MIGRATION_DIR);
+    std::fs::create_dir_all(new_dir)?;
+    std::fs::rename(legacy_dir, &staging)?;
+    finish_migration(&staging, new_dir)
+}
+
+/// 把暂存目录中的文件移到新目录（旧目录的锁文件不移动），完成后删除暂存目录
+fn finish_migration(staging: &Path, new_dir: &Path) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(staging)? {
+        let entry = entry?;
+        if entry.file_name() != "updater" {
+            std::fs::rename(entry.path(), new_dir.join(entry.file_name()))?;
+            continue;
+        }
+        let updater = new_dir.join("updater");
+        std::fs::create_dir_all(&updater)?;
+        for child in std::fs::read_dir(entry.path())? {
+            let child = child?;
+            let target = updater.join(child.file_name());
+            if !is_lock_file(new_dir, &target) {
+                std::fs::rename(child.path(), target)?;
             }
         }
     }
+    std::fs::remove_dir_all(staging)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn legacy_install_merges_next_to_lock() {
+        let root = http::fake::temp_dir("legacy_migration");
+        let legacy_dir = root.join("legacy");
+        let new_dir = root.join("new");
+        fs::create_dir_all(legacy_dir.join("updater")).unwrap();
+        fs::write(legacy_dir.join("local.json"), "{}").unwrap();
+        fs::write(legacy_dir.join(config::INSTANCE_LOCK_FILE), "old").unwrap();
+        fs::write(legacy_dir.join("updater/settings.json"), "{}").unwrap();
 
-    new_dir
+        // 新目录里只有本进程刚创建的锁文件，不算已有安装
+        let lock = instance_lock::try_acquire(&new_dir).unwrap();
+        assert!(matches!(lock, LockState::Acquired(_)));
+        assert!(!has_install(&new_dir));
+
+        migrate_legacy(&legacy_dir, &new_dir).unwrap();
+        assert!(!legacy_dir.exists());
+        assert!(!new_dir.join(config::LEGACY_MIGRATION_DIR).exists());
+        assert!(new_dir.join("local.json").exists());
+        assert!(new_dir.join("updater/settings.json").exists());
+        assert!(has_install(&new_dir));
+        drop(lock);
+
+        fs::remove_dir_all(&root).ok();
+    }
 }
@@ -0,0 +1,232 @@
+// ============================================================
+// managed.rs — 整合包管理的文件清单
+// ============================================================
+// 每次同步后记录整合包安装到 .minecraft 的文件（路径 + 索引中的哈希），
+// 保存在 updater/managed_files.json。据此区分：
+//   - 整合包管理的文件：大版本升级时直接删除，由 packwiz 重新下载
+//   - 玩家自己添加的模组：升级时移到 mods/.disabled-<旧版本>/ 保留，不再删除
+//
+// 每次启动还会读取 mods/ 中每个 jar 的 fabric.mod.json，
+// 玩家添加的 jar 与整合包中的模组 id 相同时给出警告（重复加载会导致崩溃）。
+// ============================================================
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use crate::config;
+use crate::mod_info;
+use crate::pack_index::ResolvedFile;
+use crate::state;
+
+/// 一个受管理的文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManagedFile {
+    pub hash_format: String,
+    pub hash: String,
+}
+
+/// 受管理文件清单
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ManagedFiles {
+    /// 记录时的整合包版本（version_tag）
+    pub version_tag: String,
+    /// 键为相对 .minecraft 的路径（`/` 分隔）
+    pub files: BTreeMap<String, ManagedFile>,
+}
+
+impl ManagedFiles {
+    /// 从整合包文件列表生成清单（包括可选模组，不论是否启用）
+    pub fn from_files(version_tag: &str, files: &[ResolvedFile]) -> Self {
+        let files = files
+            .iter()
+            .map(|file| {
+                let (hash_format, hash) = match &file.meta {
+                    Some(meta) => (meta.hash_format.clone(), meta.hash.clone()),
+                    None => (file.entry.hash_format.clone(), file.entry.hash.clone()),
+                };
+                (file.target.clone(), ManagedFile { hash_format, hash })
+            })
+            .collect();
+        Self {
+            version_tag: version_tag.to_string(),
+            files,
+        }
+    }
+
+    /// 路径是否由整合包管理（被禁用的可选模组 `<文件>.disabled` 也算）
+    pub fn contains(&self, relative: &str) -> bool {
+        let relative = relative.strip_suffix(".disabled").unwrap_or(relative);
+        self.files.contains_key(relative)
+    }
+}
+
+/// 保存清单
+pub fn save(base_dir: &Path, managed: &ManagedFiles) -> Result<()> {
+    state::MANAGED_FILES
+        .write_json(base_dir, managed)
+        .context("写入受管理文件清单失败")
+}
+
+/// 读取清单。从未记录过（旧版本更新器安装的）时返回 None。
+pub fn load(base_dir: &Path) -> Option<ManagedFiles> {
+    state::MANAGED_FILES.read_json(base_dir)
+}
+
+/// 清单对应的版本是否是当前整合包版本
+pub fn is_recorded(base_dir: &Path, version_tag: &str) -> bool {
+    load(base_dir).is_some_and(|managed| managed.version_tag == version_tag)
+}
+
+/// 大版本升级前清理 mods/：
+/// 整合包管理的 .jar 直接删除（packwiz 会下载新版本），
+/// 其余 .jar 视为玩家自己添加的，移到 `mods/.disabled-<旧版本>/`（旧版本的模组通常不兼容新版本）。
+///
+/// 没有清单时无法区分，所有 .jar 都会被移走保留。返回被移走的文件名。
+pub fn set_aside_mods(base_dir: &Path, old_version_tag: &str) -> Result<Vec<String>> {
+    let mods_dir = base_dir.join(config::MINECRAFT_DIR).join("mods");
+    let managed = load(base_dir).unwrap_or_default();
+    let keep_dir = mods_dir.join(format!(".disabled-{old_version_tag}"));
+
+    let mut moved = Vec::new();
+    for name in mod_info::list_mod_jars(&mods_dir) {
+        let path = mods_dir.join(&name);
+        // best-effort（文件可能被游戏进程锁定）
+        if managed.contains(&format!("mods/{name}")) {
+            if let Err(e) = fs::remove_file(&path) {
+                eprintln!("删除模组失败（已跳过）: {}: {e}", path.display());
+            }
+            continue;
+        }
+        fs::create_dir_all(&keep_dir)
+            .with_context(|| format!("创建目录失败: {}", keep_dir.display()))?;
+        match fs::rename(&path, keep_dir.join(&name)) {
+            Ok(()) => moved.push(name),
+            Err(e) => eprintln!("移动玩家模组失败（已跳过）: {}: {e}", path.display()),
+        }
+    }
+    Ok(moved)
+}
+
+/// 删除整合包管理的模组（切换整合包通道时使用，新通道的模组由 packwiz 重新下载），
+/// 玩家自己添加的模组不受影响。返回删除的数量。
+pub fn remove_managed_mods(base_dir: &Path) -> usize {
+    let Some(managed) = load(base_dir) else {
+        return 0;
+    };
+    let minecraft_dir = base_dir.join(config::MINECRAFT_DIR);
+    let mut removed = 0;
+    for relative in managed.files.keys().filter(|path| path.starts_with("mods/")) {
+        for path in [relative.clone(), format!("{relative}.disabled")] {
+            let path = minecraft_dir.join(path);
+            if !path.is_file() {
+                continue;
+            }
+            // best-effort（文件可能被游戏进程锁定）
+            match fs::remove_file(&path) {
+                Ok(()) => removed += 1,
+                Err(e) => eprintln!("删除模组失败（已跳过）: {}: {e}", path.display()),
+            }
+        }
+    }
+    removed
+}
+
+/// 检查玩家添加的模组是否与整合包中的模组重复（fabric.mod.json 的 id 相同）。
+///
+/// 返回给玩家看的警告文本。没有清单时无法区分，不检查。
+pub fn find_conflicts(base_dir: &Path) -> Vec<String> {
+    let Some(managed) = load(base_dir) else {
+        return Vec::new();
+    };
+    let mods_dir = base_dir.join(config::MINECRAFT_DIR).join("mods");
+
+    let mut managed_ids: HashMap<String, String> = HashMap::new();
+    let mut user_mods = Vec::new();
+    for name in mod_info::list_mod_jars(&mods_dir) {
+        let info = match mod_info::read_fabric_mod(&mods_dir.join(&name)) {
+            Ok(Some(info)) => info,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("{e:#}");
+                continue;
+            }
+        };
+        if managed.contains(&format!("mods/{name}")) {
+            managed_ids.insert(info.id, name);
+        } else {
+            user_mods.push((name, info.id));
+        }
+    }
+
+    user_mods
+        .into_iter()
+        .filter_map(|(name, id)| {
+            managed_ids.get(&id).map(|managed_name| {
+                format!("mods/{name} 与整合包自带的 mods/{managed_name} 是同一个模组（{id}），请删除其中一个")
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pack_index::{IndexEntry, ModMeta};
+
+    fn resolved(file: &str, target: &str, meta: bool) -> ResolvedFile {
+        ResolvedFile {
+            entry: IndexEntry {
+                file: file.to_string(),
+                hash: "index-hash".to_string(),
+                hash_format: "sha256".to_string(),
+                metafile: meta,
+                preserve: false,
+            },
+            target: target.to_string(),
+            meta: meta.then(|| ModMeta {
+                hash_format: "sha512".to_string(),
+                hash: "jar-hash".to_string(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn from_files_uses_target_and_meta_hash() {
+        let managed = ManagedFiles::from_files(
+            "1.21.11-fabric",
+            &[
+                resolved("mods/sodium.pw.toml", "mods/sodium.jar", true),
+                resolved("config/a.json", "config/a.json", false),
+            ],
+        );
+        assert_eq!(managed.files["mods/sodium.jar"].hash, "jar-hash");
+        assert_eq!(managed.files["config/a.json"].hash, "index-hash");
+        assert!(managed.contains("mods/sodium.jar.disabled"));
+        assert!(!managed.contains("mods/sodium.pw.toml"));
+    }
+
+    #[test]
+    fn set_aside_keeps_user_mods() {
+        let dir = std::env::temp_dir().join(format!("upmc_managed_{}", std::process::id()));
+        let mods_dir = dir.join(".minecraft/mods");
+        fs::create_dir_all(&mods_dir).unwrap();
+        fs::write(mods_dir.join("sodium.jar"), b"PK").unwrap();
+        fs::write(mods_dir.join("my-mod.jar"), b"PK").unwrap();
+        let managed = ManagedFiles::from_files(
+            "old",
+            &[resolved("mods/sodium.pw.toml", "mods/sodium.jar", true)],
+        );
+        save(&dir, &managed).unwrap();
+
+        let moved = set_aside_mods(&dir, "old").unwrap();
+        assert_eq!(moved, vec!["my-mod.jar".to_string()]);
+        assert!(!mods_dir.join("sodium.jar").exists());
+        assert!(mods_dir.join(".disabled-old/my-mod.jar").exists());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,524 @@
+// ============================================================
+// mirrors.rs — 远程端点镜像列表 + 自动故障转移
+// ============================================================
+// 每类远程端点（server.json、更新器 version.json、GitHub 代理、
+// Fabric 源、Mojang 版本清单）都对应一个有序镜像列表，来源按优先级：
+//   1. 用户设置 settings.json 的 mirrors 字段
+//   2. server.json 下发的 mirrors 字段（缓存在 updater/mirrors.json）
+//   3. 编译期默认值
+//
+// 请求时先尝试上次成功的镜像，失败后按顺序尝试其余镜像，
+// 成功的镜像会被记住，下次启动优先使用。
+// 主机被熔断（retry.rs：连续失败）的镜像暂时跳过，全部熔断时仍依次尝试。
+//
+// 所有镜像都必须通过下载白名单校验（HTTPS + 可信域名），
+// server.json 或用户设置无法借此引入白名单之外的主机。
+// server.json 决定下载项，它的镜像还要求位于编译期地址所在站点，
+// 或 branding.toml 固定的 GitHub 仓库中，不能是任意 GitHub 仓库。
+// ============================================================
+
+use anyhow::{Context, Result, bail, ensure};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::bootstrap;
+use crate::branding;
+use crate::config::{self, UpdateChannel};
+use crate::retry;
+use crate::state;
+
+/// 支持镜像的远程端点类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    /// 远程 server.json
+    ServerJson,
+    /// 更新器 version.json（stable 通道）
+    UpdaterVersion,
+    /// 更新器 version.json（dev 通道）
+    UpdaterDevVersion,
+    /// GitHub 下载代理前缀（空字符串表示直连 GitHub）
+    GithubProxy,
+    /// Fabric meta 源
+    FabricMeta,
+    /// Fabric maven 源（按站点与 FabricMeta 配对，见 `paired`）
+    FabricMaven,
+    /// Mojang 版本清单
+    VersionManifest,
+}
+
+impl Endpoint {
+    /// 在 server.json / settings.json 中使用的键名
+    pub fn key(self) -> &'static str {
+        match self {
+            Endpoint::ServerJson => "server_json",
+            Endpoint::UpdaterVersion => "updater_version",
+            Endpoint::UpdaterDevVersion => "updater_dev_version",
+            Endpoint::GithubProxy => "github_proxy",
+            Endpoint::FabricMeta => "fabric_meta",
+            Endpoint::FabricMaven => "fabric_maven",
+            Endpoint::VersionManifest => "version_manifest",
+        }
+    }
+
+    /// 根据更新通道选择更新器 version.json 端点
+    pub fn updater_version(channel: UpdateChannel) -> Self {
+        match channel {
+            UpdateChannel::Stable => Endpoint::UpdaterVersion,
+            UpdateChannel::Dev => Endpoint::UpdaterDevVersion,
+        }
+    }
+
+    /// 编译期默认镜像列表（第一个为主地址）
+    fn compiled_defaults(self) -> &'static [&'static str] {
+        match self {
+            Endpoint::ServerJson => &[config::REMOTE_SERVER_JSON_URL],
+            Endpoint::UpdaterVersion => &[config::UPDATER_VERSION_URL],
+            Endpoint::UpdaterDevVersion => &[config::UPDATER_DEV_VERSION_URL],
+            Endpoint::GithubProxy => &[config::GITHUB_PROXY, ""],
+            Endpoint::FabricMeta => &[config::FABRIC_META_URL, config::FABRIC_OFFICIAL_META_URL],
+            Endpoint::FabricMaven => {
+                &[config::FABRIC_MAVEN_URL, config::FABRIC_OFFICIAL_MAVEN_URL]
+            }
+            Endpoint::VersionManifest => {
+                &[config::VERSION_MANIFEST_URL, config::VERSION_MANIFEST_MIRROR_URL]
+            }
+        }
+    }
+}
+
+/// updater/mirrors.json 的内容
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct MirrorState {
+    /// 上次从 server.json 获取的镜像列表
+    #[serde(default)]
+    server: BTreeMap<String, Vec<String>>,
+    /// 每类端点上次请求成功的镜像
+    #[serde(default)]
+    healthy: BTreeMap<String, String>,
+}
+
+fn load_state(base_dir: &Path) -> MirrorState {
+    state::MIRROR_STATE.read_json(base_dir).unwrap_or_default()
+}
+
+fn save_state(base_dir: &Path, state: &MirrorState) -> Result<()> {
+    state::MIRROR_STATE
+        .write_json(base_dir, state)
+        .context("写入 mirrors.json 失败")
+}
+
+/// 保存 server.json 下发的镜像列表，供之后（包括下次启动）使用。
+pub fn save_server_mirrors(base_dir: &Path, mirrors: &BTreeMap<String, Vec<String>>) {
+    let mut state = load_state(base_dir);
+    if state.server == *mirrors {
+        return;
+    }
+    state.server = mirrors.clone();
+    if let Err(e) = save_state(base_dir, &state) {
+        eprintln!("保存镜像列表失败: {e:#}");
+    }
+}
+
+/// 校验单个镜像地址是否在下载白名单内。
+///
+/// GitHub 代理允许空字符串，表示不经代理直连 GitHub。
+fn validate_mirror(endpoint: Endpoint, url: &str) -> Result<()> {
+    if endpoint == Endpoint::GithubProxy && url.is_empty() {
+        return Ok(());
+    }
+    bootstrap::validate_download_url(url)?;
+    match endpoint {
+        Endpoint::ServerJson => validate_pinned_mirror(
+            url,
+            "server.json",
+            config::REMOTE_SERVER_JSON_URL,
+            branding::SERVER_JSON_GITHUB_REPO,
+        ),
+        Endpoint::UpdaterVersion => validate_pinned_mirror(
+            url,
+            "更新器 version.json",
+            config::UPDATER_VERSION_URL,
+            branding::UPDATER_GITHUB_REPO,
+        ),
+        Endpoint::UpdaterDevVersion => validate_pinned_mirror(
+            url,
+            "更新器 version.json",
+            config::UPDATER_DEV_VERSION_URL,
+            branding::UPDATER_GITHUB_REPO,
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// server.json 和更新器 version.json 决定下载什么、用什么替换更新器，
+/// 它们的镜像只能位于编译期地址所在站点，或 branding.toml 指定的 GitHub 仓库中
+/// （直连或经 GitHub 代理）。
+fn validate_pinned_mirror(
+    url: &str,
+    label: &str,
+    compiled: &str,
+    github_repo: Option<&str>,
+) -> Result<()> {
+    if url == compiled {
+        return Ok(());
+    }
+    if let Some(raw) = strip_github_proxy(&[config::GITHUB_PROXY.to_string()], url) {
+        let repo = github_repo_of(raw);
+        let pinned = github_repo.map(str::to_ascii_lowercase);
+        ensure!(
+            repo.is_some() && repo == pinned,
+            "{label} 镜像只能指向仓库 {}",
+            pinned.as_deref().unwrap_or("（未配置）")
+        );
+        return Ok(());
+    }
+    ensure!(
+        site_of(url) == site_of(compiled),
+        "{label} 镜像必须与 {compiled} 位于同一站点"
+    );
+    Ok(())
+}
+
+/// GitHub 仓库文件或 Release 附件地址所属的仓库（小写 `owner/repo`）。
+fn github_repo_of(url: &str) -> Option<String> {
+    let mut parts = url.strip_prefix("https://")?.split('/');
+    let host = parts.next()?.to_ascii_lowercase();
+    if host != "github.com" && host != "raw.githubusercontent.com" {
+        return None;
+    }
+    let owner = parts.next().filter(|s| !s.is_empty())?;
+    let repo = parts.next().filter(|s| !s.is_empty())?;
+    Some(format!("{owner}/{repo}").to_ascii_lowercase())
+}
+
+/// 合并各来源的镜像列表：用户设置 → server.json → 编译期默认值，去重并过滤不可信地址。
+/// 上次成功的镜像会被移到最前面。
+fn ordered_candidates(
+    endpoint: Endpoint,
+    user: &BTreeMap<String, Vec<String>>,
+    state: &MirrorState,
+) -> Vec<String> {
+    let key = endpoint.key();
+    let mut result: Vec<String> = Vec::new();
+
+    let sources = user
+        .get(key)
+        .into_iter()
+        .flatten()
+        .chain(state.server.get(key).into_iter().flatten())
+        .map(String::as_str)
+        .chain(endpoint.compiled_defaults().iter().copied());
+
+    for url in sources {
+        let url = url.trim();
+        if result.iter().any(|u| u == url) {
+            continue;
+        }
+        match validate_mirror(endpoint, url) {
+            Ok(()) => result.push(url.to_string()),
+            Err(e) => eprintln!("忽略不可信镜像 {key}: {url}: {e:#}"),
+        }
+    }
+
+    if let Some(healthy) = state.healthy.get(key)
+        && let Some(pos) = result.iter().position(|u| u == healthy)
+    {
+        let url = result.remove(pos);
+        result.insert(0, url);
+    }
+
+    result
+}
+
+/// 返回某类端点的镜像列表（按尝试顺序）。
+pub fn candidates(base_dir: &Path, endpoint: Endpoint) -> Vec<String> {
+    let user = config::load_user_settings(base_dir).mirrors;
+    ordered_candidates(endpoint, &user, &load_state(base_dir))
+}
+
+/// 返回某类端点当前首选的镜像（上次成功的镜像或列表第一个）。
+pub fn preferred(base_dir: &Path, endpoint: Endpoint) -> String {
+    candidates(base_dir, endpoint)
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| endpoint.compiled_defaults()[0].to_string())
+}
+
+/// 在某类端点中挑选与参考地址属于同一站点的镜像（如 Fabric meta 与 maven 配对），
+/// 找不到时返回首选镜像。
+pub fn paired(base_dir: &Path, endpoint: Endpoint, reference_url: &str) -> String {
+    let list = candidates(base_dir, endpoint);
+    let site = site_of(reference_url);
+    list.iter()
+        .find(|url| site.is_some() && site_of(url) == site)
+        .or_else(|| list.first())
+        .cloned()
+        .unwrap_or_else(|| endpoint.compiled_defaults()[0].to_string())
+}
+
+/// 取 URL 主机名的最后两段作为站点标识，如 `meta.fabricmc.net` → `fabricmc.net`。
+fn site_of(url: &str) -> Option<String> {
+    let host = url
+        .strip_prefix("https://")?
+        .split(['/', ':', '?', '#'])
+        .next()?
+        .to_ascii_lowercase();
+    let labels: Vec<&str> = host.rsplitn(3, '.').collect();
+    match labels.as_slice() {
+        [tld, domain, ..] => Some(format!("{domain}.{tld}")),
+        _ => None,
+    }
+}
+
+/// 记录某类端点最近一次成功的镜像。
+fn mark_healthy(base_dir: &Path, endpoint: Endpoint, url: &str) {
+    let mut state = load_state(base_dir);
+    if state.healthy.get(endpoint.key()).map(String::as_str) == Some(url) {
+        return;
+    }
+    state.healthy.insert(endpoint.key().to_string(), url.to_string());
+    if let Err(e) = save_state(base_dir, &state) {
+        eprintln!("保存镜像状态失败: {e:#}");
+    }
+}
+
+/// 依次尝试端点的各个镜像，直到某个镜像上的操作成功。
+///
+/// `f` 接收镜像地址并执行一次请求；成功后该镜像会被记住。
+/// 熔断中的镜像会被跳过（全部熔断时不跳过）。
+/// 全部失败时返回最后一个错误，并附带尝试过的镜像数量。
+pub fn with_failover<T>(
+    base_dir: &Path,
+    endpoint: Endpoint,
+    f: impl Fn(&str) -> Result<T>,
+) -> Result<T> {
+    let mut list = candidates(base_dir, endpoint);
+    if list.iter().any(|url| !retry::circuit_open(url)) {
+        list.retain(|url| {
+            let open = retry::circuit_open(url);
+            if open {
+                eprintln!("[镜像] {} 跳过熔断中的镜像: {url}", endpoint.key());
+            }
+            !open
+        });
+    }
+    let mut last_error = None;
+
+    for (i, url) in list.iter().enumerate() {
+        match f(url) {
+            Ok(value) => {
+                if i > 0 {
+                    eprintln!("[镜像] {} 已切换到: {url}", endpoint.key());
+                }
+                mark_healthy(base_dir, endpoint, url);
+                return Ok(value);
+            }
+            Err(e) => {
+                if i + 1 < list.len() {
+                    eprintln!("[镜像] {} 镜像不可用，尝试下一个: {url}\n  原因: {e:#}", endpoint.key());
+                }
+                last_error = Some(e);
+            }
+        }
+    }
+
+    match last_error {
+        Some(e) => Err(e.context(format!(
+            "{} 的 {} 个镜像均不可用",
+            endpoint.key(),
+            list.len()
+        ))),
+        None => bail!("{} 没有可用的镜像", endpoint.key()),
+    }
+}
+
+/// 返回去掉 GitHub 代理前缀后的原始 GitHub URL。
+///
+/// URL 本身就是 GitHub 地址时原样返回；既不经代理也不是 GitHub 地址时返回 None。
+fn strip_github_proxy<'a>(proxies: &[String], url: &'a str) -> Option<&'a str> {
+    let raw = proxies
+        .iter()
+        .filter(|p| !p.is_empty())
+        .find_map(|p| url.strip_prefix(p.as_str()))
+        .unwrap_or(url);
+    is_github_url(raw).then_some(raw)
+}
+
+fn is_github_url(url: &str) -> bool {
+    site_of(url).is_some_and(|site| site == "github.com" || site == "githubusercontent.com")
+}
+
+/// 对 GitHub 下载 URL 执行代理故障转移。
+///
+/// 如果 URL 指向 GitHub（无论是否经过已知代理），则依次换用各个代理（包括直连）；
+/// 否则只按原 URL 执行一次。
+pub fn with_github_failover<T>(
+    base_dir: &Path,
+    url: &str,
+    f: impl Fn(&str) -> Result<T>,
+) -> Result<T> {
+    let proxies = candidates(base_dir, Endpoint::GithubProxy);
+    match strip_github_proxy(&proxies, url) {
+        Some(raw) => {
+            let raw = raw.to_string();
+            with_failover(base_dir, Endpoint::GithubProxy, |proxy| {
+                f(&format!("{proxy}{raw}"))
+            })
+        }
+        None => f(url),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_mirrors(key: &str, urls: &[&str]) -> BTreeMap<String, Vec<String>> {
+        let mut map = BTreeMap::new();
+        map.insert(key.to_string(), urls.iter().map(|u| u.to_string()).collect());
+        map
+    }
+
+    #[test]
+    fn defaults_are_used_without_overrides() {
+        let list = ordered_candidates(
+            Endpoint::FabricMeta,
+            &BTreeMap::new(),
+            &MirrorState::default(),
+        );
+        assert_eq!(list[0], config::FABRIC_META_URL);
+        assert!(list.contains(&config::FABRIC_OFFICIAL_META_URL.to_string()));
+    }
+
+    #[test]
+    fn user_then_server_then_defaults() {
+        let user = user_mirrors("server_json", &["https://a.chenjicheng.cn/server.json"]);
+        let state = MirrorState {
+            server: user_mirrors("server_json", &["https://b.chenjicheng.cn/server.json"]),
+            healthy: BTreeMap::new(),
+        };
+        let list = ordered_candidates(Endpoint::ServerJson, &user, &state);
+        assert_eq!(
+            list,
+            vec![
+                "https://a.chenjicheng.cn/server.json".to_string(),
+                "https://b.chenjicheng.cn/server.json".to_string(),
+                config::REMOTE_SERVER_JSON_URL.to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn untrusted_mirrors_are_dropped() {
+        let user = user_mirrors(
+            "version_manifest",
+            &["https://evil.example/manifest.json", "http://piston-meta.mojang.com/x"],
+        );
+        let list = ordered_candidates(Endpoint::VersionManifest, &user, &MirrorState::default());
+        assert!(list.iter().all(|u| !u.contains("evil") && u.starts_with("https://")));
+    }
+
+    #[test]
+    fn server_json_mirrors_are_pinned() {
+        let accepted = |url: &str| validate_mirror(Endpoint::ServerJson, url).is_ok();
+        assert!(accepted(config::REMOTE_SERVER_JSON_URL));
+        assert!(accepted("https://backup.chenjicheng.cn/server.json"));
+        // 可信域名但不是 server.json 的站点
+        assert!(!accepted("https://meta.fabricmc.net/server.json"));
+        // 任意 GitHub 仓库（直连或经代理）
+        let other_repo = "https://raw.githubusercontent.com/someone/else/main/server.json";
+        assert!(!accepted(other_repo));
+        assert!(!accepted(&format!("{}{other_repo}", config::GITHUB_PROXY)));
+        // 其他端点仍只按域名白名单校验
+        assert!(validate_mirror(Endpoint::FabricMeta, other_repo).is_ok());
+    }
+
+    #[test]
+    fn updater_version_mirrors_are_pinned() {
+        for (endpoint, compiled) in [
+            (Endpoint::UpdaterVersion, config::UPDATER_VERSION_URL),
+            (Endpoint::UpdaterDevVersion, config::UPDATER_DEV_VERSION_URL),
+        ] {
+            let accepted = |url: &str| validate_mirror(endpoint, url).is_ok();
+            assert!(accepted(compiled));
+            assert!(accepted("https://backup.chenjicheng.cn/version.json"));
+            // 可信域名但不是更新器的站点
+            assert!(!accepted("https://meta.fabricmc.net/version.json"));
+            // server.json 或用户设置不能把自更新指向任意 GitHub 仓库
+            let other_repo = "https://raw.githubusercontent.com/someone/else/main/server.json";
+            assert!(!accepted(other_repo));
+            assert!(!accepted(&format!("{}{other_repo}", config::GITHUB_PROXY)));
+        }
+    }
+
+    #[test]
+    fn github_repo_of_url() {
+        assert_eq!(
+            github_repo_of("https://raw.githubusercontent.com/Owner/Repo/main/server.json"),
+            Some("owner/repo".to_string())
+        );
+        assert_eq!(
+            github_repo_of("https://github.com/owner/repo/releases/download/v1/server.json"),
+            Some("owner/repo".to_string())
+        );
+        assert_eq!(github_repo_of("https://github.com/owner"), None);
+        assert_eq!(github_repo_of("https://objects.githubusercontent.com/x/y"), None);
+    }
+
+    #[test]
+    fn healthy_mirror_is_tried_first() {
+        let mut state = MirrorState::default();
+        state.healthy.insert(
+            "version_manifest".to_string(),
+            config::VERSION_MANIFEST_MIRROR_URL.to_string(),
+        );
+        let list = ordered_candidates(Endpoint::VersionManifest, &BTreeMap::new(), &state);
+        assert_eq!(list[0], config::VERSION_MANIFEST_MIRROR_URL);
+        assert_eq!(list.len(), 2);
+    }
+
+    #[test]
+    fn github_proxy_allows_direct() {
+        let list = ordered_candidates(
+            Endpoint::GithubProxy,
+            &BTreeMap::new(),
+            &MirrorState::default(),
+        );
+        assert_eq!(list, vec![config::GITHUB_PROXY.to_string(), String::new()]);
+    }
+
+    #[test]
+    fn site_of_groups_subdomains() {
+        assert_eq!(
+            site_of("https://meta.fabricmc.net/"),
+            site_of("https://maven.fabricmc.net/")
+        );
+        assert_eq!(
+            site_of("https://bmclapi2.bangbang93.com/maven/").as_deref(),
+            Some("bangbang93.com")
+        );
+        assert_ne!(
+            site_of("https://meta.fabricmc.net/"),
+            site_of("https://bmclapi2.bangbang93.com/fabric-meta/")
+        );
+    }
+
+    #[test]
+    fn strip_known_github_proxy() {
+        let proxies = vec![config::GITHUB_PROXY.to_string(), String::new()];
+        let url = "https://gh.cjcx.org/https://github.com/a/b/releases/download/v1/x.zip";
+        assert_eq!(
+            strip_github_proxy(&proxies, url),
+            Some("https://github.com/a/b/releases/download/v1/x.zip")
+        );
+        assert_eq!(
+            strip_github_proxy(&proxies, "https://github.com/a/b"),
+            Some("https://github.com/a/b")
+        );
+        assert_eq!(
+            strip_github_proxy(&proxies, "https://bmclapi2.bangbang93.com/x"),
+            None
+        );
+    }
+}
@@ -0,0 +1,117 @@
+// ============================================================
+// mod_info.rs — 读取模组 jar 中的 fabric.mod.json
+// ============================================================
+// 文件名不可靠（玩家可能改名，不同网站的命名也不同），
+// 判断两个 jar 是不是同一个模组要看 fabric.mod.json 中的 id。
+// ============================================================
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// fabric.mod.json 的大小上限（正常只有几 KB）
+const MAX_MOD_JSON_SIZE: u64 = 1024 * 1024;
+
+/// fabric.mod.json 中更新器关心的字段
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FabricModInfo {
+    pub id: String,
+    pub version: String,
+    pub name: String,
+}
+
+/// 读取 jar 中的 fabric.mod.json。不是 Fabric 模组（没有该文件）时返回 None。
+pub fn read_fabric_mod(jar_path: &Path) -> Result<Option<FabricModInfo>> {
+    let file = fs::File::open(jar_path)
+        .with_context(|| format!("打开模组失败: {}", jar_path.display()))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .with_context(|| format!("读取模组失败（不是有效的 jar）: {}", jar_path.display()))?;
+    let Ok(entry) = archive.by_name("fabric.mod.json") else {
+        return Ok(None);
+    };
+
+    let mut text = String::new();
+    entry
+        .take(MAX_MOD_JSON_SIZE)
+        .read_to_string(&mut text)
+        .context("读取 fabric.mod.json 失败")?;
+    parse_fabric_mod_json(&text).map(Some)
+}
+
+/// 解析 fabric.mod.json。
+///
+/// 有些模组的 fabric.mod.json 在字符串里直接换行（严格来说不是合法 JSON），
+/// Fabric Loader 能接受，这里把换行替换成空格后再解析。
+pub fn parse_fabric_mod_json(text: &str) -> Result<FabricModInfo> {
+    let text = text.trim_start_matches('\u{feff}').replace(['\r', '\n', '\t'], " ");
+    let json: serde_json::Value =
+        serde_json::from_str(&text).context("fabric.mod.json 不是有效的 JSON")?;
+    let id = json["id"]
+        .as_str()
+        .context("fabric.mod.json 缺少 id")?
+        .to_string();
+    Ok(FabricModInfo {
+        name: json["name"].as_str().unwrap_or(&id).to_string(),
+        version: json["version"].as_str().unwrap_or_default().to_string(),
+        id,
+    })
+}
+
+/// 列出 mods/ 目录下的 .jar 文件名（不递归，按名称排序）
+pub fn list_mod_jars(mods_dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(mods_dir) else {
+        return Vec::new();
+    };
+    let mut jars: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.to_ascii_lowercase().ends_with(".jar"))
+        .collect();
+    jars.sort();
+    jars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_basic_mod_json() {
+        let info = parse_fabric_mod_json(
+            r#"{"schemaVersion":1,"id":"sodium","version":"0.6.0","name":"Sodium"}"#,
+        )
+        .unwrap();
+        assert_eq!(info.id, "sodium");
+        assert_eq!(info.version, "0.6.0");
+        assert_eq!(info.name, "Sodium");
+    }
+
+    #[test]
+    fn parse_tolerates_raw_newlines() {
+        let info = parse_fabric_mod_json("{\"id\":\"a\",\"description\":\"line1\nline2\"}").unwrap();
+        assert_eq!(info.id, "a");
+        assert_eq!(info.name, "a");
+    }
+
+    #[test]
+    fn read_from_jar() {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("upmc_mod_info_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let jar = dir.join("a.jar");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&jar).unwrap());
+        zip.start_file("fabric.mod.json", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(br#"{"id":"lithium","version":"1.0"}"#).unwrap();
+        zip.finish().unwrap();
+
+        let info = read_fabric_mod(&jar).unwrap().unwrap();
+        assert_eq!(info.id, "lithium");
+        assert_eq!(list_mod_jars(&dir), vec!["a.jar".to_string()]);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
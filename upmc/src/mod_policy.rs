@@ -0,0 +1,437 @@
+// ============================================================
+// mod_policy.rs — 未授权模组检测（客户端反作弊报告）
+// ============================================================
+// 管理员可以在 server.json 中配置模组策略：
+//   "mod_policy": {
+//     "deny": ["*xray*", "freecam"],     // 禁止的模组 id / 文件名（支持 * 通配）
+//     "allow": ["journeymap"],           // 整合包之外允许玩家自行添加的模组 id / 文件名
+//     "strict": false,                   // true = 整合包和 allow 之外的模组都算未授权
+//     "quarantine": false                // true = 发现后自动移到 mods/.quarantine/
+//   }
+//
+// 每次启动读取 mods/ 中每个 jar 的 fabric.mod.json，与整合包（受管理文件清单，
+// 按哈希确认没有被替换）和策略对比，在启用「启动 PCL」按钮之前把结果告诉玩家。
+// 同时在 updater/mod_report.json 生成签名报告，玩家可以提交给管理员，
+// 管理员用 `upmc mod-report verify <文件>` 校验。
+//
+// 签名：每个安装首次生成报告时创建一对 Ed25519 密钥（私钥只保存在本机
+// updater/report_key.txt），报告附带公钥和对报告内容的签名。签名能证明：
+//   - 报告生成后没有被改动过（手工编辑、转发途中被改都会校验失败）
+//   - 同一公钥签名的报告来自同一个安装：管理员第一次收到某位玩家的报告时
+//     记下公钥，之后换了公钥的报告就不是原来那台电脑生成的
+// 签名不能证明报告内容属实：私钥和更新器都在玩家电脑上，玩家可以改程序后
+// 签出任意内容。这是客户端检查，只能发现「无意中」或「不加掩饰」的违规，
+// 不能作为作弊与否的证据，服务端仍需自己的反作弊。
+// 旧版 server.json 中的 report_key 会被忽略。
+// ============================================================
+
+use anyhow::{Context, Result, bail};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+use crate::config;
+use crate::managed;
+use crate::mod_info;
+use crate::pack_index;
+use crate::state;
+
+/// 隔离目录（Fabric 不会加载 mods/ 的子目录）
+const QUARANTINE_DIR: &str = ".quarantine";
+
+/// server.json 中的模组策略
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModPolicy {
+    #[serde(default)]
+    pub deny: Vec<String>,
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub strict: bool,
+    #[serde(default)]
+    pub quarantine: bool,
+}
+
+/// 单个 jar 的判定
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    /// 整合包自带（哈希一致）
+    Managed,
+    /// 玩家自行添加，策略允许
+    Allowed,
+    /// 命中禁止列表
+    Denied,
+    /// 严格模式下不在整合包和允许列表中
+    Unlisted,
+}
+
+impl Verdict {
+    pub fn is_violation(self) -> bool {
+        matches!(self, Verdict::Denied | Verdict::Unlisted)
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Verdict::Managed => "整合包",
+            Verdict::Allowed => "允许",
+            Verdict::Denied => "禁止",
+            Verdict::Unlisted => "未授权",
+        }
+    }
+}
+
+/// 扫描到的一个模组
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScannedMod {
+    /// mods/ 下的文件名
+    pub file: String,
+    /// fabric.mod.json 的 id（不是 Fabric 模组时为空）
+    pub id: String,
+    pub version: String,
+    pub sha256: String,
+    pub verdict: Verdict,
+    /// 是否已移到隔离目录
+    pub quarantined: bool,
+}
+
+impl ScannedMod {
+    /// 给玩家看的一行描述
+    pub fn describe(&self) -> String {
+        let id = if self.id.is_empty() { "未知模组" } else { &self.id };
+        let mut line = format!("[{}] mods/{}（{id} {}）", self.verdict.label(), self.file, self.version);
+        if self.quarantined {
+            line.push_str(" — 已隔离");
+        }
+        line
+    }
+}
+
+/// 签名报告（updater/mod_report.json）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModReport {
+    /// 生成时间（Unix 秒）
+    pub generated_at: u64,
+    pub version_tag: String,
+    pub mods: Vec<ScannedMod>,
+    /// 本安装的 Ed25519 公钥（十六进制）
+    #[serde(default)]
+    pub public_key: String,
+    /// Ed25519(私钥, signature 为空时的紧凑 JSON)（十六进制）
+    #[serde(default)]
+    pub signature: String,
+}
+
+impl ModReport {
+    /// 被签名的内容：signature 为空时的紧凑 JSON
+    fn payload(&self) -> Result<Vec<u8>> {
+        let unsigned = ModReport {
+            signature: String::new(),
+            ..self.clone()
+        };
+        serde_json::to_vec(&unsigned).context("序列化模组报告失败")
+    }
+
+    fn sign(&mut self, key: &SigningKey) -> Result<()> {
+        self.public_key = to_hex(key.verifying_key().as_bytes());
+        self.signature = String::new();
+        self.signature = to_hex(&key.sign(&self.payload()?).to_bytes());
+        Ok(())
+    }
+
+    /// 校验签名，返回签名所用的公钥（十六进制）
+    pub fn verify(&self) -> Result<&str> {
+        let public_key: [u8; 32] = from_hex(&self.public_key)
+            .and_then(|bytes| bytes.try_into().ok())
+            .context("报告缺少公钥或公钥格式错误")?;
+        let signature: [u8; 64] = from_hex(&self.signature)
+            .and_then(|bytes| bytes.try_into().ok())
+            .context("报告缺少签名或签名格式错误")?;
+        let public_key = VerifyingKey::from_bytes(&public_key).context("报告公钥无效")?;
+        if public_key
+            .verify(&self.payload()?, &Signature::from_bytes(&signature))
+            .is_err()
+        {
+            bail!("签名不匹配：报告生成后被改动过");
+        }
+        Ok(&self.public_key)
+    }
+}
+
+/// 扫描 mods/，按策略判定，需要时隔离并写入报告。返回所有扫描结果。
+pub fn check(base_dir: &Path, policy: &ModPolicy, version_tag: &str) -> Result<Vec<ScannedMod>> {
+    let mut mods = scan(base_dir, policy)?;
+    if policy.quarantine {
+        quarantine(base_dir, &mut mods)?;
+    }
+    write_report(base_dir, version_tag, &mods)?;
+    Ok(mods)
+}
+
+/// 玩家确认后隔离违规模组并重新生成报告，返回违规项
+#[cfg(windows)]
+pub fn quarantine_violations(
+    base_dir: &Path,
+    policy: &ModPolicy,
+    version_tag: &str,
+) -> Result<Vec<ScannedMod>> {
+    let mut mods = scan(base_dir, policy)?;
+    quarantine(base_dir, &mut mods)?;
+    write_report(base_dir, version_tag, &mods)?;
+    Ok(mods.into_iter().filter(|m| m.verdict.is_violation()).collect())
+}
+
+/// 把违规的模组移到 mods/.quarantine/
+pub fn quarantine(base_dir: &Path, mods: &mut [ScannedMod]) -> Result<()> {
+    let mods_dir = base_dir.join(config::MINECRAFT_DIR).join("mods");
+    let target_dir = mods_dir.join(QUARANTINE_DIR);
+    for module in mods.iter_mut().filter(|m| m.verdict.is_violation() && !m.quarantined) {
+        fs::create_dir_all(&target_dir).context("创建隔离目录失败")?;
+        fs::rename(mods_dir.join(&module.file), target_dir.join(&module.file))
+            .with_context(|| format!("隔离 mods/{} 失败（游戏是否正在运行？）", module.file))?;
+        module.quarantined = true;
+    }
+    Ok(())
+}
+
+fn scan(base_dir: &Path, policy: &ModPolicy) -> Result<Vec<ScannedMod>> {
+    let mods_dir = base_dir.join(config::MINECRAFT_DIR).join("mods");
+    let managed = managed::load(base_dir);
+
+    let mut result = Vec::new();
+    for file in mod_info::list_mod_jars(&mods_dir) {
+        let path = mods_dir.join(&file);
+        let data = fs::read(&path).with_context(|| format!("读取 {} 失败", path.display()))?;
+        let info = mod_info::read_fabric_mod(&path).ok().flatten().unwrap_or_default();
+
+        // 只看文件名不够：作弊模组可以改名成整合包里的文件，必须哈希一致
+        let is_managed = managed
+            .as_ref()
+            .and_then(|m| m.files.get(&format!("mods/{file}")))
+            .is_some_and(|entry| {
+                pack_index::hash_bytes(&entry.hash_format, &data)
+                    .is_ok_and(|actual| actual.eq_ignore_ascii_case(&entry.hash))
+            });
+
+        let verdict = if is_managed {
+            Verdict::Managed
+        } else if policy
+            .deny
+            .iter()
+            .any(|p| matches_pattern(p, &info.id) || matches_pattern(p, &file))
+        {
+            Verdict::Denied
+        } else if policy.strict
+            && managed.is_some()
+            && !policy
+                .allow
+                .iter()
+                .any(|p| matches_pattern(p, &info.id) || matches_pattern(p, &file))
+        {
+            Verdict::Unlisted
+        } else {
+            Verdict::Allowed
+        };
+
+        result.push(ScannedMod {
+            file,
+            id: info.id,
+            version: info.version,
+            sha256: format!("{:x}", Sha256::digest(&data)),
+            verdict,
+            quarantined: false,
+        });
+    }
+    Ok(result)
+}
+
+/// 生成并写入签名报告
+pub fn write_report(base_dir: &Path, version_tag: &str, mods: &[ScannedMod]) -> Result<()> {
+    let generated_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut report = ModReport {
+        generated_at,
+        version_tag: version_tag.to_string(),
+        mods: mods.to_vec(),
+        public_key: String::new(),
+        signature: String::new(),
+    };
+    report.sign(&signing_key(base_dir)?)?;
+
+    state::MOD_REPORT
+        .write_json(base_dir, &report)
+        .context("写入模组报告失败")
+}
+
+/// 读取并校验报告文件（管理员收到玩家提交的报告时使用）
+pub fn verify_report_file(path: &Path) -> Result<ModReport> {
+    let text = fs::read_to_string(path).with_context(|| format!("读取 {} 失败", path.display()))?;
+    let mut value: serde_json::Value =
+        serde_json::from_str(&text).context("报告不是有效的 JSON")?;
+    if let Some(map) = value.as_object_mut() {
+        map.remove("schema_version");
+    }
+    let report: ModReport = serde_json::from_value(value).context("报告格式错误")?;
+    report.verify()?;
+    Ok(report)
+}
+
+/// 本安装的报告签名私钥，不存在（或损坏）时生成新的
+fn signing_key(base_dir: &Path) -> Result<SigningKey> {
+    if let Some(seed) = state::REPORT_KEY
+        .read_text(base_dir)
+        .and_then(|text| from_hex(text.trim()))
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+    {
+        return Ok(SigningKey::from_bytes(&seed));
+    }
+    let mut seed = [0u8; 32];
+    getrandom::fill(&mut seed).map_err(|e| anyhow::anyhow!("生成报告签名密钥失败: {e}"))?;
+    state::REPORT_KEY
+        .write_text(base_dir, &to_hex(&seed))
+        .context("保存报告签名密钥失败")?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// 不区分大小写的 `*` 通配匹配（空字符串不匹配任何模式）
+fn matches_pattern(pattern: &str, value: &str) -> bool {
+    if value.is_empty() {
+        return false;
+    }
+    let pattern = pattern.to_ascii_lowercase();
+    let value = value.to_ascii_lowercase();
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == value;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !value.starts_with(first) || !value[first.len()..].ends_with(last) {
+        return false;
+    }
+    let mut rest = &value[first.len()..value.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_matching() {
+        assert!(matches_pattern("freecam", "FreeCam"));
+        assert!(!matches_pattern("freecam", "freecam2"));
+        assert!(matches_pattern("*xray*", "advanced-xray-fabric"));
+        assert!(matches_pattern("xray*", "xray-1.0.jar"));
+        assert!(!matches_pattern("xray*", "my-xray.jar"));
+        assert!(matches_pattern("*x*ray*", "xenon-ray"));
+        assert!(!matches_pattern("*", ""));
+        assert!(!matches_pattern("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn report_signature_detects_edits() {
+        let dir = crate::http::fake::temp_dir("mod_report");
+        let module = ScannedMod {
+            file: "freecam.jar".to_string(),
+            id: "freecam".to_string(),
+            version: "1.0".to_string(),
+            sha256: String::new(),
+            verdict: Verdict::Denied,
+            quarantined: false,
+        };
+        write_report(&dir, "v1", std::slice::from_ref(&module)).unwrap();
+        let path = dir.join(config::MOD_REPORT_FILE);
+        let report = verify_report_file(&path).unwrap();
+
+        // 同一安装再次生成的报告使用同一公钥
+        write_report(&dir, "v2", &[]).unwrap();
+        assert_eq!(
+            verify_report_file(&path).unwrap().public_key,
+            report.public_key
+        );
+
+        // 改动报告内容后签名失效
+        write_report(&dir, "v1", &[module]).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::write(&path, text.replace("\"denied\"", "\"allowed\"")).unwrap();
+        assert!(verify_report_file(&path).is_err());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn scan_flags_denied_and_renamed_mods() {
+        let dir = std::env::temp_dir().join(format!("upmc_mod_policy_{}", std::process::id()));
+        let mods_dir = dir.join(".minecraft/mods");
+        fs::create_dir_all(&mods_dir).unwrap();
+        // 整合包里的 sodium.jar 被替换成了别的内容
+        fs::write(mods_dir.join("sodium.jar"), b"not the real sodium").unwrap();
+        fs::write(mods_dir.join("xray-helper.jar"), b"PK").unwrap();
+        let mut managed = managed::ManagedFiles::default();
+        managed.files.insert(
+            "mods/sodium.jar".to_string(),
+            managed::ManagedFile {
+                hash_format: "sha256".to_string(),
+                hash: format!("{:x}", Sha256::digest(b"real sodium")),
+            },
+        );
+        managed::save(&dir, &managed).unwrap();
+
+        let policy = ModPolicy {
+            deny: vec!["*xray*".to_string()],
+            strict: true,
+            ..Default::default()
+        };
+        let mods = scan(&dir, &policy).unwrap();
+        let verdict = |file: &str| mods.iter().find(|m| m.file == file).unwrap().verdict;
+        assert_eq!(verdict("sodium.jar"), Verdict::Unlisted);
+        assert_eq!(verdict("xray-helper.jar"), Verdict::Denied);
+
+        // 不是 Fabric 模组（读不到 id）时按文件名匹配允许列表
+        fs::write(mods_dir.join("journeymap-1.0.jar"), b"PK").unwrap();
+        let allowed = ModPolicy {
+            allow: vec!["journeymap*".to_string()],
+            ..policy.clone()
+        };
+        let rescanned = scan(&dir, &allowed).unwrap();
+        let module = rescanned
+            .iter()
+            .find(|m| m.file == "journeymap-1.0.jar")
+            .unwrap();
+        assert!(module.id.is_empty());
+        assert_eq!(module.verdict, Verdict::Allowed);
+        fs::remove_file(mods_dir.join("journeymap-1.0.jar")).unwrap();
+
+        let mut mods = mods;
+        quarantine(&dir, &mut mods).unwrap();
+        assert!(mods_dir.join(".quarantine/xray-helper.jar").exists());
+        assert!(mods.iter().all(|m| m.quarantined));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,257 @@
+// ============================================================
+// optional_mods.rs — 可选模组（packwiz [option] 元数据）
+// ============================================================
+// packwiz 的 .pw.toml 可以声明：
+//   [option]
+//   optional = true
+//   default = false
+//   description = "光影支持，低配电脑不建议开启"
+//
+// packwiz-installer 以 -g 无头模式运行时只会按 default 安装，
+// 玩家没有机会选择。这里在同步后按玩家的选择（settings.json 的
+// optional_mods，键为元数据文件路径）调整：
+//   - 启用：恢复 <文件>.disabled，或按 [download] 下载并校验哈希。
+//     已有文件也要校验哈希：整合包更新模组时路径不变、只换哈希，
+//     禁用期间留下的旧版本不能直接拿来启用
+//   - 禁用：重命名为 <文件>.disabled（Fabric 不会加载）
+//
+// 可选模组列表在整合包变化时随文件列表（pack_index::resolve_files）刷新，
+// 缓存在 updater/optional_mods.json，设置窗口据此显示选择列表。
+// ============================================================
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::bootstrap;
+use crate::config;
+use crate::pack_index::{self, ResolvedFile};
+use crate::state;
+
+/// 一个可选模组
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptionalMod {
+    /// 元数据文件路径（相对 .minecraft），作为玩家选择的键
+    pub metafile: String,
+    pub name: String,
+    pub description: String,
+    pub default: bool,
+    /// 实际文件路径（相对 .minecraft）
+    pub target: String,
+    pub download_url: Option<String>,
+    pub hash_format: String,
+    pub hash: String,
+}
+
+/// 从整合包文件列表中找出可选模组并写入缓存。
+pub fn refresh(base_dir: &Path, files: &[ResolvedFile]) -> Result<Vec<OptionalMod>> {
+    let mut mods = Vec::new();
+    for file in files {
+        let Some(meta) = &file.meta else {
+            continue;
+        };
+        let Some(option) = meta.option.as_ref().filter(|o| o.optional) else {
+            continue;
+        };
+        if meta.side == "server" {
+            continue;
+        }
+        mods.push(OptionalMod {
+            metafile: file.entry.file.clone(),
+            name: meta.name.clone(),
+            description: option.description.clone(),
+            default: option.default,
+            target: file.target.clone(),
+            download_url: meta.download_url.clone(),
+            hash_format: meta.hash_format.clone(),
+            hash: meta.hash.clone(),
+        });
+    }
+
+    save(base_dir, &mods)?;
+    Ok(mods)
+}
+
+/// 可选模组列表是否已缓存
+pub fn is_cached(base_dir: &Path) -> bool {
+    state::OPTIONAL_MODS.exists(base_dir)
+}
+
+/// 读取缓存的可选模组列表（不存在时为空）
+pub fn load(base_dir: &Path) -> Vec<OptionalMod> {
+    state::OPTIONAL_MODS.read_list(base_dir)
+}
+
+fn save(base_dir: &Path, mods: &[OptionalMod]) -> Result<()> {
+    state::OPTIONAL_MODS
+        .write_list(base_dir, mods)
+        .context("写入可选模组列表失败")
+}
+
+/// 玩家选择的启用状态，未选择过时使用整合包的默认值
+pub fn is_enabled(module: &OptionalMod, choices: &BTreeMap<String, bool>) -> bool {
+    choices.get(&module.metafile).copied().unwrap_or(module.default)
+}
+
+/// 按玩家选择启用 / 禁用可选模组。
+///
+/// 单个模组失败不影响其他模组，失败信息汇总后返回错误。
+pub fn apply(base_dir: &Path, mods: &[OptionalMod], choices: &BTreeMap<String, bool>) -> Result<()> {
+    let mc_dir = base_dir.join(config::MINECRAFT_DIR);
+    let mut failures = Vec::new();
+
+    for module in mods {
+        let result = if is_enabled(module, choices) {
+            enable(&mc_dir, module)
+        } else {
+            disable(&mc_dir, module)
+        };
+        if let Err(e) = result {
+            failures.push(format!("  • {}: {e:#}", module.name));
+        }
+    }
+
+    if !failures.is_empty() {
+        bail!("部分可选模组处理失败:\n{}", failures.join("\n"));
+    }
+    Ok(())
+}
+
+fn paths(mc_dir: &Path, module: &OptionalMod) -> Result<(PathBuf, PathBuf)> {
+    let target = bootstrap::safe_zip_output_path(mc_dir, &module.target)?;
+    let mut disabled = target.clone().into_os_string();
+    disabled.push(".disabled");
+    Ok((target, PathBuf::from(disabled)))
+}
+
+fn enable(mc_dir: &Path, module: &OptionalMod) -> Result<()> {
+    let (target, disabled) = paths(mc_dir, module)?;
+    if target.exists() {
+        if is_current(&target, module) {
+            fs::remove_file(&disabled).ok();
+            return Ok(());
+        }
+        fs::remove_file(&target)
+            .with_context(|| format!("删除旧版本 {} 失败", target.display()))?;
+    }
+    if disabled.exists() {
+        if is_current(&disabled, module) {
+            return fs::rename(&disabled, &target)
+                .with_context(|| format!("恢复 {} 失败", disabled.display()));
+        }
+        fs::remove_file(&disabled)
+            .with_context(|| format!("删除旧版本 {} 失败", disabled.display()))?;
+    }
+
+    let url = module
+        .download_url
+        .as_deref()
+        .context("整合包没有提供下载地址，无法单独下载")?;
+    if !url.starts_with("https://") {
+        bail!("下载地址必须使用 HTTPS: {url}");
+    }
+
+    let data = pack_index::download_verified(url, &module.hash_format, &module.hash)?;
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&target, data).with_context(|| format!("写入 {} 失败", target.display()))?;
+    Ok(())
+}
+
+/// 本地文件是否与整合包中的版本一致（读取失败或哈希不符都视为不一致）
+fn is_current(path: &Path, module: &OptionalMod) -> bool {
+    fs::read(path)
+        .is_ok_and(|data| pack_index::verify_hash(&module.hash_format, &data, &module.hash).is_ok())
+}
+
+fn disable(mc_dir: &Path, module: &OptionalMod) -> Result<()> {
+    let (target, disabled) = paths(mc_dir, module)?;
+    if !target.exists() {
+        return Ok(());
+    }
+    // packwiz 可能在同步时重新下载了被禁用的模组，以最新文件为准
+    fs::remove_file(&disabled).ok();
+    fs::rename(&target, &disabled).with_context(|| format!("禁用 {} 失败", target.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(default: bool) -> OptionalMod {
+        OptionalMod {
+            metafile: "mods/iris.pw.toml".to_string(),
+            name: "Iris".to_string(),
+            description: String::new(),
+            default,
+            target: "mods/iris.jar".to_string(),
+            download_url: None,
+            hash_format: "sha256".to_string(),
+            hash: pack_index::hash_bytes("sha256", b"PK").unwrap(),
+        }
+    }
+
+    #[test]
+    fn choice_overrides_default() {
+        let module = sample(false);
+        let mut choices = BTreeMap::new();
+        assert!(!is_enabled(&module, &choices));
+        choices.insert(module.metafile.clone(), true);
+        assert!(is_enabled(&module, &choices));
+    }
+
+    #[test]
+    fn disable_then_enable_renames() {
+        let dir = crate::http::fake::temp_dir("optional_rename");
+        let mods_dir = dir.join("mods");
+        fs::create_dir_all(&mods_dir).unwrap();
+        fs::write(mods_dir.join("iris.jar"), b"PK").unwrap();
+        let module = sample(true);
+
+        disable(&dir, &module).unwrap();
+        assert!(!mods_dir.join("iris.jar").exists());
+        assert!(mods_dir.join("iris.jar.disabled").exists());
+
+        enable(&dir, &module).unwrap();
+        assert!(mods_dir.join("iris.jar").exists());
+        assert!(!mods_dir.join("iris.jar.disabled").exists());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn enable_replaces_stale_disabled_file() {
+        let dir = crate::http::fake::temp_dir("optional_stale");
+        let mods_dir = dir.join("mods");
+        fs::create_dir_all(&mods_dir).unwrap();
+        // 禁用期间整合包更新了模组，.disabled 还是旧版本
+        fs::write(mods_dir.join("iris.jar.disabled"), b"old iris").unwrap();
+        let url = "https://cdn.modrinth.com/data/iris/iris.jar";
+        let module = OptionalMod {
+            download_url: Some(url.to_string()),
+            ..sample(true)
+        };
+        let fake = crate::http::fake::FakeServer::new()
+            .route(url, "PK")
+            .install();
+
+        enable(&dir, &module).unwrap();
+        assert_eq!(fs::read(mods_dir.join("iris.jar")).unwrap(), b"PK");
+        assert!(!mods_dir.join("iris.jar.disabled").exists());
+        assert_eq!(fake.server.requested_urls(), vec![url.to_string()]);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn enable_without_url_fails() {
+        let dir = crate::http::fake::temp_dir("optional_no_url");
+        fs::create_dir_all(&dir).unwrap();
+        assert!(enable(&dir, &sample(false)).is_err());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
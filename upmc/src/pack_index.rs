@@ -0,0 +1,505 @@
+// ============================================================
+// pack_index.rs — packwiz 索引解析
+// ============================================================
+// packwiz 整合包的结构：
+//   pack.toml        [index] file = "index.toml"，hash-format / hash
+//   index.toml       [[files]] file / hash / metafile / preserve
+//   mods/*.pw.toml   name / filename / side / version / [download] / [option] / [update.*]
+//
+// 这里只解析更新器需要的字段（可选模组、完整性校验等）：
+// 用 toml 反序列化到下面的 *Toml 结构，再转换成对外的类型，未知字段忽略。
+// ============================================================
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::error::{self, ErrorCode};
+use crate::http::{self, RequestKind};
+use crate::source;
+
+/// 单个整合包文件的下载大小上限
+const MAX_FILE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// index.toml 中的一个文件条目
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// 相对整合包根目录（= .minecraft）的路径，如 "mods/sodium.pw.toml"
+    pub file: String,
+    pub hash: String,
+    /// 条目自身的 hash-format，缺省时使用索引的 hash-format
+    pub hash_format: String,
+    /// 是否为 .pw.toml 元数据文件（实际文件需按其中的 [download] 下载）
+    pub metafile: bool,
+    /// 是否只在首次安装时写入（之后保留玩家修改）
+    pub preserve: bool,
+}
+
+/// index.toml 解析结果
+#[derive(Debug, Clone, Default)]
+pub struct PackIndex {
+    pub files: Vec<IndexEntry>,
+}
+
+/// 索引中的一个文件及其在 .minecraft 中的实际位置
+#[derive(Debug, Clone)]
+pub struct ResolvedFile {
+    pub entry: IndexEntry,
+    /// 实际文件路径（相对 .minecraft）：普通文件即 entry.file，元数据文件为其指向的文件
+    pub target: String,
+    /// 元数据文件的内容（普通文件为 None）
+    pub meta: Option<ModMeta>,
+}
+
+/// .pw.toml 中的 [option] 段
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ModOption {
+    pub optional: bool,
+    pub default: bool,
+    pub description: String,
+}
+
+/// .pw.toml 元数据
+#[derive(Debug, Clone, Default)]
+pub struct ModMeta {
+    pub name: String,
+    /// 下载后的文件名，位于元数据文件同一目录
+    pub filename: String,
+    /// client / server / both
+    pub side: String,
+    pub download_url: Option<String>,
+    pub hash_format: String,
+    pub hash: String,
+    pub option: Option<ModOption>,
+    /// 版本：`version` 字段，没有时取 [update.*] 中的 version / file-id（用于更新日志）
+    pub version: Option<String>,
+}
+
+fn default_hash_format() -> String {
+    "sha256".to_string()
+}
+
+fn default_side() -> String {
+    "both".to_string()
+}
+
+/// pack.toml（只取 [index] 段）
+#[derive(Deserialize)]
+struct PackToml {
+    index: PackIndexToml,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct PackIndexToml {
+    file: String,
+    #[serde(default = "default_hash_format")]
+    hash_format: String,
+    #[serde(default)]
+    hash: String,
+}
+
+/// index.toml
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct IndexToml {
+    #[serde(default = "default_hash_format")]
+    hash_format: String,
+    #[serde(default)]
+    files: Vec<IndexFileToml>,
+}
+
+/// index.toml 中的 [[files]] 条目
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct IndexFileToml {
+    file: String,
+    #[serde(default)]
+    hash: String,
+    hash_format: Option<String>,
+    #[serde(default)]
+    metafile: bool,
+    #[serde(default)]
+    preserve: bool,
+}
+
+/// .pw.toml
+#[derive(Deserialize)]
+struct ModToml {
+    name: Option<String>,
+    filename: String,
+    #[serde(default = "default_side")]
+    side: String,
+    download: DownloadToml,
+    option: Option<ModOption>,
+    version: Option<String>,
+    #[serde(default)]
+    update: BTreeMap<String, UpdateToml>,
+}
+
+/// .pw.toml 中的 [update.modrinth] / [update.curseforge] 等段
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct UpdateToml {
+    version: Option<String>,
+    /// CurseForge 的文件 id 是整数
+    file_id: Option<toml::Value>,
+}
+
+impl UpdateToml {
+    fn version(&self) -> Option<String> {
+        self.version.clone().or_else(|| match &self.file_id {
+            Some(toml::Value::String(id)) => Some(id.clone()),
+            Some(toml::Value::Integer(id)) => Some(id.to_string()),
+            _ => None,
+        })
+    }
+}
+
+/// .pw.toml 中的 [download] 段
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct DownloadToml {
+    url: Option<String>,
+    #[serde(default)]
+    hash_format: String,
+    #[serde(default)]
+    hash: String,
+}
+
+/// 从 pack.toml 中读取索引位置：(文件路径, hash-format, hash)
+pub fn index_location(pack_toml: &str) -> Result<(String, String, String)> {
+    let pack: PackToml = toml::from_str(pack_toml).context("解析 pack.toml 的 [index] 段失败")?;
+    Ok((pack.index.file, pack.index.hash_format, pack.index.hash))
+}
+
+/// 解析 index.toml
+pub fn parse_index(text: &str) -> Result<PackIndex> {
+    let index: IndexToml = toml::from_str(text).context("解析 index.toml 失败")?;
+
+    let mut files = Vec::new();
+    for entry in index.files {
+        validate_relative_path(&entry.file)?;
+        files.push(IndexEntry {
+            file: entry.file,
+            hash: entry.hash,
+            hash_format: entry
+                .hash_format
+                .unwrap_or_else(|| index.hash_format.clone()),
+            metafile: entry.metafile,
+            preserve: entry.preserve,
+        });
+    }
+
+    Ok(PackIndex { files })
+}
+
+/// 解析 .pw.toml 元数据
+pub fn parse_mod_meta(text: &str) -> Result<ModMeta> {
+    let meta: ModToml = toml::from_str(text).context("解析 .pw.toml 失败")?;
+
+    if meta.filename.contains(['/', '\\']) || meta.filename.contains("..") {
+        bail!(".pw.toml 中的文件名非法: {}", meta.filename);
+    }
+
+    let version = meta
+        .version
+        .or_else(|| meta.update.values().find_map(UpdateToml::version))
+        .filter(|version| !version.trim().is_empty());
+
+    Ok(ModMeta {
+        name: meta.name.unwrap_or_else(|| meta.filename.clone()),
+        filename: meta.filename,
+        side: meta.side,
+        download_url: meta.download.url,
+        hash_format: meta.download.hash_format,
+        hash: meta.download.hash,
+        option: meta.option,
+        version,
+    })
+}
+
+/// 元数据文件对应的实际文件路径（相对 .minecraft），如 mods/sodium.pw.toml → mods/sodium.jar
+pub fn meta_target_path(metafile: &str, filename: &str) -> String {
+    match metafile.rfind('/') {
+        Some(i) => format!("{}/{filename}", &metafile[..i]),
+        None => filename.to_string(),
+    }
+}
+
+/// 计算内容哈希（小写十六进制）。支持 sha1 / sha256 / sha512。
+pub fn hash_bytes(hash_format: &str, data: &[u8]) -> Result<String> {
+    Ok(match hash_format {
+        "sha1" => format!("{:x}", Sha1::digest(data)),
+        "sha256" => format!("{:x}", Sha256::digest(data)),
+        "sha512" => format!("{:x}", Sha512::digest(data)),
+        other => bail!("不支持的哈希格式: {other}"),
+    })
+}
+
+/// 校验内容哈希
+pub fn verify_hash(hash_format: &str, data: &[u8], expected: &str) -> Result<()> {
+    let actual = hash_bytes(hash_format, data)?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(error::new(
+            ErrorCode::HashMismatch,
+            format!("哈希不匹配（{hash_format}）：期望 {expected}，实际 {actual}"),
+        ));
+    }
+    Ok(())
+}
+
+/// 按 URL 读取 index.toml / .pw.toml 文本的方式：联网读取来源（`source::read_text`），
+/// 或读取离线安装包中的快照（见 bundle.rs）。
+pub type ReadText<'a> = &'a dyn Fn(&str) -> Result<String>;
+
+/// 获取并校验整合包索引，返回 (index.toml 的 URL, 索引)。
+pub fn fetch_index(base_dir: &Path, pack_url: &str, pack_toml: &str) -> Result<(String, PackIndex)> {
+    read_index(pack_url, pack_toml, &|url| source::read_text(base_dir, url))
+}
+
+fn read_index(pack_url: &str, pack_toml: &str, read: ReadText) -> Result<(String, PackIndex)> {
+    let (file, hash_format, hash) = index_location(pack_toml)?;
+    let index_url = source::resolve_relative(pack_url, &file);
+    let text = read(&index_url).with_context(|| format!("获取 index.toml 失败: {index_url}"))?;
+    if !hash.is_empty() {
+        verify_hash(&hash_format, text.as_bytes(), &hash).context("index.toml 校验失败")?;
+    }
+    Ok((index_url, parse_index(&text)?))
+}
+
+/// 获取并校验一个 .pw.toml 元数据文件。
+pub fn fetch_mod_meta(base_dir: &Path, index_url: &str, entry: &IndexEntry) -> Result<ModMeta> {
+    read_mod_meta(index_url, entry, &|url| source::read_text(base_dir, url))
+}
+
+fn read_mod_meta(index_url: &str, entry: &IndexEntry, read: ReadText) -> Result<ModMeta> {
+    let url = source::resolve_relative(index_url, &entry.file);
+    let text = read(&url).with_context(|| format!("获取 {} 失败", entry.file))?;
+    if !entry.hash.is_empty() {
+        verify_hash(&entry.hash_format, text.as_bytes(), &entry.hash)
+            .with_context(|| format!("{} 校验失败", entry.file))?;
+    }
+    parse_mod_meta(&text).with_context(|| format!("解析 {} 失败", entry.file))
+}
+
+/// 下载（或从本地来源读取）一个整合包文件并校验哈希。
+pub fn download_verified(url: &str, hash_format: &str, hash: &str) -> Result<Vec<u8>> {
+    // 不支持的哈希格式下载了也无法校验，直接报错
+    hash_bytes(hash_format, &[])?;
+    let data = if url.starts_with("file://") {
+        let path = source::path_from_arg(url);
+        std::fs::read(&path).with_context(|| format!("读取本地文件失败: {}", path.display()))?
+    } else {
+        http::get(url, &[], RequestKind::Download)
+            .with_context(|| format!("下载失败: {url}"))?
+            .read_to_vec(MAX_FILE_SIZE)
+            .context("读取下载数据失败")?
+    };
+    verify_hash(hash_format, &data, hash)?;
+    Ok(data)
+}
+
+/// 获取索引并读取所有元数据文件，得到每个文件的实际位置。
+///
+/// HTTP 条件缓存让未变化的元数据文件只需一次 304。
+pub fn resolve_files(base_dir: &Path, pack_url: &str, pack_toml: &str) -> Result<Vec<ResolvedFile>> {
+    resolve_files_with(pack_url, pack_toml, &|url| source::read_text(base_dir, url))
+}
+
+/// 同 `resolve_files`，用指定方式读取索引和元数据文件。
+pub fn resolve_files_with(
+    pack_url: &str,
+    pack_toml: &str,
+    read: ReadText,
+) -> Result<Vec<ResolvedFile>> {
+    let (index_url, index) = read_index(pack_url, pack_toml, read)?;
+    index
+        .files
+        .into_iter()
+        .map(|entry| {
+            if !entry.metafile {
+                return Ok(ResolvedFile {
+                    target: entry.file.clone(),
+                    entry,
+                    meta: None,
+                });
+            }
+            let meta = read_mod_meta(&index_url, &entry, read)?;
+            Ok(ResolvedFile {
+                target: meta_target_path(&entry.file, &meta.filename),
+                entry,
+                meta: Some(meta),
+            })
+        })
+        .collect()
+}
+
+/// 拒绝绝对路径和 `..`，防止索引把文件写到 .minecraft 之外
+fn validate_relative_path(path: &str) -> Result<()> {
+    if path.starts_with(['/', '\\'])
+        || path.contains(':')
+        || path.split(['/', '\\']).any(|part| part == "..")
+    {
+        bail!("索引中的路径非法: {path}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INDEX: &str = r#"
+hash-format = "sha256"
+
+[[files]]
+file = "config/sodium-options.json"
+hash = "aaaa"
+preserve = true
+
+[[files]]
+file = "mods/sodium.pw.toml"
+hash = "bbbb"
+metafile = true
+"#;
+
+    const META: &str = r#"
+name = "Iris Shaders"
+filename = "iris-1.8.0.jar"
+side = "client"
+
+[download]
+url = "https://cdn.modrinth.com/data/YL57xq9U/versions/x/iris-1.8.0.jar"
+hash-format = "sha512"
+hash = "cccc"
+
+[option]
+optional = true
+default = false
+description = "光影支持，低配电脑不建议开启"
+
+[update]
+[update.modrinth]
+mod-id = "YL57xq9U"
+version = "x"
+"#;
+
+    #[test]
+    fn parse_index_entries() {
+        let index = parse_index(INDEX).unwrap();
+        assert_eq!(index.files.len(), 2);
+        assert!(index.files[0].preserve);
+        assert!(!index.files[0].metafile);
+        assert!(index.files[1].metafile);
+        assert_eq!(index.files[1].hash_format, "sha256");
+    }
+
+    #[test]
+    fn parse_index_handles_full_toml_syntax() {
+        // 多行字符串、行内表、转义、行尾注释都按 TOML 规范处理
+        let text = r#"
+hash-format = "sha1"
+files = [
+  { file = "mods/a.pw.toml", hash = "aa", metafile = true }, # 行内表
+  { file = "config/\u0061.txt", hash = 'bb', hash-format = "sha256" },
+]
+"#;
+        let index = parse_index(text).unwrap();
+        assert_eq!(index.files.len(), 2);
+        assert_eq!(index.files[0].hash_format, "sha1");
+        assert!(index.files[0].metafile);
+        assert_eq!(index.files[1].file, "config/a.txt");
+        assert_eq!(index.files[1].hash_format, "sha256");
+
+        let meta = parse_mod_meta(
+            "filename = \"a.jar\"\n[download]\nurl = \"https://x/a.jar\"\n\
+             [option]\noptional = true\ndescription = \"\"\"\n第一行\n第二行\"\"\"\n",
+        )
+        .unwrap();
+        assert_eq!(meta.option.unwrap().description, "第一行\n第二行");
+    }
+
+    #[test]
+    fn parse_rejects_invalid_toml() {
+        assert!(parse_index("[[files]\nfile = \"a\"").is_err());
+        assert!(parse_mod_meta("filename = \"a.jar\"\n").is_err());
+        assert!(index_location("name = \"p\"\n").is_err());
+    }
+
+    #[test]
+    fn parse_index_rejects_traversal() {
+        let text = "[[files]]\nfile = \"../evil.jar\"\nhash = \"x\"\n";
+        assert!(parse_index(text).is_err());
+    }
+
+    #[test]
+    fn parse_meta_with_option() {
+        let meta = parse_mod_meta(META).unwrap();
+        assert_eq!(meta.name, "Iris Shaders");
+        assert_eq!(meta.filename, "iris-1.8.0.jar");
+        assert_eq!(meta.side, "client");
+        assert_eq!(meta.hash_format, "sha512");
+        let option = meta.option.unwrap();
+        assert!(option.optional);
+        assert!(!option.default);
+        assert!(option.description.contains("光影"));
+        assert_eq!(meta.version.as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn parse_meta_version_sources() {
+        let base = "filename = \"a.jar\"\n";
+        let download = "[download]\nurl = \"https://x/a.jar\"\n";
+        let parse = |extra: &str, update: &str| {
+            parse_mod_meta(&format!("{base}{extra}{download}{update}"))
+                .unwrap()
+                .version
+        };
+        let modrinth = "[update.modrinth]\nversion = \"abc\"\n";
+        let curseforge = "[update.curseforge]\nfile-id = 4567\nproject-id = 1\n";
+        assert_eq!(
+            parse("version = \"1.2.0\"\n", modrinth).as_deref(),
+            Some("1.2.0")
+        );
+        assert_eq!(parse("", modrinth).as_deref(), Some("abc"));
+        assert_eq!(parse("", curseforge).as_deref(), Some("4567"));
+        assert_eq!(parse("", ""), None);
+    }
+
+    #[test]
+    fn parse_meta_without_option() {
+        let text = "name = \"A\"\nfilename = \"a.jar\"\n[download]\nurl = \"https://x/a.jar\"\nhash-format = \"sha1\"\nhash = \"d\"\n";
+        let meta = parse_mod_meta(text).unwrap();
+        assert!(meta.option.is_none());
+        assert_eq!(meta.side, "both");
+    }
+
+    #[test]
+    fn index_location_from_pack() {
+        let pack = "name = \"p\"\n[index]\nfile = \"index.toml\"\nhash-format = \"sha256\"\nhash = \"e\"\n[versions]\nminecraft = \"1.21.11\"\n";
+        let (file, format, hash) = index_location(pack).unwrap();
+        assert_eq!(file, "index.toml");
+        assert_eq!(format, "sha256");
+        assert_eq!(hash, "e");
+    }
+
+    #[test]
+    fn meta_target_path_uses_meta_dir() {
+        assert_eq!(meta_target_path("mods/iris.pw.toml", "iris.jar"), "mods/iris.jar");
+        assert_eq!(meta_target_path("iris.pw.toml", "iris.jar"), "iris.jar");
+    }
+
+    #[test]
+    fn verify_hash_formats() {
+        let sha256 = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        assert!(verify_hash("sha256", b"hello", sha256).is_ok());
+        assert!(verify_hash("sha256", b"hello!", sha256).is_err());
+        assert!(verify_hash("sha1", b"hello", "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d").is_ok());
+        assert!(verify_hash("murmur2", b"hello", "1").is_err());
+    }
+}
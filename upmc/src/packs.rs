@@ -0,0 +1,401 @@
+// ============================================================
+// packs.rs — 整合包版本快照与回退
+// ============================================================
+// 只缓存最新的 pack.toml 时，管理员发布了有问题的整合包，玩家就没有退路。
+// 每次同步后把整合包的元数据完整保存到 updater/packs/<id>/：
+//   pack.toml + index.toml + 所有 .pw.toml + 配置等普通文件
+// 保留最近 MAX_SNAPSHOTS 个（history.json 记录顺序，最新的在前）。
+//
+// 回退时写入 updater/pack_pin.json 固定到上一个快照，之后每次启动
+// 都用快照的 pack.toml（file:// 地址）代替服务器上的整合包，
+// 版本检查（needs_version_upgrade）也以固定的版本为准。
+// 玩家取消固定，或服务器发布了新的 pack.toml 时，固定自动失效。
+// ============================================================
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::bootstrap;
+use crate::config;
+use crate::pack_index;
+use crate::platform;
+use crate::source;
+use crate::state;
+use crate::version::{self, RemoteVersion};
+
+/// 保留的快照数量
+const MAX_SNAPSHOTS: usize = 5;
+/// 快照目录中的 pack.toml 文件名
+const PACK_TOML: &str = "pack.toml";
+
+/// 一个已保存的整合包版本
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackRecord {
+    /// pack.toml 内容的哈希（前 16 位），同时是快照目录名
+    pub id: String,
+    pub version_tag: String,
+    /// 同步时间（本地时间）
+    pub date: String,
+}
+
+/// 固定的整合包版本
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackPin {
+    /// 固定到的快照
+    pub id: String,
+    /// 固定时服务器上的 pack.toml 的 id；服务器变化即视为发布了新版本
+    pub remote_id: String,
+}
+
+/// pack.toml 内容对应的快照 id
+pub fn pack_id(pack_toml: &str) -> String {
+    let hash = pack_index::hash_bytes("sha256", pack_toml.as_bytes()).unwrap_or_default();
+    hash.chars().take(16).collect()
+}
+
+/// 同步完成后保存当前整合包的快照（已存在时只调整顺序），并清理多余的旧快照。
+///
+/// 元数据文件在同步后已在 HTTP 缓存中，普通文件优先从 .minecraft 复制，
+/// 只有被玩家改过（哈希不符）时才重新下载。
+pub fn save_snapshot(base_dir: &Path, remote: &RemoteVersion) -> Result<()> {
+    let id = pack_id(&remote.pack_toml_raw);
+    let dir = snapshot_dir(base_dir, &id);
+    if !dir.join(PACK_TOML).is_file() {
+        // 先写到临时目录，避免中途失败留下不完整的快照
+        let temp = dir.with_extension("tmp");
+        let _ = fs::remove_dir_all(&temp);
+        write_snapshot(base_dir, remote, &temp)?;
+        fs::rename(&temp, &dir)
+            .with_context(|| format!("保存整合包快照失败: {}", dir.display()))?;
+    }
+
+    let mut history = load_history(base_dir);
+    history.retain(|record| record.id != id);
+    history.insert(
+        0,
+        PackRecord {
+            id,
+            version_tag: remote.version_tag.clone(),
+            date: platform::local_timestamp(),
+        },
+    );
+    let pinned = load_pin(base_dir).map(|pin| pin.id);
+    let mut kept = 0;
+    history.retain(|record| {
+        kept += 1;
+        kept <= MAX_SNAPSHOTS || pinned.as_ref() == Some(&record.id)
+    });
+    save_history(base_dir, &history)?;
+    remove_stale_snapshots(base_dir, &history);
+    Ok(())
+}
+
+/// 该 pack.toml 是否已有快照
+pub fn has_snapshot(base_dir: &Path, pack_toml: &str) -> bool {
+    let id = pack_id(pack_toml);
+    load_history(base_dir).iter().any(|record| record.id == id)
+        && snapshot_dir(base_dir, &id).join(PACK_TOML).is_file()
+}
+
+/// 读取已保存的整合包版本（最新的在前）
+pub fn load_history(base_dir: &Path) -> Vec<PackRecord> {
+    state::PACK_HISTORY.read_list(base_dir)
+}
+
+/// 读取固定的整合包版本
+pub fn load_pin(base_dir: &Path) -> Option<PackPin> {
+    state::PACK_PIN.read_json(base_dir)
+}
+
+/// 固定到的整合包版本记录（没有固定时返回 None）
+pub fn pinned_record(base_dir: &Path) -> Option<PackRecord> {
+    let pin = load_pin(base_dir)?;
+    load_history(base_dir).into_iter().find(|record| record.id == pin.id)
+}
+
+/// 有固定版本时，用快照替换服务器上的整合包。
+///
+/// 服务器的 pack.toml 与固定时不同（发布了新版本）或快照已损坏时取消固定，
+/// 返回原来的远程版本。
+pub fn apply_pin(base_dir: &Path, remote: RemoteVersion) -> RemoteVersion {
+    let Some(pin) = load_pin(base_dir) else {
+        return remote;
+    };
+    if pin.remote_id != pack_id(&remote.pack_toml_raw) {
+        eprintln!("服务器已发布新的整合包版本，取消固定");
+        let _ = unpin(base_dir);
+        return remote;
+    }
+
+    let path = snapshot_dir(base_dir, &pin.id).join(PACK_TOML);
+    let pinned = fs::read_to_string(&path)
+        .with_context(|| format!("读取整合包快照失败: {}", path.display()))
+        .and_then(|raw| version::with_pack(&remote, source::path_to_file_url(&path), raw));
+    match pinned {
+        Ok(pinned) => pinned,
+        Err(e) => {
+            eprintln!("固定的整合包版本不可用，已取消固定: {e:#}");
+            let _ = unpin(base_dir);
+            remote
+        }
+    }
+}
+
+/// 回退到上一个整合包版本并固定，下次启动时重新同步。返回回退到的版本。
+pub fn rollback(base_dir: &Path) -> Result<PackRecord> {
+    let remote = version::cached_remote_version(base_dir)
+        .context("缺少整合包信息，请先联网运行一次更新器")?;
+    let current = version::read_pack_cache(base_dir)
+        .map(|raw| pack_id(&raw))
+        .unwrap_or_default();
+
+    let history = load_history(base_dir);
+    let start = history
+        .iter()
+        .position(|record| record.id == current)
+        .map_or(0, |i| i + 1);
+    let target = history[start..]
+        .iter()
+        .find(|record| {
+            record.id != current && snapshot_dir(base_dir, &record.id).join(PACK_TOML).is_file()
+        })
+        .context("没有更早的整合包版本可以回退")?
+        .clone();
+
+    let pin = PackPin {
+        id: target.id.clone(),
+        remote_id: pack_id(&remote.pack_toml_raw),
+    };
+    state::PACK_PIN
+        .write_json(base_dir, &pin)
+        .context("写入整合包固定信息失败")?;
+    Ok(target)
+}
+
+/// 取消固定。返回之前是否有固定。
+pub fn unpin(base_dir: &Path) -> Result<bool> {
+    let path = base_dir.join(state::PACK_PIN.path);
+    if !path.exists() {
+        return Ok(false);
+    }
+    fs::remove_file(&path).context("删除整合包固定信息失败")?;
+    Ok(true)
+}
+
+/// 已保存的版本列表文本（命令行 `upmc pack list` 使用）
+pub fn render_history(base_dir: &Path) -> String {
+    let history = load_history(base_dir);
+    if history.is_empty() {
+        return "还没有保存的整合包版本".to_string();
+    }
+    let current = version::read_pack_cache(base_dir)
+        .map(|raw| pack_id(&raw))
+        .unwrap_or_default();
+    let pinned = load_pin(base_dir).map(|pin| pin.id);
+    history
+        .iter()
+        .map(|record| {
+            let mut line = format!("{}  {}  {}", record.id, record.date, record.version_tag);
+            if record.id == current {
+                line.push_str("  [当前]");
+            }
+            if pinned.as_ref() == Some(&record.id) {
+                line.push_str("  [已固定]");
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn snapshot_dir(base_dir: &Path, id: &str) -> PathBuf {
+    base_dir.join(config::PACK_HISTORY_DIR).join(id)
+}
+
+/// 把 pack.toml、索引和索引中的所有文件写入快照目录（保持与服务器相同的相对位置）
+fn write_snapshot(base_dir: &Path, remote: &RemoteVersion, dir: &Path) -> Result<()> {
+    let (index_file, _, _) = pack_index::index_location(&remote.pack_toml_raw)?;
+    let index_url = source::resolve_relative(&remote.pack_url, &index_file);
+    let index_text = read_cached(base_dir, &index_url)?;
+    let index = pack_index::parse_index(&index_text)?;
+
+    write_file(dir, PACK_TOML, remote.pack_toml_raw.as_bytes())?;
+    write_file(dir, &index_file, index_text.as_bytes())?;
+
+    let index_dir = index_file.rfind('/').map_or("", |i| &index_file[..=i]);
+    let minecraft_dir = base_dir.join(config::MINECRAFT_DIR);
+    for entry in &index.files {
+        let url = source::resolve_relative(&index_url, &entry.file);
+        let data = if entry.metafile {
+            read_cached(base_dir, &url)?.into_bytes()
+        } else {
+            match fs::read(minecraft_dir.join(&entry.file)) {
+                Ok(data)
+                    if pack_index::verify_hash(&entry.hash_format, &data, &entry.hash).is_ok() =>
+                {
+                    data
+                }
+                _ => pack_index::download_verified(&url, &entry.hash_format, &entry.hash)
+                    .with_context(|| format!("下载 {} 失败", entry.file))?,
+            }
+        };
+        write_file(dir, &format!("{index_dir}{}", entry.file), &data)?;
+    }
+    Ok(())
+}
+
+/// 读取已缓存的元数据（刚同步过，正常不需要联网）
+fn read_cached(base_dir: &Path, url: &str) -> Result<String> {
+    match source::cached_text(base_dir, url) {
+        Some(text) => Ok(text),
+        None => source::read_text(base_dir, url),
+    }
+}
+
+fn write_file(dir: &Path, relative: &str, data: &[u8]) -> Result<()> {
+    let path = bootstrap::safe_zip_output_path(dir, relative)?;
+    state::write_atomic(&path, data)
+        .with_context(|| format!("写入快照文件失败: {}", path.display()))
+}
+
+fn save_history(base_dir: &Path, history: &[PackRecord]) -> Result<()> {
+    state::PACK_HISTORY
+        .write_list(base_dir, history)
+        .context("写入整合包版本记录失败")
+}
+
+/// 删除不在记录中的快照目录（best-effort）
+fn remove_stale_snapshots(base_dir: &Path, history: &[PackRecord]) {
+    let Ok(entries) = fs::read_dir(base_dir.join(config::PACK_HISTORY_DIR)) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type().is_ok_and(|t| t.is_dir())
+            && !history.iter().any(|record| record.id == name)
+            && let Err(e) = fs::remove_dir_all(entry.path())
+        {
+            eprintln!("删除旧整合包快照失败（已跳过）: {name}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ChannelConfig;
+    use crate::http::fake;
+    use crate::installer::fake::FakeInstaller;
+    use crate::update;
+
+    fn record(id: &str) -> PackRecord {
+        PackRecord {
+            id: id.to_string(),
+            version_tag: "tag".to_string(),
+            date: String::new(),
+        }
+    }
+
+    #[test]
+    fn pack_id_is_stable_prefix() {
+        let id = pack_id("[versions]\nminecraft = \"1.21.11\"\n");
+        assert_eq!(id.len(), 16);
+        assert_eq!(id, pack_id("[versions]\nminecraft = \"1.21.11\"\n"));
+        assert_ne!(id, pack_id("[versions]\nminecraft = \"1.21.10\"\n"));
+    }
+
+    #[test]
+    fn stale_snapshots_are_removed() {
+        let dir = fake::temp_dir("packs_stale");
+        let packs = dir.join(config::PACK_HISTORY_DIR);
+        fs::create_dir_all(packs.join("keep")).unwrap();
+        fs::create_dir_all(packs.join("old")).unwrap();
+        save_history(&dir, &[record("keep")]).unwrap();
+
+        remove_stale_snapshots(&dir, &load_history(&dir));
+        assert!(packs.join("keep").is_dir());
+        assert!(!packs.join("old").exists());
+        assert!(state::PACK_HISTORY.exists(&dir));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn rollback_pins_previous_snapshot_until_server_publishes() {
+        let base_dir = fake::temp_dir("packs_rollback");
+        let fake = fake::fixture().install();
+        let installer = FakeInstaller::new(&base_dir).install();
+        let channel = ChannelConfig::default();
+        let lithium = base_dir
+            .join(config::MINECRAFT_DIR)
+            .join("mods/lithium.jar");
+        let update = || update::run_update(&base_dir, &channel, &|_| {}).unwrap();
+        let synced_id = || pack_id(&version::read_pack_cache(&base_dir).unwrap());
+
+        update();
+        let first = synced_id();
+        fake.server.publish_pack(
+            "2026.11",
+            &[
+                ("sodium", b"PK sodium 0.6"),
+                ("lithium", b"PK lithium 0.14"),
+            ],
+        );
+        update();
+        let second = synced_id();
+        assert!(lithium.exists());
+        let ids: Vec<String> = load_history(&base_dir).into_iter().map(|r| r.id).collect();
+        assert_eq!(ids, [second.clone(), first.clone()]);
+
+        // 回退选择上一个快照并固定
+        let record = rollback(&base_dir).unwrap();
+        assert_eq!(record.id, first);
+        assert_eq!(pinned_record(&base_dir), Some(record));
+
+        // 固定后 pack_url 指向快照中的 pack.toml
+        let remote = version::fetch_remote_version(&base_dir).unwrap();
+        let pinned = apply_pin(&base_dir, remote);
+        let snapshot = snapshot_dir(&base_dir, &first).join(PACK_TOML);
+        assert_eq!(pinned.pack_url, source::path_to_file_url(&snapshot));
+        assert_eq!(pack_id(&pinned.pack_toml_raw), first);
+
+        // 下次更新按快照同步（经 file:// 读取快照中的索引和元数据）
+        let runs = installer.installer.runs().len();
+        update();
+        assert_eq!(installer.installer.runs().len(), runs + 1);
+        assert_eq!(synced_id(), first);
+        assert!(load_pin(&base_dir).is_some());
+
+        // 服务器发布新版本后固定自动失效
+        fake.server
+            .publish_pack("2026.12", &[("sodium", b"PK sodium 0.6")]);
+        let remote = version::fetch_remote_version(&base_dir).unwrap();
+        let current = apply_pin(&base_dir, remote);
+        assert_eq!(current.pack_url, fake::PACK_URL);
+        assert!(load_pin(&base_dir).is_none());
+
+        fs::remove_dir_all(&base_dir).ok();
+    }
+
+    #[test]
+    fn pinned_pack_url_is_a_uri_packwiz_installer_accepts() {
+        // 安装目录可能含空格和中文（默认目录名就是中文）
+        let root = fake::temp_dir("packs_pin_url");
+        let base_dir = root.join("Jo Smith").join("CJC整合包");
+        let path = snapshot_dir(&base_dir, "0123456789abcdef").join(PACK_TOML);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "").unwrap();
+
+        let url = source::path_to_file_url(&path);
+        // packwiz-installer 用 java.net.URI 解析：只能包含 RFC 3986 允许的 ASCII 字符
+        let allowed = |c: char| c.is_ascii_alphanumeric() || "-._~/:%".contains(c);
+        assert!(url.starts_with("file:///"));
+        assert!(url.chars().all(allowed), "{url}");
+        assert_eq!(source::path_from_arg(&url), path);
+        assert!(source::read_text(&base_dir, &url).is_ok());
+
+        fs::remove_dir_all(&root).ok();
+    }
+}
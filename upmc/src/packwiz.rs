@@ -12,12 +12,28 @@
 // ============================================================
 
 use anyhow::{bail, Context, Result};
-use std::os::windows::process::CommandExt;
+use std::io::BufRead;
 use std::path::Path;
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::config;
+use crate::error::{self, ErrorCode};
+use crate::installer;
+use crate::platform::{self, HideWindow};
 use crate::retry;
+use crate::settings;
+use crate::update::Progress;
+
+/// 正在运行的 packwiz-installer 进程 PID（用于取消时结束进程树）
+static RUNNING_PID: Mutex<Option<u32>> = Mutex::new(None);
+/// 是否已取消（更新器窗口关闭、终端中断）；取消后不再启动新的同步，每次同步开始时清除
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// 错误信息中最多列出的失败文件数
+const MAX_REPORTED_FAILURES: usize = 10;
 
 /// 调用 packwiz-installer-bootstrap 同步模组和配置。
 ///
@@ -32,13 +48,47 @@ use crate::retry;
 /// `-g` 让 packwiz-installer 不弹出自己的窗口（我们有自己的 GUI）
 /// `-s client` 指定只同步客户端需要的文件
 ///
+/// 输出逐行解析为进度（80% → 95%），进程长时间无输出或超过总时长时结束进程树。
 /// 内置重试机制：如果同步失败（通常因网络不稳定），
 /// 会自动重试最多 RETRY_MAX_ATTEMPTS 次。
-pub fn sync_modpack(base_dir: &Path, pack_url: &str) -> Result<()> {
+pub fn sync_modpack(base_dir: &Path, pack_url: &str, on_progress: &dyn Fn(Progress)) -> Result<()> {
+    let mc_dir = base_dir.join(config::MINECRAFT_DIR);
+    sync(base_dir, &mc_dir, pack_url, "client", (80, 95), on_progress)
+}
+
+/// 以服务端模式同步到专用服务器目录（`-s server`：只同步 side 为 server / both 的文件）。
+pub fn sync_server(
+    base_dir: &Path,
+    server_dir: &Path,
+    pack_url: &str,
+    on_progress: &dyn Fn(Progress),
+) -> Result<()> {
+    sync(base_dir, server_dir, pack_url, "server", (70, 95), on_progress)
+}
+
+/// 结束正在运行的 packwiz-installer（更新器窗口关闭、终端前端收到 Ctrl+C 时调用）。
+pub fn cancel_running() {
+    CANCELLED.store(true, Ordering::SeqCst);
+    let pid = *RUNNING_PID.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(pid) = pid {
+        platform::kill_process_tree(pid);
+    }
+}
+
+/// 把整合包同步到 `mc_dir`，`side` 为 client 或 server，进度映射到 `percent_range`。
+fn sync(
+    base_dir: &Path,
+    mc_dir: &Path,
+    pack_url: &str,
+    side: &str,
+    percent_range: (u32, u32),
+    on_progress: &dyn Fn(Progress),
+) -> Result<()> {
+    // 上一次同步的取消不影响这一次
+    CANCELLED.store(false, Ordering::SeqCst);
+
     // ── 前置检查（确定性失败，不需要重试） ──
-    let java = config::find_java()?;
     let bootstrap_jar = base_dir.join(config::PACKWIZ_BOOTSTRAP_JAR);
-    let mc_dir = base_dir.join(config::MINECRAFT_DIR);
 
     if !bootstrap_jar.exists() {
         bail!(
@@ -47,160 +97,358 @@ pub fn sync_modpack(base_dir: &Path, pack_url: &str) -> Result<()> {
         );
     }
 
-    std::fs::create_dir_all(&mc_dir).context("创建 .minecraft 目录失败")?;
+    std::fs::create_dir_all(mc_dir).context("创建 .minecraft 目录失败")?;
 
     // 前置验证 Java 可用（确定性失败，不进入重试循环）
-    verify_java(&java)?;
+    let java = installer::java()?;
 
     // ── 网络操作（可能因网络波动失败，需要重试） ──
-    let url_owned = pack_url.to_string();
-
-    retry::with_retry(
-        config::RETRY_MAX_ATTEMPTS,
-        config::RETRY_BASE_DELAY_SECS,
-        "模组同步",
-        || run_packwiz_installer(&java, &bootstrap_jar, &mc_dir, &url_owned),
-    )
+    let run = PackwizRun {
+        java: &java,
+        bootstrap_jar: &bootstrap_jar,
+        mc_dir,
+        pack_url,
+        side,
+    };
+
+    retry::with_default_retry("模组同步", || {
+        if CANCELLED.load(Ordering::SeqCst) {
+            bail!("模组同步已取消");
+        }
+        installer::run_packwiz(&run, percent_range, on_progress)
+    })
+}
+
+/// 一次 packwiz-installer 调用的参数
+pub struct PackwizRun<'a> {
+    pub java: &'a Path,
+    pub bootstrap_jar: &'a Path,
+    pub mc_dir: &'a Path,
+    pub pack_url: &'a str,
+    pub side: &'a str,
+}
+
+/// packwiz-installer 输出的一行
+#[derive(Debug, PartialEq, Eq)]
+enum InstallerLine {
+    /// `(3/120) Downloaded sodium.jar` 形式的文件进度
+    File {
+        current: u32,
+        total: u32,
+        message: String,
+    },
+    /// 下载失败 / 异常
+    Failure(String),
+    /// 其他状态（Loading pack file... 等）
+    Status(String),
+}
+
+/// 解析 packwiz-installer 无头模式的一行输出
+fn parse_line(line: &str) -> Option<InstallerLine> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+
+    if let Some(rest) = line.strip_prefix('(')
+        && let Some((counts, message)) = rest.split_once(')')
+        && let Some((current, total)) = counts.split_once('/')
+        && let (Ok(current), Ok(total)) = (current.trim().parse(), total.trim().parse())
+    {
+        let message = message.trim().to_string();
+        let lower = message.to_lowercase();
+        if lower.contains("failed") || lower.contains("error") {
+            return Some(InstallerLine::Failure(message));
+        }
+        return Some(InstallerLine::File { current, total, message });
+    }
+
+    let lower = line.to_lowercase();
+    if lower.contains("failed") || lower.contains("error") || lower.contains("exception") {
+        return Some(InstallerLine::Failure(line.to_string()));
+    }
+    Some(InstallerLine::Status(line.to_string()))
 }
 
 /// 执行 packwiz-installer 进程（单次尝试）。
-fn run_packwiz_installer(
-    java: &Path,
-    bootstrap_jar: &Path,
-    mc_dir: &Path,
-    pack_url: &str,
+///
+/// stdout / stderr 由两个线程逐行读取，主循环解析为进度并检查超时。
+pub(crate) fn run_packwiz_installer(
+    installer: &PackwizRun,
+    (percent_start, percent_end): (u32, u32),
+    on_progress: &dyn Fn(Progress),
 ) -> Result<()> {
     // 调用 packwiz-installer-bootstrap
-    // 注意：工作目录设置为 .minecraft，
+    // 注意：工作目录设置为 .minecraft（服务端模式为服务器目录），
     // 因为 packwiz-installer 相对于工作目录来存放文件
-    let output = Command::new(java)
+    let mut child = Command::new(installer.java)
         .arg("-jar")
-        .arg(bootstrap_jar)
+        .arg(installer.bootstrap_jar)
         .arg("-g") // 无头模式（不弹 GUI）
         .arg("-s")
-        .arg("client") // 客户端模式
-        .arg(pack_url) // 远程 pack.toml URL
-        .current_dir(mc_dir) // 工作目录 = .minecraft
-        .creation_flags(config::CREATE_NO_WINDOW)
-        .output()
+        .arg(installer.side) // 客户端 / 服务端模式
+        .arg(installer.pack_url) // 远程 pack.toml URL
+        .current_dir(installer.mc_dir) // 工作目录 = .minecraft
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .hide_window()
+        .spawn()
         .context("启动 packwiz-installer 失败，请检查 Java 运行时是否正常")?;
+    *RUNNING_PID.lock().unwrap_or_else(|e| e.into_inner()) = Some(child.id());
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-
-        let exit_code_str = match output.status.code() {
-            Some(code) => format!("{}", code),
-            None => "未知（进程被终止）".to_string(),
-        };
-
-        // 分析输出，推断可能的失败原因
-        let hints = diagnose_sync_failure(&stdout, &stderr);
-
-        let stdout_display = if stdout.trim().is_empty() {
-            "（无输出）".to_string()
-        } else {
-            stdout.trim().to_string()
-        };
-        let stderr_display = if stderr.trim().is_empty() {
-            "（无输出）".to_string()
-        } else {
-            stderr.trim().to_string()
-        };
+    // (是否为 stderr, 行内容)
+    let (sender, receiver) = mpsc::channel::<(bool, String)>();
+    if let Some(stdout) = child.stdout.take() {
+        spawn_line_reader(stdout, false, sender.clone());
+    }
+    if let Some(stderr) = child.stderr.take() {
+        spawn_line_reader(stderr, true, sender);
+    }
 
-        bail!(
-            "模组同步失败（退出码: {}）\n\
-             \n\
-             ── 标准输出 ──\n{}\n\
-             \n\
-             ── 错误输出 ──\n{}\n\
-             {}\n\
-             建议: 请检查网络连接后重试，如果问题持续请截图联系管理员。",
-            exit_code_str,
-            stdout_display,
-            stderr_display,
-            hints,
-        );
+    let settings = settings::current();
+    let (timeout_secs, idle_timeout_secs) = (
+        settings.packwiz_timeout_secs,
+        settings.packwiz_idle_timeout_secs,
+    );
+    let started = Instant::now();
+    let mut last_output = Instant::now();
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+    let mut failures = Vec::new();
+    let mut abort_reason = None;
+
+    loop {
+        match receiver.recv_timeout(Duration::from_secs(1)) {
+            Ok((is_stderr, line)) => {
+                last_output = Instant::now();
+                let buffer = if is_stderr { &mut stderr } else { &mut stdout };
+                buffer.push_str(&line);
+                buffer.push('\n');
+
+                match parse_line(&line) {
+                    Some(InstallerLine::File { current, total, message }) if total > 0 => {
+                        let span = percent_end.saturating_sub(percent_start);
+                        let percent = percent_start + span * current.min(total) / total;
+                        on_progress(Progress::new(percent, format!(
+                            "正在同步模组 ({current}/{total}) {message}"
+                        )));
+                    }
+                    Some(InstallerLine::Failure(message)) => failures.push(message),
+                    Some(InstallerLine::Status(message)) => {
+                        on_progress(Progress::new(percent_start, format!("正在同步模组: {message}")));
+                    }
+                    _ => {}
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            // 两个管道都已关闭，进程即将退出
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+
+        abort_reason = check_abort(started, last_output, timeout_secs, idle_timeout_secs);
+        if abort_reason.is_some() {
+            platform::kill_process_tree(child.id());
+            break;
+        }
     }
 
-    Ok(())
-}
+    // 管道关闭后进程通常马上退出；关闭了输出但不退出的进程按同一期限结束
+    if abort_reason.is_none() {
+        while matches!(child.try_wait(), Ok(None)) {
+            abort_reason = check_abort(started, last_output, timeout_secs, idle_timeout_secs);
+            if abort_reason.is_some() {
+                platform::kill_process_tree(child.id());
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(200));
+        }
+    }
 
-/// 前置验证 Java 是否能正常启动。
-///
-/// 运行 `java -version`，如果失败且输出包含环境异常关键词，
-/// 自动打开下载页面并返回错误（不进入重试循环）。
-fn verify_java(java: &Path) -> Result<()> {
-    let output = Command::new(java)
-        .arg("-version")
-        .creation_flags(config::CREATE_NO_WINDOW)
-        .output()
-        .context("无法启动 Java 进程")?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-
-        let _ = Command::new("cmd")
-            .args(["/c", "start", "", config::JAVA_DOWNLOAD_URL])
-            .creation_flags(config::CREATE_NO_WINDOW)
-            .spawn();
+    let status = child.wait().context("等待 packwiz-installer 退出失败");
+    *RUNNING_PID.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    if let Some(reason) = abort_reason {
+        // 超时多半是网络卡住；取消不归类
+        if CANCELLED.load(Ordering::SeqCst) {
+            bail!("{reason}");
+        }
+        return Err(error::new(ErrorCode::Network, reason));
+    }
+    let status = status?;
 
-        bail!(
-            "Java 环境异常\n\
-             \n\
-             错误信息: {}\n\
-             \n\
-             当前 Java 路径: {}\n\
-             该 Java 安装可能已损坏或版本不兼容。\n\
-             正在尝试打开 Java 下载页面，如未自动打开请手动访问：\n\
-             {}\n\
-             \n\
-             安装完成后请重新运行更新器。",
-            stderr.trim(),
-            java.display(),
-            config::JAVA_DOWNLOAD_URL,
-        );
+    if status.success() {
+        if !failures.is_empty() {
+            eprintln!("packwiz-installer 报告了以下问题:\n{}", failures.join("\n"));
+        }
+        return Ok(());
     }
 
-    Ok(())
+    let exit_code_str = match status.code() {
+        Some(code) => format!("{}", code),
+        None => "未知（进程被终止）".to_string(),
+    };
+
+    // 分析输出，推断可能的失败原因
+    let hints = diagnose_sync_failure(&stdout, &stderr);
+
+    let stdout_display = if stdout.trim().is_empty() {
+        "（无输出）".to_string()
+    } else {
+        stdout.trim().to_string()
+    };
+    let stderr_display = if stderr.trim().is_empty() {
+        "（无输出）".to_string()
+    } else {
+        stderr.trim().to_string()
+    };
+    let failures_display = if failures.is_empty() {
+        String::new()
+    } else {
+        let shown: Vec<&str> = failures
+            .iter()
+            .take(MAX_REPORTED_FAILURES)
+            .map(String::as_str)
+            .collect();
+        format!("\n── 失败的文件 ──\n{}\n", shown.join("\n"))
+    };
+
+    let message = format!(
+        "模组同步失败（退出码: {}）\n\
+         {}\n\
+         ── 标准输出 ──\n{}\n\
+         \n\
+         ── 错误输出 ──\n{}\n\
+         {}\n\
+         建议: 请检查网络连接后重试，如果问题持续请截图联系管理员。",
+        exit_code_str,
+        failures_display,
+        stdout_display,
+        stderr_display,
+        hints,
+    );
+    Err(match failure_code(&stdout, &stderr) {
+        Some(code) => error::new(code, message),
+        None => anyhow::anyhow!(message),
+    })
 }
 
-/// 分析 packwiz-installer 的输出，推断可能的失败原因。
-fn diagnose_sync_failure(stdout: &str, stderr: &str) -> String {
-    let combined = format!("{}\n{}", stdout.to_lowercase(), stderr.to_lowercase());
-    let mut hints = Vec::new();
-
-    if combined.contains("connection")
-        || combined.contains("timeout")
-        || combined.contains("timed out")
-        || combined.contains("unresolvedaddressexception")
-        || combined.contains("unknownhostexception")
-        || combined.contains("connect")
-        || combined.contains("网络")
-    {
-        hints.push("网络连接失败或超时，请检查网络是否正常");
+/// 需要终止 packwiz-installer 时返回原因：已取消、超过总时长或长时间没有输出
+fn check_abort(
+    started: Instant,
+    last_output: Instant,
+    timeout_secs: u64,
+    idle_timeout_secs: u64,
+) -> Option<String> {
+    if CANCELLED.load(Ordering::SeqCst) {
+        Some("模组同步已取消".to_string())
+    } else if started.elapsed() > Duration::from_secs(timeout_secs) {
+        Some(format!(
+            "模组同步超过 {} 分钟仍未完成，已终止",
+            timeout_secs / 60
+        ))
+    } else if last_output.elapsed() > Duration::from_secs(idle_timeout_secs) {
+        Some(format!(
+            "packwiz-installer 已 {} 分钟没有输出，疑似卡死，已终止",
+            idle_timeout_secs / 60
+        ))
+    } else {
+        None
     }
+}
 
-    if combined.contains("null") || combined.contains("nullpointerexception") {
-        hints.push("版本信息获取失败（显示为 null），可能是网络问题导致远程数据未正确下载");
-    }
+/// 安装器（Java 程序）输出中的一类错误：关键字（小写）、错误码和给玩家的提示
+struct OutputPattern {
+    keywords: &'static [&'static str],
+    code: Option<ErrorCode>,
+    hint: &'static str,
+}
 
-    if hints.is_empty() && (combined.contains("java.lang") || combined.contains("exception")) {
-        hints.push("packwiz-installer 运行时发生 Java 异常");
-    }
+/// 错误码取第一条命中的规则（TLS 优先于一般网络错误），提示列出所有命中的规则
+const OUTPUT_PATTERNS: &[OutputPattern] = &[
+    OutputPattern {
+        keywords: &["ssl", "handshake_failure", "certificate"],
+        code: Some(ErrorCode::Tls),
+        hint: "SSL/TLS 连接问题，可能是证书错误或网络代理干扰",
+    },
+    OutputPattern {
+        keywords: &[
+            "connect",
+            "timeout",
+            "timed out",
+            "unresolvedaddressexception",
+            "unknownhostexception",
+            "网络",
+        ],
+        code: Some(ErrorCode::Network),
+        hint: "网络连接失败或超时，请检查网络是否正常",
+    },
+    OutputPattern {
+        keywords: &["access denied", "accessdeniedexception", "permission"],
+        code: Some(ErrorCode::PermissionDenied),
+        hint: "文件访问权限不足，请检查目录权限或关闭占用文件的程序",
+    },
+    OutputPattern {
+        keywords: &["no space left", "not enough space"],
+        code: Some(ErrorCode::DiskFull),
+        hint: "磁盘空间不足，请清理磁盘后重试",
+    },
+    OutputPattern {
+        keywords: &["null"],
+        code: None,
+        hint: "版本信息获取失败（显示为 null），可能是网络问题导致远程数据未正确下载",
+    },
+];
+
+/// 输出命中的规则（按 `OUTPUT_PATTERNS` 的顺序）
+fn matched_patterns(stdout: &str, stderr: &str) -> Vec<&'static OutputPattern> {
+    let combined = format!("{}\n{}", stdout.to_lowercase(), stderr.to_lowercase());
+    OUTPUT_PATTERNS
+        .iter()
+        .filter(|pattern| pattern.keywords.iter().any(|k| combined.contains(k)))
+        .collect()
+}
 
-    if combined.contains("ssl")
-        || combined.contains("sslexception")
-        || combined.contains("sslhandshakeexception")
-        || combined.contains("handshake_failure")
-        || combined.contains("certificate")
-        || combined.contains("certificateerror")
-    {
-        hints.push("SSL/TLS 连接问题，可能是证书错误或网络代理干扰");
-    }
+/// 根据 packwiz-installer / Fabric 安装器的 Java 输出识别错误码
+pub(crate) fn failure_code(stdout: &str, stderr: &str) -> Option<ErrorCode> {
+    matched_patterns(stdout, stderr)
+        .iter()
+        .find_map(|pattern| pattern.code)
+}
+
+/// 在后台线程中逐行读取管道并发送到主循环（非 UTF-8 内容按有损方式转换）
+fn spawn_line_reader(
+    pipe: impl std::io::Read + Send + 'static,
+    is_stderr: bool,
+    sender: mpsc::Sender<(bool, String)>,
+) {
+    std::thread::spawn(move || {
+        let mut reader = std::io::BufReader::new(pipe);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&buf).trim_end().to_string();
+                    if sender.send((is_stderr, line)).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
 
-    if combined.contains("permission") || combined.contains("access denied") {
-        hints.push("文件访问权限不足，请检查目录权限或关闭占用文件的程序");
+/// 分析 packwiz-installer 的输出，推断可能的失败原因。
+fn diagnose_sync_failure(stdout: &str, stderr: &str) -> String {
+    let mut hints: Vec<&str> = matched_patterns(stdout, stderr)
+        .iter()
+        .map(|pattern| pattern.hint)
+        .collect();
+
+    let combined = format!("{}\n{}", stdout.to_lowercase(), stderr.to_lowercase());
+    if hints.is_empty() && (combined.contains("java.lang") || combined.contains("exception")) {
+        hints.push("packwiz-installer 运行时发生 Java 异常");
     }
 
     if hints.is_empty() {
@@ -215,6 +463,65 @@ fn diagnose_sync_failure(stdout: &str, stderr: &str) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn check_abort_after_deadlines() {
+        let now = Instant::now();
+        let earlier = now.checked_sub(Duration::from_secs(120)).unwrap();
+        assert_eq!(check_abort(now, now, 60, 60), None);
+        let total = check_abort(earlier, now, 60, 600).unwrap();
+        assert!(total.contains("1 分钟仍未完成"));
+        let idle = check_abort(now, earlier, 600, 60).unwrap();
+        assert!(idle.contains("没有输出"));
+    }
+
+    #[test]
+    fn failure_code_from_output() {
+        assert_eq!(
+            failure_code("", "javax.net.ssl.SSLHandshakeException: PKIX path building failed"),
+            Some(ErrorCode::Tls)
+        );
+        assert_eq!(
+            failure_code("", "java.net.SocketTimeoutException: Read timed out"),
+            Some(ErrorCode::Network)
+        );
+        assert_eq!(failure_code("", "java.lang.NullPointerException"), None);
+        // 同一张表同时给出错误码和提示
+        let output = "java.nio.file.FileSystemException: No space left on device";
+        assert_eq!(failure_code(output, ""), Some(ErrorCode::DiskFull));
+        assert!(diagnose_sync_failure(output, "").contains("磁盘空间不足"));
+    }
+
+    #[test]
+    fn parse_file_progress_line() {
+        assert_eq!(
+            parse_line("(3/120) Downloaded sodium-fabric-0.6.0.jar"),
+            Some(InstallerLine::File {
+                current: 3,
+                total: 120,
+                message: "Downloaded sodium-fabric-0.6.0.jar".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_failure_and_status_lines() {
+        assert_eq!(
+            parse_line("(4/120) Failed to download iris.jar"),
+            Some(InstallerLine::Failure("Failed to download iris.jar".to_string()))
+        );
+        assert_eq!(
+            parse_line("java.net.SocketTimeoutException: Read timed out"),
+            Some(InstallerLine::Failure(
+                "java.net.SocketTimeoutException: Read timed out".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_line("Loading pack file..."),
+            Some(InstallerLine::Status("Loading pack file...".to_string()))
+        );
+        assert_eq!(parse_line("   "), None);
+    }
+
     #[test]
     fn diagnose_network_error() {
         let result = diagnose_sync_failure("", "java.net.UnknownHostException: example.com");
@@ -0,0 +1,271 @@
+// ============================================================
+// platform.rs — 平台相关操作
+// ============================================================
+// 更新流程本身（下载、校验、packwiz、Fabric 安装）与平台无关，
+// 与操作系统打交道的部分集中在这里，分别提供 Windows 和 Unix 实现：
+//   - 启动子进程时不弹出控制台窗口（HideWindow）
+//   - 在 PATH 中查找可执行文件（where / which）
+//   - 用默认浏览器打开网址（cmd start / xdg-open / open）
+//   - 结束进程（树）（taskkill / kill）
+//   - 把其他进程的窗口切到前台（单实例时唤起已运行的更新器）
+//   - 本地时间、物理内存
+//   - 可执行文件名（java.exe / java）
+//
+// GUI 只有 Windows 版；其他平台由 headless.rs 在终端中运行更新流程。
+// ============================================================
+
+use std::path::PathBuf;
+use std::process::Command;
+
+/// 启动子进程时不弹出控制台窗口（非 Windows 平台无操作）
+pub trait HideWindow {
+    fn hide_window(&mut self) -> &mut Self;
+}
+
+/// 可执行文件名（Windows 上追加 .exe）
+pub fn exe_name(stem: &str) -> String {
+    format!("{stem}{}", std::env::consts::EXE_SUFFIX)
+}
+
+/// 在 PATH 中查找可执行文件，返回第一个存在的结果
+pub fn find_on_path(name: &str) -> Option<PathBuf> {
+    let finder = if cfg!(windows) { "where" } else { "which" };
+    let output = Command::new(finder).arg(name).hide_window().output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|line| PathBuf::from(line.trim()))
+        .filter(|path| path.exists())
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::HideWindow;
+    use std::os::windows::process::CommandExt;
+    use std::process::Command;
+
+    /// 创建子进程时不弹出控制台窗口
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    impl HideWindow for Command {
+        fn hide_window(&mut self) -> &mut Self {
+            self.creation_flags(CREATE_NO_WINDOW)
+        }
+    }
+
+    pub fn open_url(url: &str) {
+        let _ = Command::new("cmd")
+            .args(["/c", "start", "", url])
+            .hide_window()
+            .spawn();
+    }
+
+    pub fn kill(pid: u32, tree: bool) {
+        let mut command = Command::new("taskkill");
+        if tree {
+            command.arg("/T");
+        }
+        let _ = command
+            .args(["/F", "/PID", &pid.to_string()])
+            .hide_window()
+            .output();
+    }
+
+    pub fn focus_window(pid: u32) -> bool {
+        use winapi::shared::minwindef::{BOOL, LPARAM};
+        use winapi::shared::windef::HWND;
+        use winapi::um::winuser::{
+            EnumWindows, GW_OWNER, GetWindow, GetWindowThreadProcessId, IsIconic, IsWindowVisible,
+            SW_RESTORE, SetForegroundWindow, ShowWindow,
+        };
+
+        /// 找到的窗口写回 (pid, hwnd)
+        unsafe extern "system" fn find(hwnd: HWND, param: LPARAM) -> BOOL {
+            let target = unsafe { &mut *(param as *mut (u32, HWND)) };
+            let mut pid = 0;
+            unsafe { GetWindowThreadProcessId(hwnd, &mut pid) };
+            let is_main = unsafe { IsWindowVisible(hwnd) } != 0
+                && unsafe { GetWindow(hwnd, GW_OWNER) }.is_null();
+            if pid == target.0 && is_main {
+                target.1 = hwnd;
+                return 0;
+            }
+            1
+        }
+
+        let mut target: (u32, HWND) = (pid, std::ptr::null_mut());
+        unsafe { EnumWindows(Some(find), &mut target as *mut _ as LPARAM) };
+        let hwnd = target.1;
+        if hwnd.is_null() {
+            return false;
+        }
+        unsafe {
+            if IsIconic(hwnd) != 0 {
+                ShowWindow(hwnd, SW_RESTORE);
+            }
+            SetForegroundWindow(hwnd) != 0
+        }
+    }
+
+    pub fn local_timestamp() -> String {
+        use winapi::um::minwinbase::SYSTEMTIME;
+        use winapi::um::sysinfoapi::GetLocalTime;
+
+        let mut now: SYSTEMTIME = unsafe { std::mem::zeroed() };
+        unsafe { GetLocalTime(&mut now) };
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}",
+            now.wYear, now.wMonth, now.wDay, now.wHour, now.wMinute
+        )
+    }
+
+    pub fn total_memory_mb() -> u64 {
+        use winapi::um::sysinfoapi::{GlobalMemoryStatusEx, MEMORYSTATUSEX};
+
+        let mut status: MEMORYSTATUSEX = unsafe { std::mem::zeroed() };
+        status.dwLength = std::mem::size_of::<MEMORYSTATUSEX>() as u32;
+        if unsafe { GlobalMemoryStatusEx(&mut status) } != 0 {
+            status.ullTotalPhys / 1024 / 1024
+        } else {
+            0
+        }
+    }
+
+    pub fn attach_parent_console() {
+        use winapi::um::wincon::{ATTACH_PARENT_PROCESS, AttachConsole};
+        // 没有父控制台（如双击启动）时调用失败，忽略即可
+        unsafe {
+            AttachConsole(ATTACH_PARENT_PROCESS);
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use super::HideWindow;
+    use std::process::Command;
+
+    impl HideWindow for Command {
+        fn hide_window(&mut self) -> &mut Self {
+            self
+        }
+    }
+
+    pub fn open_url(url: &str) {
+        let opener = if cfg!(target_os = "macos") {
+            "open"
+        } else {
+            "xdg-open"
+        };
+        let _ = Command::new(opener).arg(url).spawn();
+    }
+
+    /// `tree` 时同时结束直接子进程（pkill -P），再结束进程本身
+    pub fn kill(pid: u32, tree: bool) {
+        let pid = pid.to_string();
+        if tree {
+            let _ = Command::new("pkill").args(["-KILL", "-P", &pid]).output();
+        }
+        let _ = Command::new("kill").args(["-KILL", &pid]).output();
+    }
+
+    pub fn local_timestamp() -> String {
+        Command::new("date")
+            .arg("+%Y-%m-%d %H:%M")
+            .output()
+            .ok()
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn total_memory_mb() -> u64 {
+        if cfg!(target_os = "macos") {
+            let bytes = Command::new("sysctl")
+                .args(["-n", "hw.memsize"])
+                .output()
+                .ok()
+                .and_then(|output| {
+                    String::from_utf8_lossy(&output.stdout)
+                        .trim()
+                        .parse::<u64>()
+                        .ok()
+                });
+            return bytes.unwrap_or(0) / 1024 / 1024;
+        }
+        std::fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|text| parse_meminfo_kb(&text))
+            .unwrap_or(0)
+            / 1024
+    }
+
+    /// /proc/meminfo 中 `MemTotal:  16318412 kB` 的数值
+    pub(super) fn parse_meminfo_kb(text: &str) -> Option<u64> {
+        text.lines()
+            .find_map(|line| line.strip_prefix("MemTotal:"))
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|kb| kb.parse().ok())
+    }
+
+    /// 终端程序本身就有控制台
+    pub fn attach_parent_console() {}
+}
+
+/// 用默认浏览器打开网址（失败时忽略，调用方会同时显示网址）
+pub fn open_url(url: &str) {
+    imp::open_url(url);
+}
+
+/// 强制结束进程
+pub fn kill_process(pid: u32) {
+    imp::kill(pid, false);
+}
+
+/// 强制结束进程及其子进程
+pub fn kill_process_tree(pid: u32) {
+    imp::kill(pid, true);
+}
+
+/// 把进程的主窗口切到前台（最小化时先还原）。找不到窗口时返回 false。
+#[cfg(windows)]
+pub fn focus_process_window(pid: u32) -> bool {
+    imp::focus_window(pid)
+}
+
+/// 当前本地时间，格式 "YYYY-MM-DD HH:MM"
+pub fn local_timestamp() -> String {
+    imp::local_timestamp()
+}
+
+/// 物理内存（MB），检测失败时为 0
+pub fn total_memory_mb() -> u64 {
+    imp::total_memory_mb()
+}
+
+/// 附加到父进程控制台，让 println!/eprintln! 在命令行中可见（release 版 GUI 程序没有控制台）
+pub fn attach_parent_console() {
+    imp::attach_parent_console();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exe_name_has_platform_suffix() {
+        let name = exe_name("java");
+        assert_eq!(name.ends_with(".exe"), cfg!(windows));
+        assert!(name.starts_with("java"));
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn parse_meminfo() {
+        let text = "MemFree:  1000 kB\nMemTotal:       16318412 kB\n";
+        assert_eq!(imp::parse_meminfo_kb(text), Some(16318412));
+        assert_eq!(imp::parse_meminfo_kb(""), None);
+    }
+}
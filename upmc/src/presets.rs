@@ -0,0 +1,314 @@
+// ============================================================
+// presets.rs — 画质预设（低配 / 标准 / 高画质）
+// ============================================================
+// 预设由管理员在 server.json 中定义，按从低到高的顺序排列：
+//   "presets": [
+//     { "id": "low", "label": "低配", "min_ram_mb": 0, "min_cores": 0,
+//       "optional_mods": { "mods/iris.pw.toml": false },
+//       "options": { "renderDistance": "6", "graphicsMode": "0" },
+//       "memory_mb": 3072 },
+//     ...
+//   ]
+//
+// 每个预设对应：
+//   - 一组可选模组的默认开关（玩家在「可选模组」中手动勾选的优先）
+//   - .minecraft/options.txt 中的键值
+//   - PCL2 版本级内存分配
+//
+// 玩家未手动选择时，按检测到的内存和 CPU 核心数选出满足条件的最高预设。
+// options.txt 和内存只在生效的预设变化时写入一次，之后玩家在游戏里改的设置不会被覆盖。
+// 写入都是原子的：写了一半的 options.txt 会让游戏丢掉玩家的全部选项。
+// options 的键只允许 [A-Za-z0-9_.:]，值不能含换行等控制字符，
+// 不符合的条目跳过并记录，避免借此向 options.txt 注入额外的行。
+// ============================================================
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::config;
+use crate::state;
+
+/// 一个画质预设
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Preset {
+    pub id: String,
+    /// 显示名称，如 "低配"
+    pub label: String,
+    /// 自动选择所需的最低物理内存（MB）
+    #[serde(default)]
+    pub min_ram_mb: u64,
+    /// 自动选择所需的最少 CPU 逻辑核心数
+    #[serde(default)]
+    pub min_cores: u32,
+    /// 可选模组默认开关，键为元数据文件路径
+    #[serde(default)]
+    pub optional_mods: BTreeMap<String, bool>,
+    /// 写入 options.txt 的键值
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
+    /// PCL2 为该版本分配的内存（MB）
+    #[serde(default)]
+    pub memory_mb: Option<u32>,
+}
+
+/// 本机硬件信息
+#[derive(Debug, Clone, Copy)]
+pub struct Hardware {
+    pub ram_mb: u64,
+    pub cores: u32,
+}
+
+/// 检测物理内存和 CPU 逻辑核心数
+pub fn detect_hardware() -> Hardware {
+    let ram_mb = crate::platform::total_memory_mb();
+    let cores = std::thread::available_parallelism()
+        .map(|n| n.get() as u32)
+        .unwrap_or(1);
+
+    Hardware { ram_mb, cores }
+}
+
+/// 按硬件自动选择：满足条件的最后一个（最高）预设；都不满足时取第一个。
+pub fn auto_select<'a>(presets: &'a [Preset], hardware: &Hardware) -> Option<&'a Preset> {
+    presets
+        .iter()
+        .rev()
+        .find(|p| hardware.ram_mb >= p.min_ram_mb && hardware.cores >= p.min_cores)
+        .or_else(|| presets.first())
+}
+
+/// 当前生效的预设：玩家选择（settings.json 的 preset）> 按硬件自动选择
+pub fn active<'a>(presets: &'a [Preset], chosen: Option<&str>) -> Option<&'a Preset> {
+    chosen
+        .and_then(|id| presets.iter().find(|p| p.id == id))
+        .or_else(|| auto_select(presets, &detect_hardware()))
+}
+
+/// 合并可选模组开关：预设提供默认值，玩家手动选择优先
+pub fn effective_choices(
+    preset: Option<&Preset>,
+    user_choices: &BTreeMap<String, bool>,
+) -> BTreeMap<String, bool> {
+    let mut choices = preset
+        .map(|p| p.optional_mods.clone())
+        .unwrap_or_default();
+    choices.extend(user_choices.iter().map(|(k, v)| (k.clone(), *v)));
+    choices
+}
+
+/// 保存 server.json 下发的预设列表（供离线模式和设置窗口使用）
+pub fn save(base_dir: &Path, presets: &[Preset]) -> Result<()> {
+    state::PRESETS
+        .write_list(base_dir, presets)
+        .context("写入预设列表失败")
+}
+
+/// 读取缓存的预设列表（不存在时为空）
+pub fn load(base_dir: &Path) -> Vec<Preset> {
+    state::PRESETS.read_list(base_dir)
+}
+
+/// 应用预设的 options.txt 和内存设置。
+///
+/// 只在预设内容与上次应用的不同时写入，返回是否实际写入。
+pub fn apply(base_dir: &Path, preset: &Preset, version_tag: &str) -> Result<bool> {
+    let fingerprint = format!(
+        "{}:{:x}",
+        version_tag,
+        Sha256::digest(serde_json::to_vec(preset).context("序列化预设失败")?)
+    );
+    if state::PRESET_APPLIED.read_text(base_dir).as_deref() == Some(fingerprint.as_str()) {
+        return Ok(false);
+    }
+
+    let mc_dir = base_dir.join(config::MINECRAFT_DIR);
+    if !preset.options.is_empty() {
+        let options_path = mc_dir.join("options.txt");
+        let mut content = fs::read_to_string(&options_path).unwrap_or_default();
+        for (key, value) in &preset.options {
+            if !is_valid_option(key, value) {
+                eprintln!(
+                    "警告: 忽略预设 {} 中无效的 options 条目: {key:?} = {value:?}",
+                    preset.id
+                );
+                continue;
+            }
+            content = set_colon_value(&content, key, value);
+        }
+        state::write_atomic(&options_path, content.as_bytes()).context("写入 options.txt 失败")?;
+    }
+
+    if let Some(memory_mb) = preset.memory_mb {
+        let pcl_dir = mc_dir.join("versions").join(version_tag).join("PCL");
+        let setup_ini = pcl_dir.join("Setup.ini");
+        let content = fs::read_to_string(&setup_ini).unwrap_or_default();
+        // VersionRamType: 1 = 自定义
+        let content = set_colon_value(&content, "VersionRamType", "1");
+        let content = set_colon_value(
+            &content,
+            "VersionRamCustom",
+            &pcl_ram_slider(memory_mb).to_string(),
+        );
+        state::write_atomic(&setup_ini, content.as_bytes()).context("写入版本级 Setup.ini 失败")?;
+    }
+
+    state::PRESET_APPLIED
+        .write_text(base_dir, &fingerprint)
+        .context("写入预设应用标记失败")?;
+    Ok(true)
+}
+
+/// options.txt 条目是否可以写入：键只含 `[A-Za-z0-9_.:]`，值不含控制字符
+fn is_valid_option(key: &str, value: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | ':'))
+        && !value.chars().any(char::is_control)
+}
+
+/// 设置 `key:value` 格式文件（options.txt / PCL 版本级 Setup.ini）中的一个键，
+/// 不存在时追加到末尾。
+fn set_colon_value(content: &str, key: &str, value: &str) -> String {
+    let prefix = format!("{key}:");
+    let mut found = false;
+    let mut lines: Vec<String> = content
+        .lines()
+        .map(|line| {
+            if line.starts_with(&prefix) {
+                found = true;
+                format!("{prefix}{value}")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+    if !found {
+        lines.push(format!("{prefix}{value}"));
+    }
+    let mut result = lines.join("\n");
+    result.push('\n');
+    result
+}
+
+/// 把内存（MB）换算成 PCL2 内存滑块的值（向下取整）。
+///
+/// PCL2 的滑块刻度：
+///   0..=12  → 0.3 GB 起，每格 0.1 GB
+///   13..=25 → 1.5 GB 起，每格 0.5 GB
+///   26..=33 → 8 GB 起，每格 1 GB
+///   34..    → 16 GB 起，每格 2 GB
+fn pcl_ram_slider(memory_mb: u32) -> u32 {
+    let gb = memory_mb as f64 / 1024.0;
+    let slider = if gb <= 1.5 {
+        ((gb - 0.3) / 0.1).max(0.0)
+    } else if gb <= 8.0 {
+        12.0 + (gb - 1.5) / 0.5
+    } else if gb <= 16.0 {
+        25.0 + (gb - 8.0)
+    } else {
+        33.0 + (gb - 16.0) / 2.0
+    };
+    (slider + 1e-6).floor() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(id: &str, min_ram_mb: u64, min_cores: u32) -> Preset {
+        Preset {
+            id: id.to_string(),
+            label: id.to_string(),
+            min_ram_mb,
+            min_cores,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn auto_select_picks_highest_satisfied() {
+        let presets = vec![preset("low", 0, 0), preset("std", 8192, 4), preset("high", 16384, 8)];
+        let hw = |ram_mb, cores| Hardware { ram_mb, cores };
+        assert_eq!(auto_select(&presets, &hw(4096, 2)).unwrap().id, "low");
+        assert_eq!(auto_select(&presets, &hw(16000, 8)).unwrap().id, "std");
+        assert_eq!(auto_select(&presets, &hw(32768, 16)).unwrap().id, "high");
+        // 内存够但核心不够
+        assert_eq!(auto_select(&presets, &hw(32768, 2)).unwrap().id, "low");
+    }
+
+    #[test]
+    fn auto_select_falls_back_to_first() {
+        let presets = vec![preset("only", 999_999, 0)];
+        let hw = Hardware { ram_mb: 1024, cores: 2 };
+        assert_eq!(auto_select(&presets, &hw).unwrap().id, "only");
+        assert!(auto_select(&[], &hw).is_none());
+    }
+
+    #[test]
+    fn user_choice_overrides_preset() {
+        let mut p = preset("low", 0, 0);
+        p.optional_mods.insert("mods/iris.pw.toml".to_string(), false);
+        p.optional_mods.insert("mods/minimap.pw.toml".to_string(), true);
+        let mut user = BTreeMap::new();
+        user.insert("mods/iris.pw.toml".to_string(), true);
+
+        let choices = effective_choices(Some(&p), &user);
+        assert_eq!(choices.get("mods/iris.pw.toml"), Some(&true));
+        assert_eq!(choices.get("mods/minimap.pw.toml"), Some(&true));
+    }
+
+    #[test]
+    fn set_colon_value_replaces_or_appends() {
+        let content = "version:3955\nrenderDistance:12\n";
+        let updated = set_colon_value(content, "renderDistance", "6");
+        assert_eq!(updated, "version:3955\nrenderDistance:6\n");
+        let appended = set_colon_value(&updated, "graphicsMode", "0");
+        assert_eq!(appended, "version:3955\nrenderDistance:6\ngraphicsMode:0\n");
+        assert_eq!(set_colon_value("", "a", "1"), "a:1\n");
+    }
+
+    #[test]
+    fn apply_skips_invalid_options() {
+        let dir = crate::http::fake::temp_dir("presets_apply");
+        fs::create_dir_all(dir.join("updater")).unwrap();
+        let mut p = preset("low", 0, 0);
+        for (key, value) in [
+            ("renderDistance", "6"),
+            ("key_key.attack", "key.mouse.left"),
+            ("evil\nfov", "1"),
+            ("a b", "1"),
+            ("", "1"),
+            ("graphicsMode", "0\nfov:1.0"),
+            ("gamma", "0.5\r"),
+        ] {
+            p.options.insert(key.to_string(), value.to_string());
+        }
+
+        assert!(apply(&dir, &p, "1.21.11-Fabric").unwrap());
+        let options =
+            fs::read_to_string(dir.join(config::MINECRAFT_DIR).join("options.txt")).unwrap();
+        assert_eq!(options, "key_key.attack:key.mouse.left\nrenderDistance:6\n");
+
+        // 标记带版本行；预设不变时不再写入
+        let marker = fs::read_to_string(dir.join(config::PRESET_APPLIED_FILE)).unwrap();
+        assert!(marker.starts_with("# upmc-state v1\n1.21.11-Fabric:"));
+        assert!(!apply(&dir, &p, "1.21.11-Fabric").unwrap());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn pcl_ram_slider_matches_scale() {
+        assert_eq!(pcl_ram_slider(1024), 7); // 1.0 GB
+        assert_eq!(pcl_ram_slider(2048), 13); // 2.0 GB
+        assert_eq!(pcl_ram_slider(4096), 17); // 4.0 GB
+        assert_eq!(pcl_ram_slider(8192), 25); // 8.0 GB
+        assert_eq!(pcl_ram_slider(12288), 29); // 12 GB
+        assert_eq!(pcl_ram_slider(20480), 35); // 20 GB
+    }
+}
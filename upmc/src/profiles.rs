@@ -0,0 +1,353 @@
+// ============================================================
+// profiles.rs — 多个整合包（实例）
+// ============================================================
+// 社区同时运营生存服和创造服时，一个更新器要能管理多个整合包。
+// 每个整合包（profile）对应一个独立的实例目录：
+//   默认整合包        → 安装基准目录本身（与旧版本完全兼容）
+//   其他整合包 <id>   → 安装基准目录/profiles/<id>/
+// 实例目录有自己的 .minecraft、local.json、pack.toml 缓存和 PCL2 配置，
+// 其 settings.json 的 server_source 指向该整合包的 server.json，
+// 所以更新流程的其余部分不需要知道 profile 的存在。
+//
+// 整合包列表来自两处：
+//   - 默认整合包的 server.json 中的 profiles（只接受 HTTPS 地址，
+//     始终按远程来源处理：下载地址只认 HTTPS 白名单，私有地址也不放宽）
+//   - 玩家在主窗口「添加...」中输入的 server.json 地址（本地路径和局域网地址按 source.rs 放宽）
+// GUI 使用上次选择的整合包；命令行用 --profile <id> 指定，有多个整合包时必须指定。
+// Discord 代理和更新器通道是整台电脑共用的，始终保存在安装基准目录。
+// ============================================================
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::settings;
+use crate::source::ServerSource;
+use crate::state;
+
+/// 默认整合包的 id
+pub const DEFAULT_PROFILE: &str = "default";
+/// 其他整合包的实例目录所在的子目录
+const PROFILES_DIR: &str = "profiles";
+
+/// 一个整合包
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    /// 唯一标识，同时是实例目录名（只允许字母、数字、`-`、`_`）
+    pub id: String,
+    /// 主窗口中显示的名称
+    pub name: String,
+    /// server.json 地址（https:// / http:// / 本地路径），默认整合包为空
+    #[serde(default)]
+    pub server_url: String,
+    /// 是否来自服务器声明（由 list 设置，不读写文件）
+    #[serde(skip)]
+    pub from_server: bool,
+}
+
+/// profiles.json 内容
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ProfilesFile {
+    /// 上次选择的整合包
+    #[serde(default)]
+    selected: Option<String>,
+    /// 玩家自己添加的整合包
+    #[serde(default)]
+    user: Vec<Profile>,
+}
+
+/// 默认整合包（安装基准目录本身）
+pub fn default_profile() -> Profile {
+    Profile {
+        id: DEFAULT_PROFILE.to_string(),
+        name: "主整合包".to_string(),
+        server_url: String::new(),
+        from_server: false,
+    }
+}
+
+/// 实例目录对应的安装基准目录（默认整合包就是它自己）
+pub fn root_dir(base_dir: &Path) -> PathBuf {
+    match base_dir.parent() {
+        Some(parent) if parent.file_name().is_some_and(|name| name == PROFILES_DIR) => parent
+            .parent()
+            .map_or_else(|| base_dir.to_path_buf(), Path::to_path_buf),
+        _ => base_dir.to_path_buf(),
+    }
+}
+
+/// 保存默认整合包的 server.json 声明的整合包列表（其他实例的声明忽略）
+pub fn save_declared(base_dir: &Path, profiles: &[Profile]) -> Result<()> {
+    if root_dir(base_dir) != base_dir {
+        return Ok(());
+    }
+    state::SERVER_PROFILES
+        .write_list(base_dir, profiles)
+        .context("写入整合包列表失败")
+}
+
+/// 所有整合包：默认整合包、服务器声明的、玩家添加的（id 重复时前者优先）
+pub fn list(root: &Path) -> Vec<Profile> {
+    let declared: Vec<Profile> = state::SERVER_PROFILES.read_list(root);
+    let declared = declared
+        .into_iter()
+        .filter(|profile| {
+            let ok = profile.server_url.starts_with("https://");
+            if !ok {
+                eprintln!(
+                    "忽略 server.json 中的整合包 {}：地址必须使用 HTTPS",
+                    profile.id
+                );
+            }
+            ok
+        })
+        .map(|profile| Profile {
+            from_server: true,
+            ..profile
+        });
+    let user = load_file(root).user;
+
+    let mut profiles = vec![default_profile()];
+    for profile in declared.chain(user) {
+        if validate_id(&profile.id).is_ok() && !profiles.iter().any(|p| p.id == profile.id) {
+            profiles.push(profile);
+        }
+    }
+    profiles
+}
+
+/// 上次选择的整合包（已不存在时回到默认整合包）
+pub fn selected(root: &Path) -> Profile {
+    let selected = load_file(root).selected;
+    list(root)
+        .into_iter()
+        .find(|profile| Some(&profile.id) == selected.as_ref())
+        .unwrap_or_else(default_profile)
+}
+
+/// 确定本次运行的整合包：指定了 id 时使用它；
+/// 没有指定时 GUI 使用上次选择的，命令行只在仅有一个整合包时才能确定。
+pub fn resolve(root: &Path, requested: Option<&str>, gui: bool) -> Result<Profile> {
+    let profiles = list(root);
+    let ids = || {
+        profiles
+            .iter()
+            .map(|profile| profile.id.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match requested {
+        Some(id) => match profiles.iter().find(|profile| profile.id == id) {
+            Some(profile) => Ok(profile.clone()),
+            None => bail!("没有 id 为 {id} 的整合包，可用的整合包: {}", ids()),
+        },
+        None if gui => Ok(selected(root)),
+        None if profiles.len() > 1 => bail!(
+            "有多个整合包，请用 --profile <id> 指定要操作的整合包: {}",
+            ids()
+        ),
+        None => Ok(default_profile()),
+    }
+}
+
+/// 记住选择的整合包
+#[cfg(any(windows, test))]
+pub fn select(root: &Path, id: &str) -> Result<()> {
+    let mut file = load_file(root);
+    file.selected = Some(id.to_string());
+    save_file(root, &file)
+}
+
+/// 添加一个整合包。id 由地址生成，同一地址重复添加时返回已有的整合包。
+#[cfg(any(windows, test))]
+pub fn add(root: &Path, name: &str, server_url: &str) -> Result<Profile> {
+    let server_url = server_url.trim();
+    if server_url.is_empty() {
+        bail!("请填写 server.json 地址");
+    }
+    let hash = crate::pack_index::hash_bytes("sha256", server_url.as_bytes())?;
+    let id = format!("u{}", &hash[..8]);
+    if let Some(existing) = list(root).into_iter().find(|profile| profile.id == id) {
+        return Ok(existing);
+    }
+
+    let name = name.trim();
+    let profile = Profile {
+        name: if name.is_empty() {
+            server_url.to_string()
+        } else {
+            name.to_string()
+        },
+        id,
+        server_url: server_url.to_string(),
+        from_server: false,
+    };
+    let mut file = load_file(root);
+    file.user.push(profile.clone());
+    save_file(root, &file)?;
+    Ok(profile)
+}
+
+/// 准备整合包的实例目录并返回，作为更新流程的 base_dir。
+///
+/// 非默认整合包会把 settings.json 的 server_source 指向它的 server.json。
+/// 服务器声明的整合包固定为远程来源，只有玩家自己输入的本地 / 局域网地址才放宽校验。
+pub fn instance_dir(root: &Path, profile: &Profile) -> Result<PathBuf> {
+    if profile.id == DEFAULT_PROFILE {
+        return Ok(root.to_path_buf());
+    }
+    validate_id(&profile.id)?;
+    let dir = root.join(PROFILES_DIR).join(&profile.id);
+    fs::create_dir_all(&dir).with_context(|| format!("创建实例目录失败: {}", dir.display()))?;
+
+    let mut user_settings = config::load_user_settings(&dir);
+    let server_source = if profile.from_server {
+        ServerSource::Remote {
+            url: profile.server_url.clone(),
+        }
+    } else {
+        ServerSource::from_arg(&profile.server_url)
+    };
+    if user_settings.server_source != server_source {
+        user_settings.server_source = server_source;
+        settings::save_user(&dir, &user_settings)?;
+    }
+    Ok(dir)
+}
+
+/// id 会成为目录名，只允许字母、数字、`-`、`_`
+fn validate_id(id: &str) -> Result<()> {
+    if id.is_empty()
+        || id.len() > 32
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("整合包 id 非法: {id}");
+    }
+    Ok(())
+}
+
+fn load_file(root: &Path) -> ProfilesFile {
+    state::PROFILES.read_json(root).unwrap_or_default()
+}
+
+#[cfg(any(windows, test))]
+fn save_file(root: &Path, file: &ProfilesFile) -> Result<()> {
+    state::PROFILES
+        .write_json(root, file)
+        .context("写入整合包列表失败")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_dir_of_instance() {
+        let root = Path::new("C:/Users/a/Documents/CJC整合包");
+        assert_eq!(root_dir(root), root);
+        assert_eq!(root_dir(&root.join("profiles").join("creative")), root);
+    }
+
+    fn declared(id: &str, server_url: &str) -> Profile {
+        Profile {
+            id: id.into(),
+            name: id.into(),
+            server_url: server_url.into(),
+            from_server: false,
+        }
+    }
+
+    #[test]
+    fn list_merges_declared_and_user_profiles() {
+        let root = std::env::temp_dir().join(format!("upmc_profiles_{}", std::process::id()));
+        let declared = vec![
+            declared("creative", "https://example.com/creative/server.json"),
+            declared("insecure", "http://example.com/server.json"),
+        ];
+        save_declared(&root, &declared).unwrap();
+        let added = add(&root, "朋友的服务器", "http://192.168.1.10:8000/").unwrap();
+        assert_eq!(add(&root, "", "http://192.168.1.10:8000/").unwrap(), added);
+
+        let ids: Vec<String> = list(&root).into_iter().map(|p| p.id).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "default".to_string(),
+                "creative".to_string(),
+                added.id.clone()
+            ]
+        );
+
+        select(&root, "creative").unwrap();
+        assert_eq!(selected(&root).id, "creative");
+        select(&root, "removed").unwrap();
+        assert_eq!(selected(&root).id, DEFAULT_PROFILE);
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn declared_profiles_stay_on_allowlist() {
+        let root = crate::http::fake::temp_dir("profiles_source");
+        // 服务器声明的地址即使是私有网段也不放宽
+        save_declared(
+            &root,
+            &[declared("lan", "https://192.168.1.10/server.json")],
+        )
+        .unwrap();
+        let user = add(&root, "", "http://192.168.1.20:8000/").unwrap();
+        let profiles = list(&root);
+        assert!(profiles[1].from_server);
+        assert!(!profiles[2].from_server);
+
+        let settings = |profile: &Profile| {
+            let dir = instance_dir(&root, profile).unwrap();
+            config::load_user_settings(&dir).server_source
+        };
+        assert!(matches!(
+            settings(&profiles[1]),
+            ServerSource::Remote { .. }
+        ));
+        assert!(matches!(settings(&user), ServerSource::Lan { .. }));
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn resolve_requires_profile_on_command_line() {
+        let root = crate::http::fake::temp_dir("profiles_resolve");
+        assert_eq!(resolve(&root, None, false).unwrap().id, DEFAULT_PROFILE);
+
+        save_declared(
+            &root,
+            &[declared("creative", "https://example.com/server.json")],
+        )
+        .unwrap();
+        select(&root, "creative").unwrap();
+        assert_eq!(resolve(&root, None, true).unwrap().id, "creative");
+        let err = resolve(&root, None, false).unwrap_err();
+        assert!(format!("{err:#}").contains("--profile"));
+        assert_eq!(
+            resolve(&root, Some("default"), false).unwrap().id,
+            DEFAULT_PROFILE
+        );
+        assert_eq!(
+            resolve(&root, Some("creative"), false).unwrap().id,
+            "creative"
+        );
+        assert!(resolve(&root, Some("missing"), true).is_err());
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn reject_unsafe_ids() {
+        assert!(validate_id("survival_2").is_ok());
+        assert!(validate_id("../x").is_err());
+        assert!(validate_id("").is_err());
+    }
+}
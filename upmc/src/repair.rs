@@ -0,0 +1,508 @@
+// ============================================================
+// repair.rs — 安装完整性校验与修复
+// ============================================================
+// 安装损坏（杀毒软件误删、同步中断、手动改坏文件）时，
+// 以前只能手动删除 updater/pack_toml_cache.txt 让 packwiz 重新同步。
+// 修复模式（设置窗口「修复安装...」或 `upmc repair`）依次检查：
+//   1. 自举组件（PCL2（仅 Windows）、packwiz bootstrap、Fabric 安装器）— server.json 中的 SHA256
+//   2. 原版 version JSON / client.jar — Mojang 清单中的 SHA1（Mojang 只提供 SHA1）
+//   3. Fabric 版本 JSON — 能否解析、是否指向正确的原版
+//   4. PCL2 版本级隔离设置（同 fabric::fix_version_isolation，仅 Windows）
+//   5. .minecraft 中由整合包管理的文件 — index.toml / .pw.toml 中的哈希
+// 不一致的文件重新下载，结果以逐文件表格报告。
+// ============================================================
+
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::Path;
+
+use crate::bootstrap;
+use crate::config;
+use crate::fabric;
+use crate::pack_index;
+use crate::packs;
+use crate::source;
+use crate::state;
+use crate::update::Progress;
+use crate::version::{self, RemoteVersion};
+
+/// 单个问题文件的处理结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// 已重新下载 / 修正
+    Repaired,
+    /// 修复失败（原因）
+    Failed(String),
+}
+
+/// 报告中的一行
+#[derive(Debug, Clone)]
+pub struct RepairItem {
+    /// 类别，如 "组件" / "原版" / "模组"
+    pub category: &'static str,
+    /// 相对安装目录的路径
+    pub path: String,
+    /// 发现的问题，如 "文件缺失" / "哈希不匹配"
+    pub problem: String,
+    pub outcome: Outcome,
+}
+
+/// 修复结果
+#[derive(Debug, Clone, Default)]
+pub struct RepairReport {
+    /// 检查过的文件 / 设置数量
+    pub checked: usize,
+    /// 有问题的项目（没问题的不列出）
+    pub items: Vec<RepairItem>,
+}
+
+impl RepairReport {
+    pub fn failed_count(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|item| matches!(item.outcome, Outcome::Failed(_)))
+            .count()
+    }
+
+    /// 一句话总结
+    pub fn summary(&self) -> String {
+        if self.items.is_empty() {
+            format!("检查了 {} 项，安装完好", self.checked)
+        } else {
+            format!(
+                "检查了 {} 项，发现 {} 个问题，修复 {} 个，失败 {} 个",
+                self.checked,
+                self.items.len(),
+                self.items.len() - self.failed_count(),
+                self.failed_count()
+            )
+        }
+    }
+
+    /// 逐文件表格（总结 + 每个问题一行）
+    pub fn render_table(&self) -> String {
+        if self.items.is_empty() {
+            return self.summary();
+        }
+        let mut lines = vec![self.summary(), String::new()];
+        lines.push("类别 | 文件 | 问题 | 结果".to_string());
+        lines.push("-----|------|------|-----".to_string());
+        for item in &self.items {
+            let outcome = match &item.outcome {
+                Outcome::Repaired => "已修复".to_string(),
+                Outcome::Failed(reason) => format!("失败: {reason}"),
+            };
+            lines.push(format!(
+                "{} | {} | {} | {}",
+                item.category, item.path, item.problem, outcome
+            ));
+        }
+        lines.join("\r\n")
+    }
+
+    fn record(&mut self, category: &'static str, path: &str, problem: &str, result: Result<()>) {
+        self.items.push(RepairItem {
+            category,
+            path: path.to_string(),
+            problem: problem.to_string(),
+            outcome: match result {
+                Ok(()) => Outcome::Repaired,
+                Err(e) => Outcome::Failed(format!("{e:#}")),
+            },
+        });
+    }
+}
+
+/// 执行完整的校验与修复。
+///
+/// 需要联网获取 server.json 和整合包索引；本地版本与服务器不一致时
+/// 应先走正常更新流程，这里直接报错。
+pub fn run(base_dir: &Path, on_progress: &dyn Fn(Progress)) -> Result<RepairReport> {
+    if !bootstrap::is_bootstrapped(base_dir) {
+        bail!("尚未安装，请先正常运行一次更新器");
+    }
+
+    on_progress(Progress::new(2, "正在获取整合包信息..."));
+    let remote = version::fetch_remote_version(base_dir).context("修复需要联网获取整合包信息")?;
+    let remote = packs::apply_pin(base_dir, remote);
+    let local = version::read_local_version(base_dir);
+    if version::needs_version_upgrade(&remote, &local) {
+        bail!(
+            "本地版本（{}）与服务器（{}）不一致，请先正常更新后再修复",
+            local.version_tag,
+            remote.version_tag
+        );
+    }
+
+    let mut report = RepairReport::default();
+
+    on_progress(Progress::new(5, "正在校验启动器和工具..."));
+    check_components(base_dir, &remote, &mut report, on_progress);
+
+    on_progress(Progress::new(15, "正在校验原版 MC..."));
+    check_vanilla(base_dir, &remote.mc_version, &mut report);
+
+    on_progress(Progress::new(25, "正在校验 Fabric..."));
+    check_fabric(base_dir, &remote, &mut report);
+
+    // PCL2 只有 Windows 版，其他平台没有版本隔离设置
+    if cfg!(windows) {
+        on_progress(Progress::new(30, "正在校验版本隔离设置..."));
+        report.checked += 1;
+        if !fabric::is_version_isolation_fixed(base_dir, &remote.version_tag) {
+            let path = format!(
+                "{}/versions/{}/PCL/Setup.ini",
+                config::MINECRAFT_DIR,
+                remote.version_tag
+            );
+            let result = fabric::fix_version_isolation(base_dir, &remote.version_tag);
+            report.record("设置", &path, "版本隔离已开启", result);
+        }
+    }
+
+    on_progress(Progress::new(35, "正在校验整合包文件..."));
+    check_pack_files(
+        base_dir,
+        &remote.pack_url,
+        &remote.pack_toml_raw,
+        &mut report,
+        on_progress,
+    );
+
+    on_progress(Progress::new(100, report.summary()));
+    Ok(report)
+}
+
+/// 1. 自举组件：与 server.json 中的 SHA256 对比，不一致时重新下载
+fn check_components(
+    base_dir: &Path,
+    remote: &RemoteVersion,
+    report: &mut RepairReport,
+    on_progress: &dyn Fn(Progress),
+) {
+    let downloads = &remote.downloads;
+    let components = [
+        #[cfg(windows)]
+        (
+            config::PCL2_EXE,
+            &downloads.pcl2_url,
+            &downloads.pcl2_sha256,
+        ),
+        (
+            config::PACKWIZ_BOOTSTRAP_JAR,
+            &downloads.packwiz_bootstrap_url,
+            &downloads.packwiz_bootstrap_sha256,
+        ),
+        (
+            config::FABRIC_INSTALLER_JAR,
+            &downloads.fabric_installer_url,
+            &downloads.fabric_installer_sha256,
+        ),
+    ];
+
+    for (relative, url, sha256) in components {
+        let (Some(url), Some(sha256)) = (url.as_deref(), sha256.as_deref()) else {
+            // server.json 没有提供校验值，无从判断
+            continue;
+        };
+        report.checked += 1;
+        let path = base_dir.join(relative);
+        let problem = match bootstrap::sha256_file(&path) {
+            Ok(actual) if actual.eq_ignore_ascii_case(sha256) => continue,
+            Ok(_) => "SHA256 不匹配",
+            Err(_) if !path.exists() => "文件缺失",
+            Err(_) => "无法读取",
+        };
+        let result = bootstrap::download_file_verified(base_dir, url, &path, sha256, on_progress, 5, 15);
+        report.record("组件", relative, problem, result);
+    }
+}
+
+/// 2. 原版 version JSON 与 client.jar：与 Mojang 提供的 SHA1 对比
+fn check_vanilla(base_dir: &Path, mc_version: &str, report: &mut RepairReport) {
+    let ver_dir = format!("{}/versions/{mc_version}", config::MINECRAFT_DIR);
+    let json_relative = format!("{ver_dir}/{mc_version}.json");
+    let jar_relative = format!("{ver_dir}/{mc_version}.jar");
+    let json_path = base_dir.join(&json_relative);
+    let jar_path = base_dir.join(&jar_relative);
+
+    // version JSON：清单中的 SHA1
+    report.checked += 1;
+    match fabric::vanilla_manifest_entry(base_dir, mc_version) {
+        Ok((_, Some(expected))) => {
+            if let Some(problem) = file_problem(&json_path, "sha1", &expected) {
+                fs::remove_file(&json_path).ok();
+                let result = fabric::ensure_vanilla_client(base_dir, mc_version);
+                report.record("原版", &json_relative, &problem, result);
+            }
+        }
+        Ok((_, None)) => {}
+        Err(e) => {
+            report.record("原版", &json_relative, "无法获取校验值", Err(e));
+            return;
+        }
+    }
+
+    // client.jar：version JSON 中的 SHA1
+    report.checked += 1;
+    let expected = fs::read_to_string(&json_path)
+        .ok()
+        .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+        .and_then(|json| json["downloads"]["client"]["sha1"].as_str().map(str::to_string));
+    let Some(expected) = expected else {
+        report.record(
+            "原版",
+            &jar_relative,
+            "无法获取校验值",
+            Err(anyhow::anyhow!("version JSON 中没有客户端 SHA1")),
+        );
+        return;
+    };
+    if let Some(problem) = file_problem(&jar_path, "sha1", &expected) {
+        fs::remove_file(&jar_path).ok();
+        let result = fabric::ensure_vanilla_client(base_dir, mc_version).and_then(|()| {
+            file_problem(&jar_path, "sha1", &expected)
+                .map_or(Ok(()), |problem| Err(anyhow::anyhow!("重新下载后仍然{problem}")))
+        });
+        report.record("原版", &jar_relative, &problem, result);
+    }
+}
+
+/// 3. Fabric 版本 JSON：必须能解析并继承自正确的原版，否则重新运行 Fabric 安装器
+fn check_fabric(base_dir: &Path, remote: &RemoteVersion, report: &mut RepairReport) {
+    let tag = &remote.version_tag;
+    let relative = format!("{}/versions/{tag}/{tag}.json", config::MINECRAFT_DIR);
+    report.checked += 1;
+
+    let json = fs::read_to_string(base_dir.join(&relative))
+        .ok()
+        .map(|s| serde_json::from_str::<serde_json::Value>(&s));
+    let problem = match json {
+        None => "文件缺失",
+        Some(Err(_)) => "JSON 损坏",
+        Some(Ok(json)) if json["inheritsFrom"].as_str() != Some(remote.mc_version.as_str()) => {
+            "原版版本不一致"
+        }
+        Some(Ok(_)) => return,
+    };
+    let result = fabric::install_fabric(base_dir, &remote.mc_version, &remote.fabric_version);
+    report.record("Fabric", &relative, problem, result);
+}
+
+/// 5. 整合包管理的文件：按 index.toml（普通文件）和 .pw.toml（模组）中的哈希校验
+///
+/// 获取不到索引时记为一项失败，前面各步的结果照常返回。
+fn check_pack_files(
+    base_dir: &Path,
+    pack_url: &str,
+    pack_toml: &str,
+    report: &mut RepairReport,
+    on_progress: &dyn Fn(Progress),
+) {
+    report.checked += 1;
+    let (index_url, index) = match pack_index::fetch_index(base_dir, pack_url, pack_toml) {
+        Ok(index) => index,
+        Err(e) => {
+            report.record("整合包", "整合包索引", "无法获取", Err(e));
+            return;
+        }
+    };
+    let mc_dir = base_dir.join(config::MINECRAFT_DIR);
+    let failed_before = report.failed_count();
+
+    let total = index.files.len().max(1);
+    for (i, entry) in index.files.iter().enumerate() {
+        let pct = 35 + (i * 64 / total) as u32;
+        on_progress(Progress::new(pct, format!("正在校验 {}", entry.file)));
+
+        let check = if entry.metafile {
+            check_mod(base_dir, &mc_dir, &index_url, entry)
+        } else {
+            check_plain_file(&mc_dir, &index_url, entry)
+        };
+        match check {
+            Ok(None) => {}
+            Ok(Some((target, problem, result))) => {
+                let path = format!("{}/{target}", config::MINECRAFT_DIR);
+                report.record("整合包", &path, &problem, result);
+            }
+            Err(e) => {
+                let path = format!("{}/{}", config::MINECRAFT_DIR, entry.file);
+                report.record("整合包", &path, "无法校验", Err(e));
+            }
+        }
+        report.checked += 1;
+    }
+
+    // 仍有文件没修好：清除 pack.toml 缓存，下次启动由 packwiz 完整重新同步
+    if report.failed_count() > failed_before {
+        state::PACK_TOML_CACHE.remove(base_dir);
+    }
+}
+
+/// 检查结果：(相对 .minecraft 的路径, 问题, 修复结果)，没有问题时为 None
+type FileCheck = Option<(String, String, Result<()>)>;
+
+/// 普通文件（配置等）：哈希在 index.toml 中，下载地址相对 index.toml
+fn check_plain_file(mc_dir: &Path, index_url: &str, entry: &pack_index::IndexEntry) -> Result<FileCheck> {
+    let path = bootstrap::safe_zip_output_path(mc_dir, &entry.file)?;
+    // preserve 文件只在首次安装时写入，玩家之后的修改是正常的，只补回缺失的文件
+    if entry.preserve && path.exists() {
+        return Ok(None);
+    }
+    let Some(problem) = file_problem(&path, &entry.hash_format, &entry.hash) else {
+        return Ok(None);
+    };
+    let url = source::resolve_relative(index_url, &entry.file);
+    let result = pack_index::download_verified(&url, &entry.hash_format, &entry.hash)
+        .and_then(|data| write_file(&path, &data));
+    Ok(Some((entry.file.clone(), problem, result)))
+}
+
+/// 模组：哈希和下载地址在 .pw.toml 的 [download] 段
+fn check_mod(
+    base_dir: &Path,
+    mc_dir: &Path,
+    index_url: &str,
+    entry: &pack_index::IndexEntry,
+) -> Result<FileCheck> {
+    let meta = pack_index::fetch_mod_meta(base_dir, index_url, entry)?;
+    if meta.side == "server" {
+        return Ok(None);
+    }
+    let target = pack_index::meta_target_path(&entry.file, &meta.filename);
+    let path = bootstrap::safe_zip_output_path(mc_dir, &target)?;
+    // 被玩家禁用的可选模组由 optional_mods 负责，不当作缺失
+    if meta.option.as_ref().is_some_and(|o| o.optional) && !path.exists() {
+        return Ok(None);
+    }
+    let Some(problem) = file_problem(&path, &meta.hash_format, &meta.hash) else {
+        return Ok(None);
+    };
+    let result = meta
+        .download_url
+        .as_deref()
+        .context("整合包没有提供下载地址")
+        .and_then(|url| {
+            if !url.starts_with("https://") {
+                bail!("下载地址必须使用 HTTPS: {url}");
+            }
+            pack_index::download_verified(url, &meta.hash_format, &meta.hash)
+        })
+        .and_then(|data| write_file(&path, &data));
+    Ok(Some((target, problem, result)))
+}
+
+/// 检查文件是否与期望的哈希一致，返回问题描述。
+///
+/// 不支持的哈希格式（如 CurseForge 的 murmur2）报告为无法校验，修复时同样失败，
+/// 最后会清除 pack.toml 缓存，交给 packwiz 重新同步。
+fn file_problem(path: &Path, hash_format: &str, expected: &str) -> Option<String> {
+    if !path.exists() {
+        return Some("文件缺失".to_string());
+    }
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) => return Some(format!("无法读取: {e}")),
+    };
+    match pack_index::hash_bytes(hash_format, &data) {
+        Ok(actual) if actual.eq_ignore_ascii_case(expected) => None,
+        Ok(_) => Some("哈希不匹配".to_string()),
+        Err(e) => Some(format!("无法校验（{e:#}）")),
+    }
+}
+
+fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, data).with_context(|| format!("写入 {} 失败", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn file_problem_detects_missing_and_mismatch() {
+        let dir = std::env::temp_dir().join(format!("upmc_repair_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+
+        assert_eq!(file_problem(&path, "sha256", HELLO_SHA256).as_deref(), Some("文件缺失"));
+        fs::write(&path, "hello").unwrap();
+        assert_eq!(file_problem(&path, "sha256", HELLO_SHA256), None);
+        fs::write(&path, "hello!").unwrap();
+        assert_eq!(file_problem(&path, "sha256", HELLO_SHA256).as_deref(), Some("哈希不匹配"));
+        // 无法校验的格式不能当作正常
+        assert_eq!(
+            file_problem(&path, "murmur2", "1").as_deref(),
+            Some("无法校验（不支持的哈希格式: murmur2）")
+        );
+        let err = pack_index::download_verified("https://example.com/a.jar", "murmur2", "1");
+        assert!(format!("{:#}", err.unwrap_err()).contains("murmur2"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn preserved_file_is_not_overwritten() {
+        let dir = std::env::temp_dir().join(format!("upmc_repair_preserve_{}", std::process::id()));
+        fs::create_dir_all(dir.join("config")).unwrap();
+        fs::write(dir.join("config/options.json"), "player edit").unwrap();
+        let entry = pack_index::IndexEntry {
+            file: "config/options.json".to_string(),
+            hash: HELLO_SHA256.to_string(),
+            hash_format: "sha256".to_string(),
+            metafile: false,
+            preserve: true,
+        };
+
+        let check = check_plain_file(&dir, "file:///nowhere/index.toml", &entry).unwrap();
+        assert!(check.is_none());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn unreachable_index_keeps_partial_report() {
+        let dir = crate::http::fake::temp_dir("repair_index");
+        let mut report = RepairReport::default();
+        report.record("组件", config::PCL2_EXE, "文件缺失", Ok(()));
+
+        let pack_url = source::path_to_file_url(&dir.join("pack.toml"));
+        let pack_toml = "[index]\nfile = \"index.toml\"\n";
+        check_pack_files(&dir, &pack_url, pack_toml, &mut report, &|_| {});
+
+        assert_eq!(report.items.len(), 2);
+        assert_eq!(report.items[0].outcome, Outcome::Repaired);
+        assert_eq!(report.items[1].path, "整合包索引");
+        assert!(matches!(report.items[1].outcome, Outcome::Failed(_)));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn report_summary_and_table() {
+        let mut report = RepairReport {
+            checked: 10,
+            ..Default::default()
+        };
+        assert_eq!(report.render_table(), "检查了 10 项，安装完好");
+
+        report.record("组件", "PCL/Plain Craft Launcher 2.exe", "SHA256 不匹配", Ok(()));
+        report.record(
+            "整合包",
+            ".minecraft/mods/a.jar",
+            "文件缺失",
+            Err(anyhow::anyhow!("网络错误")),
+        );
+        assert_eq!(report.failed_count(), 1);
+        let table = report.render_table();
+        assert!(table.starts_with("检查了 10 项，发现 2 个问题，修复 1 个，失败 1 个"));
+        assert!(table.contains("组件 | PCL/Plain Craft Launcher 2.exe | SHA256 不匹配 | 已修复"));
+        assert!(table.contains("整合包 | .minecraft/mods/a.jar | 文件缺失 | 失败: 网络错误"));
+    }
+}
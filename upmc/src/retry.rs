@@ -1,21 +1,155 @@
 // ============================================================
 // retry.rs — 网络操作重试模块
 // ============================================================
-// 为需要联网的操作提供自动重试机制。
-// 使用指数退避策略：每次失败后等待时间翻倍。
+// 为需要联网的操作提供自动重试机制（RetryPolicy）：
+//   - 分类：错误码标记为不可重试的错误（磁盘已满、哈希不匹配等）
+//     和 404 之类的客户端错误立即返回，只有暂时性错误才重试
+//   - 退避：指数退避（每次失败后等待时间翻倍，有上限），附加随机抖动，
+//     避免大量玩家在服务器恢复的同一时刻一起重试
+//   - Retry-After：服务器返回 429 / 503 并指定等待时间时按它等待
+//   - 时间预算：所有重试的总耗时超过预算后不再重试
+//
+// 另外按主机维护熔断器：同一主机连续失败达到阈值后，一段时间内
+// http::get 直接失败、mirrors::with_failover 跳过该主机改用其他镜像。
 //
 // 用法示例：
-//   retry::with_retry(3, 3, "下载文件", || {
+//   retry::with_default_retry("下载文件", || {
 //       download_something()
 //   })
 // ============================================================
 
+use std::collections::BTreeMap;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::Mutex;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, ensure, Result};
 
-/// 执行一个操作，失败时自动重试。
+use crate::error;
+use crate::settings;
+
+/// 重试策略
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// 最大尝试次数（含首次），必须 ≥ 1
+    pub max_attempts: u32,
+    /// 首次重试前的基础等待时间，后续翻倍
+    pub base_delay: Duration,
+    /// 单次退避等待上限
+    pub max_delay: Duration,
+    /// 所有重试的总时间预算
+    pub budget: Duration,
+}
+
+/// 一次失败后的处理方式
+#[derive(Debug, PartialEq, Eq)]
+enum Decision {
+    /// 不可重试，直接返回
+    Fatal,
+    /// 重试；Some 为服务器通过 Retry-After 指定的等待时间
+    Retry(Option<Duration>),
+}
+
+impl RetryPolicy {
+    /// 退避上限与时间预算来自当前设置
+    pub fn new(max_attempts: u32, base_delay_secs: u64) -> Self {
+        let settings = settings::current();
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_secs(base_delay_secs),
+            max_delay: Duration::from_secs(settings.retry_max_delay_secs),
+            budget: Duration::from_secs(settings.retry_budget_secs),
+        }
+    }
+
+    /// 按策略执行操作，返回第一次成功的结果或最终的错误（附加重试信息）。
+    pub fn run<F, T>(&self, operation_name: &str, f: F) -> Result<T>
+    where
+        F: Fn() -> Result<T>,
+    {
+        ensure!(self.max_attempts > 0, "max_attempts 必须至少为 1");
+
+        let started = Instant::now();
+        let mut last_error = None;
+        let mut attempts = 0;
+
+        for attempt in 1..=self.max_attempts {
+            attempts = attempt;
+            let e = match f() {
+                Ok(value) => {
+                    if attempt > 1 {
+                        eprintln!(
+                            "[重试] {} 在第 {} 次尝试时成功",
+                            operation_name, attempt
+                        );
+                    }
+                    return Ok(value);
+                }
+                Err(e) => e,
+            };
+
+            let delay = match decide(&e) {
+                Decision::Fatal => {
+                    eprintln!("[重试] {} 失败，错误不可重试: {:#}", operation_name, e);
+                    return Err(e);
+                }
+                Decision::Retry(Some(after)) => after,
+                Decision::Retry(None) => self.backoff(attempt, random_fraction()),
+            };
+
+            if attempt == self.max_attempts {
+                eprintln!(
+                    "[重试] {} 在 {} 次尝试后仍然失败",
+                    operation_name, attempt
+                );
+            } else if started.elapsed() + delay > self.budget {
+                eprintln!(
+                    "[重试] {} 失败，再等待 {} 秒将超出 {} 秒的时间预算，不再重试\n  原因: {:#}",
+                    operation_name,
+                    delay.as_secs(),
+                    self.budget.as_secs(),
+                    e
+                );
+                last_error = Some(e);
+                break;
+            } else {
+                eprintln!(
+                    "[重试] {} 失败（第 {}/{} 次尝试），{} 秒后重试...\n  原因: {:#}",
+                    operation_name,
+                    attempt,
+                    self.max_attempts,
+                    delay.as_secs_f32().round(),
+                    e
+                );
+                thread::sleep(delay);
+            }
+            last_error = Some(e);
+        }
+
+        // last_error 一定有值：ensure! 保证 max_attempts >= 1，循环至少执行一次
+        match last_error {
+            Some(err) => Err(err.context(format!(
+                "{} 重试 {} 次后仍然失败",
+                operation_name, attempts
+            ))),
+            None => bail!("{} 重试逻辑异常", operation_name),
+        }
+    }
+
+    /// 第 `attempt` 次失败后的退避时间：base × 2^(attempt-1)，不超过 max_delay，
+    /// 再按 `fraction`（0..1）取后一半区间内的随机值（等量抖动）。
+    fn backoff(&self, attempt: u32, fraction: f64) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let capped = exponential.min(self.max_delay);
+        capped.div_f64(2.0) + capped.div_f64(2.0).mul_f64(fraction.clamp(0.0, 1.0))
+    }
+}
+
+/// 执行一个操作，失败时按默认策略自动重试。
 ///
 /// # 参数
 /// - `max_attempts`: 最大尝试次数（含首次），必须 ≥ 1
@@ -24,11 +158,12 @@ use anyhow::{bail, ensure, Result};
 /// - `f`: 要执行的操作闭包
 ///
 /// # 退避策略
-/// 等待时间 = base_delay_secs × 2^(attempt-1)
-/// 例如 base_delay_secs=3: 首次失败后等 3s，第二次等 6s
+/// 等待时间 ≈ base_delay_secs × 2^(attempt-1)，上限 retry_max_delay_secs，带随机抖动；
+/// 服务器返回 Retry-After 时按其等待。总耗时不超过 retry_budget_secs（见 settings.rs）。
 ///
 /// # 返回
-/// 第一次成功的结果，或最后一次失败的错误（附加重试信息）
+/// 第一次成功的结果，或最后一次失败的错误（附加重试信息）。
+/// 不可重试的错误直接原样返回。
 ///
 /// # 错误
 /// 如果 `max_attempts` 为 0，立即返回 `Err`。
@@ -41,50 +176,120 @@ pub fn with_retry<F, T>(
 where
     F: Fn() -> Result<T>,
 {
-    ensure!(max_attempts > 0, "max_attempts 必须至少为 1");
-
-    let mut last_error = None;
-
-    for attempt in 1..=max_attempts {
-        match f() {
-            Ok(value) => {
-                if attempt > 1 {
-                    eprintln!(
-                        "[重试] {} 在第 {} 次尝试时成功",
-                        operation_name, attempt
-                    );
-                }
-                return Ok(value);
-            }
-            Err(e) => {
-                if attempt < max_attempts {
-                    let delay = base_delay_secs.saturating_mul(2u64.saturating_pow(attempt - 1));
-                    eprintln!(
-                        "[重试] {} 失败（第 {}/{} 次尝试），{} 秒后重试...\n  原因: {:#}",
-                        operation_name, attempt, max_attempts, delay, e
-                    );
-                    thread::sleep(Duration::from_secs(delay));
-                } else {
-                    eprintln!(
-                        "[重试] {} 在 {} 次尝试后仍然失败",
-                        operation_name, max_attempts
-                    );
-                }
-                last_error = Some(e);
-            }
+    RetryPolicy::new(max_attempts, base_delay_secs).run(operation_name, f)
+}
+
+/// 按当前设置的尝试次数和基础等待时间执行操作，见 `with_retry`。
+pub fn with_default_retry<F, T>(operation_name: &str, f: F) -> Result<T>
+where
+    F: Fn() -> Result<T>,
+{
+    let settings = settings::current();
+    with_retry(
+        settings.retry_max_attempts,
+        settings.retry_base_delay_secs,
+        operation_name,
+        f,
+    )
+}
+
+/// 判断失败后是否重试
+fn decide(err: &anyhow::Error) -> Decision {
+    if !error::is_retryable(err) {
+        return Decision::Fatal;
+    }
+    match error::http_status(err) {
+        // 客户端错误（404、403 等）重试也不会成功
+        Some((status, _)) if (400..500).contains(&status) && !matches!(status, 408 | 429) => {
+            Decision::Fatal
         }
+        Some((_, retry_after)) => Decision::Retry(retry_after),
+        None => Decision::Retry(None),
     }
+}
+
+/// 0..1 之间的随机数（标准库 RandomState 每次创建都带随机种子）
+fn random_fraction() -> f64 {
+    let bits = RandomState::new().hash_one(Instant::now());
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// ── 熔断器 ──
 
-    // last_error 一定有值：ensure! 保证 max_attempts >= 1，循环至少执行一次
-    match last_error {
-        Some(err) => Err(err.context(format!(
-            "{} 重试 {} 次后仍然失败",
-            operation_name, max_attempts
-        ))),
-        None => bail!("{} 重试逻辑异常", operation_name),
+/// 熔断器是否正在阻止访问该 URL 的主机（镜像故障转移据此跳过）
+pub fn circuit_open(url: &str) -> bool {
+    circuit_remaining(url).is_some()
+}
+
+/// 该 URL 的主机熔断中时返回剩余时长
+pub fn circuit_remaining(url: &str) -> Option<Duration> {
+    let host = host_of(url)?;
+    lock_breaker().open_for(&host, Instant::now())
+}
+
+/// 记录一次对该 URL 主机的请求结果
+pub fn record_host(url: &str, ok: bool) {
+    if let Some(host) = host_of(url) {
+        lock_breaker().record(&host, ok, Instant::now());
     }
 }
 
+static BREAKER: Mutex<Breaker> = Mutex::new(Breaker {
+    hosts: BTreeMap::new(),
+});
+
+fn lock_breaker() -> std::sync::MutexGuard<'static, Breaker> {
+    BREAKER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 每个主机的连续失败次数与熔断截止时间
+struct Breaker {
+    hosts: BTreeMap<String, HostState>,
+}
+
+#[derive(Default)]
+struct HostState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl Breaker {
+    /// 熔断中时返回剩余时长
+    fn open_for(&self, host: &str, now: Instant) -> Option<Duration> {
+        let until = self.hosts.get(host)?.open_until?;
+        (until > now).then(|| until - now)
+    }
+
+    fn record(&mut self, host: &str, ok: bool, now: Instant) {
+        if ok {
+            self.hosts.remove(host);
+            return;
+        }
+        let settings = settings::current();
+        let threshold = settings.circuit_failure_threshold;
+        let open_secs = settings.circuit_open_secs;
+        let state = self.hosts.entry(host.to_string()).or_default();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= threshold {
+            eprintln!(
+                "[熔断] {host} 连续失败 {} 次，{open_secs} 秒内不再请求",
+                state.consecutive_failures
+            );
+            state.open_until = Some(now + Duration::from_secs(open_secs));
+            // 熔断结束后给一次试探机会，再失败立即重新熔断
+            state.consecutive_failures = threshold - 1;
+        }
+    }
+}
+
+/// URL 的主机名（小写，不含端口）
+fn host_of(url: &str) -> Option<String> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -130,6 +335,91 @@ mod tests {
         assert!(msg.contains("2"));
     }
 
+    #[test]
+    fn fatal_error_is_not_retried() {
+        let call_count = Cell::new(0u32);
+        let result: Result<()> = with_retry(3, 0, "test", || {
+            call_count.set(call_count.get() + 1);
+            Err(error::new(error::ErrorCode::DiskFull, "磁盘已满"))
+        });
+        assert_eq!(
+            error::classify(&result.unwrap_err()),
+            Some(error::ErrorCode::DiskFull)
+        );
+        assert_eq!(call_count.get(), 1);
+    }
+
+    #[test]
+    fn client_errors_are_fatal_and_retry_after_is_honoured() {
+        let not_found = anyhow::Error::new(ureq::Error::StatusCode(404));
+        assert_eq!(decide(&not_found), Decision::Fatal);
+
+        let busy = anyhow::Error::new(error::HttpStatus {
+            status: 503,
+            retry_after: Some(Duration::from_secs(12)),
+        });
+        assert_eq!(decide(&busy), Decision::Retry(Some(Duration::from_secs(12))));
+    }
+
+    #[test]
+    fn backoff_is_capped_and_jittered() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_secs(3),
+            max_delay: Duration::from_secs(30),
+            budget: Duration::from_secs(180),
+        };
+        assert_eq!(policy.backoff(1, 1.0), Duration::from_secs(3));
+        assert_eq!(policy.backoff(2, 0.0), Duration::from_secs(3));
+        assert_eq!(policy.backoff(8, 1.0), Duration::from_secs(30));
+        assert!((0..100).all(|_| (0.0..1.0).contains(&random_fraction())));
+    }
+
+    #[test]
+    fn budget_stops_retrying() {
+        let call_count = Cell::new(0u32);
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(60),
+            max_delay: Duration::from_secs(60),
+            budget: Duration::from_secs(1),
+        };
+        let result: Result<()> = policy.run("test", || {
+            call_count.set(call_count.get() + 1);
+            bail!("timeout");
+        });
+        assert!(result.is_err());
+        assert_eq!(call_count.get(), 1);
+    }
+
+    #[test]
+    fn breaker_opens_after_consecutive_failures() {
+        let mut breaker = Breaker {
+            hosts: BTreeMap::new(),
+        };
+        let settings = settings::current();
+        let now = Instant::now();
+        for _ in 0..settings.circuit_failure_threshold - 1 {
+            breaker.record("a.example.com", false, now);
+        }
+        assert!(breaker.open_for("a.example.com", now).is_none());
+        breaker.record("a.example.com", false, now);
+        assert!(breaker.open_for("a.example.com", now).is_some());
+        assert!(breaker.open_for("b.example.com", now).is_none());
+
+        let later = now + Duration::from_secs(settings.circuit_open_secs + 1);
+        assert!(breaker.open_for("a.example.com", later).is_none());
+        breaker.record("a.example.com", true, later);
+        assert!(breaker.hosts.is_empty());
+    }
+
+    #[test]
+    fn host_of_url() {
+        assert_eq!(host_of("https://Meta.FabricMC.net:443/v2/x").as_deref(), Some("meta.fabricmc.net"));
+        assert_eq!(host_of("http://192.168.1.10:8000/server.json").as_deref(), Some("192.168.1.10"));
+        assert_eq!(host_of(""), None);
+    }
+
     #[test]
     fn zero_attempts_returns_error() {
         let result: Result<()> = with_retry(0, 1, "test", || Ok(()));
@@ -6,7 +6,7 @@
 //      - Stable: upmc.chenjicheng.cn/version.json
 //      - Dev:    upmc.chenjicheng.cn/dev/version.json
 //   2. 对比编译期硬编码的 build_id（commit SHA）与远程 build_id
-//   3. 如果不同，下载新 exe → 启动自拷贝 helper 替换并重启
+//   3. 如果不同且本机落在灰度比例内，下载新 exe → 启动自拷贝 helper 替换并重启
 //   4. 清理残留临时文件
 //
 // 所有通道统一使用 build_id（commit SHA）判断是否需要更新，
@@ -20,6 +20,11 @@
 //
 // 该策略避免调用 PowerShell / cmd / 脚本解释器，也不使用
 // ExecutionPolicy Bypass，降低 Defender 启发式误报概率。
+//
+// 灰度发布：
+//   version.json 可选 rollout_percent（0-100，超出范围时截断，无法解析时视为 0）。每个安装首次运行时
+//   生成一个固定的随机桶号（0-99）保存在 updater/rollout_bucket.txt，
+//   桶号 < rollout_percent 的安装才会更新。测试者可在设置中忽略灰度。
 // ============================================================
 
 use anyhow::{Context, Result, bail};
@@ -32,8 +37,13 @@ use std::process::Command;
 use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::config::{self, UpdateChannel};
+use crate::config::UpdateChannel;
+use crate::http::{self, RequestKind};
+use crate::http_cache;
+use crate::mirrors::{self, Endpoint};
 use crate::retry;
+use crate::settings;
+use crate::state;
 
 /// 当前构建 ID（CI 编译时注入的 commit SHA）
 /// 本地开发时为 None
@@ -188,34 +198,34 @@ fn is_helper_file_name(name: &str) -> bool {
 pub fn cleanup_old_exe() {
     if let Ok(exe) = current_exe_path() {
         let new = exe.with_extension("exe.new");
-        if new.exists() {
-            if let Err(e) = fs::remove_file(&new) {
-                eprintln!("清理残留 .exe.new 失败: {e}");
-            }
+        if new.exists()
+            && let Err(e) = fs::remove_file(&new)
+        {
+            eprintln!("清理残留 .exe.new 失败: {e}");
         }
 
         // 兼容旧版自更新策略可能残留的 .old 文件
         let old = exe.with_extension("exe.old");
-        if old.exists() {
-            if let Err(e) = fs::remove_file(&old) {
-                eprintln!("清理残留 .exe.old 失败: {e}");
-            }
+        if old.exists()
+            && let Err(e) = fs::remove_file(&old)
+        {
+            eprintln!("清理残留 .exe.old 失败: {e}");
         }
 
         // 清理上次复制出来的 helper。Windows 下 helper 运行时无法删除自身，
         // 因此通常会在新版启动时由主程序清理。
-        if let Some(parent) = exe.parent() {
-            if let Ok(entries) = fs::read_dir(parent) {
-                for entry in entries.flatten() {
-                    let path = entry.path();
-                    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
-                        continue;
-                    };
-                    if is_helper_file_name(name) {
-                        if let Err(e) = fs::remove_file(&path) {
-                            eprintln!("清理残留 helper 失败: {}: {e}", path.display());
-                        }
-                    }
+        if let Some(parent) = exe.parent()
+            && let Ok(entries) = fs::read_dir(parent)
+        {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                    continue;
+                };
+                if is_helper_file_name(name)
+                    && let Err(e) = fs::remove_file(&path)
+                {
+                    eprintln!("清理残留 helper 失败: {}: {e}", path.display());
                 }
             }
         }
@@ -233,34 +243,45 @@ pub struct UpdaterVersionInfo {
     /// exe 文件的 SHA256 哈希（小写十六进制），用于下载后完整性校验
     #[serde(default)]
     pub sha256: Option<String>,
+    /// 灰度发布比例（0-100）。缺省表示全量发布。
+    #[serde(default, deserialize_with = "deserialize_rollout_percent")]
+    pub rollout_percent: Option<u8>,
 }
 
-/// 从版本信息 URL 获取更新器版本信息（带重试）。
-fn fetch_updater_info(channel: UpdateChannel) -> Result<UpdaterVersionInfo> {
-    retry::with_retry(
-        config::RETRY_MAX_ATTEMPTS,
-        config::RETRY_BASE_DELAY_SECS,
-        "获取更新器版本信息",
-        || fetch_updater_info_inner(channel),
-    )
+/// 宽松解析 rollout_percent：超出 0-100 的数值（如 300、-1、12.5）截断到范围内，
+/// 避免一个写错的比例让整个 version.json 解析失败、阻断所有自更新。
+/// 不是数值（如 "50"、"abc"）时按 0% 处理：写错的比例不能变成全量发布。
+fn deserialize_rollout_percent<'de, D>(deserializer: D) -> Result<Option<u8>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(match value {
+        None => None,
+        Some(value) => match value.as_f64() {
+            Some(percent) => Some(percent.clamp(0.0, 100.0) as u8),
+            None => {
+                eprintln!("警告: version.json 中的 rollout_percent 无效，暂不推送: {value}");
+                Some(0)
+            }
+        },
+    })
 }
 
-/// fetch_updater_info 的内部实现（单次尝试）。
-fn fetch_updater_info_inner(channel: UpdateChannel) -> Result<UpdaterVersionInfo> {
-    let url = config::updater_version_url(channel);
-
-    let agent = config::http_agent();
+/// 从版本信息 URL 获取更新器版本信息（带重试，镜像不可用时自动切换）。
+fn fetch_updater_info(base_dir: &Path, channel: UpdateChannel) -> Result<UpdaterVersionInfo> {
+    retry::with_default_retry("获取更新器版本信息", || {
+        mirrors::with_failover(base_dir, Endpoint::updater_version(channel), |url| {
+            fetch_updater_info_inner(base_dir, url)
+        })
+    })
+}
 
-    let body = agent
-        .get(url)
-        .call()
+/// fetch_updater_info 的内部实现（单次尝试）。
+fn fetch_updater_info_inner(base_dir: &Path, url: &str) -> Result<UpdaterVersionInfo> {
+    let text = http_cache::get_text(base_dir, url, &[])
         .context("无法连接到更新器版本服务器")?;
 
-    let text = body
-        .into_body()
-        .read_to_string()
-        .context("读取版本信息失败")?;
-
     serde_json::from_str(&text).context("解析 version.json 失败")
 }
 
@@ -269,18 +290,26 @@ fn fetch_updater_info_inner(channel: UpdateChannel) -> Result<UpdaterVersionInfo
 /// 所有通道统一使用 build_id（commit SHA）判断是否需要更新：
 ///   本地 build_id != 远程 build_id → 需要更新
 ///
+/// 远程 build_id 不同但本机不在灰度比例内时，视为无需更新。
+///
 /// 返回 `SelfUpdateResult::Restarting` 时，调用方应立即退出进程。
 pub fn check_and_update(
+    base_dir: &Path,
     channel: UpdateChannel,
     on_progress: &dyn Fn(crate::update::Progress),
 ) -> Result<SelfUpdateResult> {
+    // 发布的只有 Windows 版 exe，其他平台自行构建，不参与自更新
+    if !cfg!(windows) {
+        return Ok(SelfUpdateResult::UpToDate);
+    }
+
     on_progress(crate::update::Progress::new(
         1,
         format!("检查更新器版本 ({channel})..."),
     ));
 
     // 从对应通道的 version.json 获取版本信息
-    let info = fetch_updater_info(channel)?;
+    let info = fetch_updater_info(base_dir, channel)?;
 
     // 统一用 build_id 判断是否需要更新
     let needs_update = match (&info.build_id, CURRENT_BUILD_ID) {
@@ -293,6 +322,17 @@ pub fn check_and_update(
         return Ok(SelfUpdateResult::UpToDate);
     }
 
+    if !settings::current().ignore_rollout {
+        let bucket = load_or_create_rollout_bucket(base_dir);
+        if !is_in_rollout(bucket, info.rollout_percent) {
+            eprintln!(
+                "新版更新器灰度发布中（{}%），本机分桶 {bucket} 暂不更新",
+                info.rollout_percent.unwrap_or(100)
+            );
+            return Ok(SelfUpdateResult::UpToDate);
+        }
+    }
+
     let local_id = CURRENT_BUILD_ID.unwrap_or("local");
     let remote_id = info.build_id.as_deref().unwrap_or("unknown");
     on_progress(crate::update::Progress::new(
@@ -319,18 +359,14 @@ pub fn check_and_update(
     let expected_sha256 = info.sha256.clone();
 
     // 下载 + 校验：用闭包包裹，出错时统一清理临时文件
-    let download_and_verify = || -> Result<()> {
-        let agent = config::download_agent();
-
-        let response = agent
-            .get(download_url)
-            .call()
+    let download_and_verify = |download_url: &str| -> Result<()> {
+        let response = http::get(download_url, &[], RequestKind::Download)
             .context("下载更新器新版本失败")?;
 
         // 获取文件大小
-        let total_size = response.body().content_length().unwrap_or(0);
+        let total_size = response.content_length().unwrap_or(0);
 
-        let mut reader = response.into_body().into_reader();
+        let mut reader = response.into_reader();
         let mut file = fs::File::create(&temp_path).context("创建临时文件失败")?;
 
         let mut buf = [0u8; 65536];
@@ -388,12 +424,9 @@ pub fn check_and_update(
         Ok(())
     };
 
-    let result = retry::with_retry(
-        config::RETRY_MAX_ATTEMPTS,
-        config::RETRY_BASE_DELAY_SECS,
-        "下载更新器",
-        download_and_verify,
-    );
+    let result = retry::with_default_retry("下载更新器", || {
+        mirrors::with_github_failover(base_dir, download_url, download_and_verify)
+    });
 
     if let Err(e) = result {
         let _ = fs::remove_file(&temp_path);
@@ -412,6 +445,47 @@ pub fn check_and_update(
     Ok(SelfUpdateResult::Restarting)
 }
 
+/// 判断分桶是否落在灰度比例内。未设置比例视为 100%。
+fn is_in_rollout(bucket: u8, rollout_percent: Option<u8>) -> bool {
+    match rollout_percent {
+        Some(percent) => bucket < percent.min(100),
+        None => true,
+    }
+}
+
+/// 读取本机的灰度分桶（0-99），不存在或损坏时重新生成并保存。
+///
+/// 保存失败不影响本次判定，只是下次启动会重新抽签。系统随机数不可用时
+/// 本次归入最后一个分桶（只在全量发布时更新），不保存。
+fn load_or_create_rollout_bucket(base_dir: &Path) -> u8 {
+    if let Some(bucket) = state::ROLLOUT_BUCKET
+        .read_text(base_dir)
+        .and_then(|s| s.trim().parse::<u8>().ok())
+        .filter(|b| *b < 100)
+    {
+        return bucket;
+    }
+
+    let bucket = match random_bucket() {
+        Ok(bucket) => bucket,
+        Err(e) => {
+            eprintln!("生成灰度分桶失败: {e}");
+            return 99;
+        }
+    };
+    if let Err(e) = state::ROLLOUT_BUCKET.write_text(base_dir, &bucket.to_string()) {
+        eprintln!("保存灰度分桶失败: {e:#}");
+    }
+    bucket
+}
+
+/// 用系统随机数生成 0-99 的分桶
+fn random_bucket() -> Result<u8, getrandom::Error> {
+    let mut bytes = [0u8; 4];
+    getrandom::fill(&mut bytes)?;
+    Ok((u32::from_le_bytes(bytes) % 100) as u8)
+}
+
 /// 复制当前 exe 为 helper，并由 helper 完成替换。
 fn spawn_update_helper(exe_path: &Path, temp_path: &Path) -> Result<()> {
     let helper_name = unique_helper_file_name();
@@ -577,6 +651,69 @@ mod tests {
         ))
     }
 
+    #[test]
+    fn rollout_defaults_to_everyone() {
+        assert!(is_in_rollout(0, None));
+        assert!(is_in_rollout(99, None));
+    }
+
+    #[test]
+    fn rollout_percent_limits_buckets() {
+        assert!(is_in_rollout(9, Some(10)));
+        assert!(!is_in_rollout(10, Some(10)));
+        assert!(!is_in_rollout(0, Some(0)));
+        assert!(is_in_rollout(99, Some(100)));
+        assert!(is_in_rollout(99, Some(200)));
+    }
+
+    #[test]
+    fn out_of_range_rollout_percent_is_clamped() {
+        let parse = |percent: &str| {
+            let json = format!(
+                r#"{{"download_url": "https://example.com/upmc.exe", "rollout_percent": {percent}}}"#
+            );
+            serde_json::from_str::<UpdaterVersionInfo>(&json)
+                .unwrap()
+                .rollout_percent
+        };
+        assert_eq!(parse("300"), Some(100));
+        assert_eq!(parse("-1"), Some(0));
+        assert_eq!(parse("12.5"), Some(12));
+        assert_eq!(parse("null"), None);
+
+        let info: UpdaterVersionInfo =
+            serde_json::from_str(r#"{"download_url": "https://example.com/upmc.exe"}"#).unwrap();
+        assert_eq!(info.rollout_percent, None);
+    }
+
+    #[test]
+    fn malformed_rollout_percent_fails_closed() {
+        let info: UpdaterVersionInfo = serde_json::from_str(
+            r#"{"download_url": "https://example.com/upmc.exe", "rollout_percent": "50"}"#,
+        )
+        .unwrap();
+        assert_eq!(info.rollout_percent, Some(0));
+        assert!(!is_in_rollout(0, info.rollout_percent));
+
+        let info: UpdaterVersionInfo = serde_json::from_str(
+            r#"{"download_url": "https://example.com/upmc.exe", "rollout_percent": "abc"}"#,
+        )
+        .unwrap();
+        assert_eq!(info.rollout_percent, Some(0));
+    }
+
+    #[test]
+    fn rollout_bucket_is_persisted() {
+        let dir = unique_test_dir("bucket");
+        let first = load_or_create_rollout_bucket(&dir);
+        assert!(first < 100);
+        assert_eq!(load_or_create_rollout_bucket(&dir), first);
+
+        fs::write(dir.join(state::ROLLOUT_BUCKET.path), "250").unwrap();
+        assert!(load_or_create_rollout_bucket(&dir) < 100);
+        fs::remove_dir_all(&dir).ok();
+    }
+
     #[test]
     fn validate_helper_paths_accepts_expected_layout() {
         let dir = unique_test_dir("valid");
@@ -626,4 +763,16 @@ mod tests {
         assert!(result.is_err());
         fs::remove_dir_all(&dir).ok();
     }
+
+    #[test]
+    fn fetch_updater_info_from_fake_server() {
+        let base_dir = crate::http::fake::temp_dir("selfupdate");
+        let _fake = crate::http::fake::fixture().install();
+
+        let info = fetch_updater_info(&base_dir, UpdateChannel::Stable).unwrap();
+        assert_eq!(info.build_id.as_deref(), Some("fixture"));
+        assert!(info.download_url.ends_with("/upmc.exe"));
+
+        fs::remove_dir_all(&base_dir).ok();
+    }
 }
@@ -0,0 +1,138 @@
+// ============================================================
+// server.rs — 专用服务器安装（upmc server install <目录>）
+// ============================================================
+// 与玩家使用同一份 server.json / pack.toml，让服务器始终与客户端保持一致：
+//   1. 下载 packwiz / Fabric 安装器（与首次安装相同，需要 SHA256）
+//   2. 下载原版服务端 server.jar，按 Mojang 提供的 SHA1 校验
+//   3. 安装 Fabric 服务端启动器 fabric-server-launch.jar（版本变化时重新安装）
+//   4. packwiz 以服务端模式同步（只包含 side 为 server / both 的文件）
+//   5. 只有管理员明确同意后才写入 eula.txt
+// 安装状态保存在服务器目录的 upmc-server.json（带格式版本号，见 state.rs），
+// 再次运行即可跟进新版本。
+// ============================================================
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::bootstrap;
+use crate::fabric;
+use crate::packwiz;
+use crate::state;
+use crate::update::Progress;
+use crate::version;
+
+/// 原版服务端文件名（Fabric 服务端启动器默认加载它）
+const VANILLA_SERVER_JAR: &str = "server.jar";
+/// Fabric 服务端启动器
+pub const FABRIC_SERVER_LAUNCH_JAR: &str = "fabric-server-launch.jar";
+/// Minecraft EULA 地址
+pub const EULA_URL: &str = "https://aka.ms/MinecraftEULA";
+
+/// 服务器目录的安装状态
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerState {
+    pub mc_version: String,
+    pub fabric_version: String,
+    pub version_tag: String,
+}
+
+/// 安装或更新专用服务器。
+///
+/// `accept_eula` 为 true 时写入 `eula=true`；否则不动 eula.txt，
+/// 由管理员自行确认（服务器在同意前不会启动）。
+pub fn install(
+    base_dir: &Path,
+    server_dir: &Path,
+    accept_eula: bool,
+    on_progress: &dyn Fn(Progress),
+) -> Result<ServerState> {
+    fs::create_dir_all(server_dir)
+        .with_context(|| format!("创建服务器目录失败: {}", server_dir.display()))?;
+
+    on_progress(Progress::new(5, "正在获取整合包信息..."));
+    let remote = version::fetch_remote_version(base_dir)?;
+    on_progress(Progress::new(10, format!(
+        "整合包版本: MC {} / Fabric {}",
+        remote.mc_version, remote.fabric_version
+    )));
+
+    bootstrap::ensure_tools(base_dir, &remote.downloads, on_progress)?;
+
+    on_progress(Progress::new(50, "正在下载原版服务端..."));
+    fabric::download_vanilla_server(
+        base_dir,
+        &server_dir.join(VANILLA_SERVER_JAR),
+        &remote.mc_version,
+    )?;
+
+    let state = ServerState {
+        mc_version: remote.mc_version.clone(),
+        fabric_version: remote.fabric_version.clone(),
+        version_tag: remote.version_tag.clone(),
+    };
+    if load_state(server_dir).as_ref() != Some(&state)
+        || !server_dir.join(FABRIC_SERVER_LAUNCH_JAR).exists()
+    {
+        on_progress(Progress::new(60, "正在安装 Fabric 服务端..."));
+        fabric::install_fabric_server(
+            base_dir,
+            server_dir,
+            &remote.mc_version,
+            &remote.fabric_version,
+        )?;
+    }
+
+    on_progress(Progress::new(70, "正在同步服务端模组..."));
+    packwiz::sync_server(base_dir, server_dir, &remote.pack_url, on_progress)?;
+    save_state(server_dir, &state)?;
+
+    if accept_eula {
+        write_eula(server_dir)?;
+    }
+    on_progress(Progress::new(100, "服务器安装完成"));
+    Ok(state)
+}
+
+/// eula.txt 中是否已同意
+pub fn is_eula_accepted(server_dir: &Path) -> bool {
+    fs::read_to_string(server_dir.join("eula.txt")).is_ok_and(|text| {
+        text.lines()
+            .any(|line| line.trim().eq_ignore_ascii_case("eula=true"))
+    })
+}
+
+fn write_eula(server_dir: &Path) -> Result<()> {
+    let text = format!("# 管理员已通过 upmc 同意 Minecraft EULA ({EULA_URL})\neula=true\n");
+    fs::write(server_dir.join("eula.txt"), text).context("写入 eula.txt 失败")
+}
+
+fn load_state(server_dir: &Path) -> Option<ServerState> {
+    state::SERVER_INSTALL.read_json(server_dir)
+}
+
+fn save_state(server_dir: &Path, state: &ServerState) -> Result<()> {
+    state::SERVER_INSTALL
+        .write_json(server_dir, state)
+        .context("写入服务器安装状态失败")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eula_is_only_accepted_explicitly() {
+        let dir = std::env::temp_dir().join(format!("upmc_server_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        assert!(!is_eula_accepted(&dir));
+        fs::write(dir.join("eula.txt"), "#comment\neula=false\n").unwrap();
+        assert!(!is_eula_accepted(&dir));
+        write_eula(&dir).unwrap();
+        assert!(is_eula_accepted(&dir));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,602 @@
+// ============================================================
+// settings.rs — 分层设置
+// ============================================================
+// 可调的运行参数（更新通道、server.json 来源、超时、重试、代理端口等）
+// 由多层来源依次覆盖，后面的优先：
+//   1. 编译期默认值（config.rs 中的常量、构建时的 UPMC_CHANNEL / UPMC_SUB_URL）
+//   2. server.json 的 settings 字段（只能调整超时、重试等运维参数）
+//   3. 用户文件：settings.json、channel.json（更新通道）
+//   4. 环境变量 UPMC_<键名大写>，如 UPMC_HTTP_TIMEOUT_SECS=60
+//   5. 命令行：--channel、--source、--set 键=值（由 cli.rs 与其他选项一起解析）
+//
+// 每个值单独校验，无效的值给出警告并保留上一层的值。
+// 启动时（和切换整合包时）合并一次并设为当前设置，之后经 `current()` 读取；
+// 设置文件都经 `save_*` 写入，写入后立即重新合并。
+// `upmc settings` 列出每个值及其来源。
+//
+// 设置文件统一原子写入（先写临时文件再重命名），中途崩溃不会留下写了一半的文件。
+// settings.json、channel.json 和 server_settings.json 带格式版本号，经 state.rs 读写和迁移。
+// ============================================================
+
+use anyhow::{Context, Result, ensure};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use crate::config::{self, ChannelConfig, UpdateChannel, UserSettings};
+use crate::source::ServerSource;
+use crate::state;
+
+/// 合并后的设置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    /// 更新器通道
+    pub channel: UpdateChannel,
+    /// server.json 来源（公共服务器 / 本地目录 / 局域网）
+    pub server_source: ServerSource,
+    /// 是否劫持 UDP 流量（Discord 语音走代理）
+    pub proxy_udp: bool,
+    /// 忽略灰度发布比例，总是更新到最新构建（供测试者使用）
+    pub ignore_rollout: bool,
+    /// 代理订阅地址（VLESS REALITY），为空时无法启用代理
+    pub sub_url: String,
+    /// Xray 本地 SOCKS5 监听端口
+    pub socks_port: u16,
+    /// 小文件请求超时（秒）
+    pub http_timeout_secs: u64,
+    /// 大文件下载超时（秒）
+    pub download_timeout_secs: u64,
+    /// 网络操作最大尝试次数（含首次）
+    pub retry_max_attempts: u32,
+    /// 首次重试前等待秒数，后续翻倍
+    pub retry_base_delay_secs: u64,
+    /// 单次退避等待上限（秒）
+    pub retry_max_delay_secs: u64,
+    /// 一个操作所有重试的总时间预算（秒）
+    pub retry_budget_secs: u64,
+    /// 同一主机连续失败多少次后熔断
+    pub circuit_failure_threshold: u32,
+    /// 熔断持续秒数
+    pub circuit_open_secs: u64,
+    /// packwiz-installer 单次运行的总时长上限（秒）
+    pub packwiz_timeout_secs: u64,
+    /// packwiz-installer 连续无输出的时长上限（秒）
+    pub packwiz_idle_timeout_secs: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            channel: UpdateChannel::COMPILED_DEFAULT,
+            server_source: ServerSource::Public,
+            proxy_udp: false,
+            ignore_rollout: false,
+            sub_url: config::SUBSCRIPTION_URL.to_string(),
+            socks_port: config::XRAY_SOCKS_PORT,
+            http_timeout_secs: config::HTTP_TIMEOUT_SECS,
+            download_timeout_secs: config::DOWNLOAD_TIMEOUT_SECS,
+            retry_max_attempts: config::RETRY_MAX_ATTEMPTS,
+            retry_base_delay_secs: config::RETRY_BASE_DELAY_SECS,
+            retry_max_delay_secs: config::RETRY_MAX_DELAY_SECS,
+            retry_budget_secs: config::RETRY_BUDGET_SECS,
+            circuit_failure_threshold: config::CIRCUIT_FAILURE_THRESHOLD,
+            circuit_open_secs: config::CIRCUIT_OPEN_SECS,
+            packwiz_timeout_secs: config::PACKWIZ_TIMEOUT_SECS,
+            packwiz_idle_timeout_secs: config::PACKWIZ_IDLE_TIMEOUT_SECS,
+        }
+    }
+}
+
+/// server.json 可以调整的设置（其余设置只属于玩家）
+const SERVER_KEYS: &[&str] = &[
+    "http_timeout_secs",
+    "download_timeout_secs",
+    "retry_max_attempts",
+    "retry_base_delay_secs",
+    "retry_max_delay_secs",
+    "retry_budget_secs",
+    "circuit_failure_threshold",
+    "circuit_open_secs",
+    "packwiz_timeout_secs",
+    "packwiz_idle_timeout_secs",
+];
+
+impl Settings {
+    /// 校验单个设置，`key` 之外的值不检查（它们已在各自的层校验过）
+    fn check(&self, key: &str) -> Result<()> {
+        match key {
+            "retry_max_attempts" => ensure!(
+                (1..=10).contains(&self.retry_max_attempts),
+                "必须在 1 到 10 之间"
+            ),
+            "circuit_failure_threshold" => ensure!(
+                (1..=100).contains(&self.circuit_failure_threshold),
+                "必须在 1 到 100 之间"
+            ),
+            "retry_base_delay_secs" | "retry_max_delay_secs" => ensure!(
+                self.retry_base_delay_secs <= self.retry_max_delay_secs,
+                "retry_base_delay_secs 不能大于 retry_max_delay_secs"
+            ),
+            "socks_port" => ensure!(self.socks_port != 0, "端口不能为 0"),
+            "sub_url" => ensure!(
+                self.sub_url.is_empty() || self.sub_url.starts_with("https://"),
+                "订阅地址必须使用 HTTPS"
+            ),
+            "http_timeout_secs"
+            | "download_timeout_secs"
+            | "retry_budget_secs"
+            | "circuit_open_secs"
+            | "packwiz_timeout_secs"
+            | "packwiz_idle_timeout_secs" => {
+                let secs = self.to_map().get(key).and_then(Value::as_u64);
+                ensure!(secs.is_some_and(|secs| secs > 0), "必须大于 0");
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn to_map(&self) -> Map<String, Value> {
+        match serde_json::to_value(self) {
+            Ok(Value::Object(map)) => map,
+            _ => Map::new(),
+        }
+    }
+}
+
+/// 设置值的来源，按优先级从低到高排列
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    Default,
+    Server,
+    User,
+    Env,
+    Cli,
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Layer::Default => "编译期默认值",
+            Layer::Server => "server.json",
+            Layer::User => "用户设置",
+            Layer::Env => "环境变量",
+            Layer::Cli => "命令行",
+        })
+    }
+}
+
+/// 合并结果：设置 + 每个值的来源 + 被忽略的值
+#[derive(Debug, Clone, Default)]
+pub struct Resolved {
+    pub settings: Settings,
+    sources: BTreeMap<String, Layer>,
+    pub warnings: Vec<String>,
+}
+
+impl Deref for Resolved {
+    type Target = Settings;
+
+    fn deref(&self) -> &Settings {
+        &self.settings
+    }
+}
+
+impl Resolved {
+    /// 某个设置的来源
+    pub fn source(&self, key: &str) -> Layer {
+        self.sources.get(key).copied().unwrap_or(Layer::Default)
+    }
+
+    /// 每行一个设置：`键 = 值（来源）`
+    pub fn explain(&self) -> String {
+        self.settings
+            .to_map()
+            .iter()
+            .map(|(key, value)| format!("{key} = {value}（{}）", self.source(key)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// 用一层中的一个值覆盖当前设置，无效时记录警告并保留原值
+    fn apply(&mut self, layer: Layer, key: &str, value: Value) {
+        let mut map = self.settings.to_map();
+        if !map.contains_key(key) {
+            self.warnings
+                .push(format!("未知设置 {key}（{layer}），已忽略"));
+            return;
+        }
+        if layer == Layer::Server && !SERVER_KEYS.contains(&key) {
+            self.warnings
+                .push(format!("server.json 不能修改 {key}，已忽略"));
+            return;
+        }
+        map.insert(key.to_string(), value);
+        let result = serde_json::from_value::<Settings>(Value::Object(map))
+            .map_err(anyhow::Error::from)
+            .and_then(|settings| settings.check(key).map(|()| settings));
+        match result {
+            Ok(settings) => {
+                self.settings = settings;
+                self.sources.insert(key.to_string(), layer);
+            }
+            Err(e) => self
+                .warnings
+                .push(format!("忽略无效设置 {key}（{layer}）: {e:#}")),
+        }
+    }
+
+    /// 环境变量 / 命令行中的文本值：按默认值的类型解析
+    fn apply_text(&mut self, layer: Layer, key: &str, text: &str) {
+        let value = match self.settings.to_map().get(key) {
+            _ if key == "server_source" => {
+                serde_json::to_value(ServerSource::from_arg(text)).unwrap_or(Value::Null)
+            }
+            Some(Value::String(_)) => Value::String(text.to_string()),
+            _ => serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string())),
+        };
+        self.apply(layer, key, value);
+    }
+}
+
+/// 命令行上覆盖设置的选项，每个选项后面跟一个值
+pub const CLI_FLAGS: &[&str] = &["--channel", "--source", "--set"];
+
+/// 命令行上的设置覆盖（由 cli.rs 解析），按出现顺序排列的 (选项, 值)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CliOverrides(Vec<(String, String)>);
+
+impl CliOverrides {
+    /// 记录一个 `CLI_FLAGS` 中的选项及其值
+    pub fn push(&mut self, flag: String, value: String) {
+        self.0.push((flag, value));
+    }
+}
+
+/// 读取 `root_dir`（更新通道）和 `base_dir`（整合包实例）的设置，
+/// 合并环境变量（经 `env` 读取）和命令行覆盖。
+fn resolve(
+    root_dir: &Path,
+    base_dir: &Path,
+    env: &dyn Fn(&str) -> Option<String>,
+    cli: &CliOverrides,
+) -> Resolved {
+    let mut resolved = Resolved::default();
+    let keys: Vec<String> = resolved.settings.to_map().keys().cloned().collect();
+
+    // 2. server.json（上次成功获取时缓存）
+    let server: Map<String, Value> = state::SERVER_SETTINGS
+        .read_json(base_dir)
+        .unwrap_or_default();
+    for (key, value) in server {
+        resolved.apply(Layer::Server, &key, value);
+    }
+
+    // 3. 用户文件。settings.json 中的其他内容（可选模组、镜像等）不属于分层设置
+    let user: Map<String, Value> = state::USER_SETTINGS.read_json(base_dir).unwrap_or_default();
+    for (key, value) in user {
+        if keys.contains(&key) {
+            resolved.apply(Layer::User, &key, value);
+        }
+    }
+    if let Some(channel) = state::CHANNEL_CONFIG.read_json::<ChannelConfig>(root_dir) {
+        resolved.apply(
+            Layer::User,
+            "channel",
+            serde_json::to_value(channel.channel).unwrap_or(Value::Null),
+        );
+    }
+
+    // 4. 环境变量
+    for key in &keys {
+        if let Some(text) = env(&format!("UPMC_{}", key.to_ascii_uppercase())) {
+            resolved.apply_text(Layer::Env, key, &text);
+        }
+    }
+
+    // 5. 命令行
+    for (flag, value) in &cli.0 {
+        match flag.as_str() {
+            "--channel" => resolved.apply_text(Layer::Cli, "channel", &value.to_lowercase()),
+            "--source" => resolved.apply_text(Layer::Cli, "server_source", value),
+            _ => match value.split_once('=') {
+                Some((key, text)) => resolved.apply_text(Layer::Cli, key.trim(), text.trim()),
+                None => resolved
+                    .warnings
+                    .push(format!("--set 需要 键=值 格式: {value}")),
+            },
+        }
+    }
+
+    resolved
+}
+
+// ── 当前设置 ──
+
+/// 当前设置及合并它所用的目录和命令行覆盖（保存设置后按同样的输入重新合并）
+struct Current {
+    root_dir: PathBuf,
+    base_dir: PathBuf,
+    cli: CliOverrides,
+    resolved: Arc<Resolved>,
+}
+
+/// 存放当前设置的位置。进程内只有一个（`CURRENT`，读取真实的环境变量），
+/// 测试可以另建一个并指定环境变量，不影响其他测试。
+struct Slot {
+    current: RwLock<Option<Current>>,
+    env: fn(&str) -> Option<String>,
+}
+
+impl Slot {
+    const fn new(env: fn(&str) -> Option<String>) -> Self {
+        Self {
+            current: RwLock::new(None),
+            env,
+        }
+    }
+
+    fn get(&self) -> Arc<Resolved> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .map(|current| current.resolved.clone())
+            .unwrap_or_default()
+    }
+
+    fn install(&self, root_dir: &Path, base_dir: &Path, cli: &CliOverrides) -> Arc<Resolved> {
+        let resolved = Arc::new(resolve(root_dir, base_dir, &self.env, cli));
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Some(Current {
+            root_dir: root_dir.to_path_buf(),
+            base_dir: base_dir.to_path_buf(),
+            cli: cli.clone(),
+            resolved: resolved.clone(),
+        });
+        resolved
+    }
+
+    fn save_with(&self, write: impl FnOnce() -> Result<()>) -> Result<()> {
+        write()?;
+        let mut current = self.current.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(current) = current.as_mut() {
+            current.resolved = Arc::new(resolve(
+                &current.root_dir,
+                &current.base_dir,
+                &self.env,
+                &current.cli,
+            ));
+        }
+        Ok(())
+    }
+}
+
+static CURRENT: Slot = Slot::new(|name| std::env::var(name).ok());
+
+/// 当前生效的设置（未调用 install 时为编译期默认值）
+pub fn current() -> Arc<Resolved> {
+    CURRENT.get()
+}
+
+/// 合并 `root_dir` 和 `base_dir` 的设置、环境变量和命令行覆盖并设为当前设置
+/// （启动时、切换整合包时调用），之后读取设置都经 `current()`，不再重新读文件。
+pub fn install(root_dir: &Path, base_dir: &Path, cli: &CliOverrides) -> Arc<Resolved> {
+    CURRENT.install(root_dir, base_dir, cli)
+}
+
+/// 所有设置文件的唯一写入口：写入文件后按当前目录重新合并，`current()` 立即反映新值
+fn save_with(write: impl FnOnce() -> Result<()>) -> Result<()> {
+    CURRENT.save_with(write)
+}
+
+/// 保存整合包实例的 settings.json
+pub fn save_user(base_dir: &Path, user_settings: &UserSettings) -> Result<()> {
+    save_with(|| {
+        state::USER_SETTINGS
+            .write_json(base_dir, user_settings)
+            .context("写入 settings.json 失败")
+    })
+}
+
+/// 保存更新通道到安装基准目录的 channel.json（仅在内容变化时写入）
+pub fn save_channel(root_dir: &Path, channel_config: &ChannelConfig) -> Result<()> {
+    if state::CHANNEL_CONFIG
+        .read_json::<ChannelConfig>(root_dir)
+        .is_some_and(|existing| existing.channel == channel_config.channel)
+    {
+        return Ok(());
+    }
+    save_with(|| {
+        state::CHANNEL_CONFIG
+            .write_json(root_dir, channel_config)
+            .context("写入 channel.json 失败")
+    })
+}
+
+/// 保存 server.json 下发的设置（仅在内容变化时写入）
+pub fn save_server_overrides(base_dir: &Path, overrides: &Map<String, Value>) {
+    let existing: Option<Map<String, Value>> = state::SERVER_SETTINGS.read_json(base_dir);
+    if existing.as_ref() == Some(overrides) || (existing.is_none() && overrides.is_empty()) {
+        return;
+    }
+    if let Err(e) = save_with(|| state::SERVER_SETTINGS.write_json(base_dir, overrides)) {
+        eprintln!("保存服务器设置失败: {e:#}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn overrides(cli: &[(&str, &str)]) -> CliOverrides {
+        let mut overrides = CliOverrides::default();
+        for (flag, value) in cli {
+            overrides.push(flag.to_string(), value.to_string());
+        }
+        overrides
+    }
+
+    fn resolve_with(dir: &Path, env: &[(&str, &str)], cli: &[(&str, &str)]) -> Resolved {
+        let env: BTreeMap<String, String> = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        resolve(dir, dir, &|name| env.get(name).cloned(), &overrides(cli))
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        let defaults = Settings::default();
+        for key in defaults.to_map().keys() {
+            defaults.check(key).unwrap();
+        }
+        let resolved = resolve_with(&crate::http::fake::temp_dir("settings_empty"), &[], &[]);
+        assert_eq!(resolved.settings, defaults);
+        assert!(resolved.warnings.is_empty());
+        assert_eq!(resolved.source("retry_max_attempts"), Layer::Default);
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let dir = crate::http::fake::temp_dir("settings_layers");
+        state::SERVER_SETTINGS
+            .write_json(
+                &dir,
+                &serde_json::json!({ "http_timeout_secs": 45, "retry_max_attempts": 5 }),
+            )
+            .unwrap();
+        state::USER_SETTINGS
+            .write_json(
+                &dir,
+                &serde_json::json!({ "retry_max_attempts": 4, "proxy_udp": true, "preset": "high" }),
+            )
+            .unwrap();
+        state::CHANNEL_CONFIG
+            .write_json(&dir, &serde_json::json!({ "channel": "stable" }))
+            .unwrap();
+
+        let resolved = resolve_with(
+            &dir,
+            &[("UPMC_RETRY_MAX_ATTEMPTS", "6"), ("UPMC_CHANNEL", "dev")],
+            &[("--channel", "Stable"), ("--set", "socks_port=20808")],
+        );
+        assert!(resolved.warnings.is_empty(), "{:?}", resolved.warnings);
+        assert_eq!(resolved.http_timeout_secs, 45);
+        assert_eq!(resolved.source("http_timeout_secs"), Layer::Server);
+        assert!(resolved.proxy_udp);
+        assert_eq!(resolved.source("proxy_udp"), Layer::User);
+        assert_eq!(resolved.retry_max_attempts, 6);
+        assert_eq!(resolved.source("retry_max_attempts"), Layer::Env);
+        assert_eq!(resolved.channel, UpdateChannel::Stable);
+        assert_eq!(resolved.source("channel"), Layer::Cli);
+        assert_eq!(resolved.socks_port, 20808);
+        assert!(resolved.explain().contains("socks_port = 20808（命令行）"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn invalid_values_keep_previous_layer() {
+        let dir = crate::http::fake::temp_dir("settings_invalid");
+        state::SERVER_SETTINGS
+            .write_json(
+                &dir,
+                &serde_json::json!({
+                    "retry_max_attempts": 5,
+                    "circuit_open_secs": 120,
+                    "sub_url": "https://evil.example/sub",
+                }),
+            )
+            .unwrap();
+
+        let resolved = resolve_with(
+            &dir,
+            &[
+                ("UPMC_RETRY_MAX_ATTEMPTS", "0"),
+                ("UPMC_SOCKS_PORT", "abc"),
+                ("UPMC_CIRCUIT_FAILURE_THRESHOLD", "0"),
+            ],
+            &[
+                ("--set", "retry_base_delay_secs=999"),
+                ("--set", "nonsense=1"),
+                ("--set", "socks_port"),
+            ],
+        );
+        assert_eq!(resolved.retry_max_attempts, 5);
+        assert_eq!(resolved.source("retry_max_attempts"), Layer::Server);
+        assert_eq!(resolved.socks_port, config::XRAY_SOCKS_PORT);
+        assert_eq!(resolved.circuit_open_secs, 120);
+        assert_eq!(
+            resolved.circuit_failure_threshold,
+            config::CIRCUIT_FAILURE_THRESHOLD
+        );
+        assert_eq!(
+            resolved.retry_base_delay_secs,
+            config::RETRY_BASE_DELAY_SECS
+        );
+        // server.json 不能改订阅地址
+        assert_eq!(resolved.sub_url, config::SUBSCRIPTION_URL);
+        assert_eq!(resolved.warnings.len(), 7, "{:?}", resolved.warnings);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn source_from_command_line() {
+        let dir = crate::http::fake::temp_dir("settings_source");
+        let resolved = resolve_with(&dir, &[], &[("--source", "http://192.168.1.10:8000/")]);
+        assert_eq!(
+            resolved.server_source,
+            ServerSource::Lan {
+                url: "http://192.168.1.10:8000/".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn server_overrides_are_versioned_and_skip_unchanged() {
+        let dir = crate::http::fake::temp_dir("settings_write");
+        let path = dir.join(config::SERVER_SETTINGS_FILE);
+        let overrides = Map::from_iter([("http_timeout_secs".to_string(), Value::from(45))]);
+        save_server_overrides(&dir, &overrides);
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["schema_version"], state::SERVER_SETTINGS.version());
+        assert_eq!(resolve_with(&dir, &[], &[]).http_timeout_secs, 45);
+
+        // 内容不变时不改写
+        fs::write(&path, saved.to_string()).unwrap();
+        save_server_overrides(&dir, &overrides);
+        assert_eq!(fs::read_to_string(&path).unwrap(), saved.to_string());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn save_refreshes_current() {
+        // 用独立的 Slot，不改动其他测试经 current() 读取的全局设置
+        // 环境变量和命令行都由测试指定，不读取进程真实的环境和参数
+        let dir = crate::http::fake::temp_dir("settings_save");
+        let slot = Slot::new(|name| (name == "UPMC_HTTP_TIMEOUT_SECS").then(|| "45".to_string()));
+        slot.install(&dir, &dir, &overrides(&[("--set", "socks_port=20808")]));
+        assert!(!slot.get().proxy_udp);
+        assert_eq!(slot.get().http_timeout_secs, 45);
+
+        let user = UserSettings {
+            proxy_udp: true,
+            ..UserSettings::default()
+        };
+        slot.save_with(|| state::USER_SETTINGS.write_json(&dir, &user))
+            .unwrap();
+        assert!(slot.get().proxy_udp);
+        assert_eq!(slot.get().source("proxy_udp"), Layer::User);
+        // 重新合并时保留同一组环境变量和命令行覆盖
+        assert_eq!(slot.get().source("http_timeout_secs"), Layer::Env);
+        assert_eq!(slot.get().socks_port, 20808);
+        assert!(config::load_user_settings(&dir).proxy_udp);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,444 @@
+// ============================================================
+// source.rs — server.json 来源（公共服务器 / 本地目录 / 局域网）
+// ============================================================
+// 默认从公共服务器（HTTPS + 镜像列表）获取 server.json。
+// 活动组织者可以把整合包放在自己的电脑上：
+//   - 本地目录或文件：  --source D:\upmc-dist  或  --source \\nas\share\server.json
+//   - 局域网 HTTP 端点：--source http://192.168.1.10:8000/
+// 也可以写在 settings.json 的 server_source 中（或环境变量 UPMC_SERVER_SOURCE）。
+// 命令行优先于设置，见 settings.rs。
+//
+// 非公共来源的 pack_url / 下载地址可以是相对路径，按 server.json 所在位置解析。
+// 只有本地和局域网来源放宽下载白名单：
+//   - 本地来源 → 规范化后位于来源目录之下的 file:// 路径；
+//   - 局域网来源 → 私有地址（RFC1918、链路本地、回环、.local）上的同一主机端口。
+// 其他 http(s) 地址是自定义远程来源，与公共服务器一样只认 HTTPS 白名单。
+// 下载项仍然必须提供并通过 SHA256 校验。
+// 窗口标题会标出当前来源，让玩家知道自己不在公共服务器上。
+// ============================================================
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use crate::http_cache;
+use crate::settings;
+
+/// server.json 来源
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ServerSource {
+    /// 公共服务器（config::REMOTE_SERVER_JSON_URL + 镜像）
+    #[default]
+    Public,
+    /// 本地目录（读取其中的 server.json）或 server.json 文件路径
+    Local { path: String },
+    /// 局域网 HTTP 端点（目录 URL 或 server.json 的完整 URL），主机必须是私有地址
+    Lan { url: String },
+    /// 公网上的其他 server.json 地址：下载地址仍只认 HTTPS 白名单
+    Remote { url: String },
+}
+
+impl ServerSource {
+    /// 解析命令行 / 设置窗口中的输入：http(s):// 开头且主机为私有地址时为局域网端点，
+    /// 其他 URL 为远程来源，其余为本地路径。空字符串表示公共服务器。
+    pub fn from_arg(value: &str) -> Self {
+        let value = value.trim();
+        if value.is_empty() {
+            ServerSource::Public
+        } else if value.starts_with("http://") || value.starts_with("https://") {
+            let url = value.to_string();
+            if is_private_url(&url) {
+                ServerSource::Lan { url }
+            } else {
+                ServerSource::Remote { url }
+            }
+        } else {
+            ServerSource::Local {
+                path: path_from_arg(value).to_string_lossy().to_string(),
+            }
+        }
+    }
+
+    /// 设置窗口中显示的原始输入
+    pub fn as_arg(&self) -> &str {
+        match self {
+            ServerSource::Public => "",
+            ServerSource::Local { path } => path,
+            ServerSource::Lan { url } | ServerSource::Remote { url } => url,
+        }
+    }
+
+    pub fn is_public(&self) -> bool {
+        matches!(self, ServerSource::Public)
+    }
+
+    /// 窗口标题后缀，公共服务器返回 None
+    #[cfg(windows)]
+    pub fn title_suffix(&self) -> Option<String> {
+        match self {
+            ServerSource::Public => None,
+            ServerSource::Local { path } => Some(format!("本地源: {path}")),
+            ServerSource::Lan { url } => Some(format!("局域网源: {}", origin_of(url))),
+            ServerSource::Remote { url } => Some(format!("远程源: {}", origin_of(url))),
+        }
+    }
+
+    /// 非公共来源的 server.json 位置（file:// 或 http(s):// URL）
+    pub fn server_json_url(&self) -> Option<String> {
+        match self {
+            ServerSource::Public => None,
+            ServerSource::Local { path } => {
+                let path = Path::new(path);
+                let file = if path.is_dir() {
+                    path.join("server.json")
+                } else {
+                    path.to_path_buf()
+                };
+                Some(path_to_file_url(&file))
+            }
+            ServerSource::Lan { url } | ServerSource::Remote { url } => {
+                Some(if url.ends_with(".json") {
+                    url.clone()
+                } else {
+                    format!("{}/server.json", url.trim_end_matches('/'))
+                })
+            }
+        }
+    }
+
+    /// 判断 URL 是否属于当前来源，可以不经下载白名单使用。
+    ///
+    /// 本地来源要求 file:// 路径规范化后仍位于来源目录之下（拒绝 `..` 和链接跳出）；
+    /// 局域网来源要求同一 scheme + 主机 + 端口且主机为私有地址；其他来源一律不放宽。
+    pub fn allows(&self, url: &str) -> bool {
+        match self {
+            ServerSource::Public | ServerSource::Remote { .. } => false,
+            ServerSource::Local { path } => {
+                if !url.starts_with("file://") {
+                    return false;
+                }
+                let path = Path::new(path);
+                let root = if path.is_dir() {
+                    Some(path)
+                } else {
+                    path.parent()
+                };
+                let (Some(Ok(root)), Ok(target)) = (
+                    root.map(Path::canonicalize),
+                    path_from_arg(url).canonicalize(),
+                ) else {
+                    return false;
+                };
+                target.starts_with(root)
+            }
+            ServerSource::Lan { url: lan } => {
+                is_private_url(lan) && origin_of(url) == origin_of(lan)
+            }
+        }
+    }
+}
+
+/// 当前整合包实例使用的来源：命令行 `--source` > 环境变量 > settings.json > 公共服务器
+/// （启动时已合并到 `settings::current()`）。
+///
+/// 按原始输入重新分类，settings.json 中手写的 `"kind": "lan"` 指向公网主机时按远程来源处理。
+pub fn resolve() -> ServerSource {
+    let configured = settings::current().server_source.clone();
+    match configured {
+        ServerSource::Lan { .. } => ServerSource::from_arg(configured.as_arg()),
+        other => other,
+    }
+}
+
+/// 相对 server.json 所在位置解析 URL。已经是绝对 URL 时原样返回。
+pub fn resolve_relative(base_url: &str, reference: &str) -> String {
+    if reference.contains("://") {
+        return reference.to_string();
+    }
+    if let Some(rest) = reference.strip_prefix('/') {
+        return format!("{}/{rest}", origin_of(base_url));
+    }
+    let dir = match base_url.rfind('/') {
+        Some(i) => &base_url[..=i],
+        None => base_url,
+    };
+    format!("{dir}{}", reference.trim_start_matches("./"))
+}
+
+/// 读取来源中的文本：file:// 直接读盘，http(s):// 走 HTTP 条件缓存。
+pub fn read_text(base_dir: &Path, url: &str) -> Result<String> {
+    if url.starts_with("file://") {
+        let path = path_from_arg(url);
+        std::fs::read_to_string(&path)
+            .with_context(|| format!("读取本地文件失败: {}", path.display()))
+    } else {
+        http_cache::get_text(base_dir, url, &[])
+    }
+}
+
+/// 不发请求读取来源中的文本：file:// 直接读盘，http(s):// 读 HTTP 缓存。
+pub fn cached_text(base_dir: &Path, url: &str) -> Option<String> {
+    if url.starts_with("file://") {
+        std::fs::read_to_string(path_from_arg(url)).ok()
+    } else {
+        http_cache::cached_text(base_dir, url)
+    }
+}
+
+/// 接受普通路径或 `file://` URL（URL 中的 %XX 会被解码）。
+pub fn path_from_arg(value: &str) -> PathBuf {
+    match value.strip_prefix("file://") {
+        // file:///C:/x → C:/x；file://server/share/x → //server/share/x（UNC）
+        Some(rest) => {
+            let rest = percent_decode(rest);
+            match rest.strip_prefix('/') {
+                Some(local) if local.as_bytes().get(1) == Some(&b':') => PathBuf::from(local),
+                Some(_) => PathBuf::from(rest),
+                None => PathBuf::from(format!("//{rest}")),
+            }
+        }
+        None => PathBuf::from(value),
+    }
+}
+
+/// 本地路径 → file:// URL。
+///
+/// packwiz-installer 用 Java 的 URI 解析 pack.toml 地址，不接受空格等字符，
+/// 因此除字母数字和 `-._~/:` 外（包括中文目录名）都按 UTF-8 百分号编码。
+pub fn path_to_file_url(path: &Path) -> String {
+    let text = path.to_string_lossy().replace('\\', "/");
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/:".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    if let Some(unc) = encoded.strip_prefix("//") {
+        format!("file://{unc}")
+    } else if encoded.starts_with('/') {
+        format!("file://{encoded}")
+    } else {
+        format!("file:///{encoded}")
+    }
+}
+
+/// 解码 %XX（按 UTF-8），不完整的序列原样保留
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// URL 的主机是否为私有地址：RFC1918、链路本地、回环、localhost 或 .local 主机名
+fn is_private_url(url: &str) -> bool {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return false;
+    };
+    if !matches!(scheme.to_ascii_lowercase().as_str(), "http" | "https") {
+        return false;
+    }
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    if authority.contains('@') {
+        return false;
+    }
+    let host = match authority.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or(""),
+        None => authority.split(':').next().unwrap_or(""),
+    }
+    .to_ascii_lowercase();
+
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => ip.is_private() || ip.is_link_local() || ip.is_loopback(),
+        Ok(IpAddr::V6(ip)) => {
+            ip.is_loopback() || ip.is_unicast_link_local() || ip.is_unique_local()
+        }
+        Err(_) => host == "localhost" || host.ends_with(".local"),
+    }
+}
+
+/// scheme://authority 部分（小写），用于同源判断
+fn origin_of(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    format!("{scheme}://{authority}").to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_arg_detects_kind() {
+        assert_eq!(ServerSource::from_arg("  "), ServerSource::Public);
+        assert!(matches!(
+            ServerSource::from_arg("http://192.168.1.10:8000/"),
+            ServerSource::Lan { .. }
+        ));
+        assert!(matches!(
+            ServerSource::from_arg("D:/upmc-dist"),
+            ServerSource::Local { .. }
+        ));
+        assert!(matches!(
+            ServerSource::from_arg("https://example.com/server.json"),
+            ServerSource::Remote { .. }
+        ));
+        assert!(matches!(
+            ServerSource::from_arg("http://example.com/"),
+            ServerSource::Remote { .. }
+        ));
+    }
+
+    #[test]
+    fn private_url_detection() {
+        for url in [
+            "http://192.168.1.10:8000/",
+            "https://10.0.0.5/server.json",
+            "http://172.16.3.4/",
+            "http://169.254.1.1/",
+            "http://127.0.0.1:8000/",
+            "http://[fe80::1]:8000/",
+            "http://[fd00::1]/",
+            "http://nas.local/",
+            "http://localhost:8000/",
+        ] {
+            assert!(is_private_url(url), "{url}");
+        }
+        for url in [
+            "https://example.com/",
+            "http://172.32.0.1/",
+            "http://8.8.8.8/",
+            "http://192.168.1.10@example.com/",
+            "http://local.example.com/",
+            "ftp://192.168.1.10/",
+        ] {
+            assert!(!is_private_url(url), "{url}");
+        }
+    }
+
+    #[test]
+    fn remote_allows_nothing() {
+        let remote = ServerSource::from_arg("https://example.com/dist/");
+        assert!(!remote.allows("https://example.com/dist/pack.toml"));
+        assert_eq!(
+            remote.server_json_url().as_deref(),
+            Some("https://example.com/dist/server.json")
+        );
+        // 手写成局域网来源的公网主机同样不放宽
+        let forged = ServerSource::Lan {
+            url: "https://example.com/".to_string(),
+        };
+        assert!(!forged.allows("https://example.com/pack.toml"));
+    }
+
+    #[test]
+    fn lan_server_json_url() {
+        let lan = ServerSource::from_arg("http://192.168.1.10:8000/dist/");
+        assert_eq!(
+            lan.server_json_url().as_deref(),
+            Some("http://192.168.1.10:8000/dist/server.json")
+        );
+        let exact = ServerSource::from_arg("http://192.168.1.10:8000/custom.json");
+        assert_eq!(
+            exact.server_json_url().as_deref(),
+            Some("http://192.168.1.10:8000/custom.json")
+        );
+    }
+
+    #[test]
+    fn lan_allows_same_origin_only() {
+        let lan = ServerSource::from_arg("http://192.168.1.10:8000/");
+        assert!(lan.allows("http://192.168.1.10:8000/pack.toml"));
+        assert!(!lan.allows("http://192.168.1.11:8000/pack.toml"));
+        assert!(!lan.allows("https://192.168.1.10:8000/pack.toml"));
+        assert!(!lan.allows("file:///C:/pack.toml"));
+    }
+
+    #[test]
+    fn local_allows_files_under_root_only() {
+        let dir = crate::http::fake::temp_dir("source_local");
+        let root = dir.join("dist");
+        std::fs::create_dir_all(root.join("pack")).unwrap();
+        std::fs::write(root.join("server.json"), "{}").unwrap();
+        std::fs::write(root.join("pack/pack.toml"), "").unwrap();
+        std::fs::write(dir.join("secret.txt"), "").unwrap();
+
+        let local = ServerSource::from_arg(&root.to_string_lossy());
+        let url = |path: &Path| path_to_file_url(path);
+        assert!(local.allows(&url(&root.join("pack/pack.toml"))));
+        assert!(!local.allows(&url(&root.join("pack/../../secret.txt"))));
+        assert!(!local.allows(&url(&dir.join("secret.txt"))));
+        assert!(!local.allows(&url(&root.join("missing.toml"))));
+        assert!(!local.allows("http://192.168.1.10/pack.toml"));
+
+        // 指向 server.json 文件时以其所在目录为根
+        let file = ServerSource::from_arg(&root.join("server.json").to_string_lossy());
+        assert!(file.allows(&url(&root.join("pack/pack.toml"))));
+        assert!(!file.allows(&url(&dir.join("secret.txt"))));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn resolve_relative_urls() {
+        assert_eq!(
+            resolve_relative("http://h:1/dist/server.json", "pack.toml"),
+            "http://h:1/dist/pack.toml"
+        );
+        assert_eq!(
+            resolve_relative("http://h:1/dist/server.json", "./pack/pack.toml"),
+            "http://h:1/dist/pack/pack.toml"
+        );
+        assert_eq!(
+            resolve_relative("http://h:1/dist/server.json", "/pack.toml"),
+            "http://h:1/pack.toml"
+        );
+        assert_eq!(
+            resolve_relative("file:///D:/dist/server.json", "https://x.github.io/pack.toml"),
+            "https://x.github.io/pack.toml"
+        );
+    }
+
+    #[test]
+    fn file_url_roundtrip() {
+        assert_eq!(path_to_file_url(Path::new("D:/dist/server.json")), "file:///D:/dist/server.json");
+        assert_eq!(path_to_file_url(Path::new("//nas/share/a.json")), "file://nas/share/a.json");
+        assert_eq!(
+            path_from_arg("file:///D:/share/upmc-bundle.zip"),
+            PathBuf::from("D:/share/upmc-bundle.zip")
+        );
+        assert_eq!(
+            path_from_arg("file://nas/share/upmc-bundle.zip"),
+            PathBuf::from("//nas/share/upmc-bundle.zip")
+        );
+
+        // 空格、中文目录名编码后仍能还原
+        let path = Path::new("C:/Users/Jo Smith/Documents/CJC整合包/pack.toml");
+        let url = path_to_file_url(path);
+        assert_eq!(
+            url,
+            "file:///C:/Users/Jo%20Smith/Documents/CJC%E6%95%B4%E5%90%88%E5%8C%85/pack.toml"
+        );
+        assert_eq!(path_from_arg(&url), path);
+    }
+}
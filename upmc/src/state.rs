@@ -0,0 +1,558 @@
+// ============================================================
+// state.rs — 更新器状态文件的格式版本与迁移
+// ============================================================
+// 更新器在 updater/ 下保存的状态文件（local.json、channel.json、
+// settings.json、server.json 下发的设置和品牌覆盖、pack.toml 缓存、各类清单 / 历史记录 / 缓存、
+// Xray 版本和 PID 等）都带有格式版本号：
+//   - JSON 文件：顶层的 "schema_version" 字段
+//   - 列表文件：{"schema_version": n, "items": [...]}（旧版直接是数组）
+//   - 文本文件：首行 "# upmc-state v<版本>"
+// 没有版本号的文件视为版本 0（旧版更新器写入的格式）。
+//
+// 每个文件有一串迁移函数，第 i 个把版本 i 升级到 i + 1。
+// 启动时 migrate_all 把旧格式的文件就地升级；读取时也会在内存中
+// 迁移（从旧版离线安装包导入的文件不必等到下次启动）。
+// 比当前版本更新的文件（降级使用旧版更新器）尽量按当前格式读取，但不改写。
+//
+// 所有写入先写临时文件、刷到磁盘再重命名，中途崩溃或断电不会留下写了一半的文件。
+// ============================================================
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::config;
+
+/// JSON 状态文件中的版本字段名
+const VERSION_KEY: &str = "schema_version";
+
+/// 列表状态文件中存放元素的字段名
+const ITEMS_KEY: &str = "items";
+
+/// 文本状态文件的版本行前缀
+const TEXT_HEADER: &str = "# upmc-state v";
+
+/// JSON 迁移：就地修改顶层对象
+type JsonMigration = fn(&mut Map<String, Value>);
+
+/// 文本迁移：返回新内容（不含版本行）
+type TextMigration = fn(String) -> String;
+
+/// 状态文件格式及其迁移列表
+enum Kind {
+    Json(&'static [JsonMigration]),
+    Text(&'static [TextMigration]),
+}
+
+/// 一个带版本号的状态文件
+pub struct StateFile {
+    /// 相对于安装基准目录（或整合包实例目录）的路径。
+    /// 目录型状态（如 HTTP 缓存）为目录路径，条目用 `*_in` 方法读写。
+    pub path: &'static str,
+    kind: Kind,
+}
+
+/// 本地已安装的 MC / Fabric 版本（见 version.rs）
+pub static LOCAL_VERSION: StateFile = StateFile {
+    path: config::LOCAL_VERSION_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// 更新器通道
+pub static CHANNEL_CONFIG: StateFile = StateFile {
+    path: config::CHANNEL_CONFIG_FILE,
+    kind: Kind::Json(&[drop_dev_build_id]),
+};
+
+/// 用户设置
+pub static USER_SETTINGS: StateFile = StateFile {
+    path: config::USER_SETTINGS_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// 整合包通道
+pub static PACK_CHANNEL: StateFile = StateFile {
+    path: config::PACK_CHANNEL_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// server.json 下发的设置覆盖（见 settings.rs）
+pub static SERVER_SETTINGS: StateFile = StateFile {
+    path: config::SERVER_SETTINGS_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// server.json 下发的显示文字覆盖（见 branding.rs）
+pub static BRANDING: StateFile = StateFile {
+    path: config::BRANDING_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// 上次同步的 pack.toml 原文
+pub static PACK_TOML_CACHE: StateFile = StateFile {
+    path: config::PACK_TOML_CACHE_FILE,
+    kind: Kind::Text(&[unchanged_text]),
+};
+
+/// 首次安装时已应用默认设置包的标记
+pub static SETTINGS_INSTALLED: StateFile = StateFile {
+    path: config::SETTINGS_INSTALLED_FILE,
+    kind: Kind::Text(&[unchanged_text]),
+};
+
+/// 上次应用的画质预设指纹（见 presets.rs）
+pub static PRESET_APPLIED: StateFile = StateFile {
+    path: config::PRESET_APPLIED_FILE,
+    kind: Kind::Text(&[unchanged_text]),
+};
+
+/// 已下载的 Xray 版本号
+pub static XRAY_VERSION: StateFile = StateFile {
+    path: config::XRAY_VERSION_FILE,
+    kind: Kind::Text(&[unchanged_text]),
+};
+
+/// 本程序启动的 Xray 进程 PID
+pub static XRAY_PID: StateFile = StateFile {
+    path: config::XRAY_PID_FILE,
+    kind: Kind::Text(&[unchanged_text]),
+};
+
+/// 受管理文件清单（见 managed.rs）
+pub static MANAGED_FILES: StateFile = StateFile {
+    path: config::MANAGED_FILES_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// 可选模组列表缓存（列表）
+pub static OPTIONAL_MODS: StateFile = StateFile {
+    path: config::OPTIONAL_MODS_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// 上次同步的整合包文件列表快照
+pub static PACK_SNAPSHOT: StateFile = StateFile {
+    path: config::PACK_SNAPSHOT_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// 整合包更新日志历史（列表）
+pub static CHANGELOG_HISTORY: StateFile = StateFile {
+    path: config::CHANGELOG_HISTORY_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// 已保存的整合包版本记录（列表，见 packs.rs）
+pub static PACK_HISTORY: StateFile = StateFile {
+    path: config::PACK_HISTORY_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// 固定的整合包版本
+pub static PACK_PIN: StateFile = StateFile {
+    path: config::PACK_PIN_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// 镜像列表和健康状态
+pub static MIRROR_STATE: StateFile = StateFile {
+    path: config::MIRROR_STATE_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// 玩家添加的整合包和上次选择
+pub static PROFILES: StateFile = StateFile {
+    path: config::PROFILES_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// server.json 声明的整合包列表（列表）
+pub static SERVER_PROFILES: StateFile = StateFile {
+    path: config::SERVER_PROFILES_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// 画质预设缓存（列表）
+pub static PRESETS: StateFile = StateFile {
+    path: config::PRESETS_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// 模组策略检查报告
+pub static MOD_REPORT: StateFile = StateFile {
+    path: config::MOD_REPORT_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// 模组报告签名私钥
+pub static REPORT_KEY: StateFile = StateFile {
+    path: config::REPORT_KEY_FILE,
+    kind: Kind::Text(&[unchanged_text]),
+};
+
+/// 灰度发布分桶
+pub static ROLLOUT_BUCKET: StateFile = StateFile {
+    path: config::ROLLOUT_BUCKET_FILE,
+    kind: Kind::Text(&[unchanged_text]),
+};
+
+/// 专用服务器的安装状态（相对于服务器目录，见 server.rs）。
+/// 不在安装基准目录下，不参与启动时迁移，读取时在内存中迁移。
+pub static SERVER_INSTALL: StateFile = StateFile {
+    path: config::SERVER_INSTALL_FILE,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// HTTP 条件缓存（目录，每个 URL 一个条目）
+pub static HTTP_CACHE: StateFile = StateFile {
+    path: config::HTTP_CACHE_DIR,
+    kind: Kind::Json(&[unchanged_json]),
+};
+
+/// 启动时需要迁移的全部状态文件（HTTP 缓存条目和服务器安装状态在读取时迁移，不逐个改写）
+const ALL: [&StateFile; 24] = [
+    &LOCAL_VERSION,
+    &CHANNEL_CONFIG,
+    &USER_SETTINGS,
+    &PACK_CHANNEL,
+    &SERVER_SETTINGS,
+    &BRANDING,
+    &PACK_TOML_CACHE,
+    &SETTINGS_INSTALLED,
+    &PRESET_APPLIED,
+    &XRAY_VERSION,
+    &XRAY_PID,
+    &MANAGED_FILES,
+    &OPTIONAL_MODS,
+    &PACK_SNAPSHOT,
+    &CHANGELOG_HISTORY,
+    &PACK_HISTORY,
+    &PACK_PIN,
+    &MIRROR_STATE,
+    &PROFILES,
+    &SERVER_PROFILES,
+    &PRESETS,
+    &MOD_REPORT,
+    &REPORT_KEY,
+    &ROLLOUT_BUCKET,
+];
+
+/// 列表状态文件的内容
+#[derive(Serialize)]
+struct ItemsRef<'a, T> {
+    items: &'a [T],
+}
+
+#[derive(Deserialize)]
+struct Items<T> {
+    items: Vec<T>,
+}
+
+// ── 迁移函数 ──
+
+/// v0 → v1：格式不变，只补上版本号
+fn unchanged_json(_: &mut Map<String, Value>) {}
+
+/// v0 → v1：格式不变，只补上版本行
+fn unchanged_text(text: String) -> String {
+    text
+}
+
+/// channel.json v0 → v1：删除早期版本写入的 dev_build_id（构建号改由 version.json 下发）
+fn drop_dev_build_id(map: &mut Map<String, Value>) {
+    map.remove("dev_build_id");
+}
+
+// ── 读写 ──
+
+impl StateFile {
+    /// 当前格式版本（= 迁移函数个数）
+    pub fn version(&self) -> u32 {
+        match self.kind {
+            Kind::Json(migrations) => migrations.len() as u32,
+            Kind::Text(migrations) => migrations.len() as u32,
+        }
+    }
+
+    pub fn exists(&self, dir: &Path) -> bool {
+        dir.join(self.path).exists()
+    }
+
+    /// 删除文件（不存在时忽略）
+    pub fn remove(&self, dir: &Path) {
+        let _ = fs::remove_file(dir.join(self.path));
+    }
+
+    /// 读取 JSON 状态文件并迁移到当前格式。不存在时返回 None，损坏时警告并返回 None。
+    pub fn read_json<T: DeserializeOwned>(&self, dir: &Path) -> Option<T> {
+        self.read_json_at(&dir.join(self.path))
+    }
+
+    /// 以当前格式版本写入 JSON 状态文件
+    pub fn write_json<T: Serialize>(&self, dir: &Path, value: &T) -> Result<()> {
+        self.write_json_at(&dir.join(self.path), value)
+    }
+
+    /// 读取列表状态文件（不存在或损坏时为空）
+    pub fn read_list<T: DeserializeOwned>(&self, dir: &Path) -> Vec<T> {
+        self.read_json::<Items<T>>(dir)
+            .map(|list| list.items)
+            .unwrap_or_default()
+    }
+
+    /// 写入列表状态文件
+    pub fn write_list<T: Serialize>(&self, dir: &Path, items: &[T]) -> Result<()> {
+        self.write_json(dir, &ItemsRef { items })
+    }
+
+    /// 读取目录型状态中名为 `name` 的 JSON 条目
+    pub fn read_json_in<T: DeserializeOwned>(&self, dir: &Path, name: &str) -> Option<T> {
+        self.read_json_at(&dir.join(self.path).join(name))
+    }
+
+    /// 写入目录型状态中名为 `name` 的 JSON 条目
+    pub fn write_json_in<T: Serialize>(&self, dir: &Path, name: &str, value: &T) -> Result<()> {
+        self.write_json_at(&dir.join(self.path).join(name), value)
+    }
+
+    fn read_json_at<T: DeserializeOwned>(&self, path: &Path) -> Option<T> {
+        let text = fs::read_to_string(path).ok()?;
+        let map = match self.upgrade_json(&text) {
+            Ok((map, _)) => map,
+            Err(e) => {
+                eprintln!("警告: {} 已损坏，忽略: {e:#}", path.display());
+                return None;
+            }
+        };
+        match serde_json::from_value(Value::Object(map)) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                eprintln!("警告: {} 解析失败，忽略: {e}", path.display());
+                None
+            }
+        }
+    }
+
+    fn write_json_at<T: Serialize>(&self, path: &Path, value: &T) -> Result<()> {
+        let mut map = match serde_json::to_value(value).context("序列化状态失败")? {
+            Value::Object(map) => map,
+            _ => anyhow::bail!("状态文件内容必须是 JSON 对象: {}", path.display()),
+        };
+        map.insert(VERSION_KEY.to_string(), Value::from(self.version()));
+        let json = serde_json::to_string_pretty(&map).context("序列化状态失败")?;
+        write_atomic(path, json.as_bytes())
+    }
+
+    /// 读取文本状态文件（不含版本行）并迁移到当前格式
+    pub fn read_text(&self, dir: &Path) -> Option<String> {
+        let text = fs::read_to_string(dir.join(self.path)).ok()?;
+        Some(self.upgrade_text(text).0)
+    }
+
+    /// 以当前格式版本写入文本状态文件
+    pub fn write_text(&self, dir: &Path, text: &str) -> Result<()> {
+        let content = format!("{TEXT_HEADER}{}\n{text}", self.version());
+        write_atomic(&dir.join(self.path), content.as_bytes())
+    }
+
+    /// 把旧格式的文件升级到当前格式并写回。返回是否改写了文件。
+    fn migrate(&self, dir: &Path) -> Result<bool> {
+        let path = dir.join(self.path);
+        let Ok(text) = fs::read_to_string(&path) else {
+            return Ok(false);
+        };
+        match self.kind {
+            Kind::Json(_) => {
+                let (map, from) = self.upgrade_json(&text)?;
+                if from >= self.version() {
+                    return Ok(false);
+                }
+                self.write_json(dir, &map)?;
+            }
+            Kind::Text(_) => {
+                let (text, from) = self.upgrade_text(text);
+                if from >= self.version() {
+                    return Ok(false);
+                }
+                self.write_text(dir, &text)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// 解析 JSON 并依次执行迁移。返回迁移后的对象（不含版本字段）和原版本号。
+    fn upgrade_json(&self, text: &str) -> Result<(Map<String, Value>, u32)> {
+        let Kind::Json(migrations) = self.kind else {
+            anyhow::bail!("{} 不是 JSON 状态文件", self.path);
+        };
+        let mut map = match serde_json::from_str(text).context("JSON 格式错误")? {
+            Value::Object(map) => map,
+            // 旧版列表文件直接是数组
+            Value::Array(items) => Map::from_iter([(ITEMS_KEY.to_string(), Value::Array(items))]),
+            _ => anyhow::bail!("状态文件内容必须是 JSON 对象"),
+        };
+        let from = map
+            .remove(VERSION_KEY)
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0) as u32;
+        for migrate in migrations.iter().skip(from as usize) {
+            migrate(&mut map);
+        }
+        Ok((map, from))
+    }
+
+    /// 拆出版本行并依次执行迁移。返回迁移后的内容和原版本号。
+    fn upgrade_text(&self, text: String) -> (String, u32) {
+        let Kind::Text(migrations) = self.kind else {
+            return (text, self.version());
+        };
+        let (mut text, from) = split_text_header(text);
+        for migrate in migrations.iter().skip(from as usize) {
+            text = migrate(text);
+        }
+        (text, from)
+    }
+}
+
+/// 拆出文本状态文件的版本行。没有版本行时为版本 0，全部内容都是正文。
+fn split_text_header(text: String) -> (String, u32) {
+    let Some(rest) = text.strip_prefix(TEXT_HEADER) else {
+        return (text, 0);
+    };
+    let (version, body) = rest.split_once('\n').unwrap_or((rest, ""));
+    match version.trim().parse() {
+        Ok(version) => (body.to_string(), version),
+        Err(_) => (text, 0),
+    }
+}
+
+/// 把目录下旧格式的状态文件升级到当前格式（启动时调用）。
+/// 单个文件失败只给出警告，不影响其他文件和后续流程。
+pub fn migrate_all(dir: &Path) {
+    for file in ALL {
+        match file.migrate(dir) {
+            Ok(true) => eprintln!("已升级 {} 到格式版本 {}", file.path, file.version()),
+            Ok(false) => {}
+            Err(e) => eprintln!("警告: 升级 {} 失败: {e:#}", file.path),
+        }
+    }
+}
+
+/// 原子写入：先写同目录下的临时文件并刷到磁盘，再重命名覆盖。
+/// 不先 sync 的话，断电后重命名可能已落盘而内容还没有，留下空文件。
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("创建目录失败: {}", parent.display()))?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = Path::new(&temp);
+    let mut file =
+        fs::File::create(temp).with_context(|| format!("创建失败: {}", temp.display()))?;
+    file.write_all(content)
+        .and_then(|()| file.sync_all())
+        .with_context(|| format!("写入失败: {}", temp.display()))?;
+    drop(file);
+    fs::rename(temp, path).with_context(|| format!("替换失败: {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ChannelConfig, UpdateChannel};
+    use crate::http::fake;
+    use crate::version::LocalVersion;
+
+    #[test]
+    fn legacy_json_is_migrated_in_place() {
+        let dir = fake::temp_dir("state_json");
+        let path = dir.join(config::CHANNEL_CONFIG_FILE);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, r#"{ "channel": "dev", "dev_build_id": 42 }"#).unwrap();
+
+        migrate_all(&dir);
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved[VERSION_KEY], CHANNEL_CONFIG.version());
+        assert!(saved.get("dev_build_id").is_none());
+
+        let config: ChannelConfig = CHANNEL_CONFIG.read_json(&dir).unwrap();
+        assert_eq!(config.channel, UpdateChannel::Dev);
+
+        // 已是当前格式时不再改写
+        assert!(!CHANNEL_CONFIG.migrate(&dir).unwrap());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn legacy_text_is_read_and_versioned() {
+        let dir = fake::temp_dir("state_text");
+        let path = dir.join(config::XRAY_PID_FILE);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "1234").unwrap();
+
+        assert_eq!(XRAY_PID.read_text(&dir).unwrap(), "1234");
+        migrate_all(&dir);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# upmc-state v1\n1234");
+        assert_eq!(XRAY_PID.read_text(&dir).unwrap(), "1234");
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn legacy_list_is_wrapped_with_version() {
+        let dir = fake::temp_dir("state_list");
+        let path = dir.join(config::PRESETS_FILE);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, r#"[{ "id": "low" }, { "id": "high" }]"#).unwrap();
+
+        let before: Vec<Value> = PRESETS.read_list(&dir);
+        migrate_all(&dir);
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved[VERSION_KEY], PRESETS.version());
+        assert_eq!(saved[ITEMS_KEY][1]["id"], "high");
+        assert_eq!(PRESETS.read_list::<Value>(&dir), before);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn text_round_trip_keeps_content_exactly() {
+        let dir = fake::temp_dir("state_round_trip");
+        let pack_toml = "name = \"pack\"\n\n[index]\nfile = \"index.toml\"\n";
+        PACK_TOML_CACHE.write_text(&dir, pack_toml).unwrap();
+        assert_eq!(PACK_TOML_CACHE.read_text(&dir).unwrap(), pack_toml);
+        assert!(!dir.join("updater/pack_toml_cache.txt.tmp").exists());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn newer_files_are_read_but_not_rewritten() {
+        let dir = fake::temp_dir("state_newer");
+        let path = dir.join(config::LOCAL_VERSION_FILE);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let newer = r#"{ "schema_version": 99, "mc_version": "1.21.1", "fabric_version": "0.16.0", "version_tag": "t", "future": true }"#;
+        fs::write(&path, newer).unwrap();
+
+        migrate_all(&dir);
+        assert_eq!(fs::read_to_string(&path).unwrap(), newer);
+        let local: LocalVersion = LOCAL_VERSION.read_json(&dir).unwrap();
+        assert_eq!(local.mc_version, "1.21.1");
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn truncated_json_is_reported_not_migrated() {
+        let dir = fake::temp_dir("state_truncated");
+        let path = dir.join(config::LOCAL_VERSION_FILE);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, r#"{ "mc_version": "1.2"#).unwrap();
+
+        assert!(LOCAL_VERSION.migrate(&dir).is_err());
+        assert!(LOCAL_VERSION.read_json::<LocalVersion>(&dir).is_none());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -10,16 +10,27 @@
 // 通过回调函数 (callback) 向 GUI 报告进度。
 // ============================================================
 
-use anyhow::{bail, Result};
+use anyhow::Result;
 use std::path::Path;
 
 use crate::bootstrap;
+use crate::bundle;
+use crate::changelog::{self, Changelog};
 use crate::config;
 use crate::config::ChannelConfig;
 use crate::discord_proxy;
+use crate::error::{self, ErrorCode};
 use crate::fabric;
+use crate::managed;
+use crate::mod_policy::{self, ScannedMod};
+use crate::optional_mods;
+use crate::pack_index;
+use crate::packs;
 use crate::packwiz;
+use crate::presets;
+use crate::profiles;
 use crate::selfupdate;
+use crate::state;
 use crate::version;
 
 /// 更新进度信息，传给 GUI 显示。
@@ -42,10 +53,17 @@ impl Progress {
 
 /// 更新结果枚举
 pub enum UpdateResult {
-    /// 更新成功完成，proxy_running 表示是否已自动启动了代理
-    Success { proxy_running: bool },
+    /// 更新成功完成，proxy_running 表示是否已自动启动了代理，
+    /// warnings 为启动前需要提示玩家的问题（如模组冲突），
+    /// violations 为违反模组策略的模组，changelog 为本次整合包更新的变更
+    Success {
+        proxy_running: bool,
+        warnings: Vec<String>,
+        violations: Vec<ScannedMod>,
+        changelog: Option<Changelog>,
+    },
     /// 网络不可用，跳过更新（离线模式）
-    Offline,
+    Offline { violations: Vec<ScannedMod> },
     /// 更新器自身已更新并重启，当前进程应直接退出（不启动 PCL2）
     SelfUpdateRestarting,
 }
@@ -71,7 +89,7 @@ pub fn run_update(
     // ─────────────────────────────────────────────
     on_progress(Progress::new(1, "检查更新器版本..."));
 
-    match selfupdate::check_and_update(channel_config.channel, on_progress) {
+    match selfupdate::check_and_update(base_dir, channel_config.channel, on_progress) {
         Ok(selfupdate::SelfUpdateResult::Restarting) => {
             // 新版已下载并启动，当前进程应直接退出（不启动 PCL2）
             return Ok(UpdateResult::SelfUpdateRestarting);
@@ -91,31 +109,64 @@ pub fn run_update(
     on_progress(Progress::new(12, "正在连接更新服务器..."));
 
     // 尝试拉取远程版本信息
-    let remote = match version::fetch_remote_version() {
+    let remote = match version::fetch_remote_version(base_dir) {
         Ok(v) => v,
         Err(e) => {
             // 网络失败：检查是否已安装过
             if bootstrap::is_bootstrapped(base_dir) {
                 // 已安装 → 离线模式，跳过更新直接启动
                 eprintln!("网络检查失败，进入离线模式: {e:#}");
-                on_progress(Progress::new(100, "离线模式 — 跳过更新"));
-                return Ok(UpdateResult::Offline);
+                let violations = run_offline(base_dir, on_progress);
+                return Ok(UpdateResult::Offline { violations });
             }
-            // 未安装 → 无法继续，首次运行需要网络
-            bail!(
-                "首次运行需要网络连接来下载必要组件。\n\
-                 请检查网络后重试。\n\n\
-                 错误详情: {e:#}"
-            );
+            // 未安装但指定了离线安装包 → 按缓存的清单或安装包自带的快照检查并导入，
+            // 以离线模式启动，联网后再追上最新版本
+            if let Some(bundle_path) = bundle::requested() {
+                eprintln!("网络不可用，从离线安装包安装: {e:#}");
+                on_progress(Progress::new(15, "网络不可用，正在从离线安装包安装..."));
+                bundle::import(base_dir, &bundle_path, None, on_progress, 15, 95)?;
+                let violations = run_offline(base_dir, on_progress);
+                return Ok(UpdateResult::Offline { violations });
+            }
+            // 未安装 → 无法继续，首次运行需要网络（保留原错误的错误码，如 TLS）
+            return Err(error::new(
+                error::classify(&e).unwrap_or(ErrorCode::Network),
+                format!(
+                    "首次运行需要网络连接来下载必要组件。\n\
+                     请检查网络后重试，或用 --bundle <路径> 指定离线安装包 {}。\n\n\
+                     错误详情: {e:#}",
+                    config::BUNDLE_FILE_NAME
+                ),
+            ));
         }
     };
 
+    // 玩家回退过整合包时改用固定的快照（服务器发布新版本时自动取消固定）
+    let remote = packs::apply_pin(base_dir, remote);
+    let pinned = packs::pinned_record(base_dir);
+    if let Some(record) = &pinned {
+        on_progress(Progress::new(13, format!(
+            "已固定整合包版本 {}（{}）",
+            record.id, record.date
+        )));
+    }
+
+    // 缓存服务器声明的其他整合包，供主窗口选择
+    if let Err(e) = profiles::save_declared(base_dir, &remote.profiles) {
+        eprintln!("{e:#}");
+    }
+
+    // 缓存预设列表，供设置窗口和离线模式使用
+    if let Err(e) = presets::save(base_dir, &remote.presets) {
+        eprintln!("{e:#}");
+    }
+
     // ─────────────────────────────────────────────
     // 阶段 0: 首次安装自举（如果需要）
     // ─────────────────────────────────────────────
     if bootstrap::needs_bootstrap(base_dir) {
         on_progress(Progress::new(15, "首次运行，正在下载组件..."));
-        bootstrap::run_bootstrap(base_dir, &remote.downloads, on_progress)?;
+        bootstrap::run_bootstrap(base_dir, &remote, on_progress)?;
     } else {
         on_progress(Progress::new(50, "组件检查完毕"));
     }
@@ -147,13 +198,24 @@ pub fn run_update(
         on_progress(Progress::new(70, "正在清理旧版本..."));
         fabric::cleanup_old_versions(base_dir, &remote.version_tag)?;
 
-        // 2c. 清空旧模组（新版本模组由 packwiz 重新下载）
+        // 2c. 清理旧模组：整合包的模组删除（新版本由 packwiz 重新下载），
+        //     玩家自己添加的模组移到 mods/.disabled-<旧版本>/ 保留
         on_progress(Progress::new(75, "正在清理旧模组..."));
-        fabric::clean_mods_dir(base_dir)?;
+        let old_tag = if local.version_tag.is_empty() {
+            "unknown"
+        } else {
+            local.version_tag.as_str()
+        };
+        let moved = managed::set_aside_mods(base_dir, old_tag)?;
+        if !moved.is_empty() {
+            on_progress(Progress::new(76, format!(
+                "已将 {} 个自行添加的模组移到 mods/.disabled-{old_tag}/",
+                moved.len()
+            )));
+        }
 
         // 2c-2. 清除 pack.toml 缓存，强制阶段 3 重新同步
-        let cache_path = base_dir.join(config::PACK_TOML_CACHE_FILE);
-        let _ = std::fs::remove_file(&cache_path);
+        state::PACK_TOML_CACHE.remove(base_dir);
 
         // 2d. 保存新的本地版本记录
         let new_local = version::LocalVersion {
@@ -176,27 +238,107 @@ pub fn run_update(
     // ── 修正 PCL2 版本隔离设置 ──
     // PCL2 会在版本目录下自动创建 Setup.ini 并启用隔离，
     // 导致游戏目录指向 versions/<tag>/ 而非 .minecraft/，
-    // 每次启动前都需要修正为不隔离。
-    on_progress(Progress::new(79, "修正版本隔离设置..."));
-    fabric::fix_version_isolation(base_dir, &remote.version_tag)?;
+    // 每次启动前都需要修正为不隔离。PCL2 只有 Windows 版，其他平台跳过。
+    if cfg!(windows) {
+        on_progress(Progress::new(79, "修正版本隔离设置..."));
+        fabric::fix_version_isolation(base_dir, &remote.version_tag)?;
+    }
 
     // ─────────────────────────────────────────────
     // 阶段 3: 同步模组和配置
     // ─────────────────────────────────────────────
-    if version::is_pack_changed(base_dir, &remote.pack_toml_raw) {
+    // 切换了整合包通道：删除旧通道的模组（不同通道的模组版本可能不兼容），
+    // 由 packwiz 按新通道完整同步；玩家自己添加的模组保留
+    let mut pack_channel = config::load_pack_channel(base_dir);
+    if pack_channel
+        .synced
+        .as_deref()
+        .is_some_and(|synced| synced != remote.pack_channel)
+    {
+        on_progress(Progress::new(80, format!(
+            "整合包通道已切换到 {}，正在清理旧模组...",
+            remote.pack_channel
+        )));
+        let removed = managed::remove_managed_mods(base_dir);
+        eprintln!("切换整合包通道，已删除 {removed} 个旧模组");
+        state::PACK_TOML_CACHE.remove(base_dir);
+    }
+
+    let pack_changed = version::is_pack_changed(base_dir, &remote.pack_toml_raw);
+    if pack_changed {
         on_progress(Progress::new(80, "正在同步模组..."));
-        packwiz::sync_modpack(base_dir, &remote.pack_url)?;
+        packwiz::sync_modpack(base_dir, &remote.pack_url, on_progress)?;
         version::save_pack_cache(base_dir, &remote.pack_toml_raw)?;
         on_progress(Progress::new(95, "模组同步完成"));
     } else {
         on_progress(Progress::new(95, "模组已是最新，跳过同步"));
     }
 
+    if pack_channel.synced.as_deref() != Some(remote.pack_channel.as_str()) {
+        pack_channel.synced = Some(remote.pack_channel.clone());
+        if let Err(e) = config::save_pack_channel(base_dir, &pack_channel) {
+            eprintln!("{e:#}");
+        }
+    }
+
+    // 保存快照供以后回退（固定的版本本身就是快照）
+    if pinned.is_none()
+        && (pack_changed || !packs::has_snapshot(base_dir, &remote.pack_toml_raw))
+        && let Err(e) = packs::save_snapshot(base_dir, &remote)
+    {
+        eprintln!("保存整合包快照失败: {e:#}");
+    }
+
+    // 整合包变化时刷新受管理文件清单、可选模组列表和更新日志，
+    // 每次启动都按玩家选择启用 / 禁用可选模组（失败不阻塞启动，只记录日志）
+    let mut pack_changelog = None;
+    if pack_changed
+        || !optional_mods::is_cached(base_dir)
+        || !managed::is_recorded(base_dir, &remote.version_tag)
+    {
+        on_progress(Progress::new(95, "正在读取整合包文件列表..."));
+        match pack_index::resolve_files(base_dir, &remote.pack_url, &remote.pack_toml_raw) {
+            Ok(files) => {
+                let managed_files = managed::ManagedFiles::from_files(&remote.version_tag, &files);
+                if let Err(e) = managed::save(base_dir, &managed_files) {
+                    eprintln!("{e:#}");
+                }
+                if let Err(e) = optional_mods::refresh(base_dir, &files) {
+                    eprintln!("{e:#}");
+                }
+                match changelog::record(base_dir, &files, &remote.version_tag) {
+                    Ok(log) => pack_changelog = log,
+                    Err(e) => eprintln!("{e:#}"),
+                }
+            }
+            Err(e) => eprintln!("读取整合包文件列表失败: {e:#}"),
+        }
+    }
+    let optional = optional_mods::load(base_dir);
+    let settings = config::load_user_settings(base_dir);
+    let preset_list = presets::load(base_dir);
+    let preset = presets::active(&preset_list, settings.preset.as_deref());
+    if !optional.is_empty() {
+        on_progress(Progress::new(96, "正在应用可选模组选择..."));
+        let choices = presets::effective_choices(preset, &settings.optional_mods);
+        if let Err(e) = optional_mods::apply(base_dir, &optional, &choices) {
+            eprintln!("{e:#}");
+        }
+    }
+    if let Some(preset) = preset {
+        on_progress(Progress::new(97, format!("正在应用画质预设: {}", preset.label)));
+        if let Err(e) = presets::apply(base_dir, preset, &remote.version_tag) {
+            eprintln!("应用画质预设失败: {e:#}");
+        }
+    }
+
     // 如果之前已配置过代理，自动启动 Xray + 安装 DLL
     // 如果 Xray 启动失败，不安装 DLL（避免 Discord 连不上网）
-    let proxy_running = if discord_proxy::is_configured(base_dir) {
+    // 代理是整台电脑共用的，配置保存在安装基准目录（而不是整合包实例目录）
+    let proxy_dir = profiles::root_dir(base_dir);
+    let proxy_running = if discord_proxy::is_configured(&proxy_dir) {
         on_progress(Progress::new(97, "正在启动代理..."));
-        match discord_proxy::auto_start(base_dir) {
+        match discord_proxy::auto_start(&proxy_dir) {
             Ok(()) => true,
             Err(e) => {
                 eprintln!("自动启动代理失败: {e:#}");
@@ -207,7 +349,123 @@ pub fn run_update(
         false
     };
 
+    // 玩家自己添加的模组与整合包重复时提醒（不阻止启动）
+    let warnings = managed::find_conflicts(base_dir);
+    let violations = check_mod_policy(base_dir, &remote, on_progress);
+
     on_progress(Progress::new(100, "更新完成"));
 
-    Ok(UpdateResult::Success { proxy_running })
+    Ok(UpdateResult::Success {
+        proxy_running,
+        warnings,
+        violations,
+        changelog: pack_changelog,
+    })
+}
+
+/// 离线模式：用上次缓存的清单确认本地整合包版本，只做不需要网络的修正。
+/// 返回违反模组策略的模组（离线也照常检查）。
+fn run_offline(base_dir: &Path, on_progress: &dyn Fn(Progress)) -> Vec<ScannedMod> {
+    let local = version::read_local_version(base_dir);
+
+    let cached = version::cached_remote_version(base_dir).map(|cached| packs::apply_pin(base_dir, cached));
+    match cached {
+        Some(cached) if !version::needs_version_upgrade(&cached, &local) => {
+            if cfg!(windows)
+                && let Err(e) = fabric::fix_version_isolation(base_dir, &cached.version_tag)
+            {
+                eprintln!("离线模式下修正版本隔离设置失败: {e:#}");
+            }
+            let violations = check_mod_policy(base_dir, &cached, on_progress);
+            on_progress(Progress::new(100, format!(
+                "离线模式 — 使用上次的整合包 (MC {} / Fabric {})",
+                cached.mc_version, cached.fabric_version
+            )));
+            violations
+        }
+        _ => {
+            on_progress(Progress::new(100, "离线模式 — 跳过更新"));
+            Vec::new()
+        }
+    }
+}
+
+/// 按 server.json 的模组策略检查 mods/，返回违规项（检查失败只记录日志）。
+fn check_mod_policy(
+    base_dir: &Path,
+    remote: &version::RemoteVersion,
+    on_progress: &dyn Fn(Progress),
+) -> Vec<ScannedMod> {
+    let Some(policy) = &remote.mod_policy else {
+        return Vec::new();
+    };
+    on_progress(Progress::new(98, "正在检查模组..."));
+    match mod_policy::check(base_dir, policy, &remote.version_tag) {
+        Ok(mods) => mods.into_iter().filter(|m| m.verdict.is_violation()).collect(),
+        Err(e) => {
+            eprintln!("模组检查失败: {e:#}");
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::fake;
+    use crate::installer::fake::FakeInstaller;
+
+    #[test]
+    fn fresh_install_then_incremental_update() {
+        let base_dir = fake::temp_dir("update");
+        let fake = fake::fixture().install();
+        let installer = FakeInstaller::new(&base_dir).install();
+        let channel = ChannelConfig::default();
+        let mc_dir = base_dir.join(config::MINECRAFT_DIR);
+
+        // 首次安装：下载组件、安装 Fabric、同步模组
+        let result = run_update(&base_dir, &channel, &|_| {}).unwrap();
+        assert!(matches!(
+            result,
+            UpdateResult::Success {
+                changelog: None,
+                ..
+            }
+        ));
+        assert!(!bootstrap::needs_bootstrap(&base_dir));
+        assert_eq!(installer.installer.runs(), ["fabric", "packwiz"]);
+        let local = version::read_local_version(&base_dir);
+        assert_eq!(local.mc_version, fake::MC_VERSION);
+        let client_jar = format!("versions/{0}/{0}.jar", fake::MC_VERSION);
+        assert!(mc_dir.join(client_jar).exists());
+        assert!(mc_dir.join("mods/sodium.jar").exists());
+
+        // 整合包新增一个模组：只重新同步模组，不重装 Fabric
+        fake.server.publish_pack(
+            "2026.11",
+            &[
+                ("sodium", b"PK sodium 0.6"),
+                ("lithium", b"PK lithium 0.14"),
+            ],
+        );
+        let result = run_update(&base_dir, &channel, &|_| {}).unwrap();
+        let UpdateResult::Success {
+            changelog: Some(log),
+            ..
+        } = result
+        else {
+            panic!("增量更新应返回更新日志");
+        };
+        assert_eq!(log.added.len(), 1);
+        assert!(log.added[0].contains("lithium"));
+        assert!(log.updated.is_empty());
+        assert_eq!(installer.installer.runs(), ["fabric", "packwiz", "packwiz"]);
+        assert!(mc_dir.join("mods/lithium.jar").exists());
+
+        // 整合包没有变化：不再同步
+        run_update(&base_dir, &channel, &|_| {}).unwrap();
+        assert_eq!(installer.installer.runs().len(), 3);
+
+        std::fs::remove_dir_all(&base_dir).ok();
+    }
 }
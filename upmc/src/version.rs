@@ -10,11 +10,21 @@
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::collections::BTreeMap;
 use std::path::Path;
 
+use crate::bootstrap;
+use crate::branding::{self, DisplayOverrides};
 use crate::config;
+use crate::http_cache;
+use crate::mirrors::{self, Endpoint};
+use crate::mod_policy::ModPolicy;
+use crate::presets::Preset;
+use crate::profiles::Profile;
 use crate::retry;
+use crate::settings;
+use crate::source::{self, ServerSource};
+use crate::state;
 
 /// 服务器端配置（从远程 server.json 反序列化）
 ///
@@ -27,6 +37,51 @@ pub struct ServerConfig {
     /// 可选的下载 URL 配置（首次安装时自动下载组件）
     #[serde(default)]
     pub downloads: Downloads,
+
+    /// 可选的镜像列表，键为端点名（见 `mirrors::Endpoint::key`）。
+    /// 只能引用下载白名单内的主机，下次启动起生效于 server.json 自身的获取。
+    #[serde(default)]
+    pub mirrors: BTreeMap<String, Vec<String>>,
+
+    /// 可选的画质预设（按从低到高排列，见 presets.rs）
+    #[serde(default)]
+    pub presets: Vec<Preset>,
+
+    /// 可选的模组策略（禁用 / 允许列表，见 mod_policy.rs）
+    #[serde(default)]
+    pub mod_policy: Option<ModPolicy>,
+
+    /// 可选的整合包通道（如 "beta"），每个通道有自己的 pack_url。
+    /// 顶层 pack_url 即默认的 stable 通道。
+    #[serde(default)]
+    pub pack_channels: BTreeMap<String, PackChannel>,
+
+    /// 可选的其他整合包（如创造服），每个有自己的 server.json，见 profiles.rs
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+
+    /// 可选的设置覆盖（超时、重试等运维参数，见 settings.rs），下次启动时生效
+    #[serde(default)]
+    pub settings: serde_json::Map<String, serde_json::Value>,
+
+    /// 可选的显示文字覆盖（服务器名称、启动器副标题，见 branding.rs），下次启动时生效
+    #[serde(default)]
+    pub branding: DisplayOverrides,
+
+    /// 实际使用的整合包通道（按玩家订阅选择，不来自 server.json）
+    #[serde(skip)]
+    pub pack_channel: String,
+}
+
+/// server.json 中声明的一个整合包通道
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackChannel {
+    /// 设置窗口中显示的名称，缺省时显示通道名
+    #[serde(default)]
+    pub label: Option<String>,
+
+    /// 该通道的 pack.toml 地址（规则与顶层 pack_url 相同）
+    pub pack_url: String,
 }
 
 /// 从 pack.toml 解析出的版本信息 + server.json 的配置合并后的完整远程状态
@@ -49,6 +104,22 @@ pub struct RemoteVersion {
 
     /// pack.toml 原始内容，用于增量同步判断
     pub pack_toml_raw: String,
+
+    /// 画质预设
+    pub presets: Vec<Preset>,
+
+    /// 模组策略
+    pub mod_policy: Option<ModPolicy>,
+
+    /// 实际使用的整合包通道
+    pub pack_channel: String,
+
+    /// server.json 声明的整合包通道（不含默认通道，只有设置窗口使用）
+    #[cfg(windows)]
+    pub pack_channels: BTreeMap<String, PackChannel>,
+
+    /// server.json 声明的其他整合包
+    pub profiles: Vec<Profile>,
 }
 
 /// 首次安装所需的下载 URL + SHA256 集合。
@@ -113,55 +184,140 @@ pub struct LocalVersion {
 /// 从远程拉取 server.json 和 pack.toml，合并为完整的远程版本信息。
 ///
 /// 流程：
-///   1. GET server.json → 获取 pack_url 和 downloads
+///   1. GET server.json → 获取 pack_url 和 downloads（多个镜像依次尝试；
+///      配置了本地 / 局域网来源时改从该来源读取，见 source.rs）
 ///   2. GET pack.toml   → 解析 minecraft 和 fabric 版本
 ///   3. 合并为 RemoteVersion
-pub fn fetch_remote_version() -> Result<RemoteVersion> {
-    retry::with_retry(
-        config::RETRY_MAX_ATTEMPTS,
-        config::RETRY_BASE_DELAY_SECS,
-        "获取远程版本信息",
-        || fetch_remote_version_inner(),
-    )
+pub fn fetch_remote_version(base_dir: &Path) -> Result<RemoteVersion> {
+    retry::with_default_retry("获取远程版本信息", || {
+        fetch_remote_version_inner(base_dir)
+    })
 }
 
 /// fetch_remote_version 的内部实现（单次尝试）。
-fn fetch_remote_version_inner() -> Result<RemoteVersion> {
-    let agent = config::http_agent();
+fn fetch_remote_version_inner(base_dir: &Path) -> Result<RemoteVersion> {
+    let server_source = source::resolve();
+    if let Some(server_url) = server_source.server_json_url() {
+        return fetch_from_source(base_dir, &server_source, &server_url);
+    }
 
     // 1. 拉取 server.json
-    let body = agent
-        .get(config::REMOTE_SERVER_JSON_URL)
-        .call()
-        .context("无法连接到更新服务器，请检查网络")?
-        .body_mut()
-        .read_to_string()
-        .context("读取服务器响应失败")?;
-
-    let server_config: ServerConfig =
+    let body = mirrors::with_failover(base_dir, Endpoint::ServerJson, |url| {
+        http_cache::get_text(base_dir, url, &[])
+            .context("无法连接到更新服务器，请检查网络")
+    })?;
+
+    let mut server_config: ServerConfig =
         serde_json::from_str(&body).context("解析 server.json 失败")?;
+    mirrors::save_server_mirrors(base_dir, &server_config.mirrors);
+    settings::save_server_overrides(base_dir, &server_config.settings);
+    branding::save_server_overrides(base_dir, &server_config.branding);
+    select_pack_channel(base_dir, &mut server_config);
 
     // 2. 拉取 pack.toml 并解析版本
     if !server_config.pack_url.starts_with("https://") {
         anyhow::bail!("pack_url 必须使用 HTTPS 协议: {}", server_config.pack_url);
     }
 
-    let pack_toml = agent
-        .get(&server_config.pack_url)
-        .call()
-        .context("无法获取 pack.toml，请检查网络")?
-        .body_mut()
-        .read_to_string()
-        .context("读取 pack.toml 失败")?;
+    let pack_toml = http_cache::get_text(base_dir, &server_config.pack_url, &[])
+        .context("无法获取 pack.toml，请检查网络")?;
 
-    let (mc_version, fabric_version) =
-        parse_pack_toml_versions(&pack_toml).context("从 pack.toml 解析版本信息失败")?;
+    build_remote_version(server_config, pack_toml)
+}
 
-    validate_version_string(&mc_version).context("minecraft 版本号包含非法字符")?;
-    validate_version_string(&fabric_version).context("fabric 版本号包含非法字符")?;
+/// 从本地目录 / 局域网来源获取 server.json 和 pack.toml（单次尝试）。
+///
+/// 来源中的 pack_url 可以是相对路径，但必须指向同一来源或使用 HTTPS。
+/// 远程来源的 server.json 与下载项一样必须位于 HTTPS 白名单主机上。
+fn fetch_from_source(
+    base_dir: &Path,
+    server_source: &ServerSource,
+    server_url: &str,
+) -> Result<RemoteVersion> {
+    if matches!(server_source, ServerSource::Remote { .. }) {
+        bootstrap::validate_download_url(server_url)
+            .with_context(|| format!("远程来源不可信: {server_url}"))?;
+    }
+    let body = source::read_text(base_dir, server_url)
+        .with_context(|| format!("无法读取 server.json: {server_url}"))?;
+    let server_config = parse_source_server_config(base_dir, server_source, server_url, &body)?;
 
-    // 3. 合并
-    let version_tag = format!("fabric-loader-{fabric_version}-{mc_version}");
+    let pack_toml = source::read_text(base_dir, &server_config.pack_url)
+        .context("无法获取 pack.toml，请检查来源是否可用")?;
+
+    build_remote_version(server_config, pack_toml)
+}
+
+/// 解析非公共来源的 server.json，并把其中的相对路径按 server.json 所在位置展开。
+fn parse_source_server_config(
+    base_dir: &Path,
+    server_source: &ServerSource,
+    server_url: &str,
+    body: &str,
+) -> Result<ServerConfig> {
+    let mut server_config: ServerConfig =
+        serde_json::from_str(body).context("解析 server.json 失败")?;
+    select_pack_channel(base_dir, &mut server_config);
+
+    let resolve = |url: &mut String| *url = source::resolve_relative(server_url, url);
+    resolve(&mut server_config.pack_url);
+    let downloads = &mut server_config.downloads;
+    for url in [
+        &mut downloads.jre_url,
+        &mut downloads.pcl2_url,
+        &mut downloads.packwiz_bootstrap_url,
+        &mut downloads.fabric_installer_url,
+        &mut downloads.settings_url,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        resolve(url);
+    }
+
+    if !server_config.pack_url.starts_with("https://")
+        && !server_source.allows(&server_config.pack_url)
+    {
+        anyhow::bail!(
+            "pack_url 必须使用 HTTPS 协议或指向当前来源: {}",
+            server_config.pack_url
+        );
+    }
+    Ok(server_config)
+}
+
+/// 读取上次成功获取的 server.json + pack.toml（HTTP 缓存），不发起网络请求。
+///
+/// 离线模式下用于确认本地整合包对应的版本；任一文件缺失或解析失败时返回 None。
+pub fn cached_remote_version(base_dir: &Path) -> Option<RemoteVersion> {
+    let server_source = source::resolve();
+    let (server_url, body) = cached_server_json(base_dir)?;
+    let server_config: ServerConfig = if server_source.is_public() {
+        let mut server_config = serde_json::from_str(&body).ok()?;
+        select_pack_channel(base_dir, &mut server_config);
+        server_config
+    } else {
+        parse_source_server_config(base_dir, &server_source, &server_url, &body).ok()?
+    };
+    let pack_toml = source::cached_text(base_dir, &server_config.pack_url)?;
+    build_remote_version(server_config, pack_toml).ok()
+}
+
+/// 查找上次获取的 server.json，返回 (URL, 内容)。
+///
+/// 公共来源按镜像顺序查找第一个有缓存的地址。
+pub fn cached_server_json(base_dir: &Path) -> Option<(String, String)> {
+    if let Some(url) = source::resolve().server_json_url() {
+        return source::cached_text(base_dir, &url).map(|body| (url, body));
+    }
+    mirrors::candidates(base_dir, Endpoint::ServerJson)
+        .into_iter()
+        .find_map(|url| http_cache::cached_text(base_dir, &url).map(|body| (url, body)))
+}
+
+/// 由 server.json 配置和 pack.toml 内容合成 RemoteVersion。
+fn build_remote_version(server_config: ServerConfig, pack_toml: String) -> Result<RemoteVersion> {
+    let (mc_version, fabric_version, version_tag) = pack_versions(&pack_toml)?;
 
     Ok(RemoteVersion {
         mc_version,
@@ -170,9 +326,65 @@ fn fetch_remote_version_inner() -> Result<RemoteVersion> {
         pack_url: server_config.pack_url,
         downloads: server_config.downloads,
         pack_toml_raw: pack_toml,
+        presets: server_config.presets,
+        mod_policy: server_config.mod_policy,
+        pack_channel: server_config.pack_channel,
+        #[cfg(windows)]
+        pack_channels: server_config.pack_channels,
+        profiles: server_config.profiles,
     })
 }
 
+/// 按玩家订阅的整合包通道替换 pack_url。
+///
+/// 服务器没有声明该通道（如测试结束后被移除）时回到默认通道。
+fn select_pack_channel(base_dir: &Path, server_config: &mut ServerConfig) {
+    let subscribed = config::load_pack_channel(base_dir);
+    let name = subscribed.name();
+    server_config.pack_channel = match server_config.pack_channels.get(name) {
+        Some(channel) => {
+            server_config.pack_url = channel.pack_url.clone();
+            name.to_string()
+        }
+        None => {
+            if name != config::DEFAULT_PACK_CHANNEL {
+                eprintln!("服务器没有整合包通道 {name}，使用 {}", config::DEFAULT_PACK_CHANNEL);
+            }
+            config::DEFAULT_PACK_CHANNEL.to_string()
+        }
+    };
+}
+
+/// 用另一份 pack.toml（如固定的旧版本整合包）替换远程版本中的整合包部分，
+/// server.json 下发的其余配置保持不变。
+pub(crate) fn with_pack(
+    remote: &RemoteVersion,
+    pack_url: String,
+    pack_toml: String,
+) -> Result<RemoteVersion> {
+    let (mc_version, fabric_version, version_tag) = pack_versions(&pack_toml)?;
+    Ok(RemoteVersion {
+        mc_version,
+        fabric_version,
+        version_tag,
+        pack_url,
+        pack_toml_raw: pack_toml,
+        ..remote.clone()
+    })
+}
+
+/// 从 pack.toml 解析并校验 (MC 版本, Fabric 版本, 版本文件夹名称)。
+fn pack_versions(pack_toml: &str) -> Result<(String, String, String)> {
+    let (mc_version, fabric_version) =
+        parse_pack_toml_versions(pack_toml).context("从 pack.toml 解析版本信息失败")?;
+
+    validate_version_string(&mc_version).context("minecraft 版本号包含非法字符")?;
+    validate_version_string(&fabric_version).context("fabric 版本号包含非法字符")?;
+
+    let version_tag = format!("fabric-loader-{fabric_version}-{mc_version}");
+    Ok((mc_version, fabric_version, version_tag))
+}
+
 /// 校验版本字符串是否安全用于文件路径。
 ///
 /// 拒绝包含路径分隔符（`/`、`\`）或遍历序列（`..`）的值，
@@ -188,6 +400,19 @@ fn validate_version_string(version: &str) -> Result<()> {
     Ok(())
 }
 
+/// pack.toml（只取 [versions] 段）
+#[derive(Deserialize)]
+struct PackTomlVersions {
+    #[serde(default)]
+    versions: VersionsToml,
+}
+
+#[derive(Deserialize, Default)]
+struct VersionsToml {
+    minecraft: Option<String>,
+    fabric: Option<String>,
+}
+
 /// 从 pack.toml 文本中解析 minecraft 和 fabric 版本。
 ///
 /// pack.toml 格式示例：
@@ -197,100 +422,35 @@ fn validate_version_string(version: &str) -> Result<()> {
 /// minecraft = "1.21.11"
 /// ```
 ///
-/// 使用简单字符串解析，不需要完整的 TOML 解析器。
-fn parse_pack_toml_versions(toml_text: &str) -> Result<(String, String)> {
-    let mut mc_version: Option<String> = None;
-    let mut fabric_version: Option<String> = None;
-    let mut in_versions_section = false;
-
-    for line in toml_text.lines() {
-        let trimmed = line.trim();
-
-        // 检测 [versions] 段
-        if trimmed == "[versions]" {
-            in_versions_section = true;
-            continue;
-        }
-
-        // 遇到新的段落 [xxx]，退出 versions 段
-        if trimmed.starts_with('[') && in_versions_section {
-            break;
-        }
-
-        if in_versions_section {
-            if let Some(value) = extract_toml_value(trimmed, "minecraft") {
-                mc_version = Some(value);
-            }
-            if let Some(value) = extract_toml_value(trimmed, "fabric") {
-                fabric_version = Some(value);
-            }
-        }
-    }
-
-    let mc = mc_version.context("pack.toml 中找不到 minecraft 版本")?;
-    let fabric = fabric_version.context("pack.toml 中找不到 fabric 版本")?;
+/// 与 pack_index 读取 [index] 段一样用 toml 解析整个文件，两处不会对同一文件有不同理解。
+pub(crate) fn parse_pack_toml_versions(toml_text: &str) -> Result<(String, String)> {
+    let pack: PackTomlVersions =
+        toml::from_str(toml_text).context("解析 pack.toml 的 [versions] 段失败")?;
+    let mc = pack
+        .versions
+        .minecraft
+        .context("pack.toml 中找不到 minecraft 版本")?;
+    let fabric = pack
+        .versions
+        .fabric
+        .context("pack.toml 中找不到 fabric 版本")?;
 
     Ok((mc, fabric))
 }
 
-/// 从 TOML 行中提取 `key = "value"` 形式的值
-fn extract_toml_value(line: &str, key: &str) -> Option<String> {
-    let line = line.trim();
-    if !line.starts_with(key) {
-        return None;
-    }
-
-    let rest = line[key.len()..].trim();
-    if !rest.starts_with('=') {
-        return None;
-    }
-
-    let value_part = rest[1..].trim();
-
-    // 处理带引号的字符串值：提取第一对引号之间的内容
-    // 这样可以正确忽略行内注释，如 key = "value" # comment
-    if let Some(stripped) = value_part.strip_prefix('"') {
-        let end = stripped.find('"').unwrap_or(stripped.len());
-        return Some(stripped[..end].to_string());
-    }
-
-    if let Some(stripped) = value_part.strip_prefix('\'') {
-        let end = stripped.find('\'').unwrap_or(stripped.len());
-        return Some(stripped[..end].to_string());
-    }
-
-    // 无引号的值：截断行内注释
-    let value = if let Some(hash_pos) = value_part.find('#') {
-        value_part[..hash_pos].trim()
-    } else {
-        value_part
-    };
-    Some(value.to_string())
-}
-
 /// 读取本地 local.json。
 ///
 /// 如果文件不存在（首次运行），返回一个空的 LocalVersion，
 /// 这样对比时一定会触发完整安装。
 pub fn read_local_version(base_dir: &Path) -> LocalVersion {
-    let path = base_dir.join(config::LOCAL_VERSION_FILE);
-    match fs::read_to_string(&path) {
-        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
-        Err(_) => LocalVersion::default(),
-    }
+    state::LOCAL_VERSION.read_json(base_dir).unwrap_or_default()
 }
 
 /// 将当前版本信息写入 local.json，供下次启动时对比。
 pub fn save_local_version(base_dir: &Path, version: &LocalVersion) -> Result<()> {
-    let path = base_dir.join(config::LOCAL_VERSION_FILE);
-
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).context("创建 updater 目录失败")?;
-    }
-
-    let json = serde_json::to_string_pretty(version).context("序列化版本信息失败")?;
-    fs::write(&path, json).context("写入 local.json 失败")?;
-    Ok(())
+    state::LOCAL_VERSION
+        .write_json(base_dir, version)
+        .context("写入 local.json 失败")
 }
 
 /// 判断是否需要升级 Minecraft / Fabric 版本。
@@ -303,26 +463,48 @@ pub fn needs_version_upgrade(remote: &RemoteVersion, local: &LocalVersion) -> bo
 /// 判断 pack.toml 是否有变化（用于跳过无变化的 packwiz 同步）。
 /// 对比远程 pack.toml 内容与本地缓存，内容一致则无需同步。
 pub fn is_pack_changed(base_dir: &Path, remote_pack_toml: &str) -> bool {
-    let cache_path = base_dir.join(config::PACK_TOML_CACHE_FILE);
-    match fs::read_to_string(&cache_path) {
-        Ok(cached) => cached != remote_pack_toml,
-        Err(_) => true,
-    }
+    read_pack_cache(base_dir).is_none_or(|cached| cached != remote_pack_toml)
+}
+
+/// 上次同步的 pack.toml 原文
+pub fn read_pack_cache(base_dir: &Path) -> Option<String> {
+    state::PACK_TOML_CACHE.read_text(base_dir)
 }
 
 /// 保存 pack.toml 内容缓存，供下次启动对比。
 pub fn save_pack_cache(base_dir: &Path, pack_toml: &str) -> Result<()> {
-    let cache_path = base_dir.join(config::PACK_TOML_CACHE_FILE);
-    if let Some(parent) = cache_path.parent() {
-        fs::create_dir_all(parent).context("创建 updater 目录失败")?;
-    }
-    fs::write(&cache_path, pack_toml).context("写入 pack.toml 缓存失败")?;
-    Ok(())
+    state::PACK_TOML_CACHE
+        .write_text(base_dir, pack_toml)
+        .context("写入 pack.toml 缓存失败")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::http::fake;
+
+    #[test]
+    fn fetch_remote_version_from_fake_server() {
+        let base_dir = fake::temp_dir("version");
+        let fake = fake::fixture().install();
+
+        let remote = fetch_remote_version(&base_dir).unwrap();
+        assert_eq!(remote.mc_version, fake::MC_VERSION);
+        assert_eq!(remote.fabric_version, fake::FABRIC_VERSION);
+        assert_eq!(remote.pack_url, fake::PACK_URL);
+        assert_eq!(remote.pack_channel, config::DEFAULT_PACK_CHANNEL);
+        assert_eq!(
+            fake.server.requested_urls(),
+            vec![config::REMOTE_SERVER_JSON_URL.to_string(), fake::PACK_URL.to_string()]
+        );
+
+        // pack.toml 不存在时不重试（404 不可重试），错误中保留提示
+        fake.server.set(fake::PACK_URL, 404, &[], "");
+        let err = fetch_remote_version(&base_dir).unwrap_err();
+        assert!(format!("{err:#}").contains("pack.toml"));
+
+        std::fs::remove_dir_all(&base_dir).ok();
+    }
 
     // ── parse_pack_toml_versions ──
 
@@ -388,59 +570,23 @@ fabric = "0.15.0" # required
         assert!(result.is_err());
     }
 
-    // ── extract_toml_value ──
-
-    #[test]
-    fn extract_double_quoted_value() {
-        assert_eq!(
-            extract_toml_value(r#"minecraft = "1.21.11""#, "minecraft"),
-            Some("1.21.11".to_string())
-        );
-    }
-
-    #[test]
-    fn extract_single_quoted_value() {
-        assert_eq!(
-            extract_toml_value("minecraft = '1.21.11'", "minecraft"),
-            Some("1.21.11".to_string())
-        );
-    }
-
     #[test]
-    fn extract_unquoted_value() {
-        assert_eq!(
-            extract_toml_value("count = 42", "count"),
-            Some("42".to_string())
-        );
-    }
-
-    #[test]
-    fn extract_value_with_comment() {
-        assert_eq!(
-            extract_toml_value("count = 42 # answer", "count"),
-            Some("42".to_string())
-        );
-    }
+    fn parse_versions_with_toml_syntax() {
+        // 字面量字符串、引号内的 #、内联表都按 TOML 规则解析
+        let toml = "versions = { minecraft = '1.21.1', fabric = \"0.16.0\" } # pinned\n";
+        let (mc, fabric) = parse_pack_toml_versions(toml).unwrap();
+        assert_eq!(mc, "1.21.1");
+        assert_eq!(fabric, "0.16.0");
 
-    #[test]
-    fn extract_no_match_different_key() {
-        assert_eq!(extract_toml_value("fabric = \"0.18\"", "minecraft"), None);
+        let toml = "[versions]\nminecraft = \"1.21#1\"\nfabric = \"0.16.0\"";
+        assert_eq!(parse_pack_toml_versions(toml).unwrap().0, "1.21#1");
     }
 
     #[test]
-    fn extract_no_false_prefix_match() {
-        assert_eq!(
-            extract_toml_value("fabric_loader = \"0.18\"", "fabric"),
-            None
-        );
-    }
-
-    #[test]
-    fn extract_with_extra_spaces() {
-        assert_eq!(
-            extract_toml_value("  minecraft  =  \"1.21.11\"  ", "minecraft"),
-            Some("1.21.11".to_string())
-        );
+    fn parse_rejects_invalid_toml() {
+        let toml = "[versions]\nminecraft = 1.21\nfabric = \"0.16.0\"";
+        assert!(parse_pack_toml_versions(toml).is_err());
+        assert!(parse_pack_toml_versions("[versions\nminecraft = \"1.21.1\"").is_err());
     }
 
     // ── needs_version_upgrade ──
@@ -454,6 +600,12 @@ fabric = "0.15.0" # required
             pack_url: "".into(),
             downloads: Downloads::default(),
             pack_toml_raw: "".into(),
+            presets: Vec::new(),
+            mod_policy: None,
+            pack_channel: config::DEFAULT_PACK_CHANNEL.into(),
+            #[cfg(windows)]
+            pack_channels: BTreeMap::new(),
+            profiles: Vec::new(),
         };
         let local = LocalVersion {
             mc_version: "1.20.4".into(),
@@ -472,6 +624,12 @@ fabric = "0.15.0" # required
             pack_url: "".into(),
             downloads: Downloads::default(),
             pack_toml_raw: "".into(),
+            presets: Vec::new(),
+            mod_policy: None,
+            pack_channel: config::DEFAULT_PACK_CHANNEL.into(),
+            #[cfg(windows)]
+            pack_channels: BTreeMap::new(),
+            profiles: Vec::new(),
         };
         let local = LocalVersion {
             mc_version: "1.21.11".into(),
@@ -490,6 +648,12 @@ fabric = "0.15.0" # required
             pack_url: "".into(),
             downloads: Downloads::default(),
             pack_toml_raw: "".into(),
+            presets: Vec::new(),
+            mod_policy: None,
+            pack_channel: config::DEFAULT_PACK_CHANNEL.into(),
+            #[cfg(windows)]
+            pack_channels: BTreeMap::new(),
+            profiles: Vec::new(),
         };
         let local = LocalVersion {
             mc_version: "1.21.11".into(),
@@ -499,6 +663,47 @@ fabric = "0.15.0" # required
         assert!(!needs_version_upgrade(&remote, &local));
     }
 
+    // ── select_pack_channel ──
+
+    #[test]
+    fn subscribed_pack_channel_replaces_pack_url() {
+        let dir = std::env::temp_dir().join(format!("upmc_pack_channel_{}", std::process::id()));
+        let json = r#"{
+            "pack_url": "https://example.com/stable/pack.toml",
+            "pack_channels": { "beta": { "pack_url": "https://example.com/beta/pack.toml" } }
+        }"#;
+
+        let mut server_config: ServerConfig = serde_json::from_str(json).unwrap();
+        select_pack_channel(&dir, &mut server_config);
+        assert_eq!(server_config.pack_channel, "stable");
+        assert_eq!(server_config.pack_url, "https://example.com/stable/pack.toml");
+
+        let subscribe = |channel: &str| {
+            config::save_pack_channel(
+                &dir,
+                &config::PackChannelConfig {
+                    channel: Some(channel.to_string()),
+                    synced: None,
+                },
+            )
+            .unwrap();
+        };
+        subscribe("beta");
+        let mut server_config: ServerConfig = serde_json::from_str(json).unwrap();
+        select_pack_channel(&dir, &mut server_config);
+        assert_eq!(server_config.pack_channel, "beta");
+        assert_eq!(server_config.pack_url, "https://example.com/beta/pack.toml");
+
+        // 通道被服务器移除后回到 stable
+        subscribe("removed");
+        let mut server_config: ServerConfig = serde_json::from_str(json).unwrap();
+        select_pack_channel(&dir, &mut server_config);
+        assert_eq!(server_config.pack_channel, "stable");
+        assert_eq!(server_config.pack_url, "https://example.com/stable/pack.toml");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
     // ── validate_version_string ──
 
     #[test]
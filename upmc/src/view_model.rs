@@ -0,0 +1,637 @@
+// ============================================================
+// view_model.rs — 主窗口状态机（与 nwg 控件无关）
+// ============================================================
+// 后台任务（更新 / 代理 / 修复）产生的进度和结果作为 Event 依次输入，
+// 状态机维护：
+//   - 当前要显示的画面 View（状态文本、进度、提示、按钮）
+//   - 日志（出错时整段显示给玩家）
+//   - 代理是否在运行
+// 并输出要执行的 Effect（弹窗、退出程序）。
+//
+// gui.rs 只负责把 View 画到控件上、执行 Effect，
+// 所有界面流程都可以在任意平台上单元测试。
+// ============================================================
+
+use crate::changelog::Changelog;
+use crate::config;
+use crate::mod_policy::ScannedMod;
+use crate::update::Progress;
+
+/// 后台任务种类（决定初始画面和日志前缀）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Task {
+    /// 更新当前整合包
+    Update,
+    /// 设置并启动 Discord 代理
+    ProxySetup,
+    /// 停止 Discord 代理
+    ProxyStop,
+    /// 校验并修复安装
+    Repair,
+}
+
+impl Task {
+    fn log_prefix(self) -> &'static str {
+        match self {
+            Task::Update => "",
+            Task::ProxySetup | Task::ProxyStop => "[代理]",
+            Task::Repair => "[修复]",
+        }
+    }
+}
+
+/// 后台任务的结果
+#[derive(Debug, Clone)]
+pub enum FinishState {
+    /// 更新成功，proxy_running = 代理是否已自动启动，warnings = 需要提示玩家的问题，
+    /// violations = 违反模组策略的模组，changelog = 本次整合包更新的变更
+    Success {
+        proxy_running: bool,
+        warnings: Vec<String>,
+        violations: Vec<ScannedMod>,
+        changelog: Option<Changelog>,
+    },
+    /// 网络不可用，跳过更新
+    Offline { violations: Vec<ScannedMod> },
+    /// 更新器已自更新并重启新进程，当前进程仅需退出
+    SelfUpdateRestarting,
+    /// Java 未安装，显示友好安装指引
+    JavaNotFound,
+    /// 更新出错（错误信息, 解决办法）
+    Error(String, &'static str),
+    /// Discord 代理设置成功
+    ProxySuccess,
+    /// Discord 代理设置失败（错误信息, 解决办法）
+    ProxyError(String, &'static str),
+    /// Discord 代理已停止
+    ProxyStopped,
+    /// 修复完成，附逐文件报告
+    RepairDone(String),
+    /// 修复失败（错误信息, 解决办法）
+    RepairError(String, &'static str),
+}
+
+/// 状态机的输入
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// GUI 线程启动后台任务前发出
+    Started(Task),
+    /// 后台任务的进度
+    Progress(Progress),
+    /// 后台任务结束
+    Finished(FinishState),
+    /// 玩家已处理违规模组提示（Effect::ReviewViolations 之后发出）
+    ViolationsReviewed,
+}
+
+/// 主窗口要显示的内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct View {
+    pub status: String,
+    /// 进度百分比 (0-100)
+    pub percent: u32,
+    /// 底部提示，None 时隐藏
+    pub hint: Option<String>,
+    /// 「启动 PCL」「代理」「设置」按钮是否显示
+    pub actions_visible: bool,
+    /// 代理按钮是否可点（停止代理过程中禁用）
+    pub proxy_enabled: bool,
+    /// 代理按钮文字
+    pub proxy_label: &'static str,
+    /// 整合包下拉框和「添加...」是否可用
+    pub profiles_enabled: bool,
+}
+
+/// 状态机要求 GUI 执行的动作，按顺序执行
+#[derive(Debug, Clone)]
+pub enum Effect {
+    /// 提示违反模组策略的模组，处理完后发出 Event::ViolationsReviewed
+    ReviewViolations(Vec<ScannedMod>),
+    /// 可复制文本窗口（更新日志、错误日志、修复报告等）
+    ShowText {
+        title: &'static str,
+        hint: &'static str,
+        text: String,
+    },
+    /// 简单提示框
+    Message { title: &'static str, text: String },
+    /// 退出程序
+    Quit,
+}
+
+/// 等玩家处理完违规模组再显示的更新结果
+#[derive(Debug, Clone)]
+struct PendingSuccess {
+    proxy_running: bool,
+    warnings: Vec<String>,
+    changelog: Option<Changelog>,
+}
+
+/// 主窗口状态机
+#[derive(Debug)]
+pub struct ViewModel {
+    view: View,
+    task: Task,
+    proxy_running: bool,
+    log: Vec<String>,
+    pending: Option<PendingSuccess>,
+}
+
+impl Default for ViewModel {
+    fn default() -> Self {
+        Self {
+            view: View {
+                status: "正在初始化...".to_string(),
+                percent: 0,
+                hint: None,
+                actions_visible: false,
+                proxy_enabled: false,
+                proxy_label: proxy_label(false),
+                profiles_enabled: false,
+            },
+            task: Task::Update,
+            proxy_running: false,
+            log: Vec::new(),
+            pending: None,
+        }
+    }
+}
+
+fn proxy_label(running: bool) -> &'static str {
+    if running {
+        "停止代理"
+    } else {
+        "启用代理"
+    }
+}
+
+impl ViewModel {
+    pub fn view(&self) -> &View {
+        &self.view
+    }
+
+    /// 代理是否在运行（决定代理按钮是启用还是停止）
+    pub fn proxy_running(&self) -> bool {
+        self.proxy_running
+    }
+
+    /// 输入一个事件，返回需要执行的动作
+    pub fn apply(&mut self, event: Event) -> Vec<Effect> {
+        match event {
+            Event::Started(task) => {
+                self.start(task);
+                Vec::new()
+            }
+            Event::Progress(progress) => {
+                self.log.push(format!(
+                    "{}[{}%] {}",
+                    self.task.log_prefix(),
+                    progress.percent,
+                    progress.message
+                ));
+                self.view.percent = progress.percent;
+                self.view.status = progress.message;
+                Vec::new()
+            }
+            Event::Finished(finish) => self.finish(finish),
+            Event::ViolationsReviewed => match self.pending.take() {
+                Some(pending) => self.show_success(pending),
+                None => Vec::new(),
+            },
+        }
+    }
+
+    fn start(&mut self, task: Task) {
+        self.task = task;
+        self.pending = None;
+        self.view.profiles_enabled = false;
+        match task {
+            Task::ProxyStop => {
+                // 停止代理很快，保留按钮和日志，只禁用代理按钮
+                self.view.status = "正在停止代理...".to_string();
+                self.view.proxy_enabled = false;
+            }
+            Task::Update | Task::ProxySetup | Task::Repair => {
+                self.log.clear();
+                self.view.percent = 0;
+                self.view.actions_visible = false;
+                let (status, hint) = match task {
+                    Task::Update => ("正在初始化...", Some("请勿关闭此窗口...")),
+                    Task::Repair => ("正在校验安装...", Some("请勿关闭此窗口...")),
+                    _ => ("正在设置 Discord 代理...", None),
+                };
+                self.view.status = status.to_string();
+                self.view.hint = hint.map(str::to_string);
+            }
+        }
+    }
+
+    fn finish(&mut self, finish: FinishState) -> Vec<Effect> {
+        match finish {
+            FinishState::Success {
+                proxy_running,
+                warnings,
+                violations,
+                changelog,
+            } => {
+                self.log.push("[完成] 更新成功".to_string());
+                self.review_then_show(
+                    violations,
+                    PendingSuccess {
+                        proxy_running,
+                        warnings,
+                        changelog,
+                    },
+                )
+            }
+            FinishState::Offline { violations } => {
+                self.log.push("[完成] 离线模式".to_string());
+                self.review_then_show(
+                    violations,
+                    PendingSuccess {
+                        proxy_running: false,
+                        warnings: Vec::new(),
+                        changelog: None,
+                    },
+                )
+            }
+            FinishState::SelfUpdateRestarting => {
+                self.log
+                    .push("[重启] 更新器已更新，正在重启...".to_string());
+                vec![Effect::Quit]
+            }
+            FinishState::JavaNotFound => {
+                self.view.percent = 0;
+                self.view.status = "需要安装 Java".to_string();
+                self.view.hint = Some("请安装 Java 后重新运行程序".to_string());
+                vec![
+                    Effect::Message {
+                        title: "需要安装 Java",
+                        text: format!(
+                            "未检测到系统 Java 环境。\n\
+                             请安装 Java 后重新运行程序。\n\n\
+                             下载地址（如未自动打开请手动访问）：\n{}",
+                            config::JAVA_DOWNLOAD_URL
+                        ),
+                    },
+                    Effect::Quit,
+                ]
+            }
+            FinishState::Error(message, hint) => {
+                self.log.push(format!("[错误] {message}"));
+                self.log.push(format!("[建议] {hint}"));
+                self.view.percent = 0;
+                self.view.status = format!("更新失败: {message}");
+                self.view.hint = Some(hint.to_string());
+                vec![self.error_log(), Effect::Quit]
+            }
+            FinishState::ProxySuccess => {
+                self.proxy_running = true;
+                self.show_actions(
+                    "Discord 代理已启用",
+                    Some("Xray 已在后台运行，Discord 已配置代理"),
+                );
+                Vec::new()
+            }
+            FinishState::ProxyStopped => {
+                self.proxy_running = false;
+                self.show_actions("代理已停止", None);
+                Vec::new()
+            }
+            FinishState::ProxyError(message, hint) => {
+                self.log.push(format!("[代理][错误] {message}"));
+                self.proxy_running = false;
+                self.show_actions(&format!("代理设置失败: {message}"), Some(hint));
+                self.view.percent = 0;
+                vec![self.error_log()]
+            }
+            FinishState::RepairDone(table) => {
+                let status = self.view.status.clone();
+                self.show_actions(&status, None);
+                vec![Effect::ShowText {
+                    title: "修复结果",
+                    hint: "校验与修复结果（可全选复制）：",
+                    text: table,
+                }]
+            }
+            FinishState::RepairError(message, hint) => {
+                self.log.push(format!("[修复][错误] {message}"));
+                self.show_actions(&format!("修复失败: {message}"), Some(hint));
+                self.view.percent = 0;
+                vec![self.error_log()]
+            }
+        }
+    }
+
+    /// 先让玩家看到违规模组，再启用「启动 PCL」
+    fn review_then_show(
+        &mut self,
+        violations: Vec<ScannedMod>,
+        pending: PendingSuccess,
+    ) -> Vec<Effect> {
+        if violations.is_empty() {
+            return self.show_success(pending);
+        }
+        self.pending = Some(pending);
+        vec![Effect::ReviewViolations(violations)]
+    }
+
+    fn show_success(&mut self, pending: PendingSuccess) -> Vec<Effect> {
+        self.proxy_running = pending.proxy_running;
+        if pending.proxy_running {
+            self.show_actions("更新完成，代理已就绪", Some("Xray 已在后台运行"));
+        } else {
+            self.show_actions("更新完成", None);
+        }
+
+        let mut effects = Vec::new();
+        if let Some(log) = pending.changelog {
+            effects.push(Effect::ShowText {
+                title: "更新内容",
+                hint: "本次整合包更新：",
+                text: log.render(),
+            });
+        }
+        if !pending.warnings.is_empty() {
+            effects.push(Effect::ShowText {
+                title: "模组冲突",
+                hint: "以下自行添加的模组与整合包重复，可能导致游戏崩溃：",
+                text: pending.warnings.join("\r\n"),
+            });
+        }
+        effects
+    }
+
+    /// 任务结束后的空闲画面：显示操作按钮，可选设置提示文本
+    fn show_actions(&mut self, status: &str, hint: Option<&str>) {
+        self.view = View {
+            status: status.to_string(),
+            percent: 100,
+            hint: hint.map(str::to_string),
+            actions_visible: true,
+            proxy_enabled: true,
+            proxy_label: proxy_label(self.proxy_running),
+            profiles_enabled: true,
+        };
+    }
+
+    fn error_log(&self) -> Effect {
+        Effect::ShowText {
+            title: "错误日志",
+            hint: "以下是完整日志（可全选复制）：",
+            text: self.log.join("\r\n"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mod_policy::Verdict;
+
+    fn progress(percent: u32, message: &str) -> Event {
+        Event::Progress(Progress::new(percent, message))
+    }
+
+    fn success(proxy_running: bool, violations: Vec<ScannedMod>) -> Event {
+        Event::Finished(FinishState::Success {
+            proxy_running,
+            warnings: Vec::new(),
+            violations,
+            changelog: None,
+        })
+    }
+
+    fn banned_mod() -> ScannedMod {
+        ScannedMod {
+            file: "xray-mod.jar".to_string(),
+            id: "xraymod".to_string(),
+            version: "1.0".to_string(),
+            sha256: String::new(),
+            verdict: Verdict::Denied,
+            quarantined: false,
+        }
+    }
+
+    fn updated(proxy_running: bool) -> ViewModel {
+        let mut vm = ViewModel::default();
+        vm.apply(Event::Started(Task::Update));
+        vm.apply(success(proxy_running, Vec::new()));
+        vm
+    }
+
+    fn error_log_text(effects: &[Effect]) -> Option<&str> {
+        effects.iter().find_map(|effect| match effect {
+            Effect::ShowText {
+                title: "错误日志",
+                text,
+                ..
+            } => Some(text.as_str()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn update_progress_is_shown_and_logged() {
+        let mut vm = ViewModel::default();
+        vm.apply(Event::Started(Task::Update));
+        assert_eq!(vm.view().hint.as_deref(), Some("请勿关闭此窗口..."));
+        assert!(!vm.view().actions_visible);
+        assert!(!vm.view().profiles_enabled);
+
+        assert!(vm.apply(progress(40, "正在同步模组...")).is_empty());
+        assert_eq!(vm.view().percent, 40);
+        assert_eq!(vm.view().status, "正在同步模组...");
+        assert_eq!(vm.log, ["[40%] 正在同步模组..."]);
+    }
+
+    #[test]
+    fn update_success_shows_actions() {
+        let vm = updated(false);
+        let view = vm.view();
+        assert_eq!(view.status, "更新完成");
+        assert_eq!(view.percent, 100);
+        assert_eq!(view.hint, None);
+        assert!(view.actions_visible && view.proxy_enabled && view.profiles_enabled);
+        assert_eq!(view.proxy_label, "启用代理");
+        assert!(!vm.proxy_running());
+    }
+
+    #[test]
+    fn update_success_with_running_proxy() {
+        let vm = updated(true);
+        assert_eq!(vm.view().status, "更新完成，代理已就绪");
+        assert_eq!(vm.view().proxy_label, "停止代理");
+        assert!(vm.proxy_running());
+    }
+
+    #[test]
+    fn violations_are_reviewed_before_actions() {
+        let mut vm = ViewModel::default();
+        vm.apply(Event::Started(Task::Update));
+        let effects = vm.apply(Event::Finished(FinishState::Success {
+            proxy_running: false,
+            warnings: vec!["mods/dup.jar".to_string()],
+            violations: vec![banned_mod()],
+            changelog: None,
+        }));
+        assert!(matches!(effects.as_slice(), [Effect::ReviewViolations(v)] if v.len() == 1));
+        assert!(!vm.view().actions_visible);
+
+        let effects = vm.apply(Event::ViolationsReviewed);
+        assert!(vm.view().actions_visible);
+        assert!(matches!(
+            effects.as_slice(),
+            [Effect::ShowText {
+                title: "模组冲突",
+                ..
+            }]
+        ));
+
+        // 重复的确认不会再次显示
+        assert!(vm.apply(Event::ViolationsReviewed).is_empty());
+    }
+
+    #[test]
+    fn offline_shows_actions() {
+        let mut vm = ViewModel::default();
+        vm.apply(Event::Started(Task::Update));
+        let effects = vm.apply(Event::Finished(FinishState::Offline {
+            violations: Vec::new(),
+        }));
+        assert!(effects.is_empty());
+        assert!(vm.view().actions_visible);
+        assert_eq!(vm.log.last().map(String::as_str), Some("[完成] 离线模式"));
+    }
+
+    #[test]
+    fn update_error_shows_log_and_quits() {
+        let mut vm = ViewModel::default();
+        vm.apply(Event::Started(Task::Update));
+        vm.apply(progress(12, "正在连接更新服务器..."));
+        let effects = vm.apply(Event::Finished(FinishState::Error(
+            "连接超时".to_string(),
+            "请检查网络后重试",
+        )));
+
+        assert_eq!(vm.view().status, "更新失败: 连接超时");
+        assert_eq!(vm.view().hint.as_deref(), Some("请检查网络后重试"));
+        assert_eq!(vm.view().percent, 0);
+        assert_eq!(
+            error_log_text(&effects),
+            Some("[12%] 正在连接更新服务器...\r\n[错误] 连接超时\r\n[建议] 请检查网络后重试")
+        );
+        assert!(matches!(effects.last(), Some(Effect::Quit)));
+    }
+
+    #[test]
+    fn java_missing_shows_download_url_and_quits() {
+        let mut vm = ViewModel::default();
+        vm.apply(Event::Started(Task::Update));
+        let effects = vm.apply(Event::Finished(FinishState::JavaNotFound));
+        assert_eq!(vm.view().status, "需要安装 Java");
+        assert!(matches!(
+            effects.as_slice(),
+            [Effect::Message { title: "需要安装 Java", text }, Effect::Quit]
+                if text.contains(config::JAVA_DOWNLOAD_URL)
+        ));
+    }
+
+    #[test]
+    fn self_update_quits() {
+        let mut vm = ViewModel::default();
+        vm.apply(Event::Started(Task::Update));
+        let effects = vm.apply(Event::Finished(FinishState::SelfUpdateRestarting));
+        assert!(matches!(effects.as_slice(), [Effect::Quit]));
+    }
+
+    #[test]
+    fn proxy_setup_success_then_stop() {
+        let mut vm = updated(false);
+
+        vm.apply(Event::Started(Task::ProxySetup));
+        assert!(!vm.view().actions_visible);
+        assert_eq!(vm.view().hint, None);
+        vm.apply(progress(50, "正在启动 Xray..."));
+        assert_eq!(vm.log, ["[代理][50%] 正在启动 Xray..."]);
+
+        assert!(
+            vm.apply(Event::Finished(FinishState::ProxySuccess))
+                .is_empty()
+        );
+        assert!(vm.proxy_running());
+        assert_eq!(vm.view().status, "Discord 代理已启用");
+        assert_eq!(vm.view().proxy_label, "停止代理");
+
+        vm.apply(Event::Started(Task::ProxyStop));
+        assert!(vm.view().actions_visible);
+        assert!(!vm.view().proxy_enabled);
+        assert_eq!(vm.view().status, "正在停止代理...");
+
+        vm.apply(Event::Finished(FinishState::ProxyStopped));
+        assert!(!vm.proxy_running());
+        assert!(vm.view().proxy_enabled);
+        assert_eq!(vm.view().status, "代理已停止");
+        assert_eq!(vm.view().proxy_label, "启用代理");
+    }
+
+    #[test]
+    fn proxy_setup_failure_keeps_actions_and_shows_log() {
+        let mut vm = updated(false);
+        vm.apply(Event::Started(Task::ProxySetup));
+        let effects = vm.apply(Event::Finished(FinishState::ProxyError(
+            "SOCKS5 端口未就绪".to_string(),
+            "请检查网络后重试",
+        )));
+
+        assert!(!vm.proxy_running());
+        let view = vm.view();
+        assert_eq!(view.status, "代理设置失败: SOCKS5 端口未就绪");
+        assert_eq!(view.hint.as_deref(), Some("请检查网络后重试"));
+        assert_eq!(view.percent, 0);
+        assert!(view.actions_visible && view.proxy_enabled);
+        assert_eq!(view.proxy_label, "启用代理");
+        assert_eq!(
+            error_log_text(&effects),
+            Some("[代理][错误] SOCKS5 端口未就绪")
+        );
+        assert!(!effects.iter().any(|e| matches!(e, Effect::Quit)));
+    }
+
+    #[test]
+    fn repair_done_shows_report() {
+        let mut vm = updated(false);
+        vm.apply(Event::Started(Task::Repair));
+        assert_eq!(vm.view().status, "正在校验安装...");
+        vm.apply(progress(100, "修复完成：替换 2 个文件"));
+        let effects = vm.apply(Event::Finished(FinishState::RepairDone(
+            "table".to_string(),
+        )));
+
+        assert_eq!(vm.view().status, "修复完成：替换 2 个文件");
+        assert!(vm.view().actions_visible);
+        assert!(matches!(
+            effects.as_slice(),
+            [Effect::ShowText { title: "修复结果", hint, text }]
+                if text == "table" && hint.contains("可全选复制")
+        ));
+    }
+
+    #[test]
+    fn repair_error_shows_log() {
+        let mut vm = updated(false);
+        vm.apply(Event::Started(Task::Repair));
+        vm.apply(progress(30, "正在校验模组..."));
+        let effects = vm.apply(Event::Finished(FinishState::RepairError(
+            "下载失败".to_string(),
+            "请检查网络后重试",
+        )));
+
+        assert_eq!(vm.view().status, "修复失败: 下载失败");
+        assert_eq!(vm.view().percent, 0);
+        assert_eq!(
+            error_log_text(&effects),
+            Some("[修复][30%] 正在校验模组...\r\n[修复][错误] 下载失败")
+        );
+    }
+}
@@ -10,13 +10,20 @@
 
 use anyhow::{bail, Context, Result};
 use serde::Deserialize;
-use std::os::windows::process::CommandExt;
 use std::path::Path;
 use std::time::Duration;
 
 use crate::bootstrap;
 use crate::config;
+use crate::error::{self, ErrorCode};
+#[cfg(windows)]
+use crate::http::{self, RequestKind};
+use crate::http_cache;
+use crate::mirrors::{self, Endpoint};
+use crate::platform::{self, HideWindow};
 use crate::retry;
+use crate::settings;
+use crate::state;
 use crate::update::Progress;
 
 // ── GitHub Release API ─────────────────────────────────────
@@ -36,6 +43,7 @@ struct GithubAsset {
 // ── VLESS 配置 ─────────────────────────────────────────────
 
 /// 从订阅 URL 解析出的 VLESS REALITY 代理配置
+#[cfg(any(windows, test))]
 pub struct VlessConfig {
     pub uuid: String,
     pub address: String,
@@ -59,13 +67,12 @@ pub fn download_or_update(base_dir: &Path, on_progress: &dyn Fn(Progress)) -> Re
     let xray_dir = base_dir.join(config::XRAY_DIR);
     std::fs::create_dir_all(&xray_dir).context("创建 Xray 目录失败")?;
 
-    let xray_exe = xray_dir.join("xray.exe");
-    let version_file = xray_dir.join("version.txt");
-    let local_ver = std::fs::read_to_string(&version_file).unwrap_or_default();
+    let xray_exe = xray_dir.join(platform::exe_name("xray"));
+    let local_ver = state::XRAY_VERSION.read_text(base_dir).unwrap_or_default();
 
     // 查询最新 Release
     on_progress(Progress::new(5, "检查 Xray 最新版本..."));
-    let release = fetch_latest_release()?;
+    let release = fetch_latest_release(base_dir)?;
 
     if local_ver.trim() == release.tag_name && xray_exe.exists() {
         on_progress(Progress::new(10, "Xray 已是最新版本"));
@@ -73,21 +80,22 @@ pub fn download_or_update(base_dir: &Path, on_progress: &dyn Fn(Progress)) -> Re
     }
 
     // 找到对应平台的 asset
+    let (os, arch) = (std::env::consts::OS, std::env::consts::ARCH);
+    let asset_name = asset_name(os, arch)
+        .with_context(|| format!("Xray 不提供当前平台（{os}/{arch}）的版本"))?;
     let asset = release
         .assets
         .iter()
-        .find(|a| a.name == config::XRAY_ASSET_NAME)
-        .context(format!(
-            "Xray Release 中未找到 {} 资产",
-            config::XRAY_ASSET_NAME
-        ))?;
-
-    // 通过 GitHub 镜像下载
-    let download_url = format!("{}{}", config::GITHUB_PROXY, asset.browser_download_url);
+        .find(|a| a.name == asset_name)
+        .with_context(|| format!("Xray Release 中未找到 {asset_name} 资产"))?;
+
+    // 通过 GitHub 镜像下载（镜像不可用时自动切换）
+    let proxy = mirrors::preferred(base_dir, Endpoint::GithubProxy);
+    let download_url = format!("{proxy}{}", asset.browser_download_url);
     on_progress(Progress::new(10, format!("正在下载 Xray {}...", release.tag_name)));
 
     let zip_path = xray_dir.join("xray-download.zip");
-    bootstrap::download_file(&download_url, &zip_path, on_progress, 10, 28)?;
+    bootstrap::download_file(base_dir, &download_url, &zip_path, on_progress, 10, 28)?;
 
     // 解压
     on_progress(Progress::new(30, "正在解压 Xray..."));
@@ -95,13 +103,16 @@ pub fn download_or_update(base_dir: &Path, on_progress: &dyn Fn(Progress)) -> Re
     std::fs::remove_file(&zip_path).ok();
 
     // 记录版本
-    std::fs::write(&version_file, &release.tag_name).context("保存 Xray 版本失败")?;
+    state::XRAY_VERSION
+        .write_text(base_dir, &release.tag_name)
+        .context("保存 Xray 版本失败")?;
 
     on_progress(Progress::new(35, format!("Xray {} 就绪", release.tag_name)));
     Ok(())
 }
 
 /// 从订阅 URL 拉取代理列表，筛选 REALITY 协议。
+#[cfg(windows)]
 pub fn fetch_subscription(url: &str) -> Result<Vec<VlessConfig>> {
     if url.is_empty() {
         bail!(
@@ -111,21 +122,12 @@ pub fn fetch_subscription(url: &str) -> Result<Vec<VlessConfig>> {
     }
 
     let url_owned = url.to_string();
-    let agent = config::http_agent();
-    let text = retry::with_retry(
-        config::RETRY_MAX_ATTEMPTS,
-        config::RETRY_BASE_DELAY_SECS,
-        "获取代理订阅",
-        || {
-            let body = agent
-                .get(&url_owned)
-                .call()
-                .with_context(|| format!("获取订阅失败: {url_owned}"))?;
-            body.into_body()
-                .read_to_string()
-                .context("读取订阅内容失败")
-        },
-    )?;
+    let text = retry::with_default_retry("获取代理订阅", || {
+        http::get(&url_owned, &[], RequestKind::Api)
+            .with_context(|| format!("获取订阅失败: {url_owned}"))?
+            .read_to_string()
+            .context("读取订阅内容失败")
+    })?;
 
     // base64 解码
     let decoded = base64_decode(text.trim()).context("订阅 base64 解码失败")?;
@@ -153,6 +155,7 @@ pub fn fetch_subscription(url: &str) -> Result<Vec<VlessConfig>> {
 
 /// 根据 VLESS 配置生成 Xray 的 config.json 内容。
 /// 使用 serde_json 构建，防止订阅数据中的特殊字符导致 JSON 注入。
+#[cfg(any(windows, test))]
 pub fn generate_config(vless: &VlessConfig, socks_port: u16) -> String {
     let mut user = serde_json::json!({
         "id": vless.uuid,
@@ -201,7 +204,7 @@ pub fn start(base_dir: &Path) -> Result<()> {
     kill(base_dir);
 
     let xray_dir = base_dir.join(config::XRAY_DIR);
-    let xray_exe = xray_dir.join("xray.exe");
+    let xray_exe = xray_dir.join(platform::exe_name("xray"));
 
     if !xray_exe.exists() {
         bail!("xray.exe 不存在: {}", xray_exe.display());
@@ -212,30 +215,26 @@ pub fn start(base_dir: &Path) -> Result<()> {
         .arg("-config")
         .arg(xray_dir.join("config.json"))
         .current_dir(&xray_dir)
-        .creation_flags(config::CREATE_NO_WINDOW)
+        .hide_window()
         .spawn()
         .context("启动 Xray 失败")?;
 
-    let _ = std::fs::write(xray_dir.join("xray.pid"), child.id().to_string());
+    let _ = state::XRAY_PID.write_text(base_dir, &child.id().to_string());
 
     // 等待 SOCKS5 端口就绪并验证连通性
-    wait_for_socks5(config::XRAY_SOCKS_PORT)?;
+    wait_for_socks5(settings::current().socks_port)?;
     Ok(())
 }
 
 /// 终止本程序启动的 Xray 进程。
 pub fn kill(base_dir: &Path) {
-    let pid_path = base_dir.join(config::XRAY_DIR).join("xray.pid");
     let mut killed = false;
-    if let Ok(pid_str) = std::fs::read_to_string(&pid_path) {
+    if let Some(pid_str) = state::XRAY_PID.read_text(base_dir) {
         if let Ok(pid) = pid_str.trim().parse::<u32>() {
-            let _ = std::process::Command::new("taskkill")
-                .args(["/f", "/pid", &pid.to_string()])
-                .creation_flags(config::CREATE_NO_WINDOW)
-                .output();
+            platform::kill_process(pid);
             killed = true;
         }
-        let _ = std::fs::remove_file(&pid_path);
+        state::XRAY_PID.remove(base_dir);
     }
     if killed {
         std::thread::sleep(Duration::from_millis(500));
@@ -257,10 +256,13 @@ fn wait_for_socks5(port: u16) -> Result<()> {
         std::thread::sleep(Duration::from_millis(500));
     }
 
-    bail!(
-        "Xray 代理连通性检查失败（无法通过 127.0.0.1:{port} 连接到 discord.com）\n\
-         可能原因：订阅节点不可用、Xray 配置错误、或网络问题"
-    );
+    Err(error::new(
+        ErrorCode::ProxyUnavailable,
+        format!(
+            "Xray 代理连通性检查失败（无法通过 127.0.0.1:{port} 连接到 discord.com）\n\
+             可能原因：订阅节点不可用、Xray 配置错误、或网络问题"
+        ),
+    ))
 }
 
 /// 通过 SOCKS5 代理尝试 CONNECT 到目标主机，验证完整链路。
@@ -304,33 +306,43 @@ fn test_socks5_connect(
     Ok(())
 }
 
-fn fetch_latest_release() -> Result<GithubRelease> {
-    let agent = config::http_agent();
-    retry::with_retry(
-        config::RETRY_MAX_ATTEMPTS,
-        config::RETRY_BASE_DELAY_SECS,
-        "获取 Xray 最新版本",
-        || {
-            let url = format!(
-                "https://api.github.com/repos/{}/releases/latest",
-                config::XRAY_GITHUB_REPO
-            );
-
-            let body = agent
-                .get(&url)
-                .header("User-Agent", "upmc")
-                .call()
-                .context("无法连接 GitHub API")?;
-
-            let text = body.into_body().read_to_string().context("读取响应失败")?;
-            serde_json::from_str(&text).context("解析 GitHub Release JSON 失败")
-        },
-    )
+/// 查询 Xray 最新 Release。
+///
+/// 使用条件请求（304 不计入 GitHub API 限额），网络失败时回退到上次的结果，
+/// 本地已是该版本时即可离线启动。
+/// Xray Release 中指定系统和架构（`std::env::consts` 的取值）的资产名
+fn asset_name(os: &str, arch: &str) -> Option<&'static str> {
+    let name = match (os, arch) {
+        ("windows", "x86_64") => "Xray-windows-64.zip",
+        ("windows", "x86") => "Xray-windows-32.zip",
+        ("windows", "aarch64") => "Xray-windows-arm64-v8a.zip",
+        ("macos", "x86_64") => "Xray-macos-64.zip",
+        ("macos", "aarch64") => "Xray-macos-arm64-v8a.zip",
+        ("linux", "x86_64") => "Xray-linux-64.zip",
+        ("linux", "x86") => "Xray-linux-32.zip",
+        ("linux", "aarch64") => "Xray-linux-arm64-v8a.zip",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn fetch_latest_release(base_dir: &Path) -> Result<GithubRelease> {
+    retry::with_default_retry("获取 Xray 最新版本", || {
+        let url = format!(
+            "https://api.github.com/repos/{}/releases/latest",
+            config::XRAY_GITHUB_REPO
+        );
+
+        let text = http_cache::get_text_or_cached(base_dir, &url, &[("User-Agent", "upmc")])
+            .context("无法连接 GitHub API")?;
+        serde_json::from_str(&text).context("解析 GitHub Release JSON 失败")
+    })
 }
 
 // ── VLESS URL 解析 ─────────────────────────────────────────
 
 /// 解析 `uuid@host:port?params#name` (已去掉 vless:// 前缀)
+#[cfg(any(windows, test))]
 fn parse_vless(input: &str) -> Result<VlessConfig> {
     // 片段（#名称）
     let (input, name) = match input.split_once('#') {
@@ -375,6 +387,7 @@ fn parse_vless(input: &str) -> Result<VlessConfig> {
     })
 }
 
+#[cfg(any(windows, test))]
 fn parse_query(query: &str) -> std::collections::HashMap<String, String> {
     query
         .split('&')
@@ -389,6 +402,7 @@ fn parse_query(query: &str) -> std::collections::HashMap<String, String> {
 // ── 工具函数 ───────────────────────────────────────────────
 
 /// base64 解码（同时支持标准和 URL-safe 字母表，容忍缺失 padding）。
+#[cfg(any(windows, test))]
 fn base64_decode(input: &str) -> Result<Vec<u8>> {
     let input = input.trim().trim_end_matches('=');
     let mut buf = Vec::with_capacity(input.len() * 3 / 4 + 2);
@@ -417,6 +431,7 @@ fn base64_decode(input: &str) -> Result<Vec<u8>> {
 }
 
 /// 简易 percent-decode（%XX → 字节），正确处理多字节 UTF-8。
+#[cfg(any(windows, test))]
 fn percent_decode(s: &str) -> String {
     let mut raw = Vec::with_capacity(s.len());
     let mut bytes = s.bytes();
@@ -436,6 +451,7 @@ fn percent_decode(s: &str) -> String {
     String::from_utf8(raw).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
 }
 
+#[cfg(any(windows, test))]
 fn hex_val(b: u8) -> Option<u8> {
     match b {
         b'0'..=b'9' => Some(b - b'0'),
@@ -503,4 +519,31 @@ mod tests {
         assert_eq!(parsed["outbounds"][0]["settings"]["vnext"][0]["users"][0]["flow"], "xtls-rprx-vision");
         assert_eq!(parsed["outbounds"][0]["streamSettings"]["realitySettings"]["publicKey"], "PUBKEY");
     }
+
+    #[test]
+    fn asset_name_follows_platform() {
+        assert_eq!(asset_name("windows", "x86_64"), Some("Xray-windows-64.zip"));
+        assert_eq!(
+            asset_name("linux", "aarch64"),
+            Some("Xray-linux-arm64-v8a.zip")
+        );
+        assert_eq!(
+            asset_name("macos", "aarch64"),
+            Some("Xray-macos-arm64-v8a.zip")
+        );
+        assert_eq!(asset_name("freebsd", "x86_64"), None);
+        assert!(asset_name(std::env::consts::OS, std::env::consts::ARCH).is_some());
+    }
+
+    #[test]
+    fn fetch_latest_release_from_fake_server() {
+        let base_dir = crate::http::fake::temp_dir("xray");
+        let _fake = crate::http::fake::fixture().install();
+
+        let release = fetch_latest_release(&base_dir).unwrap();
+        assert_eq!(release.tag_name, "v25.1.1");
+        assert_eq!(release.assets[0].name, "Xray-windows-64.zip");
+
+        std::fs::remove_dir_all(&base_dir).ok();
+    }
 }